@@ -1,7 +1,8 @@
-use config::{BodyTemplate, LoadTest, Provider};
+use config::{BodyTemplate, GraphqlBody, GraphqlQuery, LoadTest, Provider};
 use js_sys::Map;
 use log::{debug, LevelFilter};
-use std::{path::PathBuf, str::FromStr};
+use serde_json as json;
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
 use wasm_bindgen::{prelude::wasm_bindgen, throw_str, JsValue, UnwrapThrowExt};
 
 // Only valid because we are using this in a WebAssembly context without threads.
@@ -89,7 +90,7 @@ impl Config {
     #[wasm_bindgen(js_name = getInputFiles)]
     pub fn get_input_files(&self) -> Box<[JsValue]> {
         // We also need to include file bodies so we can validate that we have those as well.
-        // Endpoint file bodies - BodyTemplate(File)
+        // Endpoint file bodies - BodyTemplate(File) and graphql query files
         let mut body_files: Vec<JsValue> = self
             .0
             .endpoints
@@ -99,6 +100,12 @@ impl Config {
                     // The path is the base path, the template.pieces has the real path
                     debug!("endpoint::body::file.template={:?}", template);
                     Some(template.evaluate_with_star().into())
+                } else if let BodyTemplate::Graphql(GraphqlBody {
+                    query: GraphqlQuery::File(path),
+                    ..
+                }) = &endpoint.body
+                {
+                    Some(path.as_str().into())
                 } else {
                     None
                 }
@@ -118,6 +125,8 @@ impl Config {
             })
             .collect::<Vec<_>>();
         provider_files.append(&mut body_files);
+        // script files
+        provider_files.extend(self.0.script_files.iter().map(|s| s.path.as_str().into()));
         provider_files.into_boxed_slice()
     }
 
@@ -129,3 +138,24 @@ impl Config {
             .map_err(|e| JsValue::from_str(&format!("{e:?}")))
     }
 }
+
+// evaluates an expression against a JSON document (the data and vars are JSON strings) and returns
+// the result as a JSON string, throws an error describing where the expression is wrong
+#[wasm_bindgen(js_name = evaluateExpression)]
+pub fn evaluate_expression(
+    expression: &str,
+    data: &str,
+    vars: Option<String>,
+) -> Result<String, JsValue> {
+    let data: json::Value =
+        json::from_str(data).map_err(|e| JsValue::from_str(&format!("invalid data: {e}")))?;
+    let vars: BTreeMap<String, json::Value> = match vars {
+        Some(vars) => {
+            json::from_str(&vars).map_err(|e| JsValue::from_str(&format!("invalid vars: {e}")))?
+        }
+        None => BTreeMap::new(),
+    };
+    let value = config::evaluate_expression(expression, &data, vars)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(value.to_string())
+}
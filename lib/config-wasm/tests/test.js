@@ -1,6 +1,6 @@
 const { expect } = require("chai");
 const { Test } = require("mocha");
-const { Config } = require("../pkg/config_wasm");
+const { Config, evaluateExpression } = require("../pkg/config_wasm");
 const { readFile, readdir } = require("fs/promises");
 const { join: joinPath } = require("path");
 
@@ -161,4 +161,26 @@ const suite = describe("config-wasm", () => {
       done(error);
     }
   });
+
+  it("should evaluate expressions", (done) => {
+    try {
+      const data = JSON.stringify({ response: { status: 200, body: { items: [{ id: 1 }, { id: 2 }] } } });
+      expect(evaluateExpression("response.body.items[1].id", data)).to.equal("2");
+      expect(evaluateExpression("response.status == expected", data, JSON.stringify({ expected: 200 }))).to.equal("true");
+      done();
+    } catch (error) {
+      console.error("test error", error);
+      done(error);
+    }
+  });
+
+  it("should throw error on invalid expression", (done) => {
+    try {
+      evaluateExpression("upper(response.status", "{}");
+      done(new Error("invalid expression should have failed"));
+    } catch (error) {
+      expect(`${error}`).to.include("1 | upper(response.status");
+      done();
+    }
+  });
 });
@@ -0,0 +1,142 @@
+use hmac::{digest::KeyInit, Hmac, Mac};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+/// A hash algorithm for the `hash` and `hmac` expression functions and for signing requests
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Algorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Algorithm {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "md5" => Some(Algorithm::Md5),
+            "sha1" => Some(Algorithm::Sha1),
+            "sha256" => Some(Algorithm::Sha256),
+            "sha384" => Some(Algorithm::Sha384),
+            "sha512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Md5 => Md5::digest(data).to_vec(),
+            Algorithm::Sha1 => Sha1::digest(data).to_vec(),
+            Algorithm::Sha256 => Sha256::digest(data).to_vec(),
+            Algorithm::Sha384 => Sha384::digest(data).to_vec(),
+            Algorithm::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    pub fn hmac(self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        fn mac<M: Mac + KeyInit>(key: &[u8], data: &[u8]) -> Vec<u8> {
+            let mut mac = <M as Mac>::new_from_slice(key).expect("hmac should take any key");
+            mac.update(data);
+            mac.finalize().into_bytes().to_vec()
+        }
+        match self {
+            Algorithm::Md5 => mac::<Hmac<Md5>>(key, data),
+            Algorithm::Sha1 => mac::<Hmac<Sha1>>(key, data),
+            Algorithm::Sha256 => mac::<Hmac<Sha256>>(key, data),
+            Algorithm::Sha384 => mac::<Hmac<Sha384>>(key, data),
+            Algorithm::Sha512 => mac::<Hmac<Sha512>>(key, data),
+        }
+    }
+}
+
+/// Signs the data with RSASSA-PKCS1-v1_5 and SHA-256 using a PEM encoded private key
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn rs256_sign(pem: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+
+    let key = PKey::private_key_from_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(|e| e.to_string())?;
+    signer.update(data).map_err(|e| e.to_string())?;
+    signer.sign_to_vec().map_err(|e| e.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn rs256_sign(_pem: &str, _data: &[u8]) -> Result<Vec<u8>, String> {
+    Err("RS256 signatures are not supported in wasm".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn digests() {
+        let checks = vec![
+            (Algorithm::Md5, "", "d41d8cd98f00b204e9800998ecf8427e"),
+            (
+                Algorithm::Md5,
+                "The quick brown fox jumps over the lazy dog",
+                "9e107d9d372bb6826bd81d3542a419d6",
+            ),
+            (
+                Algorithm::Md5,
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+            (
+                Algorithm::Sha1,
+                "abc",
+                "a9993e364706816aba3e25717850c26c9cd0d89d",
+            ),
+            (
+                Algorithm::Sha256,
+                "abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+        ];
+
+        for (alg, data, expect) in checks {
+            assert_eq!(hex(&alg.digest(data.as_bytes())), expect, "{:?}", alg);
+        }
+    }
+
+    #[test]
+    fn hmacs() {
+        // https://datatracker.ietf.org/doc/html/rfc4231#section-4.3
+        let checks = vec![
+            (
+                Algorithm::Sha256,
+                "Jefe",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                Algorithm::Sha512,
+                "Jefe",
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+            ),
+            (Algorithm::Md5, "Jefe", "750c783e6ab0b503eaa86e310a5db738"),
+        ];
+
+        for (alg, key, expect) in checks {
+            let mac = alg.hmac(key.as_bytes(), b"what do ya want for nothing?");
+            assert_eq!(hex(&mac), expect, "{:?}", alg);
+        }
+
+        // keys longer than the block size are hashed first
+        let key = [0xaa; 131];
+        let mac = Algorithm::Sha256.hmac(
+            &key,
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+        );
+        assert_eq!(
+            hex(&mac),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+}
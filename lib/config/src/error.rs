@@ -9,15 +9,18 @@ type PestError = pest::error::Error<crate::select_parser::Rule>;
 pub enum ExecutingExpressionError {
     IndexingIntoJson(String, json::Value, Marker),
     InvalidFunctionArguments(&'static str, Marker),
+    InvalidOperand(&'static str, &'static str, Marker),
 }
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug)]
 pub enum CreatingExpressionError {
     Executing(ExecutingExpressionError),
+    InvalidArgumentCount(String, usize, Marker),
     InvalidExpression(PestError, Marker),
     UnknownFunction(String, Marker),
     UnknownProvider(String, Marker),
+    UnknownScript(String, Marker),
 }
 
 impl From<ExecutingExpressionError> for CreatingExpressionError {
@@ -49,9 +52,11 @@ impl From<ExecutingExpressionError> for Error {
 pub enum Error {
     ExpressionErr(CreatingExpressionError),
     InvalidDuration(String, Marker),
+    InvalidFunctionDeclaration(String, Marker),
     InvalidLoadPattern(Marker),
     InvalidPeakLoad(String, Marker),
     InvalidPercent(String, Marker),
+    InvalidScript(String, String, Marker),
     InvalidYaml(ScanError),
     MissingEnvironmentVariable(String, Marker),
     MissingForEach(Marker),
@@ -59,8 +64,10 @@ pub enum Error {
     MissingLoadPattern(Marker),
     MissingYamlField(&'static str, Marker),
     RecursiveForEachReference(Marker),
+    RecursiveFunction(String, Marker),
     UnknownLogger(String, Marker),
     UnrecognizedKey(String, Option<String>, Marker),
+    UnsupportedOption(&'static str, &'static str, Marker),
     YamlDeserialize(Option<String>, Marker),
 }
 
@@ -98,6 +105,14 @@ impl fmt::Display for CreatingExpressionError {
                 m.col()
             ),
             Executing(e) => e.fmt(f),
+            InvalidArgumentCount(func, count, m) => write!(
+                f,
+                "function `{}` expects {} argument(s) at line {} column {}",
+                func,
+                count,
+                m.line(),
+                m.col()
+            ),
             UnknownFunction(func, m) => write!(
                 f,
                 "unknown function `{}` at line {} column {}",
@@ -112,6 +127,13 @@ impl fmt::Display for CreatingExpressionError {
                 m.line(),
                 m.col()
             ),
+            UnknownScript(s, m) => write!(
+                f,
+                "unknown script `{}` at line {} column {}",
+                s,
+                m.line(),
+                m.col()
+            ),
         }
     }
 }
@@ -134,6 +156,14 @@ impl fmt::Display for ExecutingExpressionError {
                 m.line(),
                 m.col()
             ),
+            InvalidOperand(op, reason, m) => write!(
+                f,
+                "invalid operand for `{}`: {} at line {} column {}",
+                op,
+                reason,
+                m.line(),
+                m.col()
+            ),
         }
     }
 }
@@ -144,9 +174,11 @@ impl fmt::Display for Error {
         match self {
             ExpressionErr(e) => e.fmt(f),
             InvalidDuration(d, m) => write!(f, "invalid duration `{}` at line {} column {}", d, m.line(), m.col()),
+            InvalidFunctionDeclaration(d, m) => write!(f, "invalid function declaration `{}` at line {} column {}", d, m.line(), m.col()),
             InvalidLoadPattern(m) => write!(f, "invalid load_pattern at line {} column {}", m.line(), m.col()),
             InvalidPeakLoad(p, m) => write!(f, "invalid peak_load `{}` at line {} column {}", p, m.line(), m.col()),
             InvalidPercent(p, m) => write!(f, "invalid percent `{}` at line {} column {}", p, m.line(), m.col()),
+            InvalidScript(s, e, m) => write!(f, "invalid script `{}`: {} at line {} column {}", s, e, m.line(), m.col()),
             InvalidYaml(e) => write!(f, "yaml syntax error:\n\t{e}"),
             MissingEnvironmentVariable(v, m) => write!(f, "undefined environment variable `{}` at line {} column {}", v, m.line(), m.col()),
             MissingForEach(m) => write!(f, "missing `for_each` at line {} column {}", m.line(), m.col()),
@@ -157,9 +189,11 @@ impl fmt::Display for Error {
             ),
             MissingYamlField(field, m) => write!(f, "missing field `{}` at line {} column {}", field, m.line(), m.col()),
             RecursiveForEachReference(m) => write!(f, "recursive `for_each` reference at line {} column {}", m.line(), m.col()),
+            RecursiveFunction(func, m) => write!(f, "recursive function `{}` at line {} column {}", func, m.line(), m.col()),
             UnknownLogger(l, m) => write!(f, "unknown logger `{}` at line {} column {}", l, m.line(), m.col()),
             UnrecognizedKey(k, Some(name), m) => write!(f, "unrecognized key `{}` in `{}` at line {} column {}", k, name, m.line(), m.col()),
             UnrecognizedKey(k, None, m) => write!(f, "unrecognized key `{}` at line {} column {}", k, m.line(), m.col()),
+            UnsupportedOption(o, kind, m) => write!(f, "`{}` cannot be used on a `{}` endpoint at line {} column {}", o, kind, m.line(), m.col()),
             YamlDeserialize(Some(name), m) => write!(f, "unexpected value for `{}` at line {} column {}", name, m.line(), m.col()),
             YamlDeserialize(None, m) => write!(f, "unexpected value for field at line {} column {}", m.line(), m.col()),
         }
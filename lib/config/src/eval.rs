@@ -0,0 +1,215 @@
+use crate::create_marker;
+use crate::error::{CreatingExpressionError, Error, ExecutingExpressionError};
+use crate::select_parser::{RequiredProviders, StaticVars, ValueOrExpression};
+
+use pest::error::InputLocation;
+use serde_json as json;
+
+use std::{borrow::Cow, collections::BTreeMap, fmt};
+
+// the longest a json value is shown in an error before it is cut off
+const MAX_VALUE_LENGTH: usize = 80;
+
+/// Parses an expression and evaluates it against `data`, which stands in for the `request`,
+/// `response`, `stats` and provider values a config expression would see. `vars` are available
+/// the same as the `vars` section of a config. Indexing into a value which isn't an object or an
+/// array is an error, rather than `null` as it would be in `provides` or `logs`, so a mistake in a
+/// path isn't hidden.
+pub fn evaluate_expression(
+    expression: &str,
+    data: &json::Value,
+    vars: BTreeMap<String, json::Value>,
+) -> Result<json::Value, EvalError> {
+    let static_vars: StaticVars = vars.into();
+    let mut required_providers = RequiredProviders::new();
+    let to_eval_error = |error: Error| EvalError {
+        expression: expression.into(),
+        error,
+    };
+    let value = ValueOrExpression::new(
+        expression,
+        &mut required_providers,
+        &static_vars,
+        false,
+        create_marker(),
+    )
+    .map_err(|e| to_eval_error(e.into()))?;
+    value
+        .evaluate(Cow::Borrowed(data), false, None)
+        .map(Cow::into_owned)
+        .map_err(|e| to_eval_error(e.into()))
+}
+
+/// An error from [`evaluate_expression`]. It displays with the line of the expression where the
+/// error happened and a marker pointing to it.
+#[derive(Clone, Debug)]
+pub struct EvalError {
+    expression: String,
+    error: Error,
+}
+
+impl EvalError {
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    // a description of the error, along with where it is in the expression (as a byte offset and
+    // length) when that is known. Apart from parsing errors the expression doesn't keep track of
+    // positions, so the first place the offending piece shows up is used
+    fn describe(&self) -> (String, Option<(usize, usize)>) {
+        use CreatingExpressionError::*;
+        use ExecutingExpressionError::*;
+
+        let find = |needle: &str, len: usize| self.expression.find(needle).map(|i| (i, len));
+        let e = match &self.error {
+            Error::ExpressionErr(e) => e,
+            e => return (e.to_string(), None),
+        };
+        match e {
+            InvalidExpression(e, _) => {
+                let span = match e.location {
+                    InputLocation::Pos(p) => (p, 1),
+                    InputLocation::Span((start, end)) => (start, end - start),
+                };
+                let msg = format!("invalid expression, {}", e.variant.message());
+                (msg, Some(span))
+            }
+            InvalidArgumentCount(func, count, _) => (
+                format!("function `{func}` expects {count} argument(s)"),
+                find(&format!("{func}("), func.len()),
+            ),
+            UnknownFunction(func, _) => (
+                format!("unknown function `{func}`"),
+                find(&format!("{func}("), func.len()),
+            ),
+            UnknownProvider(p, _) => (format!("unknown provider `{p}`"), find(p, p.len())),
+            UnknownScript(s, _) => (format!("unknown script `{s}`"), find(s, s.len())),
+            Executing(IndexingIntoJson(path, value, _)) => {
+                let mut value = value.to_string();
+                if value.len() > MAX_VALUE_LENGTH {
+                    let end = (0..=MAX_VALUE_LENGTH)
+                        .rev()
+                        .find(|i| value.is_char_boundary(*i))
+                        .unwrap_or_default();
+                    value.truncate(end);
+                    value.push_str("...");
+                }
+                let msg = format!("cannot get `{path}` from `{value}`");
+                // prefer the place the path is used as a property, like `.name`
+                let span = find(&format!(".{path}"), path.len())
+                    .map(|(i, len)| (i + 1, len))
+                    .or_else(|| find(path, path.len()));
+                (msg, span)
+            }
+            Executing(InvalidFunctionArguments(func, _)) => (
+                format!("invalid arguments for function `{func}`"),
+                find(&format!("{func}("), func.len()),
+            ),
+            Executing(InvalidOperand(op, reason, _)) => (
+                format!("invalid operand for `{op}`: {reason}"),
+                find(op, op.len()),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (msg, span) = self.describe();
+        write!(f, "{msg}")?;
+        let (start, len) = match span {
+            Some(span) => span,
+            None => return Ok(()),
+        };
+        let start = start.min(self.expression.len());
+        let before = &self.expression[..start];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or_default();
+        let line = self.expression[line_start..]
+            .lines()
+            .next()
+            .unwrap_or_default();
+        let line_number = before.matches('\n').count() + 1;
+        let col = before[line_start..].chars().count();
+        let width = self.expression[start..]
+            .get(..len)
+            .map(|s| s.chars().count())
+            .unwrap_or(1)
+            .max(1);
+        let gutter = " ".repeat(line_number.to_string().len());
+        write!(
+            f,
+            "\n{gutter}--> {line_number}:{}\n{gutter} |\n{line_number} | {line}\n{gutter} | {}{}",
+            col + 1,
+            " ".repeat(col),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl std::error::Error for EvalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_expressions() {
+        let data = json::json!({
+            "response": {"status": 200, "body": {"items": [{"id": 1}, {"id": 2}]}},
+            "userId": 5
+        });
+        let vars: BTreeMap<_, _> = vec![("base".to_string(), json::json!("http://localhost"))]
+            .into_iter()
+            .collect();
+
+        let checks = vec![
+            ("response.status == 200", json::json!(true)),
+            ("response.body.items[1].id", json::json!(2)),
+            ("len(response.body.items)", json::json!(2)),
+            ("base", json::json!("http://localhost")),
+            ("userId + 1", json::json!(6)),
+            ("response.body.missing", json::Value::Null),
+        ];
+        for (i, (expression, expect)) in checks.into_iter().enumerate() {
+            let value = evaluate_expression(expression, &data, vars.clone()).unwrap();
+            assert_eq!(value, expect, "index {}", i);
+        }
+
+        let e = evaluate_expression("response.status.code", &data, vars).unwrap_err();
+        assert!(matches!(
+            e.error(),
+            Error::ExpressionErr(CreatingExpressionError::Executing(
+                ExecutingExpressionError::IndexingIntoJson(..)
+            ))
+        ));
+    }
+
+    #[test]
+    fn marks_errors() {
+        let data = json::json!({"response": {"body": {"id": 1}}});
+
+        let checks = vec![
+            (
+                "response.body.id +",
+                "\n --> 1:19\n  |\n1 | response.body.id +\n  |                   ^",
+            ),
+            (
+                "upper(foo(1))",
+                "unknown function `foo`\n --> 1:7\n  |\n1 | upper(foo(1))\n  |       ^^^",
+            ),
+            (
+                "response.body.id.name",
+                "cannot get `name` from `1`\n --> 1:18\n  |\n1 | response.body.id.name\n  |                  ^^^^",
+            ),
+        ];
+        for (i, (expression, expect)) in checks.into_iter().enumerate() {
+            let e = evaluate_expression(expression, &data, BTreeMap::new()).unwrap_err();
+            let msg = e.to_string();
+            assert!(msg.ends_with(expect), "index {}: {}", i, msg);
+        }
+    }
+}
@@ -1,12 +1,19 @@
 // This is temporary until a new version of pest is released which no longer requires this
 #![allow(legacy_derive_helpers, clippy::upper_case_acronyms)]
+mod crypto;
 mod error;
+mod eval;
 mod expression_functions;
 mod from_yaml;
+mod markup;
+mod script;
 mod select_parser;
+mod xpath;
 
+pub use crypto::Algorithm as HashAlgorithm;
 pub use error::{CreatingExpressionError, Error, ExecutingExpressionError};
 use ether::{Either, Either3};
+pub use eval::{evaluate_expression, EvalError};
 pub use from_yaml::FromYaml;
 use from_yaml::{Nullable, ParseResult, TupleVec, YamlDecoder, YamlEvent};
 use http::Method;
@@ -15,11 +22,13 @@ use rand::{
     Rng,
 };
 use regex::Regex;
-use select_parser::ValueOrExpression;
+use script::Script;
+use select_parser::{bool_value, StaticVars, ValueOrExpression};
 pub use select_parser::{
-    ProviderStream, RequiredProviders, Select, Template, REQUEST_BODY, REQUEST_HEADERS,
-    REQUEST_HEADERS_ALL, REQUEST_STARTLINE, REQUEST_URL, RESPONSE_BODY, RESPONSE_HEADERS,
-    RESPONSE_HEADERS_ALL, RESPONSE_STARTLINE, STATS,
+    ProviderStream, RequiredProviders, Select, Template, REQUEST_BODY, REQUEST_COOKIES,
+    REQUEST_HEADERS, REQUEST_HEADERS_ALL, REQUEST_STARTLINE, REQUEST_URL, RESPONSE_BODY,
+    RESPONSE_COOKIES, RESPONSE_HEADERS, RESPONSE_HEADERS_ALL, RESPONSE_REDIRECTS,
+    RESPONSE_STARTLINE, STATS,
 };
 use serde::Serialize;
 use serde_json as json;
@@ -33,6 +42,7 @@ use std::{
     num::{NonZeroU16, NonZeroUsize},
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
     time::Duration,
 };
 
@@ -1202,6 +1212,978 @@ impl FromYaml for LogsPreProcessed {
     }
 }
 
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+struct AssertPreProcessed {
+    check: WithMarker<String>,
+    abort_after: Option<NonZeroUsize>,
+}
+
+impl FromYaml for AssertPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        // an assertion is either the expression to check, or a mapping with its options
+        if let (YamlEvent::Scalar(..), _) = decoder.peek()? {
+            let (check, marker) = FromYaml::parse(decoder)?;
+            let ret = Self {
+                check,
+                abort_after: None,
+            };
+            return Ok((ret, marker));
+        }
+        let mut check = None;
+        let mut abort_after = None;
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "check" => {
+                        let r =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        check = Some(r);
+                    }
+                    "abort_after" => {
+                        let r =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        abort_after = Some(r);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let check = check.ok_or(Error::MissingYamlField("check", marker))?;
+        let ret = Self { check, abort_after };
+        Ok((ret, marker))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RetryOn {
+    ConnectionError,
+    Timeout,
+}
+
+impl FromYaml for RetryOn {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        if let Ok(s) = event.into_string() {
+            let on = match s.as_ref() {
+                "connection_error" => RetryOn::ConnectionError,
+                "timeout" => RetryOn::Timeout,
+                _ => return Err(Error::YamlDeserialize(None, marker)),
+            };
+            Ok((on, marker))
+        } else {
+            Err(Error::YamlDeserialize(None, marker))
+        }
+    }
+}
+
+/// An encoding used to compress a request body, and the value for its `content-encoding` header
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Brotli,
+    Deflate,
+    Gzip,
+}
+
+impl ContentEncoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Deflate => "deflate",
+            ContentEncoding::Gzip => "gzip",
+        }
+    }
+}
+
+impl FromYaml for ContentEncoding {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        if let Ok(s) = event.into_string() {
+            let encoding = match s.as_ref() {
+                "br" => ContentEncoding::Brotli,
+                "deflate" => ContentEncoding::Deflate,
+                "gzip" => ContentEncoding::Gzip,
+                _ => return Err(Error::YamlDeserialize(None, marker)),
+            };
+            Ok((encoding, marker))
+        } else {
+            Err(Error::YamlDeserialize(None, marker))
+        }
+    }
+}
+
+fn default_retry_backoff(marker: Marker) -> PreDuration {
+    PreDuration(PreTemplate::new(WithMarker::new("100ms".into(), marker)))
+}
+
+fn default_retry_on_errors() -> Vec<RetryOn> {
+    vec![RetryOn::ConnectionError, RetryOn::Timeout]
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+struct RetryPreProcessed {
+    max_attempts: NonZeroUsize,
+    backoff: PreDuration,
+    max_backoff: Option<PreDuration>,
+    jitter: bool,
+    on_errors: Vec<RetryOn>,
+    on_status: Vec<NonZeroU16>,
+}
+
+impl FromYaml for RetryPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut max_attempts = None;
+        let mut backoff = None;
+        let mut max_backoff = None;
+        let mut jitter = None;
+        let mut on_errors = None;
+        let mut on_status = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "max_attempts" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        max_attempts = Some(a);
+                    }
+                    "backoff" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        backoff = Some(a);
+                    }
+                    "max_backoff" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        max_backoff = Some(a);
+                    }
+                    "jitter" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        jitter = Some(a);
+                    }
+                    "on_errors" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        on_errors = Some(a);
+                    }
+                    "on_status" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        on_status = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let max_attempts = max_attempts.ok_or(Error::MissingYamlField("max_attempts", marker))?;
+        let backoff = backoff.unwrap_or_else(|| default_retry_backoff(marker));
+        let jitter = jitter.unwrap_or(true);
+        let on_errors = on_errors.unwrap_or_else(default_retry_on_errors);
+        let on_status = on_status.unwrap_or_default();
+        let ret = Self {
+            max_attempts,
+            backoff,
+            max_backoff,
+            jitter,
+            on_errors,
+            on_status,
+        };
+        Ok((ret, marker))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Retry {
+    pub max_attempts: NonZeroUsize,
+    pub backoff: Duration,
+    pub max_backoff: Option<Duration>,
+    pub jitter: bool,
+    pub on_errors: Vec<RetryOn>,
+    pub on_status: Vec<u16>,
+}
+
+impl Retry {
+    fn from_preprocessed(
+        retry: RetryPreProcessed,
+        static_vars: &StaticVars,
+    ) -> Result<Self, Error> {
+        let max_backoff = retry
+            .max_backoff
+            .map(|d| d.evaluate(static_vars))
+            .transpose()?;
+        Ok(Self {
+            max_attempts: retry.max_attempts,
+            backoff: retry.backoff.evaluate(static_vars)?,
+            max_backoff,
+            jitter: retry.jitter,
+            on_errors: retry.on_errors,
+            on_status: retry.on_status.into_iter().map(NonZeroU16::get).collect(),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StreamFormat {
+    Ndjson,
+    Sse,
+}
+
+impl FromYaml for StreamFormat {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        if let Ok(s) = event.into_string() {
+            let format = match s.as_ref() {
+                "ndjson" => StreamFormat::Ndjson,
+                "sse" => StreamFormat::Sse,
+                _ => return Err(Error::YamlDeserialize(None, marker)),
+            };
+            Ok((format, marker))
+        } else {
+            Err(Error::YamlDeserialize(None, marker))
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+struct StreamResponsePreProcessed {
+    format: StreamFormat,
+    max_duration: Option<PreDuration>,
+    max_events: Option<NonZeroUsize>,
+}
+
+impl FromYaml for StreamResponsePreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut format = None;
+        let mut max_duration = None;
+        let mut max_events = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "format" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        format = Some(a);
+                    }
+                    "max_duration" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        max_duration = Some(a);
+                    }
+                    "max_events" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        max_events = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let format = format.ok_or(Error::MissingYamlField("format", marker))?;
+        let ret = Self {
+            format,
+            max_duration,
+            max_events,
+        };
+        Ok((ret, marker))
+    }
+}
+
+/// Settings for an endpoint which handles its response as a stream of events
+#[derive(Clone, Debug)]
+pub struct StreamResponse {
+    pub format: StreamFormat,
+    pub max_duration: Option<Duration>,
+    pub max_events: Option<NonZeroUsize>,
+}
+
+impl StreamResponse {
+    fn from_preprocessed(
+        stream_response: StreamResponsePreProcessed,
+        static_vars: &StaticVars,
+    ) -> Result<Self, Error> {
+        let max_duration = stream_response
+            .max_duration
+            .map(|d| d.evaluate(static_vars))
+            .transpose()?;
+        Ok(Self {
+            format: stream_response.format,
+            max_duration,
+            max_events: stream_response.max_events,
+        })
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug, Default)]
+struct WebSocketPreProcessed {
+    enabled: bool,
+    correlate: Option<String>,
+}
+
+impl FromYaml for WebSocketPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        // either `true`/`false` or a mapping of options
+        if let (YamlEvent::Scalar(..), _) = decoder.peek()? {
+            let (enabled, marker) = FromYaml::parse(decoder)?;
+            let ret = Self {
+                enabled,
+                correlate: None,
+            };
+            return Ok((ret, marker));
+        }
+        let mut correlate = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "correlate" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        correlate = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let ret = Self {
+            enabled: true,
+            correlate,
+        };
+        Ok((ret, marker))
+    }
+}
+
+/// Settings for a `websocket` endpoint
+#[derive(Clone, Debug)]
+pub struct WebSocket {
+    // the top level field of a JSON message which matches a reply to the message it answers
+    pub correlate: Option<String>,
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+struct GrpcPreProcessed {
+    descriptor: PreTemplate,
+    method: String,
+}
+
+impl FromYaml for GrpcPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut descriptor = None;
+        let mut method = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "descriptor" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        descriptor = Some(a);
+                    }
+                    "method" => {
+                        let (a, marker): (String, _) =
+                            FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        // methods are named like `package.Service/Method`
+                        match a.split_once('/') {
+                            Some((service, name)) if !service.is_empty() && !name.is_empty() => {
+                                method = Some(a)
+                            }
+                            _ => return Err(Error::YamlDeserialize(Some("method".into()), marker)),
+                        }
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let descriptor = descriptor.ok_or(Error::MissingYamlField("descriptor", marker))?;
+        let method = method.ok_or(Error::MissingYamlField("method", marker))?;
+        let ret = Self { descriptor, method };
+        Ok((ret, marker))
+    }
+}
+
+/// Settings for an endpoint which calls a gRPC method
+#[derive(Clone, Debug)]
+pub struct Grpc {
+    // the path to a protobuf descriptor set, relative to the config file
+    pub descriptor: String,
+    // the full name of the method, like `package.Service/Method`
+    pub method: String,
+}
+
+impl Grpc {
+    fn from_preprocessed(grpc: GrpcPreProcessed, static_vars: &StaticVars) -> Result<Self, Error> {
+        let descriptor = grpc
+            .descriptor
+            .evaluate(static_vars, &mut RequiredProviders::new())?;
+        Ok(Self {
+            descriptor,
+            method: grpc.method,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SocketProtocol {
+    Tcp,
+    Udp,
+}
+
+impl SocketProtocol {
+    /// The method used in the `method` tag of a `tcp` or `udp` endpoint
+    fn method(self) -> Method {
+        let method: &[u8] = match self {
+            SocketProtocol::Tcp => b"TCP",
+            SocketProtocol::Udp => b"UDP",
+        };
+        Method::from_bytes(method).expect("should be a valid method")
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug, Default)]
+struct SocketPreProcessed {
+    read_until: Option<String>,
+    read_timeout: Option<PreDuration>,
+}
+
+impl FromYaml for SocketPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut read_until = None;
+        let mut read_timeout = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "read_until" => {
+                        let (a, marker): (String, _) =
+                            FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        if a.is_empty() {
+                            return Err(Error::YamlDeserialize(Some("read_until".into()), marker));
+                        }
+                        read_until = Some(a);
+                    }
+                    "read_timeout" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        read_timeout = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let ret = Self {
+            read_until,
+            read_timeout,
+        };
+        Ok((ret, marker))
+    }
+}
+
+/// Settings for an endpoint which sends its body over a raw `tcp` or `udp` socket
+#[derive(Clone, Debug)]
+pub struct Socket {
+    pub protocol: SocketProtocol,
+    // a response is read until this delimiter is received
+    pub read_until: Option<String>,
+    // a response is read for at most this long
+    pub read_timeout: Option<Duration>,
+}
+
+impl Socket {
+    fn from_preprocessed(
+        protocol: SocketProtocol,
+        socket: SocketPreProcessed,
+        static_vars: &StaticVars,
+    ) -> Result<Self, Error> {
+        let read_timeout = socket
+            .read_timeout
+            .map(|d| d.evaluate(static_vars))
+            .transpose()?;
+        Ok(Self {
+            protocol,
+            read_until: socket.read_until,
+            read_timeout,
+        })
+    }
+
+    /// Whether a response is read after the body is sent
+    pub fn reads_response(&self) -> bool {
+        self.read_until.is_some() || self.read_timeout.is_some()
+    }
+}
+
+/// A piece of a request which is included in an `hmac` signature
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignPart {
+    Method,
+    Url,
+    Path,
+    Query,
+    Body,
+    Header(String),
+}
+
+impl FromYaml for SignPart {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        if let Ok(s) = event.into_string() {
+            let part = match s.as_ref() {
+                "method" => SignPart::Method,
+                "url" => SignPart::Url,
+                "path" => SignPart::Path,
+                "query" => SignPart::Query,
+                "body" => SignPart::Body,
+                _ => match s.strip_prefix("header:") {
+                    Some(name) if !name.is_empty() => SignPart::Header(name.to_lowercase()),
+                    _ => return Err(Error::YamlDeserialize(None, marker)),
+                },
+            };
+            Ok((part, marker))
+        } else {
+            Err(Error::YamlDeserialize(None, marker))
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SignatureEncoding {
+    Base64,
+    Hex,
+}
+
+impl FromYaml for SignatureEncoding {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        if let Ok(s) = event.into_string() {
+            let encoding = match s.as_ref() {
+                "base64" => SignatureEncoding::Base64,
+                "hex" => SignatureEncoding::Hex,
+                _ => return Err(Error::YamlDeserialize(None, marker)),
+            };
+            Ok((encoding, marker))
+        } else {
+            Err(Error::YamlDeserialize(None, marker))
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+struct AwsSigV4PreProcessed {
+    service: String,
+    region: PreTemplate,
+    access_key_id: PreTemplate,
+    secret_access_key: PreTemplate,
+    session_token: Option<PreTemplate>,
+}
+
+impl FromYaml for AwsSigV4PreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut service = None;
+        let mut region = None;
+        let mut access_key_id = None;
+        let mut secret_access_key = None;
+        let mut session_token = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "service" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        service = Some(a);
+                    }
+                    "region" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        region = Some(a);
+                    }
+                    "access_key_id" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        access_key_id = Some(a);
+                    }
+                    "secret_access_key" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        secret_access_key = Some(a);
+                    }
+                    "session_token" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        session_token = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let service = service.ok_or(Error::MissingYamlField("service", marker))?;
+        let region = region.ok_or(Error::MissingYamlField("region", marker))?;
+        let access_key_id =
+            access_key_id.ok_or(Error::MissingYamlField("access_key_id", marker))?;
+        let secret_access_key =
+            secret_access_key.ok_or(Error::MissingYamlField("secret_access_key", marker))?;
+        let ret = Self {
+            service,
+            region,
+            access_key_id,
+            secret_access_key,
+            session_token,
+        };
+        Ok((ret, marker))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+struct HmacSignPreProcessed {
+    key: PreTemplate,
+    header: String,
+    prefix: Option<String>,
+    parts: Vec<SignPart>,
+    separator: Option<String>,
+    encoding: Option<SignatureEncoding>,
+}
+
+impl FromYaml for HmacSignPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut key = None;
+        let mut header = None;
+        let mut prefix = None;
+        let mut parts = None;
+        let mut separator = None;
+        let mut encoding = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "key" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        key = Some(a);
+                    }
+                    "header" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        header = Some(a);
+                    }
+                    "prefix" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        prefix = Some(a);
+                    }
+                    "parts" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        parts = Some(a);
+                    }
+                    "separator" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        separator = Some(a);
+                    }
+                    "encoding" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        encoding = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let key = key.ok_or(Error::MissingYamlField("key", marker))?;
+        let header = header.ok_or(Error::MissingYamlField("header", marker))?;
+        let parts = parts.ok_or(Error::MissingYamlField("parts", marker))?;
+        let ret = Self {
+            key,
+            header,
+            prefix,
+            parts,
+            separator,
+            encoding,
+        };
+        Ok((ret, marker))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+enum SignPreProcessed {
+    AwsSigV4(AwsSigV4PreProcessed),
+    Hmac(HmacSignPreProcessed),
+}
+
+impl FromYaml for SignPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        match event {
+            YamlEvent::MappingStart => (),
+            _ => return Err(Error::YamlDeserialize(None, marker)),
+        }
+        let (event, marker) = decoder.next()?;
+        let ret = match event.into_string() {
+            Ok(s) if s.as_str() == "aws_sigv4" => {
+                let (a, marker) = FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
+                (SignPreProcessed::AwsSigV4(a), marker)
+            }
+            Ok(s) if s.as_str() == "hmac" => {
+                let (a, marker) = FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
+                (SignPreProcessed::Hmac(a), marker)
+            }
+            Ok(s) => return Err(Error::UnrecognizedKey(s, None, marker)),
+            Err(_) => return Err(Error::YamlDeserialize(None, marker)),
+        };
+        let (event, marker) = decoder.next()?;
+        match event {
+            YamlEvent::MappingEnd => (),
+            _ => return Err(Error::YamlDeserialize(None, marker)),
+        }
+        Ok(ret)
+    }
+}
+
+/// Signs a request with AWS Signature Version 4
+#[derive(Clone, Debug)]
+pub struct AwsSigV4 {
+    pub service: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+/// Signs a request with an HMAC-SHA256 over the `parts`, which is put in the `header`
+#[derive(Clone, Debug)]
+pub struct HmacSign {
+    pub key: String,
+    pub header: String,
+    pub prefix: String,
+    pub parts: Vec<SignPart>,
+    pub separator: String,
+    pub encoding: SignatureEncoding,
+}
+
+/// Settings for how an endpoint's requests are signed
+#[derive(Clone, Debug)]
+pub enum Sign {
+    AwsSigV4(AwsSigV4),
+    Hmac(HmacSign),
+}
+
+impl Sign {
+    fn from_preprocessed(sign: SignPreProcessed, static_vars: &StaticVars) -> Result<Self, Error> {
+        // credentials can only come from static variables
+        let evaluate = |t: PreTemplate| t.evaluate(static_vars, &mut RequiredProviders::new());
+        let sign = match sign {
+            SignPreProcessed::AwsSigV4(aws) => Sign::AwsSigV4(AwsSigV4 {
+                service: aws.service,
+                region: evaluate(aws.region)?,
+                access_key_id: evaluate(aws.access_key_id)?,
+                secret_access_key: evaluate(aws.secret_access_key)?,
+                session_token: aws.session_token.map(evaluate).transpose()?,
+            }),
+            SignPreProcessed::Hmac(hmac) => Sign::Hmac(HmacSign {
+                key: evaluate(hmac.key)?,
+                header: hmac.header,
+                prefix: hmac.prefix.unwrap_or_default(),
+                parts: hmac.parts,
+                separator: hmac.separator.unwrap_or_else(|| "\n".into()),
+                encoding: hmac.encoding.unwrap_or(SignatureEncoding::Hex),
+            }),
+        };
+        Ok(sign)
+    }
+}
+
 #[derive(Debug)]
 struct EndpointPreProcessed {
     declare: BTreeMap<String, PreValueOrExpression>,
@@ -1215,9 +2197,20 @@ struct EndpointPreProcessed {
     url: PreTemplate,
     provides: TupleVec<String, EndpointProvidesPreProcessed>,
     logs: TupleVec<String, LogsPreProcessed>,
+    assert: TupleVec<String, AssertPreProcessed>,
     max_parallel_requests: Option<NonZeroUsize>,
     no_auto_returns: bool,
     request_timeout: Option<PreDuration>,
+    follow_redirects: Option<usize>,
+    cookie_jar: Option<PreTemplate>,
+    retry: Option<RetryPreProcessed>,
+    honor_retry_after: bool,
+    compress_body: Option<ContentEncoding>,
+    websocket: WebSocketPreProcessed,
+    stream_response: Option<StreamResponsePreProcessed>,
+    grpc: Option<GrpcPreProcessed>,
+    socket: Option<(SocketProtocol, SocketPreProcessed)>,
+    sign: Option<SignPreProcessed>,
     marker: Marker,
 }
 
@@ -1235,9 +2228,20 @@ impl PartialEq for EndpointPreProcessed {
             && self.url == other.url
             && self.provides == other.provides
             && self.logs == other.logs
+            && self.assert == other.assert
             && self.max_parallel_requests == other.max_parallel_requests
             && self.no_auto_returns == other.no_auto_returns
             && self.request_timeout == other.request_timeout
+            && self.follow_redirects == other.follow_redirects
+            && self.cookie_jar == other.cookie_jar
+            && self.retry == other.retry
+            && self.honor_retry_after == other.honor_retry_after
+            && self.compress_body == other.compress_body
+            && self.websocket == other.websocket
+            && self.stream_response == other.stream_response
+            && self.grpc == other.grpc
+            && self.socket == other.socket
+            && self.sign == other.sign
     }
 }
 
@@ -1254,9 +2258,20 @@ impl FromYaml for EndpointPreProcessed {
         let mut url = None;
         let mut provides = None;
         let mut logs = None;
+        let mut assert = None;
         let mut max_parallel_requests = None;
         let mut no_auto_returns = None;
         let mut request_timeout = None;
+        let mut follow_redirects = None;
+        let mut cookie_jar = None;
+        let mut retry = None;
+        let mut honor_retry_after = None;
+        let mut compress_body = None;
+        let mut websocket = None;
+        let mut stream_response = None;
+        let mut grpc = None;
+        let mut socket = None;
+        let mut sign = None;
 
         let mut first_marker = None;
         let mut saw_opening = false;
@@ -1350,6 +2365,12 @@ impl FromYaml for EndpointPreProcessed {
                         log::debug!("EndpointPreProcessed.parse logs: {:?}", a);
                         logs = Some(a);
                     }
+                    "assert" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse assert: {:?}", a);
+                        assert = Some(a);
+                    }
                     "max_parallel_requests" => {
                         let a =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
@@ -1368,6 +2389,75 @@ impl FromYaml for EndpointPreProcessed {
                         log::debug!("EndpointPreProcessed.parse request_timeout: {:?}", a);
                         request_timeout = Some(a);
                     }
+                    "follow_redirects" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse follow_redirects: {:?}", a);
+                        follow_redirects = Some(a);
+                    }
+                    "cookie_jar" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse cookie_jar: {:?}", a);
+                        cookie_jar = Some(a);
+                    }
+                    "retry" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse retry: {:?}", a);
+                        retry = Some(a);
+                    }
+                    "honor_retry_after" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse honor_retry_after: {:?}", a);
+                        honor_retry_after = Some(a);
+                    }
+                    "compress_body" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse compress_body: {:?}", a);
+                        compress_body = Some(a);
+                    }
+                    "websocket" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse websocket: {:?}", a);
+                        websocket = Some(a);
+                    }
+                    "stream_response" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse stream_response: {:?}", a);
+                        stream_response = Some(a);
+                    }
+                    "grpc" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse grpc: {:?}", a);
+                        grpc = Some(a);
+                    }
+                    "tcp" | "udp" => {
+                        // an endpoint can only be one of `tcp` or `udp`
+                        if socket.is_some() {
+                            return Err(Error::YamlDeserialize(Some(s), marker));
+                        }
+                        let protocol = if s == "tcp" {
+                            SocketProtocol::Tcp
+                        } else {
+                            SocketProtocol::Udp
+                        };
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse socket: {:?} {:?}", protocol, a);
+                        socket = Some((protocol, a));
+                    }
+                    "sign" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("EndpointPreProcessed.parse sign: {:?}", a);
+                        sign = Some(a);
+                    }
                     _ => return Err(Error::UnrecognizedKey(s, None, marker)),
                 },
             }
@@ -1381,7 +2471,10 @@ impl FromYaml for EndpointPreProcessed {
         let url = url.ok_or(Error::MissingYamlField("url", marker))?;
         let provides = provides.unwrap_or_default();
         let logs = logs.unwrap_or_default();
+        let assert = assert.unwrap_or_default();
         let no_auto_returns = no_auto_returns.unwrap_or_default();
+        let honor_retry_after = honor_retry_after.unwrap_or_default();
+        let websocket = websocket.unwrap_or_default();
         let ret = Self {
             declare,
             headers,
@@ -1394,9 +2487,20 @@ impl FromYaml for EndpointPreProcessed {
             url,
             provides,
             logs,
+            assert,
             max_parallel_requests,
             no_auto_returns,
             request_timeout,
+            follow_redirects,
+            cookie_jar,
+            retry,
+            honor_retry_after,
+            compress_body,
+            websocket,
+            stream_response,
+            grpc,
+            socket,
+            sign,
             marker,
         };
         Ok((ret, marker))
@@ -1409,6 +2513,7 @@ enum Body {
     String(PreTemplate),
     File(PreTemplate),
     Multipart(TupleVec<String, BodyMultipartPiece>),
+    Graphql(GraphqlBodyPreProcessed),
 }
 
 impl FromYaml for Body {
@@ -1446,6 +2551,124 @@ impl FromYaml for Body {
                 let (multipart, marker) = FromYaml::parse(decoder)?;
                 (Body::Multipart(multipart), marker)
             }
+            Ok(s) if s.as_str() == "graphql" => {
+                let (graphql, marker) = FromYaml::parse(decoder)?;
+                (Body::Graphql(graphql), marker)
+            }
+            Ok(s) => return Err(Error::UnrecognizedKey(s, None, marker)),
+            Err(_) => return Err(Error::YamlDeserialize(None, marker)),
+        };
+        let (event, marker) = decoder.next()?;
+        match event {
+            YamlEvent::MappingEnd => (),
+            _ => return Err(Error::YamlDeserialize(None, marker)),
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+struct GraphqlBodyPreProcessed {
+    // like a multipart piece, the query is either a string or a `!file`
+    query: BodyMultipartPieceBody,
+    variables: TupleVec<String, GraphqlVariablePreProcessed>,
+    operation_name: Option<String>,
+}
+
+impl FromYaml for GraphqlBodyPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut query = None;
+        let mut variables = None;
+        let mut operation_name = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "query" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        query = Some(a);
+                    }
+                    "variables" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        variables = Some(a);
+                    }
+                    "operation_name" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        operation_name = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let query = query.ok_or(Error::MissingYamlField("query", marker))?;
+        let variables = variables.unwrap_or_default();
+        let ret = Self {
+            query,
+            variables,
+            operation_name,
+        };
+        Ok((ret, marker))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+enum GraphqlVariablePreProcessed {
+    String(PreTemplate),
+    Json(PreTemplate),
+}
+
+impl FromYaml for GraphqlVariablePreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.peek()?;
+        match event {
+            YamlEvent::Scalar(_, _, Some((_, tag))) if tag.as_str() == "json" => {
+                let (t, marker) = FromYaml::parse(decoder)?;
+                return Ok((GraphqlVariablePreProcessed::Json(t), marker));
+            }
+            YamlEvent::Scalar(..) => {
+                let (t, marker) = FromYaml::parse(decoder)?;
+                return Ok((GraphqlVariablePreProcessed::String(t), marker));
+            }
+            YamlEvent::MappingStart => {
+                decoder.next()?;
+            }
+            _ => return Err(Error::YamlDeserialize(None, *marker)),
+        }
+        // untagged
+        let (event, marker) = decoder.next()?;
+        let ret = match event.into_string() {
+            Ok(s) if s.as_str() == "json" => {
+                let (t, marker) = FromYaml::parse(decoder)?;
+                (GraphqlVariablePreProcessed::Json(t), marker)
+            }
             Ok(s) => return Err(Error::UnrecognizedKey(s, None, marker)),
             Err(_) => return Err(Error::YamlDeserialize(None, marker)),
         };
@@ -1696,6 +2919,7 @@ struct ClientConfigPreProcessed {
     headers: TupleVec<String, PreTemplate>,
     keepalive: PreDuration,
     request_timeout: PreDuration,
+    follow_redirects: usize,
 }
 
 impl FromYaml for ClientConfigPreProcessed {
@@ -1703,6 +2927,7 @@ impl FromYaml for ClientConfigPreProcessed {
         let mut request_timeout = None;
         let mut headers = None;
         let mut keepalive = None;
+        let mut follow_redirects = None;
 
         let mut first_marker = None;
         let mut saw_opening = false;
@@ -1739,6 +2964,11 @@ impl FromYaml for ClientConfigPreProcessed {
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
                         keepalive = Some(a);
                     }
+                    "follow_redirects" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        follow_redirects = Some(a);
+                    }
                     "headers" => {
                         let b =
                             FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
@@ -1752,10 +2982,12 @@ impl FromYaml for ClientConfigPreProcessed {
         let request_timeout = request_timeout.unwrap_or_else(|| default_request_timeout(marker));
         let keepalive = keepalive.unwrap_or_else(|| default_keepalive(marker));
         let headers = headers.unwrap_or_default();
+        let follow_redirects = follow_redirects.unwrap_or_default();
         let ret = Self {
             headers,
             keepalive,
             request_timeout,
+            follow_redirects,
         };
         Ok((ret, marker))
     }
@@ -1764,6 +2996,7 @@ impl FromYaml for ClientConfigPreProcessed {
 pub struct ClientConfig {
     pub request_timeout: Duration,
     pub keepalive: Duration,
+    pub follow_redirects: usize,
 }
 
 impl DefaultWithMarker for ClientConfigPreProcessed {
@@ -1772,6 +3005,7 @@ impl DefaultWithMarker for ClientConfigPreProcessed {
             request_timeout: default_request_timeout(marker),
             headers: Default::default(),
             keepalive: default_keepalive(marker),
+            follow_redirects: 0,
         }
     }
 }
@@ -1896,38 +3130,290 @@ impl FromYaml for GeneralConfigPreProcessed {
             }
         }
         let marker = first_marker.expect("should have a marker");
-        let bucket_size = bucket_size.unwrap_or_else(|| default_bucket_size(marker));
+        let bucket_size = bucket_size.unwrap_or_else(|| default_bucket_size(marker));
+        let ret = Self {
+            auto_buffer_start_size,
+            bucket_size,
+            log_provider_stats,
+            watch_transition_time,
+            log_level,
+        };
+        Ok((ret, marker))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+pub struct ConfigPreProcessed {
+    client: ClientConfigPreProcessed,
+    general: GeneralConfigPreProcessed,
+}
+
+impl DefaultWithMarker for ConfigPreProcessed {
+    fn default(marker: Marker) -> Self {
+        Self {
+            client: DefaultWithMarker::default(marker),
+            general: DefaultWithMarker::default(marker),
+        }
+    }
+}
+
+impl FromYaml for ConfigPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut client = None;
+        let mut general = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "client" => {
+                        let (c, _) =
+                            FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("ConfigPreProcessed.parse client: {:?}", c);
+                        client = Some(c);
+                    }
+                    "general" => {
+                        let (a, _) =
+                            FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("ConfigPreProcessed.parse general: {:?}", a);
+                        general = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let client = client.unwrap_or_else(|| DefaultWithMarker::default(marker));
+        let general = general.unwrap_or_else(|| DefaultWithMarker::default(marker));
+        let ret = Self { client, general };
+        Ok((ret, marker))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum AuthGrantType {
+    ClientCredentials,
+    Password,
+}
+
+impl FromYaml for AuthGrantType {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        if let Ok(s) = event.into_string() {
+            let grant_type = match s.as_ref() {
+                "client_credentials" => AuthGrantType::ClientCredentials,
+                "password" => AuthGrantType::Password,
+                _ => return Err(Error::YamlDeserialize(None, marker)),
+            };
+            Ok((grant_type, marker))
+        } else {
+            Err(Error::YamlDeserialize(None, marker))
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+struct AuthPreProcessed {
+    token_url: PreTemplate,
+    grant_type: AuthGrantType,
+    client_id: PreTemplate,
+    client_secret: Option<PreTemplate>,
+    username: Option<PreTemplate>,
+    password: Option<PreTemplate>,
+    scope: Option<PreTemplate>,
+    refresh_before: Option<PreDuration>,
+}
+
+impl FromYaml for AuthPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let mut token_url = None;
+        let mut grant_type = None;
+        let mut client_id = None;
+        let mut client_secret = None;
+        let mut username = None;
+        let mut password = None;
+        let mut scope = None;
+        let mut refresh_before = None;
+
+        let mut first_marker = None;
+        let mut saw_opening = false;
+        loop {
+            let (event, marker) = decoder.next()?;
+            if first_marker.is_none() {
+                first_marker = Some(marker);
+            }
+            match event {
+                YamlEvent::MappingStart => {
+                    if saw_opening {
+                        return Err(Error::YamlDeserialize(None, marker));
+                    } else {
+                        saw_opening = true;
+                    }
+                }
+                YamlEvent::SequenceStart => {
+                    return Err(Error::YamlDeserialize(None, marker));
+                }
+                YamlEvent::MappingEnd => {
+                    break;
+                }
+                YamlEvent::SequenceEnd => {
+                    unreachable!("shouldn't see sequence end");
+                }
+                YamlEvent::Scalar(s, ..) => match s.as_str() {
+                    "token_url" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        token_url = Some(a);
+                    }
+                    "grant_type" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        grant_type = Some(a);
+                    }
+                    "client_id" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        client_id = Some(a);
+                    }
+                    "client_secret" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        client_secret = Some(a);
+                    }
+                    "username" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        username = Some(a);
+                    }
+                    "password" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        password = Some(a);
+                    }
+                    "scope" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        scope = Some(a);
+                    }
+                    "refresh_before" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        refresh_before = Some(a);
+                    }
+                    _ => return Err(Error::UnrecognizedKey(s, None, marker)),
+                },
+            }
+        }
+        let marker = first_marker.expect("should have a marker");
+        let token_url = token_url.ok_or(Error::MissingYamlField("token_url", marker))?;
+        let grant_type = grant_type.unwrap_or(AuthGrantType::ClientCredentials);
+        let client_id = client_id.ok_or(Error::MissingYamlField("client_id", marker))?;
+        if grant_type == AuthGrantType::Password {
+            if username.is_none() {
+                return Err(Error::MissingYamlField("username", marker));
+            }
+            if password.is_none() {
+                return Err(Error::MissingYamlField("password", marker));
+            }
+        }
         let ret = Self {
-            auto_buffer_start_size,
-            bucket_size,
-            log_provider_stats,
-            watch_transition_time,
-            log_level,
+            token_url,
+            grant_type,
+            client_id,
+            client_secret,
+            username,
+            password,
+            scope,
+            refresh_before,
         };
         Ok((ret, marker))
     }
 }
 
-#[cfg_attr(debug_assertions, derive(PartialEq))]
-#[derive(Debug)]
-pub struct ConfigPreProcessed {
-    client: ClientConfigPreProcessed,
-    general: GeneralConfigPreProcessed,
+/// The OAuth2 grant used to get a token for the `auth` block
+#[derive(Clone, Debug)]
+pub enum AuthGrant {
+    ClientCredentials,
+    Password { username: String, password: String },
 }
 
-impl DefaultWithMarker for ConfigPreProcessed {
-    fn default(marker: Marker) -> Self {
-        Self {
-            client: DefaultWithMarker::default(marker),
-            general: DefaultWithMarker::default(marker),
-        }
+/// Gets an OAuth2 access token which endpoints can use with `${auth.token}`
+#[derive(Clone, Debug)]
+pub struct Auth {
+    pub token_url: String,
+    pub grant: AuthGrant,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+    // how long before the token expires that a new one is requested
+    pub refresh_before: Duration,
+}
+
+impl Auth {
+    fn from_preprocessed(auth: AuthPreProcessed, static_vars: &StaticVars) -> Result<Self, Error> {
+        // credentials can only come from static variables
+        let evaluate = |t: PreTemplate| t.evaluate(static_vars, &mut RequiredProviders::new());
+        let grant = match auth.grant_type {
+            AuthGrantType::ClientCredentials => AuthGrant::ClientCredentials,
+            AuthGrantType::Password => AuthGrant::Password {
+                username: evaluate(
+                    auth.username
+                        .expect("password grant should have a username"),
+                )?,
+                password: evaluate(
+                    auth.password
+                        .expect("password grant should have a password"),
+                )?,
+            },
+        };
+        let refresh_before = auth
+            .refresh_before
+            .map(|d| d.evaluate(static_vars))
+            .transpose()?
+            .unwrap_or_else(|| Duration::from_secs(60));
+        Ok(Self {
+            token_url: evaluate(auth.token_url)?,
+            grant,
+            client_id: evaluate(auth.client_id)?,
+            client_secret: auth.client_secret.map(evaluate).transpose()?,
+            scope: auth.scope.map(evaluate).transpose()?,
+            refresh_before,
+        })
     }
 }
 
-impl FromYaml for ConfigPreProcessed {
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+struct OpenApiPreProcessed {
+    spec: PreTemplate,
+}
+
+impl FromYaml for OpenApiPreProcessed {
     fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
-        let mut client = None;
-        let mut general = None;
+        let mut spec = None;
 
         let mut first_marker = None;
         let mut saw_opening = false;
@@ -1954,27 +3440,81 @@ impl FromYaml for ConfigPreProcessed {
                     unreachable!("shouldn't see sequence end");
                 }
                 YamlEvent::Scalar(s, ..) => match s.as_str() {
-                    "client" => {
-                        let (c, _) =
-                            FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
-                        log::debug!("ConfigPreProcessed.parse client: {:?}", c);
-                        client = Some(c);
-                    }
-                    "general" => {
-                        let (a, _) =
-                            FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
-                        log::debug!("ConfigPreProcessed.parse general: {:?}", a);
-                        general = Some(a);
+                    "spec" => {
+                        let a =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        spec = Some(a);
                     }
                     _ => return Err(Error::UnrecognizedKey(s, None, marker)),
                 },
             }
         }
         let marker = first_marker.expect("should have a marker");
-        let client = client.unwrap_or_else(|| DefaultWithMarker::default(marker));
-        let general = general.unwrap_or_else(|| DefaultWithMarker::default(marker));
-        let ret = Self { client, general };
-        Ok((ret, marker))
+        let spec = spec.ok_or(Error::MissingYamlField("spec", marker))?;
+        Ok((Self { spec }, marker))
+    }
+}
+
+/// The OpenAPI 3 document which the responses of the endpoints are checked against
+#[derive(Clone, Debug)]
+pub struct OpenApi {
+    // the path to the document, relative to the config file
+    pub spec: String,
+}
+
+impl OpenApi {
+    fn from_preprocessed(
+        openapi: OpenApiPreProcessed,
+        static_vars: &StaticVars,
+    ) -> Result<Self, Error> {
+        let spec = openapi
+            .spec
+            .evaluate(static_vars, &mut RequiredProviders::new())?;
+        Ok(Self { spec })
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(PartialEq))]
+#[derive(Debug)]
+enum ScriptPreProcessed {
+    Source(String),
+    // a path relative to the config file
+    File(String),
+}
+
+impl FromYaml for ScriptPreProcessed {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.peek()?;
+        match event {
+            YamlEvent::Scalar(_, _, Some((_, tag))) if tag.as_str() == "file" => {
+                let (file, marker) = FromYaml::parse(decoder)?;
+                return Ok((ScriptPreProcessed::File(file), marker));
+            }
+            YamlEvent::Scalar(..) => {
+                let (source, marker) = FromYaml::parse(decoder)?;
+                return Ok((ScriptPreProcessed::Source(source), marker));
+            }
+            YamlEvent::MappingStart => {
+                decoder.next()?;
+            }
+            _ => return Err(Error::YamlDeserialize(None, *marker)),
+        }
+        // untagged
+        let (event, marker) = decoder.next()?;
+        let ret = match event.into_string() {
+            Ok(s) if s.as_str() == "file" => {
+                let (file, marker) = FromYaml::parse(decoder)?;
+                (ScriptPreProcessed::File(file), marker)
+            }
+            Ok(s) => return Err(Error::UnrecognizedKey(s, None, marker)),
+            Err(_) => return Err(Error::YamlDeserialize(None, marker)),
+        };
+        let (event, marker) = decoder.next()?;
+        match event {
+            YamlEvent::MappingEnd => (),
+            _ => return Err(Error::YamlDeserialize(None, marker)),
+        }
+        Ok(ret)
     }
 }
 
@@ -1987,6 +3527,10 @@ struct LoadTestPreProcessed {
     providers: BTreeMap<String, ProviderPreProcessed>,
     loggers: BTreeMap<String, LoggerPreProcessed>,
     vars: BTreeMap<String, PreVar>,
+    functions: TupleVec<String, WithMarker<String>>,
+    scripts: TupleVec<String, WithMarker<ScriptPreProcessed>>,
+    auth: Option<(AuthPreProcessed, Marker)>,
+    openapi: Option<OpenApiPreProcessed>,
 }
 
 impl FromYaml for LoadTestPreProcessed {
@@ -1998,6 +3542,10 @@ impl FromYaml for LoadTestPreProcessed {
         let mut providers = None;
         let mut loggers = None;
         let mut vars = None;
+        let mut functions = None;
+        let mut scripts = None;
+        let mut auth = None;
+        let mut openapi = None;
         let mut first_marker = None;
         let mut saw_opening = false;
         loop {
@@ -2059,6 +3607,29 @@ impl FromYaml for LoadTestPreProcessed {
                         log::debug!("LoadTestPreProcessed.parse vars: {:?}", v);
                         vars = Some(v);
                     }
+                    "functions" => {
+                        let v =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("LoadTestPreProcessed.parse functions: {:?}", v);
+                        functions = Some(v);
+                    }
+                    "scripts" => {
+                        let v =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("LoadTestPreProcessed.parse scripts: {:?}", v);
+                        scripts = Some(v);
+                    }
+                    "auth" => {
+                        let v = FromYaml::parse(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("LoadTestPreProcessed.parse auth: {:?}", v);
+                        auth = Some(v);
+                    }
+                    "openapi" => {
+                        let v =
+                            FromYaml::parse_into(decoder).map_err(map_yaml_deserialize_err(s))?;
+                        log::debug!("LoadTestPreProcessed.parse openapi: {:?}", v);
+                        openapi = Some(v);
+                    }
                     _ => return Err(Error::UnrecognizedKey(s, None, marker)),
                 },
             }
@@ -2069,6 +3640,8 @@ impl FromYaml for LoadTestPreProcessed {
         let providers = providers.unwrap_or_default();
         let loggers = loggers.unwrap_or_default();
         let vars = vars.unwrap_or_default();
+        let functions = functions.unwrap_or_default();
+        let scripts = scripts.unwrap_or_default();
         let ret = Self {
             config,
             endpoints,
@@ -2076,6 +3649,10 @@ impl FromYaml for LoadTestPreProcessed {
             providers,
             loggers,
             vars,
+            functions,
+            scripts,
+            auth,
+            openapi,
         };
         Ok((ret, marker))
     }
@@ -2127,7 +3704,7 @@ impl PreValueOrExpression {
     fn evaluate(
         &self,
         required_providers: &mut RequiredProviders,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
     ) -> Result<ValueOrExpression, Error> {
         ValueOrExpression::new(
             &self.0.inner,
@@ -2162,7 +3739,7 @@ impl PreTemplate {
 
     fn evaluate(
         &self,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
         required_providers: &mut RequiredProviders,
     ) -> Result<String, Error> {
         self.as_template(static_vars, required_providers)
@@ -2174,7 +3751,7 @@ impl PreTemplate {
 
     fn as_template(
         &self,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
         required_providers: &mut RequiredProviders,
     ) -> Result<Template, Error> {
         Template::new(
@@ -2200,10 +3777,10 @@ impl FromYaml for PreTemplate {
 pub struct PreVar(WithMarker<json::Value>);
 
 impl PreVar {
-    fn evaluate(mut self, env_vars: &BTreeMap<String, json::Value>) -> Result<json::Value, Error> {
+    fn evaluate(mut self, env_vars: &StaticVars) -> Result<json::Value, Error> {
         fn json_transform(
             v: &mut json::Value,
-            env_vars: &BTreeMap<String, json::Value>,
+            env_vars: &StaticVars,
             marker: Marker,
         ) -> Result<(), Error> {
             match v {
@@ -2290,7 +3867,7 @@ fn duration_from_string2(dur: String, marker: Marker) -> Result<Duration, Error>
 pub struct PreDuration(PreTemplate);
 
 impl PreDuration {
-    fn evaluate(&self, static_vars: &BTreeMap<String, json::Value>) -> Result<Duration, Error> {
+    fn evaluate(&self, static_vars: &StaticVars) -> Result<Duration, Error> {
         let dur = self
             .0
             .evaluate(static_vars, &mut RequiredProviders::new())?;
@@ -2310,7 +3887,7 @@ impl FromYaml for PreDuration {
 struct PrePercent(PreTemplate);
 
 impl PrePercent {
-    fn evaluate(&self, static_vars: &BTreeMap<String, json::Value>) -> Result<f64, Error> {
+    fn evaluate(&self, static_vars: &StaticVars) -> Result<f64, Error> {
         let string = self
             .0
             .evaluate(static_vars, &mut RequiredProviders::new())?;
@@ -2347,7 +3924,7 @@ impl PartialEq for PreLoadPattern {
 }
 
 impl PreLoadPattern {
-    fn evaluate(&self, static_vars: &BTreeMap<String, json::Value>) -> Result<LoadPattern, Error> {
+    fn evaluate(&self, static_vars: &StaticVars) -> Result<LoadPattern, Error> {
         let mut builder: Option<LinearBuilder> = None;
         let mut last_end = 0f64;
         for lppp in &self.0 {
@@ -2388,7 +3965,7 @@ impl FromYaml for PreLoadPattern {
 struct PreHitsPer(PreTemplate);
 
 impl PreHitsPer {
-    fn evaluate(&self, static_vars: &BTreeMap<String, json::Value>) -> Result<HitsPer, Error> {
+    fn evaluate(&self, static_vars: &StaticVars) -> Result<HitsPer, Error> {
         let string = self
             .0
             .evaluate(static_vars, &mut RequiredProviders::new())?;
@@ -2422,10 +3999,30 @@ pub struct LoadTest {
     pub endpoints: Vec<Endpoint>,
     pub providers: BTreeMap<String, Provider>,
     pub loggers: BTreeMap<String, Logger>,
-    vars: BTreeMap<String, json::Value>,
+    pub auth: Option<Auth>,
+    pub openapi: Option<OpenApi>,
+    pub script_files: Vec<ScriptFile>,
+    vars: StaticVars,
     load_test_errors: Vec<Error>,
 }
 
+/// A script kept in its own file. The config crate doesn't touch the file system, so the file
+/// (at `path`, relative to the config file) is read by the caller and handed to `load`
+pub struct ScriptFile {
+    pub name: String,
+    pub path: String,
+    script: Arc<Script>,
+    marker: Marker,
+}
+
+impl ScriptFile {
+    pub fn load(&self, source: &str) -> Result<(), Error> {
+        self.script
+            .load(source)
+            .map_err(|e| Error::InvalidScript(self.name.clone(), e, self.marker))
+    }
+}
+
 #[derive(Clone, Default, PartialEq)]
 pub struct FileProvider {
     pub csv: CsvSettings,
@@ -2456,7 +4053,7 @@ impl fmt::Display for Logger {
 impl Logger {
     pub fn from_pre_processed(
         logger: LoggerPreProcessed,
-        vars: &BTreeMap<String, json::Value>,
+        vars: &StaticVars,
         required_providers: &mut RequiredProviders,
     ) -> Result<(Self, Option<Select>), Error> {
         let LoggerPreProcessed {
@@ -2488,7 +4085,58 @@ impl Logger {
     }
 }
 
+/// A named check from the `assert` section of an endpoint
+#[derive(Clone, Debug)]
+pub struct Assertion {
+    pub name: String,
+    // the number of failures after which the test is stopped
+    pub abort_after: Option<NonZeroUsize>,
+    check: ValueOrExpression,
+}
+
+impl Assertion {
+    pub fn simple(name: &str, check: &'static str, abort_after: Option<NonZeroUsize>) -> Self {
+        let assertion = AssertPreProcessed {
+            check: WithMarker::new(check.into(), create_marker()),
+            abort_after,
+        };
+        let mut required_providers = RequiredProviders::new();
+        Self::from_preprocessed(
+            name.into(),
+            assertion,
+            &Default::default(),
+            &mut required_providers,
+        )
+        .unwrap()
+    }
+
+    fn from_preprocessed(
+        name: String,
+        assertion: AssertPreProcessed,
+        static_vars: &StaticVars,
+        required_providers: &mut RequiredProviders,
+    ) -> Result<Self, Error> {
+        let (check, marker) = assertion.check.destruct();
+        let check = ValueOrExpression::new(&check, required_providers, static_vars, true, marker)?;
+        Ok(Self {
+            name,
+            abort_after: assertion.abort_after,
+            check,
+        })
+    }
+
+    /// Checks the assertion against the values for a response. A check which doesn't evaluate
+    /// to a truthy value fails.
+    pub fn passes(&self, d: &json::Value) -> bool {
+        self.check
+            .evaluate(Cow::Borrowed(d), true, None)
+            .map(|v| bool_value(&v))
+            .unwrap_or(false)
+    }
+}
+
 pub struct Endpoint {
+    pub assertions: Vec<Assertion>,
     pub body: BodyTemplate,
     pub declare: Vec<(String, ValueOrExpression)>,
     pub headers: Vec<(String, Template)>,
@@ -2503,6 +4151,16 @@ pub struct Endpoint {
     pub providers_to_stream: RequiredProviders,
     pub required_providers: RequiredProviders,
     pub request_timeout: Option<Duration>,
+    pub follow_redirects: Option<usize>,
+    pub cookie_jar: Option<Template>,
+    pub retry: Option<Retry>,
+    pub honor_retry_after: bool,
+    pub compress_body: Option<ContentEncoding>,
+    pub websocket: Option<WebSocket>,
+    pub stream_response: Option<StreamResponse>,
+    pub grpc: Option<Grpc>,
+    pub socket: Option<Socket>,
+    pub sign: Option<Sign>,
     pub tags: BTreeMap<String, Template>,
     pub url: Template,
 }
@@ -2521,9 +4179,32 @@ pub struct MultipartBody {
     pub pieces: Vec<MultipartPiece>,
 }
 
+#[derive(Clone)]
+pub enum GraphqlQuery {
+    // the path to a file with the query, relative to the config file
+    File(String),
+    String(String),
+}
+
+#[derive(Clone)]
+pub enum GraphqlVariable {
+    // the evaluated template is sent as a string
+    String(Template),
+    // the evaluated template is parsed and sent as json
+    Json(Template),
+}
+
+#[derive(Clone)]
+pub struct GraphqlBody {
+    pub query: GraphqlQuery,
+    pub variables: Vec<(String, GraphqlVariable)>,
+    pub operation_name: Option<String>,
+}
+
 #[derive(Clone)]
 pub enum BodyTemplate {
     File(PathBuf, Template),
+    Graphql(GraphqlBody),
     Multipart(MultipartBody),
     None,
     String(Template),
@@ -2533,6 +4214,7 @@ impl fmt::Display for BodyTemplate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
             BodyTemplate::File(_, _) => write!(f, "BodyTemplate::File"),
+            BodyTemplate::Graphql(_) => write!(f, "BodyTemplate::Graphql"),
             BodyTemplate::Multipart(_) => write!(f, "BodyTemplate::Multipart"),
             BodyTemplate::None => write!(f, "BodyTemplate::None"),
             BodyTemplate::String(_) => write!(f, "BodyTemplate::String"),
@@ -2544,10 +4226,11 @@ impl Endpoint {
     fn from_preprocessed(
         endpoint: EndpointPreProcessed,
         endpoint_id: usize,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
         global_load_pattern: &Option<LoadPattern>,
         global_headers: &[(String, (Template, RequiredProviders))],
         config_path: &Path,
+        has_auth: bool,
     ) -> Result<Self, Error> {
         let EndpointPreProcessed {
             declare,
@@ -2555,6 +4238,7 @@ impl Endpoint {
             body,
             load_pattern,
             logs,
+            assert,
             max_parallel_requests,
             method,
             no_auto_returns,
@@ -2563,11 +4247,34 @@ impl Endpoint {
             provides,
             url,
             request_timeout,
+            follow_redirects,
+            cookie_jar,
+            retry,
+            honor_retry_after,
+            compress_body,
+            websocket,
+            stream_response,
+            grpc,
+            socket,
+            sign,
             mut tags,
+            marker,
             ..
         } = endpoint;
+        let WebSocketPreProcessed {
+            enabled: websocket,
+            correlate,
+        } = websocket;
+        // gRPC calls are always a POST, and `tcp` or `udp` endpoints use their protocol
+        let method = match (&grpc, &socket) {
+            (Some(_), _) => Method::POST,
+            (None, Some((protocol, _))) => protocol.method(),
+            (None, None) => method,
+        };
         let mut required_providers = RequiredProviders::new();
 
+        // `tcp` and `udp` endpoints have nowhere to send headers
+        let has_headers = !headers.0.is_empty();
         let mut headers_to_remove = BTreeSet::new();
         let mut headers_to_add = Vec::new();
         for (k, v) in headers.0 {
@@ -2616,6 +4323,9 @@ impl Endpoint {
 
         let url_marker = (url.0).marker;
         let url = url.as_template(static_vars, &mut required_providers)?;
+        let cookie_jar = cookie_jar
+            .map(|c| c.as_template(static_vars, &mut required_providers))
+            .transpose()?;
         tags.entry("url".into()).or_insert_with(|| {
             PreTemplate::new(WithMarker::new(url.evaluate_with_star(), url_marker))
         });
@@ -2690,11 +4400,108 @@ impl Endpoint {
                         };
                         BodyTemplate::Multipart(multipart)
                     }
+                    Body::Graphql(graphql) => {
+                        // the query can only use static variables, values from providers are
+                        // passed in the `variables`
+                        let query = match graphql.query {
+                            BodyMultipartPieceBody::File(t) => GraphqlQuery::File(
+                                t.evaluate(static_vars, &mut RequiredProviders::new())?,
+                            ),
+                            BodyMultipartPieceBody::String(t) => GraphqlQuery::String(
+                                t.evaluate(static_vars, &mut RequiredProviders::new())?,
+                            ),
+                        };
+                        let variables = graphql
+                            .variables
+                            .0
+                            .into_iter()
+                            .map(|(k, v)| {
+                                let variable = match v {
+                                    GraphqlVariablePreProcessed::String(t) => {
+                                        GraphqlVariable::String(
+                                            t.as_template(static_vars, &mut required_providers)?,
+                                        )
+                                    }
+                                    GraphqlVariablePreProcessed::Json(t) => GraphqlVariable::Json(
+                                        t.as_template(static_vars, &mut required_providers)?,
+                                    ),
+                                };
+                                Ok::<_, Error>((k, variable))
+                            })
+                            .collect::<Result<_, _>>()?;
+                        BodyTemplate::Graphql(GraphqlBody {
+                            query,
+                            variables,
+                            operation_name: graphql.operation_name,
+                        })
+                    }
                 };
                 Ok::<_, Error>(value)
             })
             .transpose()?
             .unwrap_or(BodyTemplate::None);
+        // each message sent on a websocket is the evaluated body
+        if websocket && !matches!(body, BodyTemplate::String(_) | BodyTemplate::None) {
+            return Err(Error::YamlDeserialize(Some("body".into()), marker));
+        }
+        // the first of the options for http requests which is set, for rejecting them on the
+        // endpoints which don't send http requests
+        let http_only = [
+            ("assert", !assert.0.is_empty()),
+            ("retry", retry.is_some()),
+            ("cookie_jar", cookie_jar.is_some()),
+            ("follow_redirects", follow_redirects.is_some()),
+            ("honor_retry_after", honor_retry_after),
+            ("compress_body", compress_body.is_some()),
+            ("stream_response", stream_response.is_some()),
+            ("sign", sign.is_some()),
+        ]
+        .iter()
+        .find_map(|(option, set)| set.then_some(*option));
+        // a websocket only uses the url and headers to open its connection
+        if let (true, Some(option)) = (websocket, http_only) {
+            return Err(Error::UnsupportedOption(option, "websocket", marker));
+        }
+        // the events of a streamed response are only run through the `provides` and `logs`
+        if stream_response.is_some() {
+            let option = [
+                ("assert", !assert.0.is_empty()),
+                ("graphql", matches!(body, BodyTemplate::Graphql(_))),
+            ]
+            .iter()
+            .find_map(|(option, set)| set.then_some(*option));
+            if let Some(option) = option {
+                return Err(Error::UnsupportedOption(option, "stream_response", marker));
+            }
+        }
+        // a gRPC request message is built from the evaluated json body
+        if grpc.is_some() {
+            if websocket {
+                return Err(Error::YamlDeserialize(Some("grpc".into()), marker));
+            }
+            if !matches!(body, BodyTemplate::String(_) | BodyTemplate::None) {
+                return Err(Error::YamlDeserialize(Some("body".into()), marker));
+            }
+            if let Some(option) = http_only {
+                return Err(Error::UnsupportedOption(option, "grpc", marker));
+            }
+        }
+        // a `tcp` or `udp` endpoint sends the evaluated body to the `host:port` in the url
+        if let Some((protocol, _)) = &socket {
+            let key = match protocol {
+                SocketProtocol::Tcp => "tcp",
+                SocketProtocol::Udp => "udp",
+            };
+            if websocket || grpc.is_some() {
+                return Err(Error::YamlDeserialize(Some(key.into()), marker));
+            }
+            if !matches!(body, BodyTemplate::String(_) | BodyTemplate::None) {
+                return Err(Error::YamlDeserialize(Some("body".into()), marker));
+            }
+            if let Some(option) = http_only.or_else(|| has_headers.then_some("headers")) {
+                return Err(Error::UnsupportedOption(option, key, marker));
+            }
+        }
 
         let mut providers_to_stream = required_providers;
         let mut required_providers2 = RequiredProviders::new();
@@ -2702,7 +4509,16 @@ impl Endpoint {
             .into_iter()
             .map(|(key, expression)| {
                 providers_to_stream.remove(&key);
-                let value = expression.evaluate(&mut required_providers2, static_vars)?;
+                let mut declare_providers = RequiredProviders::new();
+                let value = expression.evaluate(&mut declare_providers, static_vars)?;
+                // the auth token is not a stream of values, so it can't be declared from
+                if let Some((_, marker)) = declare_providers
+                    .iter()
+                    .find(|(p, _)| has_auth && p.as_str() == "auth")
+                {
+                    return Err(Error::YamlDeserialize(Some("declare".into()), *marker));
+                }
+                required_providers2.extend(declare_providers);
                 Ok((key, value))
             })
             .collect::<Result<_, Error>>()?;
@@ -2711,8 +4527,24 @@ impl Endpoint {
         let request_timeout = request_timeout
             .map(|d| d.evaluate(static_vars))
             .transpose()?;
+        let retry = retry
+            .map(|r| Retry::from_preprocessed(r, static_vars))
+            .transpose()?;
+        let stream_response = stream_response
+            .map(|s| StreamResponse::from_preprocessed(s, static_vars))
+            .transpose()?;
+        let grpc = grpc
+            .map(|g| Grpc::from_preprocessed(g, static_vars))
+            .transpose()?;
+        let socket = socket
+            .map(|(protocol, s)| Socket::from_preprocessed(protocol, s, static_vars))
+            .transpose()?;
+        let sign = sign
+            .map(|s| Sign::from_preprocessed(s, static_vars))
+            .transpose()?;
 
         let mut endpoint = Endpoint {
+            assertions: Default::default(),
             declare,
             headers,
             body,
@@ -2726,6 +4558,16 @@ impl Endpoint {
             provides,
             providers_to_stream,
             request_timeout,
+            follow_redirects,
+            cookie_jar,
+            retry,
+            honor_retry_after,
+            compress_body,
+            websocket: websocket.then_some(WebSocket { correlate }),
+            stream_response,
+            grpc,
+            socket,
+            sign,
             required_providers,
             url,
             tags,
@@ -2740,6 +4582,19 @@ impl Endpoint {
             };
             endpoint.append_logger(key, value, static_vars)?;
         }
+        // like logs, assertions are checked after the response so they don't hold up the request
+        endpoint.assertions = assert
+            .0
+            .into_iter()
+            .map(|(name, assertion)| {
+                Assertion::from_preprocessed(
+                    name,
+                    assertion,
+                    static_vars,
+                    &mut endpoint.providers_to_stream,
+                )
+            })
+            .collect::<Result<_, _>>()?;
 
         Ok(endpoint)
     }
@@ -2748,7 +4603,7 @@ impl Endpoint {
         &mut self,
         key: String,
         value: EndpointProvidesPreProcessed,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
     ) -> Result<(), Error> {
         let value = Select::new(value, static_vars, &mut self.providers_to_stream, true)?;
         self.append_processed_logger(key, value, None);
@@ -2789,14 +4644,63 @@ impl LoadTest {
             .map(|(k, v)| (k.clone(), v.as_str().into()))
             .collect();
 
-        let vars: BTreeMap<String, json::Value> = c
+        let vars = c
             .vars
             .into_iter()
             .map(|(k, v)| Ok::<_, Error>((k, v.evaluate(&env_vars)?)))
             .collect::<Result<_, _>>()?;
+        let functions = c
+            .functions
+            .0
+            .into_iter()
+            .map(|(declaration, body)| {
+                let (body, marker) = body.destruct();
+                (declaration, body, marker)
+            })
+            .collect();
+        let mut script_files = Vec::new();
+        let scripts = c
+            .scripts
+            .0
+            .into_iter()
+            .map(|(name, script)| {
+                let (script, marker) = script.destruct();
+                let script = match script {
+                    ScriptPreProcessed::Source(source) => Script::compile(&source)
+                        .map_err(|e| Error::InvalidScript(name.clone(), e, marker))?
+                        .into(),
+                    ScriptPreProcessed::File(path) => {
+                        let script = Arc::new(Script::unloaded());
+                        script_files.push(ScriptFile {
+                            name: name.clone(),
+                            path,
+                            script: script.clone(),
+                            marker,
+                        });
+                        script
+                    }
+                };
+                Ok((name, script))
+            })
+            .collect::<Result<_, Error>>()?;
+        let vars = StaticVars::new(vars, functions, scripts)?;
 
         let loggers = c.loggers;
         let providers = c.providers;
+        let auth = c
+            .auth
+            .map(|(auth, marker)| {
+                // `auth` is used in templates like a provider so the names can't overlap
+                if providers.contains_key("auth") {
+                    return Err(Error::YamlDeserialize(Some("auth".into()), marker));
+                }
+                Auth::from_preprocessed(auth, &vars)
+            })
+            .transpose()?;
+        let openapi = c
+            .openapi
+            .map(|o| OpenApi::from_preprocessed(o, &vars))
+            .transpose()?;
         let global_load_pattern = c.load_pattern.map(|l| l.evaluate(&vars)).transpose()?;
         let global_headers: Vec<_> = c
             .config
@@ -2814,6 +4718,7 @@ impl LoadTest {
             client: ClientConfig {
                 keepalive: c.config.client.keepalive.evaluate(&vars)?,
                 request_timeout: c.config.client.request_timeout.evaluate(&vars)?,
+                follow_redirects: c.config.client.follow_redirects,
             },
             general: GeneralConfig {
                 auto_buffer_start_size: c.config.general.auto_buffer_start_size,
@@ -2844,6 +4749,7 @@ impl LoadTest {
                     &global_load_pattern,
                     &global_headers,
                     config_path,
+                    auth.is_some(),
                 )?;
 
                 // check for errors which would prevent a load test (but are ok for a try run)
@@ -2911,6 +4817,9 @@ impl LoadTest {
             endpoints,
             providers,
             loggers: Default::default(),
+            auth,
+            openapi,
+            script_files,
             vars,
             load_test_errors,
         };
@@ -2987,7 +4896,9 @@ impl LoadTest {
         &self,
         mut providers: I,
     ) -> Result<(), Error> {
-        if let Some((p, marker)) = providers.find(|(p, _)| !self.providers.contains_key(*p)) {
+        let is_known =
+            |p: &String| self.providers.contains_key(p) || (p == "auth" && self.auth.is_some());
+        if let Some((p, marker)) = providers.find(|(p, _)| !is_known(p)) {
             let e = CreatingExpressionError::UnknownProvider(p.clone(), *marker);
             Err(e.into())
         } else {
@@ -3235,9 +5146,20 @@ mod tests {
             url: create_template(url),
             provides: Default::default(),
             logs: Default::default(),
+            assert: Default::default(),
             no_auto_returns: false,
             max_parallel_requests: None,
             request_timeout: None,
+            follow_redirects: None,
+            cookie_jar: None,
+            retry: None,
+            honor_retry_after: false,
+            compress_body: None,
+            websocket: Default::default(),
+            stream_response: None,
+            grpc: None,
+            socket: None,
+            sign: None,
             marker: create_marker(),
         }
     }
@@ -3294,8 +5216,32 @@ mod tests {
                         select: 1
                     foo:
                         select: 1
+                assert:
+                    ok: response.status == 200
+                    items:
+                        check: len(response.body.items) > 0
+                        abort_after: 10
                 no_auto_returns: true
-                request_timeout: 15s",
+                request_timeout: 15s
+                follow_redirects: 5
+                cookie_jar: global
+                retry:
+                    max_attempts: 3
+                honor_retry_after: true
+                compress_body: gzip
+                websocket: true
+                stream_response:
+                    format: sse
+                grpc:
+                    descriptor: greeter.pb
+                    method: helloworld.Greeter/SayHello
+                tcp:
+                    read_until: foo
+                sign:
+                    hmac:
+                        key: secret
+                        header: X-Signature
+                        parts: [method, body]",
                 Some(EndpointPreProcessed {
                     declare: btreemap! {
                         "foo".to_string() => PreValueOrExpression(create_with_marker("bar".to_string()))
@@ -3361,9 +5307,68 @@ mod tests {
                         ),
                     ]
                     .into(),
+                    assert: vec![
+                        (
+                            "ok".to_string(),
+                            AssertPreProcessed {
+                                check: create_with_marker("response.status == 200".to_string()),
+                                abort_after: None,
+                            },
+                        ),
+                        (
+                            "items".to_string(),
+                            AssertPreProcessed {
+                                check: create_with_marker(
+                                    "len(response.body.items) > 0".to_string(),
+                                ),
+                                abort_after: Some(NonZeroUsize::new(10).unwrap()),
+                            },
+                        ),
+                    ]
+                    .into(),
                     no_auto_returns: true,
                     max_parallel_requests: Some(NonZeroUsize::new(3).unwrap()),
                     request_timeout: Some(PreDuration(create_template("15s"))),
+                    follow_redirects: Some(5),
+                    cookie_jar: Some(create_template("global")),
+                    retry: Some(RetryPreProcessed {
+                        max_attempts: NonZeroUsize::new(3).unwrap(),
+                        backoff: PreDuration(create_template("100ms")),
+                        max_backoff: None,
+                        jitter: true,
+                        on_errors: default_retry_on_errors(),
+                        on_status: Vec::new(),
+                    }),
+                    honor_retry_after: true,
+                    compress_body: Some(ContentEncoding::Gzip),
+                    websocket: WebSocketPreProcessed {
+                        enabled: true,
+                        correlate: None,
+                    },
+                    stream_response: Some(StreamResponsePreProcessed {
+                        format: StreamFormat::Sse,
+                        max_duration: None,
+                        max_events: None,
+                    }),
+                    grpc: Some(GrpcPreProcessed {
+                        descriptor: create_template("greeter.pb"),
+                        method: "helloworld.Greeter/SayHello".into(),
+                    }),
+                    socket: Some((
+                        SocketProtocol::Tcp,
+                        SocketPreProcessed {
+                            read_until: Some("foo".into()),
+                            read_timeout: None,
+                        },
+                    )),
+                    sign: Some(SignPreProcessed::Hmac(HmacSignPreProcessed {
+                        key: create_template("secret"),
+                        header: "X-Signature".into(),
+                        prefix: None,
+                        parts: vec![SignPart::Method, SignPart::Body],
+                        separator: None,
+                        encoding: None,
+                    })),
                     marker: create_marker(),
                 }),
             ),
@@ -3405,6 +5410,54 @@ mod tests {
                     .into(),
                 )),
             ),
+            (
+                "graphql:
+                    query: query GetUser { user }
+                    variables:
+                        id: ${userId}
+                        age: !json ${age}
+                        filter:
+                            json: '{\"active\": true}'
+                    operation_name: GetUser",
+                Some(Body::Graphql(GraphqlBodyPreProcessed {
+                    query: BodyMultipartPieceBody::String(create_template(
+                        "query GetUser { user }",
+                    )),
+                    variables: vec![
+                        (
+                            "id".to_string(),
+                            GraphqlVariablePreProcessed::String(create_template("${userId}")),
+                        ),
+                        (
+                            "age".to_string(),
+                            GraphqlVariablePreProcessed::Json(create_template("${age}")),
+                        ),
+                        (
+                            "filter".to_string(),
+                            GraphqlVariablePreProcessed::Json(create_template(
+                                r#"{"active": true}"#,
+                            )),
+                        ),
+                    ]
+                    .into(),
+                    operation_name: Some("GetUser".into()),
+                })),
+            ),
+            (
+                "graphql:
+                    query: !file queries/user.graphql",
+                Some(Body::Graphql(GraphqlBodyPreProcessed {
+                    query: BodyMultipartPieceBody::File(create_template("queries/user.graphql")),
+                    variables: Default::default(),
+                    operation_name: None,
+                })),
+            ),
+            (
+                "graphql:
+                    variables:
+                        id: 1",
+                None,
+            ),
         ];
         check_all(values);
     }
@@ -3466,6 +5519,710 @@ mod tests {
         check_all(values);
     }
 
+    #[test]
+    fn from_yaml_content_encoding() {
+        let values = vec![
+            ("gzip", Some(ContentEncoding::Gzip)),
+            ("br", Some(ContentEncoding::Brotli)),
+            ("deflate", Some(ContentEncoding::Deflate)),
+            ("brotli", None),
+            ("GZIP", None),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_retry_pre_processed() {
+        let values = vec![
+            ("asdf", None),
+            ("backoff: 1s", None),
+            ("max_attempts: 0", None),
+            (
+                "
+                max_attempts: 4
+                backoff: 50ms
+                max_backoff: 2s
+                jitter: false
+                on_errors:
+                    - timeout
+                on_status:
+                    - 429
+                    - 503",
+                Some(RetryPreProcessed {
+                    max_attempts: NonZeroUsize::new(4).unwrap(),
+                    backoff: PreDuration(create_template("50ms")),
+                    max_backoff: Some(PreDuration(create_template("2s"))),
+                    jitter: false,
+                    on_errors: vec![RetryOn::Timeout],
+                    on_status: vec![NonZeroU16::new(429).unwrap(), NonZeroU16::new(503).unwrap()],
+                }),
+            ),
+            (
+                "
+                max_attempts: 2
+                on_errors:
+                    - foo",
+                None,
+            ),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_stream_response_pre_processed() {
+        let values = vec![
+            ("asdf", None),
+            ("max_events: 10", None),
+            ("format: xml", None),
+            (
+                "
+                format: ndjson
+                max_duration: 30s
+                max_events: 100",
+                Some(StreamResponsePreProcessed {
+                    format: StreamFormat::Ndjson,
+                    max_duration: Some(PreDuration(create_template("30s"))),
+                    max_events: Some(NonZeroUsize::new(100).unwrap()),
+                }),
+            ),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_websocket_pre_processed() {
+        let values = vec![
+            ("asdf", None),
+            ("foo: id", None),
+            ("[]", None),
+            ("false", Some(WebSocketPreProcessed::default())),
+            (
+                "true",
+                Some(WebSocketPreProcessed {
+                    enabled: true,
+                    correlate: None,
+                }),
+            ),
+            (
+                "{}",
+                Some(WebSocketPreProcessed {
+                    enabled: true,
+                    correlate: None,
+                }),
+            ),
+            (
+                "correlate: id",
+                Some(WebSocketPreProcessed {
+                    enabled: true,
+                    correlate: Some("id".into()),
+                }),
+            ),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_grpc_pre_processed() {
+        let values = vec![
+            ("asdf", None),
+            ("descriptor: greeter.pb", None),
+            ("method: helloworld.Greeter/SayHello", None),
+            (
+                "
+                descriptor: greeter.pb
+                method: SayHello",
+                None,
+            ),
+            (
+                "
+                descriptor: greeter.pb
+                method: helloworld.Greeter/",
+                None,
+            ),
+            (
+                "
+                descriptor: ${protoDir}/greeter.pb
+                method: helloworld.Greeter/SayHello",
+                Some(GrpcPreProcessed {
+                    descriptor: create_template("${protoDir}/greeter.pb"),
+                    method: "helloworld.Greeter/SayHello".into(),
+                }),
+            ),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_socket_pre_processed() {
+        let values = vec![
+            ("asdf", None),
+            ("{}", Some(SocketPreProcessed::default())),
+            ("read_until: ''", None),
+            ("read_until_: x", None),
+            (
+                "
+                read_until: \"\\n\"
+                read_timeout: 500ms",
+                Some(SocketPreProcessed {
+                    read_until: Some("\n".into()),
+                    read_timeout: Some(PreDuration(create_template("500ms"))),
+                }),
+            ),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_endpoint_socket() {
+        let mut tcp = create_endpoint_pre_processed("localhost:9000");
+        tcp.socket = Some((SocketProtocol::Tcp, SocketPreProcessed::default()));
+        let mut udp = create_endpoint_pre_processed("localhost:9000");
+        udp.socket = Some((
+            SocketProtocol::Udp,
+            SocketPreProcessed {
+                read_until: None,
+                read_timeout: Some(PreDuration(create_template("1s"))),
+            },
+        ));
+        let values = vec![
+            (
+                "
+                url: localhost:9000
+                tcp: {}",
+                Some(tcp),
+            ),
+            (
+                "
+                url: localhost:9000
+                udp:
+                    read_timeout: 1s",
+                Some(udp),
+            ),
+            (
+                "
+                url: localhost:9000
+                tcp: {}
+                udp: {}",
+                None,
+            ),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_sign_pre_processed() {
+        let values = vec![
+            ("asdf", None),
+            ("{}", None),
+            ("foo: {}", None),
+            (
+                "
+                aws_sigv4:
+                    service: execute-api
+                    region: us-east-1",
+                None,
+            ),
+            (
+                "
+                aws_sigv4:
+                    service: execute-api
+                    region: us-east-1
+                    access_key_id: ${accessKeyId}
+                    secret_access_key: ${secretAccessKey}",
+                Some(SignPreProcessed::AwsSigV4(AwsSigV4PreProcessed {
+                    service: "execute-api".into(),
+                    region: create_template("us-east-1"),
+                    access_key_id: create_template("${accessKeyId}"),
+                    secret_access_key: create_template("${secretAccessKey}"),
+                    session_token: None,
+                })),
+            ),
+            (
+                "
+                hmac:
+                    key: ${secret}
+                    header: Authorization
+                    parts: [method, path]
+                    encoding: sha1",
+                None,
+            ),
+            (
+                "
+                hmac:
+                    key: ${secret}
+                    header: Authorization
+                    parts: [method, 'header:']",
+                None,
+            ),
+            (
+                "
+                hmac:
+                    key: ${secret}
+                    header: Authorization
+                    prefix: 'HMAC '
+                    parts: [method, url, path, query, 'header:X-Date', body]
+                    separator: '|'
+                    encoding: base64",
+                Some(SignPreProcessed::Hmac(HmacSignPreProcessed {
+                    key: create_template("${secret}"),
+                    header: "Authorization".into(),
+                    prefix: Some("HMAC ".into()),
+                    parts: vec![
+                        SignPart::Method,
+                        SignPart::Url,
+                        SignPart::Path,
+                        SignPart::Query,
+                        SignPart::Header("x-date".into()),
+                        SignPart::Body,
+                    ],
+                    separator: Some("|".into()),
+                    encoding: Some(SignatureEncoding::Base64),
+                })),
+            ),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn from_yaml_auth_pre_processed() {
+        let values = vec![
+            ("asdf", None),
+            ("{}", None),
+            ("token_url: https://localhost/token", None),
+            (
+                "
+                token_url: https://localhost/token
+                client_id: ${clientId}
+                grant_type: implicit",
+                None,
+            ),
+            (
+                "
+                token_url: https://localhost/token
+                client_id: ${clientId}
+                grant_type: password
+                username: ${username}",
+                None,
+            ),
+            (
+                "
+                token_url: https://localhost/token
+                client_id: ${clientId}
+                client_secret: ${clientSecret}",
+                Some(AuthPreProcessed {
+                    token_url: create_template("https://localhost/token"),
+                    grant_type: AuthGrantType::ClientCredentials,
+                    client_id: create_template("${clientId}"),
+                    client_secret: Some(create_template("${clientSecret}")),
+                    username: None,
+                    password: None,
+                    scope: None,
+                    refresh_before: None,
+                }),
+            ),
+            (
+                "
+                token_url: https://localhost/token
+                grant_type: password
+                client_id: ${clientId}
+                username: ${username}
+                password: ${password}
+                scope: read write
+                refresh_before: 5m",
+                Some(AuthPreProcessed {
+                    token_url: create_template("https://localhost/token"),
+                    grant_type: AuthGrantType::Password,
+                    client_id: create_template("${clientId}"),
+                    client_secret: None,
+                    username: Some(create_template("${username}")),
+                    password: Some(create_template("${password}")),
+                    scope: Some(create_template("read write")),
+                    refresh_before: Some(PreDuration(create_template("5m"))),
+                }),
+            ),
+        ];
+        check_all(values);
+    }
+
+    #[test]
+    fn load_test_auth() {
+        let config_path = Path::new("./");
+        let yaml = "
+            vars:
+                secret: abc
+            auth:
+                token_url: http://localhost/token
+                client_id: pewpew
+                client_secret: ${secret}
+            endpoints:
+                - url: http://localhost/
+                  headers:
+                    Authorization: Bearer ${auth.token}
+        ";
+        let load_test =
+            LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default()).unwrap();
+        let auth = load_test.auth.expect("should have an auth block");
+        assert_eq!(auth.token_url, "http://localhost/token");
+        assert_eq!(auth.client_secret.as_deref(), Some("abc"));
+        assert_eq!(auth.refresh_before, Duration::from_secs(60));
+        assert!(load_test.endpoints[0].required_providers.contains("auth"));
+
+        let bad_yamls = [
+            // `auth` is only usable when there is an auth block
+            "
+            endpoints:
+                - url: http://localhost/${auth.token}
+            ",
+            // a provider can't share the name
+            "
+            auth:
+                token_url: http://localhost/token
+                client_id: pewpew
+            providers:
+                auth:
+                    range: {}
+            endpoints:
+                - url: http://localhost/
+            ",
+            // the token can't be declared from
+            "
+            auth:
+                token_url: http://localhost/token
+                client_id: pewpew
+            endpoints:
+                - url: http://localhost/${token}
+                  declare:
+                    token: auth.token
+            ",
+        ];
+        for (i, yaml) in bad_yamls.iter().enumerate() {
+            let r = LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default());
+            assert!(r.is_err(), "index {} should be an error", i);
+        }
+    }
+
+    #[test]
+    fn load_test_openapi() {
+        let config_path = Path::new("./");
+        let yaml = "
+            vars:
+                specDir: specs
+            openapi:
+                spec: ${specDir}/petstore.yaml
+            endpoints:
+                - url: http://localhost/pets
+        ";
+        let load_test =
+            LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default()).unwrap();
+        let openapi = load_test.openapi.expect("should have an openapi block");
+        assert_eq!(openapi.spec, "specs/petstore.yaml");
+
+        let bad_yamls = [
+            "
+            openapi: {}
+            endpoints:
+                - url: http://localhost/
+            ",
+            "
+            openapi:
+                spec: petstore.yaml
+                validate: false
+            endpoints:
+                - url: http://localhost/
+            ",
+        ];
+        for (i, yaml) in bad_yamls.iter().enumerate() {
+            let r = LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default());
+            assert!(r.is_err(), "index {} should be an error", i);
+        }
+    }
+
+    #[test]
+    fn load_test_functions() {
+        let config_path = Path::new("./");
+        let yaml = r#"
+            vars:
+                host: localhost
+            functions:
+                user_url(id): "'http://${host}/users/${id}'"
+            providers:
+                userId:
+                    range: {}
+            endpoints:
+                - url: ${user_url(userId)}
+        "#;
+        let load_test =
+            LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default()).unwrap();
+        assert!(load_test.endpoints[0].required_providers.contains("userId"));
+
+        let bad_yamls = [
+            // the wrong number of arguments
+            r#"
+            functions:
+                user_url(id): "'http://localhost/users/${id}'"
+            endpoints:
+                - url: ${user_url()}
+            "#,
+            // a body can't use a provider
+            r#"
+            functions:
+                user_url(): "'http://localhost/users/${userId}'"
+            providers:
+                userId:
+                    range: {}
+            endpoints:
+                - url: ${user_url()}
+            "#,
+            // functions can't be recursive
+            r#"
+            functions:
+                countdown(n): "if(n > 0, countdown(n - 1), 0)"
+            endpoints:
+                - url: http://localhost/
+            "#,
+        ];
+        for (i, yaml) in bad_yamls.iter().enumerate() {
+            let r = LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default());
+            assert!(r.is_err(), "index {} should be an error", i);
+        }
+    }
+
+    #[test]
+    fn load_test_scripts() {
+        let config_path = Path::new("./");
+        let yaml = r#"
+            scripts:
+                signature: |
+                    let total = 0;
+                    for c in args[0].chars() { total += c.to_int() }
+                    total % 256
+            functions:
+                sign(value): script("signature", value)
+            providers:
+                userId:
+                    range: {}
+            endpoints:
+                - url: http://localhost/users/${userId}
+                  headers:
+                    X-Signature: ${sign(userId)}
+        "#;
+        let load_test =
+            LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default()).unwrap();
+        assert!(load_test.endpoints[0].required_providers.contains("userId"));
+        assert!(load_test.script_files.is_empty());
+
+        // script files are left for the caller to read and load
+        for yaml in [
+            r#"
+            scripts:
+                signature: !file signature.rhai
+            endpoints:
+                - url: http://localhost/
+            "#,
+            r#"
+            scripts:
+                signature:
+                    file: signature.rhai
+            endpoints:
+                - url: http://localhost/
+            "#,
+        ] {
+            let load_test =
+                LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default()).unwrap();
+            assert_eq!(load_test.script_files.len(), 1);
+            let script_file = &load_test.script_files[0];
+            assert_eq!(script_file.name, "signature");
+            assert_eq!(script_file.path, "signature.rhai");
+            let e = script_file.load("let x =").unwrap_err();
+            assert!(
+                e.to_string().starts_with("invalid script `signature`"),
+                "{}",
+                e
+            );
+            script_file.load("args.len()").unwrap();
+        }
+
+        let bad_yamls = [
+            // an unknown script
+            (
+                r#"
+            endpoints:
+                - url: http://localhost/${script("missing")}
+            "#,
+                "unknown script `missing`",
+            ),
+            // a script which doesn't compile
+            (
+                r#"
+            scripts:
+                broken: "let x ="
+            endpoints:
+                - url: http://localhost/
+            "#,
+                "invalid script `broken`",
+            ),
+        ];
+        for (yaml, expect) in bad_yamls.iter() {
+            let e = LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default())
+                .err()
+                .expect("should be an error");
+            assert!(e.to_string().starts_with(expect), "{} != {}", e, expect);
+        }
+    }
+
+    #[test]
+    fn load_test_assertions() {
+        let config_path = Path::new("./");
+        let yaml = r#"
+            endpoints:
+                - url: http://localhost/items
+                  assert:
+                    ok: response.status == 200
+                    has items:
+                        check: len(response.body.items) > 0
+                        abort_after: 10
+        "#;
+        let load_test =
+            LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default()).unwrap();
+        let endpoint = &load_test.endpoints[0];
+        let names: Vec<_> = endpoint.assertions.iter().map(|a| &*a.name).collect();
+        assert_eq!(names, ["ok", "has items"]);
+        assert_eq!(endpoint.assertions[1].abort_after, NonZeroUsize::new(10));
+        assert_ne!(
+            endpoint.providers_to_stream.get_special() & RESPONSE_BODY,
+            0
+        );
+
+        let bad_yamls = [
+            r#"
+            endpoints:
+                - url: http://localhost/
+                  assert:
+                    ok:
+                        abort_after: 10
+            "#,
+            r#"
+            endpoints:
+                - url: http://localhost/
+                  assert:
+                    ok:
+                        check: response.status == 200
+                        after: 10
+            "#,
+            r#"
+            endpoints:
+                - url: http://localhost/
+                  assert:
+                    ok: response.status ==
+            "#,
+        ];
+        for yaml in bad_yamls.iter() {
+            assert!(
+                LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default()).is_err(),
+                "{}",
+                yaml
+            );
+        }
+    }
+
+    #[test]
+    fn load_test_unsupported_options() {
+        let config_path = Path::new("./");
+        let checks = [
+            (
+                "grpc: {descriptor: service.pb, method: a.B/C}",
+                "assert: {ok: 'true'}",
+                "assert",
+            ),
+            (
+                "grpc: {descriptor: service.pb, method: a.B/C}",
+                "retry: {max_attempts: 3}",
+                "retry",
+            ),
+            (
+                "grpc: {descriptor: service.pb, method: a.B/C}",
+                "cookie_jar: global",
+                "cookie_jar",
+            ),
+            (
+                "grpc: {descriptor: service.pb, method: a.B/C}",
+                "follow_redirects: 2",
+                "follow_redirects",
+            ),
+            (
+                "grpc: {descriptor: service.pb, method: a.B/C}",
+                "compress_body: gzip",
+                "compress_body",
+            ),
+            (
+                "grpc: {descriptor: service.pb, method: a.B/C}",
+                "stream_response: {format: sse}",
+                "stream_response",
+            ),
+            ("websocket: true", "assert: {ok: 'true'}", "assert"),
+            ("websocket: true", "retry: {max_attempts: 3}", "retry"),
+            ("websocket: true", "follow_redirects: 2", "follow_redirects"),
+            (
+                "websocket: true",
+                "honor_retry_after: true",
+                "honor_retry_after",
+            ),
+            ("tcp: {}", "assert: {ok: 'true'}", "assert"),
+            ("tcp: {}", "headers: {x-id: '1'}", "headers"),
+            ("tcp: {}", "honor_retry_after: true", "honor_retry_after"),
+            ("tcp: {}", "compress_body: gzip", "compress_body"),
+            ("udp: {}", "retry: {max_attempts: 3}", "retry"),
+            ("udp: {}", "cookie_jar: global", "cookie_jar"),
+            ("udp: {}", "follow_redirects: 2", "follow_redirects"),
+            (
+                "udp: {}",
+                "stream_response: {format: ndjson}",
+                "stream_response",
+            ),
+            (
+                "websocket: true",
+                "sign: {hmac: {key: k, header: X-Signature, parts: [body]}}",
+                "sign",
+            ),
+            (
+                "grpc: {descriptor: service.pb, method: a.B/C}",
+                "sign: {hmac: {key: k, header: X-Signature, parts: [body]}}",
+                "sign",
+            ),
+            (
+                "tcp: {}",
+                "sign: {hmac: {key: k, header: X-Signature, parts: [body]}}",
+                "sign",
+            ),
+            (
+                "stream_response: {format: sse}",
+                "assert: {ok: 'true'}",
+                "assert",
+            ),
+            (
+                "stream_response: {format: ndjson}",
+                "body: {graphql: {query: '{ a }'}}",
+                "graphql",
+            ),
+        ];
+        for (kind, option, name) in checks.iter() {
+            let yaml = format!(
+                "
+                endpoints:
+                    - url: http://localhost:50051
+                      {}
+                      {}
+                ",
+                kind, option
+            );
+            match LoadTest::from_config(yaml.as_bytes(), config_path, &Default::default()) {
+                Err(Error::UnsupportedOption(o, _, _)) => assert_eq!(o, *name, "{}", yaml),
+                Err(e) => panic!("unexpected error {} for {}", e, yaml),
+                Ok(_) => panic!("expected an error for {}", yaml),
+            }
+        }
+    }
+
     #[test]
     fn from_yaml_client_config_pre_processed() {
         let values = vec![
@@ -3481,6 +6238,13 @@ mod tests {
                     ..DefaultWithMarker::default(create_marker())
                 }),
             ),
+            (
+                "follow_redirects: 3",
+                Some(ClientConfigPreProcessed {
+                    follow_redirects: 3,
+                    ..DefaultWithMarker::default(create_marker())
+                }),
+            ),
             (
                 "headers:
                     foo: bar
@@ -3523,7 +6287,11 @@ mod tests {
                     load_pattern: None,
                     loggers: Default::default(),
                     vars: Default::default(),
+                    functions: Default::default(),
+                    scripts: Default::default(),
                     endpoints: vec![create_endpoint_pre_processed("http://localhost:8080")],
+                    auth: None,
+                    openapi: None,
                 }),
             ),
             ("config: {}", None),
@@ -0,0 +1,141 @@
+// CSS selectors for pulling values out of HTML responses, using `scraper`
+use serde_json as json;
+
+use std::fmt;
+
+/// A CSS selector, with the selectors and pseudo-classes `scraper` supports
+pub(crate) struct Selector {
+    source: String,
+    selector: scraper::Selector,
+}
+
+impl fmt::Debug for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Selector {{ {} }}", self.source)
+    }
+}
+
+impl Selector {
+    pub(crate) fn compile(source: &str) -> Result<Self, String> {
+        let selector = scraper::Selector::parse(source).map_err(|e| e.to_string())?;
+        Ok(Selector {
+            source: source.into(),
+            selector,
+        })
+    }
+
+    /// Parses the document as HTML and returns an array with an object for each matching element,
+    /// which has the element's `name`, `attributes` and `text`
+    pub(crate) fn search(&self, document: &str) -> json::Value {
+        let html = scraper::Html::parse_document(document);
+        html.select(&self.selector)
+            .map(|element| {
+                let attributes = element
+                    .value()
+                    .attrs()
+                    .map(|(k, v)| (k.to_string(), json::Value::String(v.into())))
+                    .collect();
+                json::json!({
+                    "name": element.value().name(),
+                    "attributes": json::Value::Object(attributes),
+                    "text": element.text().collect::<String>(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+        <html>
+        <head><title>Sign in &amp; more</title><script>if (a < b) { x = "</p>"; }</script></head>
+        <body>
+            <!-- the form -->
+            <form id="login" class="form wide" action=/session>
+                <input type="hidden" name="_csrf" value="abc123">
+                <INPUT type=text name=user disabled>
+                <p>First <b>bold</b> paragraph</p>
+                <p lang="en-US">Second</p>
+            </form>
+            <ul><li>a</li><li>b</li><li>c</li></ul>
+        </body>
+        </html>"#;
+
+    #[test]
+    fn selects() {
+        let checks = vec![
+            ("input[name=_csrf]", vec![("input", "")]),
+            ("input[disabled]", vec![("input", "")]),
+            (
+                "#login > p",
+                vec![("p", "First bold paragraph"), ("p", "Second")],
+            ),
+            ("form.wide b", vec![("b", "bold")]),
+            ("p + p", vec![("p", "Second")]),
+            ("input ~ p[lang|=en]", vec![("p", "Second")]),
+            (
+                "li:first-child, li:last-child",
+                vec![("li", "a"), ("li", "c")],
+            ),
+            ("li:nth-child(2)", vec![("li", "b")]),
+            ("li:nth-child(odd)", vec![("li", "a"), ("li", "c")]),
+            ("li:not(:first-child)", vec![("li", "b"), ("li", "c")]),
+            (
+                "[action^='/sess']",
+                vec![("form", "First bold paragraph Second")],
+            ),
+            ("title", vec![("title", "Sign in & more")]),
+            ("p[lang*=x]", vec![]),
+            ("script p", vec![]),
+        ];
+        for (selector, expect) in checks {
+            let s = Selector::compile(selector).unwrap();
+            let left: Vec<_> = s
+                .search(PAGE)
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|e| {
+                    let text = e["text"].as_str().unwrap().split_whitespace();
+                    let text = text.collect::<Vec<_>>().join(" ");
+                    (e["name"].as_str().unwrap().to_string(), text)
+                })
+                .collect();
+            let expect: Vec<_> = expect
+                .into_iter()
+                .map(|(n, t)| (n.to_string(), t.to_string()))
+                .collect();
+            assert_eq!(left, expect, "selector `{}`", selector);
+        }
+
+        let s = Selector::compile("p[title=ñ]").unwrap();
+        assert_eq!(
+            s.search("<p title=ñ>été</p><p>nö</p>"),
+            json::json!([{ "name": "p", "attributes": { "title": "ñ" }, "text": "été" }])
+        );
+
+        let s = Selector::compile("input[name='_csrf']").unwrap();
+        assert_eq!(
+            s.search(PAGE),
+            json::json!([{
+                "name": "input",
+                "attributes": { "type": "hidden", "name": "_csrf", "value": "abc123" },
+                "text": "",
+            }])
+        );
+    }
+
+    #[test]
+    fn invalid_selectors() {
+        for selector in ["", "a >", "a[", "a[b=", "a,", "#", "a:nth-child(x)"] {
+            assert!(
+                Selector::compile(selector).is_err(),
+                "`{}` should not compile",
+                selector
+            );
+        }
+    }
+}
@@ -0,0 +1,175 @@
+// scripts are written in Rhai (https://rhai.rs), which is sandboxed by default: a script cannot
+// touch the file system or the network, and `import` is compiled out with the `no_module` feature
+use rhai::{serde::from_dynamic, serde::to_dynamic, Array, Dynamic, Engine, Scope, AST};
+use serde_json as json;
+
+use std::{fmt, sync::OnceLock};
+
+// the number of operations a single call can run before it is stopped, which keeps a runaway
+// script (like an infinite loop) from tying up a worker
+const MAX_OPERATIONS: u64 = 1_000_000;
+// limits on the size of each value a call builds. A value counts everything nested in it, so an
+// array of strings is held to the string limit in total, but values held in separate variables are
+// each checked on their own. There is no cap on a call's total memory, only on what it can build
+// within `MAX_OPERATIONS`
+const MAX_STRING_SIZE: usize = 1024 * 1024;
+const MAX_ARRAY_SIZE: usize = 100_000;
+const MAX_MAP_SIZE: usize = 100_000;
+const MAX_CALL_LEVELS: usize = 64;
+const MAX_EXPR_DEPTH: usize = 64;
+
+/// A script from the `scripts` section of a config, compiled once when the config is loaded. A
+/// script kept in its own file is compiled when the file is read, with `load`
+pub(crate) struct Script {
+    engine: Engine,
+    ast: OnceLock<AST>,
+}
+
+impl Script {
+    pub(crate) fn compile(source: &str) -> Result<Self, String> {
+        let script = Script::unloaded();
+        script.load(source)?;
+        Ok(script)
+    }
+
+    pub(crate) fn unloaded() -> Self {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_ARRAY_SIZE)
+            .set_max_map_size(MAX_MAP_SIZE)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+            .disable_symbol("eval")
+            .on_print(|s| log::info!("script: {}", s))
+            .on_debug(|s, _, pos| log::debug!("script at {}: {}", pos, s));
+        Script {
+            engine,
+            ast: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn load(&self, source: &str) -> Result<(), String> {
+        let ast = self.engine.compile(source).map_err(|e| e.to_string())?;
+        // a script is only loaded once, so there is never an existing ast to keep
+        let _ = self.ast.set(ast);
+        Ok(())
+    }
+
+    /// Runs the script with the arguments bound to `args`. The value of the last statement (or
+    /// of a `return`) is the result.
+    pub(crate) fn call(&self, args: Vec<json::Value>) -> Result<json::Value, String> {
+        let ast = self
+            .ast
+            .get()
+            .ok_or("the script's file has not been loaded")?;
+        let args = args
+            .iter()
+            .map(to_dynamic)
+            .collect::<Result<Array, _>>()
+            .map_err(|e| e.to_string())?;
+        let mut scope = Scope::new();
+        scope.push("args", args);
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, ast)
+            .map_err(|e| e.to_string())?;
+        from_dynamic(&result).map_err(|e| e.to_string())
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Script").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calls_scripts() {
+        let checks = vec![
+            (
+                "args[0] + args[1]",
+                vec![json::json!(1), json::json!(2)],
+                json::json!(3),
+            ),
+            (
+                "args[0] + args[1]",
+                vec![json::json!(1), json::json!("a")],
+                json::json!("1a"),
+            ),
+            (
+                r#"let out = #{}; out.name = args[0].to_upper(); out"#,
+                vec![json::json!("foo")],
+                json::json!({"name": "FOO"}),
+            ),
+            (
+                "let sum = 0; for x in args[0] { sum += x.id } sum",
+                vec![json::json!([{"id": 1}, {"id": 2}, {"id": 3}])],
+                json::json!(6),
+            ),
+            (
+                "fn double(x) { x * 2 } if args.len() > 0 { return double(args[0]); } ()",
+                vec![json::json!(2.5)],
+                json::json!(5.0),
+            ),
+            ("args.len()", vec![], json::json!(0)),
+            ("()", vec![], json::Value::Null),
+        ];
+
+        for (i, (source, args, expect)) in checks.into_iter().enumerate() {
+            let script = Script::compile(source).unwrap();
+            let result = script.call(args).unwrap();
+            assert_eq!(result, expect, "index {}", i);
+        }
+    }
+
+    #[test]
+    fn limits_scripts() {
+        assert!(Script::compile("let x = ").is_err());
+        assert!(Script::compile(r#"eval("1")"#).is_err());
+        assert!(Script::compile(r#"import "foo" as foo;"#).is_err());
+
+        let checks = vec![
+            "loop { }",
+            r#"let s = "a"; loop { s += s }"#,
+            "fn f(x) { f(x) } f(1)",
+            "args[0].foo()",
+        ];
+
+        for (i, source) in checks.into_iter().enumerate() {
+            let script = Script::compile(source).unwrap();
+            let result = script.call(vec![json::json!(1)]);
+            assert!(result.is_err(), "index {}", i);
+        }
+
+        // the strings nested in an array or map count towards the string limit together
+        let checks = vec![
+            r#"let s = "a"; while s.len() < 65536 { s += s } let a = []; for i in 0..20 { a.push(s) } a.len()"#,
+            r#"let s = "a"; while s.len() < 65536 { s += s } let m = #{}; for i in 0..20 { m[`${i}`] = s } m.len()"#,
+        ];
+        for (i, source) in checks.into_iter().enumerate() {
+            let script = Script::compile(source).unwrap();
+            let err = script.call(Vec::new()).unwrap_err();
+            assert!(err.contains("too large"), "index {}: {}", i, err);
+        }
+        let script = Script::compile(
+            r#"let s = "a"; while s.len() < 65536 { s += s } let a = []; for i in 0..15 { a.push(s) } a.len()"#,
+        )
+        .unwrap();
+        assert_eq!(script.call(Vec::new()).unwrap(), json::json!(15));
+    }
+
+    #[test]
+    fn loads_scripts() {
+        let script = Script::unloaded();
+        assert!(script.call(Vec::new()).is_err());
+        assert!(script.load("let x = ").is_err());
+        script.load("args.len()").unwrap();
+        assert_eq!(script.call(Vec::new()).unwrap(), json::json!(0));
+    }
+}
@@ -1,8 +1,9 @@
 #![allow(clippy::empty_docs)]
 #![allow(clippy::result_large_err)]
 use crate::expression_functions::{
-    Collect, Encode, Entries, Epoch, If, Join, JsonPath, Match, MinMax, Pad, ParseNum, Random,
-    Range, Repeat, Replace,
+    Array, ArrayFunction, Collect, Date, DateFunction, Decode, Encode, Entries, Epoch, Hash, If,
+    JmesPath, Join, JsonPath, JwtSign, Markup, Match, Math, MathFunction, MinMax, Now, Pad,
+    ParseNum, Random, Range, Repeat, Replace, ScriptCall, Text, TextFunction, UserFunctionCall,
 };
 use crate::{
     create_marker, json_value_to_string, EndpointProvidesPreProcessed, EndpointProvidesSendOptions,
@@ -10,6 +11,7 @@ use crate::{
 };
 
 use crate::error::{self, CreatingExpressionError, ExecutingExpressionError};
+use crate::script::Script;
 
 use ether::{Either, Either3, EitherExt};
 use futures::{stream, Stream, StreamExt, TryStreamExt};
@@ -86,11 +88,14 @@ impl RequiredProviders {
             "request.body" => *special |= REQUEST_BODY,
             "request.method" => *special |= REQUEST_METHOD,
             "request.url" => *special |= REQUEST_URL,
+            "request.cookies" => *special |= REQUEST_COOKIES,
             "request" => *special |= REQUEST_ALL,
             "response.start-line" => *special |= RESPONSE_STARTLINE,
             "response.headers" => *special |= RESPONSE_HEADERS,
             "response.headers_all" => *special |= RESPONSE_HEADERS_ALL,
             "response.body" => *special |= RESPONSE_BODY,
+            "response.redirects" => *special |= RESPONSE_REDIRECTS,
+            "response.cookies" => *special |= RESPONSE_COOKIES,
             "response" => *special |= RESPONSE_ALL,
             "response.status" => *special |= RESPONSE_STATUS,
             "stats" => *special |= STATS,
@@ -143,23 +148,264 @@ impl RequiredProviders {
     }
 }
 
+// providers with a special meaning in expressions, which cannot be used as function parameters
+const RESERVED_PARAMETERS: [(u16, &str); 5] = [
+    (ERROR, "error"),
+    (FOR_EACH, "for_each"),
+    (REQUEST_ALL, "request"),
+    (RESPONSE_ALL, "response"),
+    (STATS, "stats"),
+];
+
+/// A function declared in the `functions` section of a config
+#[derive(Debug)]
+pub(crate) struct UserFunction {
+    name: String,
+    params: Vec<String>,
+    body: ValueOrExpression,
+}
+
+impl UserFunction {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Evaluates the body with each parameter bound to the value of its argument
+    pub(crate) fn call(
+        &self,
+        args: Vec<json::Value>,
+        no_recoverable_error: bool,
+    ) -> Result<json::Value, ExecutingExpressionError> {
+        let bindings = self.params.iter().cloned().zip(args).collect();
+        self.body
+            .evaluate(
+                Cow::Owned(json::Value::Object(bindings)),
+                no_recoverable_error,
+                None,
+            )
+            .map(Cow::into_owned)
+    }
+}
+
+/// The values which are known when a config is loaded. These are the `vars` along with any
+/// `functions` and `scripts`, which can be called from any expression after the config is loaded.
+#[derive(Clone, Debug, Default)]
+pub struct StaticVars {
+    vars: BTreeMap<String, json::Value>,
+    functions: BTreeMap<String, Arc<UserFunction>>,
+    scripts: BTreeMap<String, Arc<Script>>,
+}
+
+impl StaticVars {
+    /// Creates the static vars for a config. Each function is declared as `name(param, ...)`
+    /// mapped to the expression for its body. The scripts are already compiled so function bodies
+    /// can call them.
+    pub(crate) fn new(
+        vars: BTreeMap<String, json::Value>,
+        functions: Vec<(String, String, Marker)>,
+        scripts: BTreeMap<String, Arc<Script>>,
+    ) -> Result<Self, error::Error> {
+        let mut static_vars = StaticVars {
+            vars,
+            functions: BTreeMap::new(),
+            scripts,
+        };
+        let mut pending = functions
+            .into_iter()
+            .map(
+                |(declaration, body, marker)| match parse_function_declaration(&declaration) {
+                    Some((name, params)) => Ok((name, params, body, marker)),
+                    None => Err(error::Error::InvalidFunctionDeclaration(
+                        declaration,
+                        marker,
+                    )),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut declared = BTreeSet::new();
+        for (name, _, _, marker) in &pending {
+            // a function cannot share its name with a built-in function or another declaration
+            let builtin = !matches!(
+                FunctionCall::new(
+                    name,
+                    Vec::new(),
+                    &mut RequiredProviders::new(),
+                    &StaticVars::default(),
+                    *marker
+                ),
+                Err(CreatingExpressionError::UnknownFunction(..))
+            );
+            if builtin || !declared.insert(name.clone()) {
+                return Err(error::Error::InvalidFunctionDeclaration(
+                    name.clone(),
+                    *marker,
+                ));
+            }
+        }
+        // A body can only be parsed once every function it calls has been parsed, so keep making
+        // passes until nothing is left. A pass which makes no progress means the functions which
+        // are left call themselves, directly or through each other.
+        while !pending.is_empty() {
+            let count = pending.len();
+            let mut deferred = Vec::new();
+            for (name, params, body, marker) in pending {
+                match static_vars.parse_function_body(&params, &body, marker) {
+                    Ok(body) => {
+                        let function = UserFunction {
+                            name: name.clone(),
+                            params,
+                            body,
+                        };
+                        static_vars.functions.insert(name, function.into());
+                    }
+                    Err(CreatingExpressionError::UnknownFunction(f, _))
+                        if declared.contains(&f) =>
+                    {
+                        deferred.push((name, params, body, marker));
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if deferred.len() == count {
+                let (name, .., marker) = deferred.swap_remove(0);
+                return Err(error::Error::RecursiveFunction(name, marker));
+            }
+            pending = deferred;
+        }
+        Ok(static_vars)
+    }
+
+    pub(crate) fn function(&self, name: &str) -> Option<&Arc<UserFunction>> {
+        self.functions.get(name)
+    }
+
+    pub(crate) fn script(&self, name: &str) -> Option<&Arc<Script>> {
+        self.scripts.get(name)
+    }
+
+    fn parse_function_body(
+        &self,
+        params: &[String],
+        body: &str,
+        marker: Marker,
+    ) -> Result<ValueOrExpression, CreatingExpressionError> {
+        // parameters shadow any vars with the same name
+        let scope = StaticVars {
+            vars: self
+                .vars
+                .iter()
+                .filter(|(k, _)| !params.contains(k))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            functions: self.functions.clone(),
+            scripts: self.scripts.clone(),
+        };
+        let mut providers = RequiredProviders::new();
+        let body = ValueOrExpression::new(body, &mut providers, &scope, false, marker)?;
+        // the body can only reference its parameters, the vars and other functions
+        let special = providers.get_special();
+        let unknown = providers
+            .iter()
+            .map(|(p, _)| p.as_str())
+            .find(|p| !params.iter().any(|param| param == p))
+            .or_else(|| {
+                RESERVED_PARAMETERS
+                    .iter()
+                    .find(|(bits, _)| special & bits != 0)
+                    .map(|(_, p)| *p)
+            });
+        match unknown {
+            Some(p) => Err(CreatingExpressionError::UnknownProvider(p.into(), marker)),
+            None => Ok(body),
+        }
+    }
+}
+
+impl std::ops::Deref for StaticVars {
+    type Target = BTreeMap<String, json::Value>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vars
+    }
+}
+
+impl From<BTreeMap<String, json::Value>> for StaticVars {
+    fn from(vars: BTreeMap<String, json::Value>) -> Self {
+        StaticVars {
+            vars,
+            functions: BTreeMap::new(),
+            scripts: BTreeMap::new(),
+        }
+    }
+}
+
+impl iter::FromIterator<(String, json::Value)> for StaticVars {
+    fn from_iter<I: IntoIterator<Item = (String, json::Value)>>(iter: I) -> Self {
+        iter.into_iter().collect::<BTreeMap<_, _>>().into()
+    }
+}
+
+/// Parses a declaration like `name(param1, param2)` into the function name and its parameters
+fn parse_function_declaration(declaration: &str) -> Option<(String, Vec<String>)> {
+    fn is_ident(s: &str) -> bool {
+        let mut chars = s.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    let (name, params) = declaration.trim().strip_suffix(')')?.split_once('(')?;
+    let name = name.trim();
+    let params: Vec<String> = if params.trim().is_empty() {
+        Vec::new()
+    } else {
+        params.split(',').map(|p| p.trim().to_string()).collect()
+    };
+    let valid = is_ident(name)
+        && params.iter().enumerate().all(|(i, p)| {
+            is_ident(p)
+                && !params[..i].contains(p)
+                && !RESERVED_PARAMETERS.iter().any(|(_, r)| r == p)
+        });
+    if valid {
+        Some((name.into(), params))
+    } else {
+        None
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(super) enum FunctionCall {
+    Array(Array),
     Collect(Collect),
+    Date(Box<Date>),
+    Decode(Decode),
     Encode(Encode),
     Entries(Entries),
     Epoch(Epoch),
+    Hash(Box<Hash>),
     If(Box<If>),
+    JmesPath(JmesPath),
     Join(Join),
     JsonPath(JsonPath),
+    JwtSign(Box<JwtSign>),
+    Markup(Markup),
     Match(Box<Match>),
+    Math(Box<Math>),
     MinMax(MinMax),
+    Now(Now),
     Pad(Pad),
     Random(Random),
     Range(Box<Range>),
     Repeat(Repeat),
     Replace(Box<Replace>),
     ParseNum(ParseNum),
+    Script(ScriptCall),
+    Text(Text),
+    User(UserFunctionCall),
 }
 
 impl FunctionCall {
@@ -167,38 +413,98 @@ impl FunctionCall {
         ident: &str,
         args: Vec<ValueOrExpression>,
         providers: &mut RequiredProviders,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
         marker: Marker,
     ) -> Result<Either<Self, json::Value>, CreatingExpressionError> {
         debug!("FunctionCall::new ident=\"{}\" args=\"{:?}\" providers=\"{:?}\" static_vars=\"{:?}\" marker=\"{:?}\"",
             ident, args, providers, static_vars, marker);
         let r = match ident {
+            "abs" => {
+                Math::new(MathFunction::Abs, args, marker)?.map_a(|m| FunctionCall::Math(m.into()))
+            }
+            "base64_decode" => Decode::new(false, args, marker)?.map_a(FunctionCall::Decode),
+            "ceil" => {
+                Math::new(MathFunction::Ceil, args, marker)?.map_a(|m| FunctionCall::Math(m.into()))
+            }
+            "css" => Markup::new(false, args, marker)?.map_a(FunctionCall::Markup),
+            "contains" => {
+                Text::new(TextFunction::Contains, args, marker)?.map_a(FunctionCall::Text)
+            }
             "collect" => Either::A(FunctionCall::Collect(Collect::new(args, marker)?)),
+            "date_add" => {
+                Date::new(DateFunction::Add, args, marker)?.map_a(|d| FunctionCall::Date(d.into()))
+            }
+            "date_format" => Date::new(DateFunction::Format, args, marker)?
+                .map_a(|d| FunctionCall::Date(d.into())),
+            "date_parse" => Date::new(DateFunction::Parse, args, marker)?
+                .map_a(|d| FunctionCall::Date(d.into())),
             "encode" => Encode::new(args, marker)?.map_a(FunctionCall::Encode),
             "end_pad" => Pad::new(false, args, marker)?.map_a(FunctionCall::Pad),
+            "ends_with" => {
+                Text::new(TextFunction::EndsWith, args, marker)?.map_a(FunctionCall::Text)
+            }
             "entries" => Either::A(FunctionCall::Entries(Entries::new(args, marker)?)),
             "epoch" => Either::A(FunctionCall::Epoch(Epoch::new(args, marker)?)),
+            "first" => Array::new(ArrayFunction::First, args, marker)?.map_a(FunctionCall::Array),
+            "floor" => Math::new(MathFunction::Floor, args, marker)?
+                .map_a(|m| FunctionCall::Math(m.into())),
+            "hash" => Hash::new(false, args, marker)?.map_a(|h| FunctionCall::Hash(h.into())),
+            "hmac" => Hash::new(true, args, marker)?.map_a(|h| FunctionCall::Hash(h.into())),
             "if" => If::new(args, marker)?.map_a(|a| FunctionCall::If(a.into())),
+            "jmespath" => JmesPath::new(args, marker)?.map_a(FunctionCall::JmesPath),
             "join" => Join::new(args, marker)?.map_a(FunctionCall::Join),
             "json_path" => {
                 JsonPath::new(args, providers, static_vars, marker)?.map_a(FunctionCall::JsonPath)
             }
+            "jwt_decode" => Decode::new(true, args, marker)?.map_a(FunctionCall::Decode),
+            "jwt_sign" => JwtSign::new(args, marker)?.map_a(|j| FunctionCall::JwtSign(j.into())),
             "match" => Match::new(args, marker)?.map_a(|m| FunctionCall::Match(m.into())),
+            "last" => Array::new(ArrayFunction::Last, args, marker)?.map_a(FunctionCall::Array),
+            "len" => Text::new(TextFunction::Len, args, marker)?.map_a(FunctionCall::Text),
+            "lower" => Text::new(TextFunction::Lower, args, marker)?.map_a(FunctionCall::Text),
             "max" => MinMax::new(false, args)?.map_a(FunctionCall::MinMax),
             "min" => MinMax::new(true, args)?.map_a(FunctionCall::MinMax),
+            "now" => Either::A(FunctionCall::Now(Now::new(args, marker)?)),
+            "pow" => {
+                Math::new(MathFunction::Pow, args, marker)?.map_a(|m| FunctionCall::Math(m.into()))
+            }
+            "script" => Either::A(FunctionCall::Script(ScriptCall::new(
+                args,
+                static_vars,
+                marker,
+            )?)),
+            "slice" => Array::new(ArrayFunction::Slice, args, marker)?.map_a(FunctionCall::Array),
+            "split" => Text::new(TextFunction::Split, args, marker)?.map_a(FunctionCall::Text),
             "start_pad" => Pad::new(true, args, marker)?.map_a(FunctionCall::Pad),
+            "starts_with" => {
+                Text::new(TextFunction::StartsWith, args, marker)?.map_a(FunctionCall::Text)
+            }
+            "substr" => Text::new(TextFunction::Substr, args, marker)?.map_a(FunctionCall::Text),
+            "trim" => Text::new(TextFunction::Trim, args, marker)?.map_a(FunctionCall::Text),
+            "unique" => Array::new(ArrayFunction::Unique, args, marker)?.map_a(FunctionCall::Array),
+            "upper" => Text::new(TextFunction::Upper, args, marker)?.map_a(FunctionCall::Text),
+            "xpath" => Markup::new(true, args, marker)?.map_a(FunctionCall::Markup),
             "random" => Either::A(FunctionCall::Random(Random::new(args, marker)?)),
             "range" => Either::A(FunctionCall::Range(Range::new(args, marker)?.into())),
             "repeat" => Either::A(FunctionCall::Repeat(Repeat::new(args, marker)?)),
+            "round" => Math::new(MathFunction::Round, args, marker)?
+                .map_a(|m| FunctionCall::Math(m.into())),
             "replace" => Replace::new(args, marker)?.map_a(|r| FunctionCall::Replace(r.into())),
             "parseInt" => Either::A(FunctionCall::ParseNum(ParseNum::new(false, args, marker)?)),
             "parseFloat" => Either::A(FunctionCall::ParseNum(ParseNum::new(true, args, marker)?)),
-            _ => {
-                return Err(CreatingExpressionError::UnknownFunction(
-                    ident.into(),
+            _ => match static_vars.function(ident) {
+                Some(f) => Either::A(FunctionCall::User(UserFunctionCall::new(
+                    f.clone(),
+                    args,
                     marker,
-                ))
-            }
+                )?)),
+                None => {
+                    return Err(CreatingExpressionError::UnknownFunction(
+                        ident.into(),
+                        marker,
+                    ))
+                }
+            },
         };
         Ok(r)
     }
@@ -211,21 +517,33 @@ impl FunctionCall {
     ) -> Result<Cow<'a, json::Value>, ExecutingExpressionError> {
         debug!("FunctionCall::evaluate function=\"{:?}\"", self);
         match self {
+            FunctionCall::Array(a) => a.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::Collect(c) => c.evaluate(d, no_recoverable_error, for_each),
+            FunctionCall::Date(da) => da.evaluate(d, no_recoverable_error, for_each),
+            FunctionCall::Decode(de) => de.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::Encode(e) => e.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::Entries(e) => e.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::Epoch(e) => e.evaluate(),
+            FunctionCall::Hash(h) => h.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::If(i) => i.evaluate(d, no_recoverable_error, for_each),
+            FunctionCall::JmesPath(j) => j.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::Join(j) => j.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::JsonPath(j) => Ok(j.evaluate(d)),
+            FunctionCall::JwtSign(j) => j.evaluate(d, no_recoverable_error, for_each),
+            FunctionCall::Markup(m) => m.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::Match(m) => m.evaluate(d, no_recoverable_error, for_each),
+            FunctionCall::Math(m) => m.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::MinMax(m) => m.evaluate(d, no_recoverable_error, for_each),
+            FunctionCall::Now(n) => Ok(n.evaluate()),
             FunctionCall::Pad(p) => p.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::Range(r) => r.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::Random(r) => Ok(r.evaluate()),
             FunctionCall::Repeat(r) => Ok(r.evaluate()),
             FunctionCall::Replace(r) => r.evaluate(d, no_recoverable_error, for_each),
             FunctionCall::ParseNum(p) => p.evaluate(d, no_recoverable_error, for_each),
+            FunctionCall::Script(s) => s.evaluate(d, no_recoverable_error, for_each),
+            FunctionCall::Text(t) => t.evaluate(d, no_recoverable_error, for_each),
+            FunctionCall::User(u) => u.evaluate(d, no_recoverable_error, for_each),
         }
     }
 
@@ -239,6 +557,85 @@ impl FunctionCall {
         // Create a unique set of Either3 options to be able to create one large Either that we can call shared methods
         let r =
             match self {
+                // functions which only return one value share a type
+                FunctionCall::Array(a) => {
+                    return Ok(Either::B(a.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::Date(da) => {
+                    return Ok(Either::B(da.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::Decode(de) => {
+                    return Ok(Either::B(de.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::Hash(h) => {
+                    return Ok(Either::B(h.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::JmesPath(j) => {
+                    return Ok(Either::B(j.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::JwtSign(j) => {
+                    return Ok(Either::B(j.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::Math(m) => {
+                    return Ok(Either::B(m.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::Markup(m) => {
+                    return Ok(Either::B(m.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::Now(n) => return Ok(Either::B(n.evaluate_as_iter())),
+                FunctionCall::Script(s) => {
+                    return Ok(Either::B(s.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::Text(t) => {
+                    return Ok(Either::B(t.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
+                FunctionCall::User(u) => {
+                    return Ok(Either::B(u.evaluate_as_iter(
+                        d,
+                        no_recoverable_error,
+                        for_each,
+                    )?))
+                }
                 FunctionCall::Collect(c) => Either3::A(Either3::A(c.evaluate_as_iter(
                     d,
                     no_recoverable_error,
@@ -291,7 +688,7 @@ impl FunctionCall {
                     parse_num.evaluate_as_iter(d, no_recoverable_error, for_each)?,
                 ))),
             };
-        Ok(r)
+        Ok(Either::A(r))
     }
 
     fn into_stream<
@@ -304,21 +701,33 @@ impl FunctionCall {
     ) -> impl Stream<Item = Result<(json::Value, Vec<Ar>), ExecutingExpressionError>> + Send {
         debug!("FunctionCall::into_stream function=\"{:?}\"", self);
         match self {
+            FunctionCall::Array(a) => a.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::Collect(c) => c.into_stream(providers, no_recoverable_error).boxed(),
+            FunctionCall::Date(d) => d.into_stream(providers, no_recoverable_error).boxed(),
+            FunctionCall::Decode(d) => d.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::Encode(e) => e.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::Entries(e) => e.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::Epoch(e) => e.into_stream().boxed(),
+            FunctionCall::Hash(h) => h.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::If(i) => i.into_stream(providers, no_recoverable_error).boxed(),
+            FunctionCall::JmesPath(j) => j.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::Join(j) => j.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::JsonPath(j) => j.into_stream(providers).boxed(),
+            FunctionCall::JwtSign(j) => j.into_stream(providers, no_recoverable_error).boxed(),
+            FunctionCall::Markup(m) => m.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::Match(m) => m.into_stream(providers, no_recoverable_error).boxed(),
+            FunctionCall::Math(m) => m.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::MinMax(m) => m.into_stream(providers, no_recoverable_error).boxed(),
+            FunctionCall::Now(n) => n.into_stream().boxed(),
             FunctionCall::Pad(p) => p.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::Random(r) => r.into_stream().boxed(),
             FunctionCall::Range(r) => r.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::Repeat(r) => r.into_stream().boxed(),
             FunctionCall::Replace(r) => r.into_stream(providers, no_recoverable_error).boxed(),
             FunctionCall::ParseNum(p) => p.into_stream(providers, no_recoverable_error).boxed(),
+            FunctionCall::Script(s) => s.into_stream(providers, no_recoverable_error).boxed(),
+            FunctionCall::Text(t) => t.into_stream(providers, no_recoverable_error).boxed(),
+            FunctionCall::User(u) => u.into_stream(providers, no_recoverable_error).boxed(),
         }
     }
 }
@@ -615,6 +1024,73 @@ pub(super) fn f64_value(json: &json::Value) -> f64 {
     json.as_f64().unwrap_or(f64::NAN)
 }
 
+/// A number used in arithmetic, which keeps integers exact
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Numeric {
+    Int(i64),
+    Float(f64),
+}
+
+impl Numeric {
+    /// Gets the number from a json number or a string of a number (like the values from templates
+    /// and `epoch`), or returns an error naming the operator or function the value was used with
+    pub(super) fn from_json(
+        json: &json::Value,
+        name: &'static str,
+        marker: Marker,
+    ) -> Result<Self, ExecutingExpressionError> {
+        let n = match json {
+            json::Value::Number(n) => Some(n.clone()),
+            json::Value::String(s) => s.trim().parse::<json::Number>().ok(),
+            _ => None,
+        };
+        let reason = match json {
+            json::Value::Null => "expected a number but got null",
+            json::Value::Bool(_) => "expected a number but got a boolean",
+            json::Value::String(_) => "expected a number but got a string",
+            json::Value::Array(_) => "expected a number but got an array",
+            json::Value::Object(_) => "expected a number but got an object",
+            json::Value::Number(_) => "expected a finite number",
+        };
+        match n {
+            Some(n) => match (n.as_i64(), n.as_f64()) {
+                (Some(i), _) => Ok(Numeric::Int(i)),
+                (None, Some(f)) if f.is_finite() => Ok(Numeric::Float(f)),
+                _ => Err(ExecutingExpressionError::InvalidOperand(
+                    name, reason, marker,
+                )),
+            },
+            None => Err(ExecutingExpressionError::InvalidOperand(
+                name, reason, marker,
+            )),
+        }
+    }
+
+    pub(super) fn as_f64(self) -> f64 {
+        match self {
+            Numeric::Int(i) => i as f64,
+            Numeric::Float(f) => f,
+        }
+    }
+
+    pub(super) fn is_zero(self) -> bool {
+        self.as_f64() == 0.0
+    }
+
+    /// Whole numbers become json integers, and numbers too large for json become null
+    pub(super) fn into_json(self) -> json::Value {
+        // 2^53, past which not every integer can be a float
+        const MAX_SAFE_FLOAT: f64 = 9_007_199_254_740_992.0;
+        match self {
+            Numeric::Int(i) => i.into(),
+            Numeric::Float(f) if f.fract() == 0.0 && f.abs() <= MAX_SAFE_FLOAT => (f as i64).into(),
+            Numeric::Float(f) => json::Number::from_f64(f)
+                .map(json::Value::Number)
+                .unwrap_or(json::Value::Null),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum ValueOrExpression {
     Value(Value),
@@ -625,7 +1101,7 @@ impl ValueOrExpression {
     pub fn new(
         expr: &str,
         providers: &mut RequiredProviders,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
         no_recoverable_error: bool,
         marker: Marker,
     ) -> Result<Self, CreatingExpressionError> {
@@ -791,7 +1267,7 @@ impl PathSegment {
     fn from_str(
         s: &str,
         providers: &mut RequiredProviders,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
         no_recoverable_error: bool,
         marker: Marker,
     ) -> Result<Self, CreatingExpressionError> {
@@ -857,33 +1333,87 @@ static INFIX_OPERATOR_PRECEDENCE: [u8; 13] = [
     4, // Subtract
 ];
 
-fn to_json_number(n: f64) -> json::Value {
-    if (n - n.trunc()).abs() < f64::EPSILON {
-        (n as u64).into()
-    } else {
-        n.into()
+impl InfixOperator {
+    fn as_str(self) -> &'static str {
+        match self {
+            InfixOperator::Add => "+",
+            InfixOperator::And => "&&",
+            InfixOperator::Divide => "/",
+            InfixOperator::Eq => "==",
+            InfixOperator::Gt => ">",
+            InfixOperator::Gte => ">=",
+            InfixOperator::Lt => "<",
+            InfixOperator::Lte => "<=",
+            InfixOperator::Mod => "%",
+            InfixOperator::Multiply => "*",
+            InfixOperator::Ne => "!=",
+            InfixOperator::Or => "||",
+            InfixOperator::Subtract => "-",
+        }
+    }
+
+    // integers stay integers unless the result doesn't fit or (for division) isn't whole
+    fn arithmetic(
+        self,
+        left: &json::Value,
+        right: &json::Value,
+        marker: Marker,
+    ) -> Result<json::Value, ExecutingExpressionError> {
+        use Numeric::*;
+        let name = self.as_str();
+        let l = Numeric::from_json(left, name, marker)?;
+        let r = Numeric::from_json(right, name, marker)?;
+        if matches!(self, InfixOperator::Divide | InfixOperator::Mod) && r.is_zero() {
+            return Err(ExecutingExpressionError::InvalidOperand(
+                name,
+                "division by zero",
+                marker,
+            ));
+        }
+        let int = match (self, l, r) {
+            (InfixOperator::Add, Int(l), Int(r)) => l.checked_add(r),
+            (InfixOperator::Subtract, Int(l), Int(r)) => l.checked_sub(r),
+            (InfixOperator::Multiply, Int(l), Int(r)) => l.checked_mul(r),
+            (InfixOperator::Divide, Int(l), Int(r)) if l.checked_rem(r) == Some(0) => {
+                l.checked_div(r)
+            }
+            // the remainder only overflows for `i64::MIN % -1`, which is 0
+            (InfixOperator::Mod, Int(l), Int(r)) => Some(l.wrapping_rem(r)),
+            _ => None,
+        };
+        let n = match int {
+            Some(i) => Int(i),
+            None => {
+                let (l, r) = (l.as_f64(), r.as_f64());
+                let f = match self {
+                    InfixOperator::Add => l + r,
+                    InfixOperator::Subtract => l - r,
+                    InfixOperator::Multiply => l * r,
+                    InfixOperator::Divide => l / r,
+                    _ => l % r,
+                };
+                Float(f)
+            }
+        };
+        Ok(n.into_json())
     }
-}
 
-impl InfixOperator {
     fn evaluate(
         self,
         left: &json::Value,
         right: Result<Cow<'_, json::Value>, ExecutingExpressionError>,
+        marker: Marker,
     ) -> Result<json::Value, ExecutingExpressionError> {
         let value = match self {
-            InfixOperator::Add => {
-                let n = f64_value(left) + f64_value(&*right?);
-                to_json_number(n)
-            }
+            InfixOperator::Add
+            | InfixOperator::Divide
+            | InfixOperator::Mod
+            | InfixOperator::Multiply
+            | InfixOperator::Subtract => self.arithmetic(left, &*right?, marker)?,
             InfixOperator::And => {
                 let b = bool_value(left) && bool_value(&*right?);
                 b.into()
             }
-            InfixOperator::Divide => {
-                let n = f64_value(left) / f64_value(&*right?);
-                to_json_number(n)
-            }
             InfixOperator::Eq => left.eq(&*right?).into(),
             InfixOperator::Gt => {
                 let b = f64_value(left) > f64_value(&*right?);
@@ -901,23 +1431,11 @@ impl InfixOperator {
                 let b = f64_value(left) <= f64_value(&*right?);
                 b.into()
             }
-            InfixOperator::Mod => {
-                let n = f64_value(left) % f64_value(&*right?);
-                to_json_number(n)
-            }
-            InfixOperator::Multiply => {
-                let n = f64_value(left) * f64_value(&*right?);
-                to_json_number(n)
-            }
             InfixOperator::Ne => left.ne(&*right?).into(),
             InfixOperator::Or => {
                 let b = bool_value(left) || bool_value(&*right?);
                 b.into()
             }
-            InfixOperator::Subtract => {
-                let n = f64_value(left) - f64_value(&*right?);
-                to_json_number(n)
-            }
         };
         Ok(value)
     }
@@ -933,7 +1451,7 @@ enum ExpressionLhs {
 pub struct Expression {
     not: Option<bool>,
     lhs: ExpressionLhs,
-    op: Option<(InfixOperator, Box<Expression>)>,
+    op: Option<(InfixOperator, Box<Expression>, Marker)>,
 }
 
 impl Expression {
@@ -943,7 +1461,7 @@ impl Expression {
         no_recoverable_error: bool,
         for_each: Option<&[Cow<'a, json::Value>]>,
     ) -> Result<Cow<'a, json::Value>, ExecutingExpressionError> {
-        let mut v = if let Some((op, rhs)) = &self.op {
+        let mut v = if let Some((op, rhs, marker)) = &self.op {
             let v = match &self.lhs {
                 ExpressionLhs::Expression(e) => {
                     e.evaluate(Cow::Borrowed(&*d), no_recoverable_error, for_each)?
@@ -953,7 +1471,7 @@ impl Expression {
                 }
             };
             let rhs = rhs.evaluate(Cow::Borrowed(&*d), no_recoverable_error, for_each);
-            Cow::Owned(op.evaluate(&v, rhs)?)
+            Cow::Owned(op.evaluate(&v, rhs, *marker)?)
         } else {
             match &self.lhs {
                 ExpressionLhs::Expression(e) => e.evaluate(d, no_recoverable_error, for_each)?,
@@ -1014,14 +1532,14 @@ impl Expression {
             ExpressionLhs::Value(v) => v.into_stream(providers, no_recoverable_error).b(),
         };
         let not = self.not;
-        let v = if let Some((op, rhs)) = self.op {
+        let v = if let Some((op, rhs, marker)) = self.op {
             v.zip(rhs.into_stream(providers, no_recoverable_error))
                 .map(move |(a, b)| {
                     let (lhs, mut returns) = a?;
                     let (r, returns2) = b?;
                     returns.extend(returns2);
                     let rhs = Ok(Cow::Owned(r));
-                    let mut v = op.evaluate(&lhs, rhs)?;
+                    let mut v = op.evaluate(&lhs, rhs, marker)?;
                     if let Some(not) = not {
                         let mut b = bool_value(&v);
                         if not {
@@ -1138,19 +1656,28 @@ pub const REQUEST_HEADERS: u16 = 0b00_0000_0010;
 pub const REQUEST_HEADERS_ALL: u16 = 0b1000_0000_0000;
 pub const REQUEST_BODY: u16 = 0b00_0000_0001;
 pub const REQUEST_METHOD: u16 = 0b100_0000_0000;
+pub const REQUEST_COOKIES: u16 = 0b100_0000_0000_0000;
 const REQUEST_ALL: u16 = REQUEST_STARTLINE
     | REQUEST_HEADERS
     | REQUEST_HEADERS_ALL
     | REQUEST_BODY
     | REQUEST_URL
-    | REQUEST_METHOD;
+    | REQUEST_METHOD
+    | REQUEST_COOKIES;
 pub const RESPONSE_STARTLINE: u16 = 0b00_0010_0000;
 pub const RESPONSE_HEADERS: u16 = 0b00_0001_0000;
 pub const RESPONSE_HEADERS_ALL: u16 = 0b1_0000_0000_0000;
 pub const RESPONSE_BODY: u16 = 0b00_0000_1000;
 pub const RESPONSE_STATUS: u16 = 0b1_0000_0000;
-const RESPONSE_ALL: u16 =
-    RESPONSE_STARTLINE | RESPONSE_HEADERS | RESPONSE_HEADERS_ALL | RESPONSE_BODY | RESPONSE_STATUS;
+pub const RESPONSE_REDIRECTS: u16 = 0b10_0000_0000_0000;
+pub const RESPONSE_COOKIES: u16 = 0b1000_0000_0000_0000;
+const RESPONSE_ALL: u16 = RESPONSE_STARTLINE
+    | RESPONSE_HEADERS
+    | RESPONSE_HEADERS_ALL
+    | RESPONSE_BODY
+    | RESPONSE_STATUS
+    | RESPONSE_REDIRECTS
+    | RESPONSE_COOKIES;
 const FOR_EACH: u16 = 0b00_0100_0000;
 pub const STATS: u16 = 0b00_1000_0000;
 pub const REQUEST_URL: u16 = 0b01_0000_0000;
@@ -1176,7 +1703,7 @@ pub struct Template {
 impl Template {
     pub(crate) fn new(
         t: &str,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
         providers: &mut RequiredProviders,
         no_recoverable_error: bool,
         marker: Marker,
@@ -1361,7 +1888,7 @@ impl Select {
 
     pub(crate) fn new(
         provides: EndpointProvidesPreProcessed,
-        static_vars: &BTreeMap<String, json::Value>,
+        static_vars: &StaticVars,
         providers: &mut RequiredProviders,
         no_recoverable_error: bool,
     ) -> Result<Self, error::Error> {
@@ -1615,7 +2142,7 @@ impl Select {
 fn parse_select(
     select: json::Value,
     providers: &mut RequiredProviders,
-    static_vars: &BTreeMap<String, json::Value>,
+    static_vars: &StaticVars,
     no_recoverable_error: bool,
     marker: Marker,
 ) -> Result<ParsedSelect, CreatingExpressionError> {
@@ -1661,7 +2188,7 @@ fn parse_select(
 fn parse_function_call(
     pair: Pair<'_, Rule>,
     providers: &mut RequiredProviders,
-    static_vars: &BTreeMap<String, json::Value>,
+    static_vars: &StaticVars,
     no_recoverable_error: bool,
     marker: Marker,
 ) -> Result<Either<FunctionCall, json::Value>, CreatingExpressionError> {
@@ -1708,7 +2235,7 @@ fn parse_function_call(
 fn parse_indexed_property(
     pair: Pair<'_, Rule>,
     providers: &mut RequiredProviders,
-    static_vars: &BTreeMap<String, json::Value>,
+    static_vars: &StaticVars,
     no_recoverable_error: bool,
     marker: Marker,
 ) -> Result<PathSegment, CreatingExpressionError> {
@@ -1741,7 +2268,7 @@ fn parse_indexed_property(
 fn parse_path(
     pair: Pair<'_, Rule>,
     providers: &mut RequiredProviders,
-    static_vars: &BTreeMap<String, json::Value>,
+    static_vars: &StaticVars,
     no_recoverable_error: bool,
     marker: Marker,
 ) -> Result<Either<json::Value, Path>, CreatingExpressionError> {
@@ -1845,7 +2372,7 @@ fn parse_path(
 fn parse_value(
     mut pairs: Pairs<'_, Rule>,
     providers: &mut RequiredProviders,
-    static_vars: &BTreeMap<String, json::Value>,
+    static_vars: &StaticVars,
     no_recoverable_error: bool,
     marker: Marker,
 ) -> Result<Value, CreatingExpressionError> {
@@ -1906,7 +2433,7 @@ fn parse_value(
 #[derive(Debug)]
 enum ExpressionOrOperator {
     Expression(Expression),
-    Operator(InfixOperator),
+    Operator(InfixOperator, Marker),
 }
 
 fn expression_helper(
@@ -1915,7 +2442,7 @@ fn expression_helper(
 ) -> Result<Expression, CreatingExpressionError> {
     log::trace!("expression_helper items: {:?}, level: {}", items, level);
     let i = items.iter().rposition(|eoo| {
-        if let ExpressionOrOperator::Operator(o) = eoo {
+        if let ExpressionOrOperator::Operator(o, _) = eoo {
             INFIX_OPERATOR_PRECEDENCE[*o as usize] == level
         } else {
             false
@@ -1925,8 +2452,9 @@ fn expression_helper(
         Some(i) => {
             let mut left = items;
             let right = left.split_off(i + 1);
-            let operator = if let Some(ExpressionOrOperator::Operator(o)) = left.pop() {
-                o
+            let (operator, marker) = if let Some(ExpressionOrOperator::Operator(o, m)) = left.pop()
+            {
+                (o, m)
             } else {
                 unreachable!("element at split should have been an operator")
             };
@@ -1940,8 +2468,9 @@ fn expression_helper(
                 expression_helper(left, level)?
             };
             let right = expression_helper(right, level)?;
-            let op = Some((operator, right.into()));
-            e = if e.op.is_none() {
+            let op = Some((operator, right.into(), marker));
+            // a `!` applies to its own value, so the value is wrapped rather than given the operator
+            e = if e.op.is_none() && e.not.is_none() {
                 e.op = op;
                 e
             } else {
@@ -1975,7 +2504,7 @@ fn expression_helper(
 fn parse_expression_pieces(
     pairs: Pairs<'_, Rule>,
     providers: &mut RequiredProviders,
-    static_vars: &BTreeMap<String, json::Value>,
+    static_vars: &StaticVars,
     pieces: &mut Vec<ExpressionOrOperator>,
     no_recoverable_error: bool,
     marker: Marker,
@@ -2032,10 +2561,10 @@ fn parse_expression_pieces(
                         0 => None,
                         n => Some(n % 2 == 1),
                     };
+                    // the `!`s inside and outside of the group cancel each other out
                     e.not = match (e.not, not) {
-                        (Some(true), _) | (_, Some(true)) => Some(true),
-                        (Some(false), _) | (_, Some(false)) => Some(false),
-                        _ => None,
+                        (Some(inner), Some(outer)) => Some(inner != outer),
+                        (inner, outer) => inner.or(outer),
                     };
                     let eoo = ExpressionOrOperator::Expression(e);
                     pieces.push(eoo);
@@ -2072,7 +2601,7 @@ fn parse_expression_pieces(
                         );
                     }
                 };
-                let eoo = ExpressionOrOperator::Operator(o);
+                let eoo = ExpressionOrOperator::Operator(o, marker);
                 pieces.push(eoo);
             }
             Rule::EOI => (),
@@ -2087,7 +2616,7 @@ fn parse_expression_pieces(
 fn parse_expression(
     pairs: Pairs<'_, Rule>,
     providers: &mut RequiredProviders,
-    static_vars: &BTreeMap<String, json::Value>,
+    static_vars: &StaticVars,
     no_recoverable_error: bool,
     marker: Marker,
 ) -> Result<Expression, CreatingExpressionError> {
@@ -2214,6 +2743,7 @@ mod tests {
     fn select() {
         let data = json::json!({
             "a": 3,
+            "min": i64::MIN,
             "b": { "foo": "bar", "some:thing": "else", "e": [5, 6, 7, 8] },
             "c": [
                 { "d": 1 },
@@ -2277,6 +2807,44 @@ mod tests {
             (json::json!("c[0].d / 2"), vec![json::json!(0.5)]),
             (json::json!("c[0].d + 2 * 2"), vec![json::json!(5)]),
             (json::json!("(c[0].d + 2) * 2"), vec![json::json!(6)]),
+            (json::json!("c[0].d - 3"), vec![json::json!(-2)]),
+            (json::json!("a / 2"), vec![json::json!(1.5)]),
+            (json::json!("c[1].d / 2"), vec![json::json!(1)]),
+            (json::json!("a % 2"), vec![json::json!(1)]),
+            (
+                json::json!("min / -1"),
+                vec![json::json!(-(i64::MIN as f64))],
+            ),
+            (json::json!("min % -1"), vec![json::json!(0)]),
+            (
+                json::json!("min - 1"),
+                vec![json::json!(i64::MIN as f64 - 1.0)],
+            ),
+            (json::json!("a * 0.5"), vec![json::json!(1.5)]),
+            (json::json!("a - c[0].d * 2 + 1"), vec![json::json!(2)]),
+            (json::json!("'4' * a"), vec![json::json!(12)]),
+            (json::json!("floor(a / 2)"), vec![json::json!(1)]),
+            (json::json!("round(a / 7, 2)"), vec![json::json!(0.43)]),
+            (json::json!("pow(a, 2) - abs(-1)"), vec![json::json!(8)]),
+            (json::json!("upper(b.foo)"), vec![json::json!("BAR")]),
+            (
+                json::json!("len(split('1-2-3', '-'))"),
+                vec![json::json!(3)],
+            ),
+            (json::json!("last(slice(b.e, 0, -1))"), vec![json::json!(7)]),
+            (json::json!("contains(b.e, a + 3)"), vec![json::json!(true)]),
+            (
+                json::json!("jmespath('[?d > `1`].d | sum(@)', c)"),
+                vec![json::json!(5)],
+            ),
+            (
+                json::json!("xpath('//li[2]', '<ul><li>one</li><li>two</li></ul>')"),
+                vec![json::json!(["two"])],
+            ),
+            (
+                json::json!("css('li.x', '<ul><li>one</li><li class=x>two</li></ul>')[0].text"),
+                vec![json::json!("two")],
+            ),
         ];
 
         for (i, (select, expect)) in check_table.into_iter().enumerate() {
@@ -2286,6 +2854,259 @@ mod tests {
         }
     }
 
+    #[test]
+    fn invalid_operands() {
+        let data = json::json!({ "a": 3, "b": "bar", "min": i64::MIN });
+        let check_table = vec![
+            ("b * 2", "*"),
+            ("a / 0", "/"),
+            ("min / 0", "/"),
+            ("min % 0", "%"),
+            ("a % (1 - 1)", "%"),
+            ("a + null", "+"),
+            ("ceil(b)", "ceil"),
+        ];
+
+        for (select, name) in check_table {
+            let select = Select::simple(select, Block, None, None, None);
+            let r = select.as_iter(&data).and_then(|mut i| i.next().unwrap());
+            match r {
+                Err(ExecutingExpressionError::InvalidOperand(n, ..)) => assert_eq!(n, name),
+                _ => panic!("expected an invalid operand error for `{}`", name),
+            }
+        }
+    }
+
+    #[test]
+    fn changed_operators() {
+        // these gave a different result before the math operators checked their operands and `!`
+        // was evaluated (it used to be parsed and then ignored). The old result is noted on each,
+        // so a change to any of them is on purpose
+        let data = json::json!({ "a": 3, "b": "bar" });
+        let check_table = vec![
+            // was null
+            ("b * 2", None),
+            // was null
+            ("a + null", None),
+            // was null
+            ("a - true", None),
+            // was null
+            ("a / 0", None),
+            // was null
+            ("'4' * 2", Some(json::json!(8))),
+            // was 0
+            ("1 - a", Some(json::json!(-2))),
+            // was true
+            ("!true", Some(json::json!(false))),
+            // was false
+            ("!(true && false)", Some(json::json!(true))),
+            // was false
+            ("true && !false", Some(json::json!(true))),
+            // was true, as `a == 3`
+            ("!a == 3", Some(json::json!(false))),
+            // was true
+            ("!(!!true)", Some(json::json!(false))),
+        ];
+
+        for (select, expect) in check_table {
+            let s = Select::simple(select, Block, None, None, None);
+            let r = s.as_iter(&data).and_then(|mut i| i.next().unwrap());
+            match (r, expect) {
+                (Ok(v), Some(expect)) => assert_eq!(*v, expect, "`{}`", select),
+                (Err(ExecutingExpressionError::InvalidOperand(..)), None) => (),
+                (r, _) => panic!("unexpected result for `{}`: {:?}", select, r),
+            }
+        }
+    }
+
+    #[test]
+    fn user_functions() {
+        let functions = vec![
+            ("quad(n)", "double(double(n))"),
+            ("double(n)", "n * 2"),
+            ("user_url(id)", "'${base}/users/${id}'"),
+            ("name(person)", "'${person.first} ${person.last}'"),
+            ("shadow(base)", "base"),
+            ("zero()", "0"),
+        ];
+        let functions = functions
+            .into_iter()
+            .map(|(d, b)| (d.to_string(), b.to_string(), create_marker()))
+            .collect();
+        let vars = btreemap! { "base".to_string() => json::json!("http://localhost") };
+        let static_vars = StaticVars::new(vars, functions, BTreeMap::new()).unwrap();
+
+        let data = json::json!({
+            "a": 3,
+            "b": { "first": "Jane", "last": "Doe" },
+        });
+        let check_table = vec![
+            ("quad(a)", json::json!(12)),
+            ("double(a) + 1", json::json!(7)),
+            ("user_url(a)", json::json!("http://localhost/users/3")),
+            ("name(b)", json::json!("Jane Doe")),
+            ("shadow(a)", json::json!(3)),
+            ("zero()", json::json!(0)),
+            ("double(zero())", json::json!(0)),
+        ];
+        for (expr, expect) in check_table {
+            let mut required_providers = RequiredProviders::new();
+            let voe = ValueOrExpression::new(
+                expr,
+                &mut required_providers,
+                &static_vars,
+                false,
+                create_marker(),
+            )
+            .unwrap();
+            let left = voe.evaluate(Cow::Borrowed(&data), false, None).unwrap();
+            assert_eq!(*left, expect, "expression `{}`", expr);
+
+            let providers: BTreeMap<_, _> = data
+                .as_object()
+                .unwrap()
+                .iter()
+                .map(|(k, v)| (k.clone(), literals(vec![v.clone()])))
+                .collect();
+            let left = block_on_stream(voe.into_stream(&providers, false))
+                .next()
+                .unwrap()
+                .unwrap()
+                .0;
+            assert_eq!(left, expect, "stream for expression `{}`", expr);
+        }
+
+        let r = ValueOrExpression::new(
+            "double(a, 1)",
+            &mut RequiredProviders::new(),
+            &static_vars,
+            false,
+            create_marker(),
+        );
+        assert!(matches!(
+            r,
+            Err(CreatingExpressionError::InvalidArgumentCount(ref f, 1, _)) if f == "double"
+        ));
+    }
+
+    #[test]
+    fn invalid_user_functions() {
+        let check_table = vec![
+            (
+                vec![("f(a)", "g(a)"), ("g(a)", "f(a)")],
+                "recursive function `f`",
+            ),
+            (vec![("f(a)", "'${f(a)}'")], "recursive function `f`"),
+            (vec![("f(a)", "a + b")], "unknown provider: `b`"),
+            (
+                vec![("f(a)", "response.body")],
+                "unknown provider: `response`",
+            ),
+            (
+                vec![("f(a)", "a"), ("f(b)", "b")],
+                "invalid function declaration `f`",
+            ),
+            (
+                vec![("join(a)", "a")],
+                "invalid function declaration `join`",
+            ),
+            (
+                vec![("script(a)", "a")],
+                "invalid function declaration `script`",
+            ),
+            (vec![("f(a", "a")], "invalid function declaration `f(a`"),
+            (
+                vec![("f(a, a)", "a")],
+                "invalid function declaration `f(a, a)`",
+            ),
+            (
+                vec![("f(stats)", "1")],
+                "invalid function declaration `f(stats)`",
+            ),
+        ];
+        for (functions, expect) in check_table {
+            let functions = functions
+                .into_iter()
+                .map(|(d, b)| (d.to_string(), b.to_string(), create_marker()))
+                .collect();
+            let e = StaticVars::new(BTreeMap::new(), functions, BTreeMap::new()).unwrap_err();
+            assert!(e.to_string().starts_with(expect), "{} != {}", e, expect);
+        }
+    }
+
+    #[test]
+    fn scripts() {
+        let scripts = btreemap! {
+            "add".to_string() => Script::compile("args[0] + args[1]").unwrap().into(),
+            "keys".to_string() => Script::compile("args[0].keys()").unwrap().into(),
+            "count".to_string() => Script::compile("args.len()").unwrap().into(),
+            "spin".to_string() => Script::compile("loop {}").unwrap().into(),
+        };
+        let functions = vec![(
+            "inc(n)".to_string(),
+            "script('add', n, 1)".to_string(),
+            create_marker(),
+        )];
+        let static_vars = StaticVars::new(BTreeMap::new(), functions, scripts).unwrap();
+
+        let data = json::json!({
+            "a": 3,
+            "b": { "foo": 1, "bar": 2 },
+        });
+        let check_table = vec![
+            ("script('add', a, 2)", json::json!(5)),
+            ("script('keys', b)", json::json!(["bar", "foo"])),
+            ("script('count')", json::json!(0)),
+            ("inc(a)", json::json!(4)),
+            // a script which fails or runs over its limits returns null
+            ("script('add', a, b)", json::Value::Null),
+            ("script('spin')", json::Value::Null),
+        ];
+        for (expr, expect) in check_table {
+            let mut required_providers = RequiredProviders::new();
+            let voe = ValueOrExpression::new(
+                expr,
+                &mut required_providers,
+                &static_vars,
+                false,
+                create_marker(),
+            )
+            .unwrap();
+            let left = voe.evaluate(Cow::Borrowed(&data), false, None).unwrap();
+            assert_eq!(*left, expect, "expression `{}`", expr);
+
+            let providers: BTreeMap<_, _> = data
+                .as_object()
+                .unwrap()
+                .iter()
+                .map(|(k, v)| (k.clone(), literals(vec![v.clone()])))
+                .collect();
+            let left = block_on_stream(voe.into_stream(&providers, false))
+                .next()
+                .unwrap()
+                .unwrap()
+                .0;
+            assert_eq!(left, expect, "stream for expression `{}`", expr);
+        }
+
+        let check_table = vec![
+            ("script('missing', a)", "unknown script `missing`"),
+            ("script(a)", "invalid arguments for function `script`"),
+            ("script()", "invalid arguments for function `script`"),
+        ];
+        for (expr, expect) in check_table {
+            let e = ValueOrExpression::new(
+                expr,
+                &mut RequiredProviders::new(),
+                &static_vars,
+                false,
+                create_marker(),
+            )
+            .unwrap_err();
+            assert!(e.to_string().starts_with(expect), "{} != {}", e, expect);
+        }
+    }
+
     #[test]
     fn voe_stream() {
         let data = btreemap! {
@@ -2352,7 +3173,7 @@ mod tests {
         ];
 
         let mut required_providers = RequiredProviders::new();
-        let static_vars = BTreeMap::new();
+        let static_vars = StaticVars::default();
         for (expr, expect) in tests.into_iter() {
             let marker = create_marker();
             let voe =
@@ -2419,6 +3240,8 @@ mod tests {
             ("false || (true || false) && false", &empty),
             ("0 || (1 && false) && 2", &empty),
             ("false || (true || false) && true", &three),
+            ("!false && false", &empty),
+            ("!true", &empty),
         ];
 
         for (i, (where_clause, expect)) in check_table.into_iter().enumerate() {
@@ -0,0 +1,282 @@
+// XPath 1.0 queries for pulling values out of a response, using `sxd-xpath`. XML documents are
+// parsed with `sxd-document` and HTML documents with `html5ever` (through `scraper`), then copied
+// into an `sxd-document` so they can be searched the same way.
+use serde_json as json;
+use sxd_document::{dom, Package};
+use sxd_xpath::{nodeset::Node, Context, Factory, Value, XPath};
+
+use std::{fmt, sync::OnceLock};
+
+/// A compiled XPath 1.0 expression, compiled once when the config is loaded
+pub(crate) struct Expression {
+    source: String,
+    xpath: Compiled,
+}
+
+// `XPath` is a boxed trait object without a `Send` or `Sync` bound, so it can't be shared between
+// the threads which search responses on its own
+struct Compiled(XPath);
+
+// SAFETY: the expressions `Factory::build` creates only own their strings, numbers, function
+// pointers and boxed sub-expressions, with no `Rc` or interior mutability, and evaluating one only
+// reads it. Anything tied to a document lives in the `Context` and the nodes, which are created for
+// each search and not shared
+unsafe impl Send for Compiled {}
+unsafe impl Sync for Compiled {}
+
+// the namespaces a document declares, which are bound to their prefixes for each search
+fn namespaces() -> &'static Compiled {
+    static NAMESPACES: OnceLock<Compiled> = OnceLock::new();
+    NAMESPACES.get_or_init(|| Compiled(build("//namespace::*").expect("valid xpath")))
+}
+
+impl fmt::Debug for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expression {{ {} }}", self.source)
+    }
+}
+
+fn build(source: &str) -> Result<XPath, String> {
+    Factory::new()
+        .build(source)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "the expression is empty".into())
+}
+
+impl Expression {
+    pub(crate) fn compile(source: &str) -> Result<Self, String> {
+        let xpath = Compiled(build(source)?);
+        Ok(Expression {
+            source: source.into(),
+            xpath,
+        })
+    }
+
+    /// Evaluates the expression against a document, which is parsed as XML when it starts with an
+    /// XML declaration and as HTML otherwise. The namespace prefixes declared in the document can
+    /// be used in the expression. Nodes become an array of their string values and anything else
+    /// becomes a string, number or boolean.
+    pub(crate) fn search(&self, document: &str) -> Result<json::Value, String> {
+        let package = if document.trim_start().starts_with("<?xml") {
+            sxd_document::parser::parse(document.trim_start()).map_err(|e| e.to_string())?
+        } else {
+            parse_html(document)
+        };
+        let doc = package.as_document();
+        let mut context = Context::new();
+        let namespaces = namespaces()
+            .0
+            .evaluate(&context, doc.root())
+            .map_err(|e| e.to_string())?;
+        if let Value::Nodeset(namespaces) = namespaces {
+            for node in namespaces.document_order() {
+                if let Node::Namespace(ns) = node {
+                    context.set_namespace(ns.prefix(), ns.uri());
+                }
+            }
+        }
+        let v = match self
+            .xpath
+            .0
+            .evaluate(&context, doc.root())
+            .map_err(|e| e.to_string())?
+        {
+            Value::Nodeset(nodes) => nodes
+                .document_order()
+                .into_iter()
+                .map(|node| json::Value::String(node.string_value()))
+                .collect(),
+            Value::String(s) => s.into(),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => (n as i64).into(),
+            Value::Number(n) => json::Number::from_f64(n)
+                .map(json::Value::Number)
+                .unwrap_or(json::Value::Null),
+            Value::Boolean(b) => b.into(),
+        };
+        Ok(v)
+    }
+}
+
+// copies the elements, text and comments of an HTML document into an `sxd-document`. Elements and
+// attributes keep only their local names, so they can be matched without a namespace prefix
+fn parse_html(input: &str) -> Package {
+    let html = scraper::Html::parse_document(input);
+    let package = Package::new();
+    let doc = package.as_document();
+    // the nodes are copied with a stack rather than recursion, so deep nesting can't overflow
+    let mut stack: Vec<_> = html
+        .tree
+        .root()
+        .children()
+        .rev()
+        .map(|node| (node, dom::ParentOfChild::Root(doc.root())))
+        .collect();
+    while let Some((node, parent)) = stack.pop() {
+        let child: dom::ChildOfElement = match node.value() {
+            scraper::Node::Element(e) => {
+                let element = doc.create_element(&*e.name.local);
+                for (name, value) in &e.attrs {
+                    element.set_attribute_value(&*name.local, value);
+                }
+                stack.extend(
+                    node.children()
+                        .rev()
+                        .map(|c| (c, dom::ParentOfChild::Element(element))),
+                );
+                element.into()
+            }
+            scraper::Node::Text(t) => doc.create_text(t).into(),
+            scraper::Node::Comment(c) => doc.create_comment(c).into(),
+            _ => continue,
+        };
+        match (parent, child) {
+            (dom::ParentOfChild::Root(root), dom::ChildOfElement::Element(e)) => {
+                root.append_child(e)
+            }
+            (dom::ParentOfChild::Root(root), dom::ChildOfElement::Comment(c)) => {
+                root.append_child(c)
+            }
+            (dom::ParentOfChild::Element(element), child) => element.append_child(child),
+            // the root can't have text, and html5ever puts all of it inside of `<html>`
+            _ => (),
+        }
+    }
+    package
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/">
+            <soap:Body>
+                <GetUsersResponse>
+                    <User id="1" active="true"><Name>Ann</Name><Age>31</Age></User>
+                    <User id="2" active="false"><Name>Bob</Name><Age>27</Age></User>
+                    <User id="3" active="true"><Name>Cy</Name><Age>45</Age></User>
+                </GetUsersResponse>
+            </soap:Body>
+        </soap:Envelope>"#;
+
+    #[test]
+    fn searches() {
+        let checks = vec![
+            ("//User/Name", json::json!(["Ann", "Bob", "Cy"])),
+            ("/soap:Envelope/soap:Body//User[1]/@id", json::json!(["1"])),
+            ("//User[last()]/Name/text()", json::json!(["Cy"])),
+            ("//User[@active='true']/@id", json::json!(["1", "3"])),
+            ("//User[Age > 30 and Age < 40]/Name", json::json!(["Ann"])),
+            (
+                "//User[Name='Bob']/following-sibling::User/@id",
+                json::json!(["3"]),
+            ),
+            (
+                "//User[Name='Bob']/preceding-sibling::*[1]/Name",
+                json::json!(["Ann"]),
+            ),
+            ("//Name[.='Cy']/../@id", json::json!(["3"])),
+            ("//Age[. = 27]/ancestor::User/@id", json::json!(["2"])),
+            ("(//User)[2]/Name", json::json!(["Bob"])),
+            (
+                "//User[2]/Name | //User[1]/Name",
+                json::json!(["Ann", "Bob"]),
+            ),
+            ("//User[not(@active='true')]/Name", json::json!(["Bob"])),
+            ("//User[starts-with(Name, 'C')]/@id", json::json!(["3"])),
+            ("//User[contains(Name, 'o')]/Age", json::json!(["27"])),
+            ("//User[position() > 1]/@id", json::json!(["2", "3"])),
+            ("count(//User)", json::json!(3)),
+            ("sum(//User/Age) div 2", json::json!(51.5)),
+            ("string(//User[3]/Age)", json::json!("45")),
+            ("name(//soap:Body)", json::json!("soap:Body")),
+            ("local-name(/*)", json::json!("Envelope")),
+            (
+                "concat(//User[1]/Name, '-', //User[1]/@id)",
+                json::json!("Ann-1"),
+            ),
+            ("substring-after(//User[2]/Name, 'B')", json::json!("ob")),
+            ("normalize-space('  a   b ')", json::json!("a b")),
+            ("//User/@id = 2", json::json!(true)),
+            ("//Missing", json::json!([])),
+            ("count(//User[1]/node())", json::json!(2)),
+        ];
+        for (expression, expect) in checks {
+            let e = Expression::compile(expression).unwrap();
+            let left = e.search(SOAP).unwrap();
+            assert_eq!(left, expect, "expression `{}`", expression);
+        }
+
+        // names in a default namespace are matched by their local name
+        let xml = r#"<?xml version="1.0"?><a xmlns="urn:x"><b>1</b></a>"#;
+        let e = Expression::compile("//*[local-name()='b']").unwrap();
+        assert_eq!(e.search(xml).unwrap(), json::json!(["1"]));
+        let e = Expression::compile("//b").unwrap();
+        assert_eq!(e.search(xml).unwrap(), json::json!([]));
+
+        let e = Expression::compile("//a").unwrap();
+        assert!(e.search(r#"<?xml version="1.0"?><a><b></a>"#).is_err());
+
+        let html = r#"<html><body><form><input type="hidden" name="_csrf" value="abc123"><INPUT name=user></form><!-- done --></body></html>"#;
+        let checks = vec![
+            ("//input[@name='_csrf']/@value", json::json!(["abc123"])),
+            ("count(//form/input)", json::json!(2)),
+            ("//body/comment()", json::json!([" done "])),
+        ];
+        for (expression, expect) in checks {
+            let e = Expression::compile(expression).unwrap();
+            let left = e.search(html).unwrap();
+            assert_eq!(left, expect, "expression `{}`", expression);
+        }
+
+        let checks = vec![
+            ("//p", json::json!(["été", "nö"])),
+            ("//p[@title='ñ']", json::json!(["été"])),
+            ("string-length(//p[1])", json::json!(3)),
+            ("//p[starts-with(., 'é')]/@title", json::json!(["ñ"])),
+        ];
+        for (expression, expect) in checks {
+            let e = Expression::compile(expression).unwrap();
+            let left = e.search("<p title=ñ>été</p><p>nö</p>").unwrap();
+            assert_eq!(left, expect, "expression `{}`", expression);
+        }
+
+        // deeply nested documents don't overflow the stack
+        let nested = "<div>".repeat(5_000);
+        let e = Expression::compile("count(//div) > 0").unwrap();
+        assert_eq!(e.search(&nested).unwrap(), json::json!(true));
+    }
+
+    #[test]
+    fn searches_from_many_threads() {
+        let e = std::sync::Arc::new(Expression::compile("//soap:Body//User[2]/Name").unwrap());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let e = e.clone();
+                std::thread::spawn(move || e.search(SOAP).unwrap())
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), json::json!(["Bob"]));
+        }
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for expression in [
+            "",
+            "//",
+            "//User[",
+            "//User[1",
+            "'unterminated",
+            "//User]",
+            "//User/#",
+        ] {
+            assert!(
+                Expression::compile(expression).is_err(),
+                "`{}` should not compile",
+                expression
+            );
+        }
+    }
+}
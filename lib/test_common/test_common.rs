@@ -1,18 +1,39 @@
 use log::{debug, info};
-use std::{future::Future, io, net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 
 use bytes::Bytes;
-use futures::{channel::oneshot, future::select, FutureExt};
+use futures::{channel::oneshot, future::select, FutureExt, StreamExt, TryStreamExt};
 use futures_timer::Delay;
 use http::{header, StatusCode};
-use http_body_util::{BodyExt, Empty};
-use hyper::{body::Incoming as Body, service::service_fn, Error, Request, Response};
+use http_body_util::{BodyExt, Empty, StreamBody};
+use hyper::{
+    body::{Frame, Incoming as Body},
+    service::service_fn,
+    Error, Request, Response,
+};
 use hyper_util::{
     rt::{TokioExecutor, TokioIo},
     server::conn::auto::Builder as HyperBuilder,
 };
 use parking_lot::Mutex;
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role},
+    WebSocketStream,
+};
 use url::Url;
 
 type HyperBody = http_body_util::combinators::BoxBody<Bytes, Error>;
@@ -25,6 +46,7 @@ async fn echo_route(req: Request<Body>) -> Response<HyperBody> {
         .unwrap_or_else(|| header::HeaderValue::from_static("text/plain"));
     let mut echo = None;
     let mut wait = None;
+    let mut header = None;
     let uri = req.uri();
     let url = uri
         .path_and_query()
@@ -35,9 +57,19 @@ async fn echo_route(req: Request<Body>) -> Response<HyperBody> {
         match &*k {
             "echo" => echo = Some(v.to_string()),
             "wait" => wait = Some(v.to_string()),
+            "header" => header = Some(v.to_string()),
             _ => (),
         }
     }
+    // echo back the value of a request header instead
+    if let Some(header) = header {
+        let value = headers
+            .get(&header)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        echo = Some(value);
+    }
     if echo.is_some() {
         debug!("Echo Body = {}", echo.clone().unwrap_or_default());
     }
@@ -67,12 +99,215 @@ async fn echo_route(req: Request<Body>) -> Response<HyperBody> {
     response
 }
 
+async fn redirect_route(req: Request<Body>) -> Response<HyperBody> {
+    let uri = req.uri();
+    let url = uri
+        .path_and_query()
+        .map(|piece| piece.as_str())
+        .unwrap_or_else(|| uri.path());
+    let url = Url::parse(&format!("http://127.0.0.1:8080{url}")).unwrap();
+    let mut count: u16 = 1;
+    let mut status = StatusCode::FOUND;
+    let mut echo = None;
+    // an absolute url the last hop redirects to
+    let mut to = None;
+    // how long each hop waits before responding
+    let mut wait = None;
+    for (k, v) in url.query_pairs() {
+        match &*k {
+            "count" => count = FromStr::from_str(&v).unwrap_or(count),
+            "status" => status = StatusCode::from_str(&v).unwrap_or(status),
+            "echo" => echo = Some(v.to_string()),
+            "to" => to = Some(v.to_string()),
+            "wait" => wait = Some(v.to_string()),
+            _ => (),
+        }
+    }
+    let mut location = if count > 1 {
+        Url::parse(&format!(
+            "http://127.0.0.1:8080/redirect?count={}&status={}",
+            count - 1,
+            status.as_u16()
+        ))
+        .unwrap()
+    } else {
+        Url::parse("http://127.0.0.1:8080/").unwrap()
+    };
+    if let Some(echo) = echo {
+        location.query_pairs_mut().append_pair("echo", &echo);
+    }
+    if let Some(wait) = &wait {
+        location.query_pairs_mut().append_pair("wait", wait);
+    }
+    let location = match (count, to) {
+        (1, Some(to)) => to,
+        (_, to) => {
+            if let Some(to) = to {
+                location.query_pairs_mut().append_pair("to", &to);
+            }
+            match location.query() {
+                Some(q) => format!("{}?{}", location.path(), q),
+                None => location.path().to_string(),
+            }
+        }
+    };
+    let ms = wait.and_then(|w| FromStr::from_str(&w).ok()).unwrap_or(0);
+    Delay::new(Duration::from_millis(ms)).await;
+    Response::builder()
+        .status(status)
+        .header(header::LOCATION, location)
+        .header(
+            header::SET_COOKIE,
+            format!("redirect{count}={count}; Path=/"),
+        )
+        .body(empty())
+        .unwrap()
+}
+
+// echoes back any text or binary messages sent on the websocket
+async fn websocket_route(mut req: Request<Body>) -> Response<HyperBody> {
+    let accept = match req.headers().get(header::SEC_WEBSOCKET_KEY) {
+        Some(key) => derive_accept_key(key.as_bytes()),
+        None => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(empty())
+                .unwrap()
+        }
+    };
+    tokio::spawn(async move {
+        if let Ok(upgraded) = hyper::upgrade::on(&mut req).await {
+            let ws =
+                WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+            let (tx, rx) = ws.split();
+            let _ = rx
+                .try_filter(|m| futures::future::ready(m.is_text() || m.is_binary()))
+                .forward(tx)
+                .await;
+        }
+    });
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::CONNECTION, "upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(empty())
+        .unwrap()
+}
+
+// echoes the message of a gRPC call back once, or three times for the streaming method
+async fn grpc_route(req: Request<Body>) -> Response<HyperBody> {
+    let repeat = if req.uri().path().ends_with("/Stream") {
+        3
+    } else {
+        1
+    };
+    let message = match req.into_body().collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(empty())
+                .unwrap()
+        }
+    };
+    let mut trailers = header::HeaderMap::new();
+    trailers.insert("grpc-status", header::HeaderValue::from_static("0"));
+    let frames = std::iter::repeat_n(message, repeat)
+        .map(Frame::data)
+        .chain(std::iter::once(Frame::trailers(trailers)))
+        .map(Ok);
+    let body = StreamBody::new(futures::stream::iter(frames));
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/grpc")
+        .body(BodyExt::boxed(body))
+        .unwrap()
+}
+
+// an OAuth2 token endpoint which hands out `token-1`, `token-2`, etc. The lifetime of the tokens
+// comes from the `expires_in` query parameter
+async fn token_route(req: Request<Body>, issued: &AtomicUsize) -> Response<HyperBody> {
+    let expires_in = req
+        .uri()
+        .query()
+        .and_then(|q| url::form_urlencoded::parse(q.as_bytes()).find(|(k, _)| k == "expires_in"))
+        .map(|(_, v)| v.into_owned())
+        .unwrap_or_else(|| "3600".into());
+    let form = match req.into_body().collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(_) => Bytes::new(),
+    };
+    let has_grant = url::form_urlencoded::parse(&form).any(|(k, _)| k == "grant_type");
+    let has_client = url::form_urlencoded::parse(&form).any(|(k, _)| k == "client_id");
+    if !has_grant || !has_client {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(empty())
+            .unwrap();
+    }
+    let n = issued.fetch_add(1, Ordering::SeqCst) + 1;
+    let body = format!(
+        r#"{{"access_token":"token-{n}","token_type":"Bearer","expires_in":{expires_in}}}"#
+    );
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body.map_err(|never| match never {}).boxed())
+        .unwrap()
+}
+
 fn empty() -> HyperBody {
     Empty::<Bytes>::new()
         .map_err(|never| match never {})
         .boxed()
 }
 
+async fn route(
+    req: Request<Body>,
+    issued_tokens: Arc<AtomicUsize>,
+) -> Result<Response<HyperBody>, Error> {
+    debug!("{:?}", req);
+    let method = req.method().to_string();
+    let uri = req.uri().to_string();
+    let headers = req.headers().clone();
+    let response = match req.uri().path() {
+        "/" => echo_route(req).await,
+        "/redirect" => redirect_route(req).await,
+        "/websocket" => websocket_route(req).await,
+        "/test.Echo/Unary" | "/test.Echo/Stream" => grpc_route(req).await,
+        "/token" => token_route(req, &issued_tokens).await,
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(empty())
+            .unwrap(),
+    };
+    debug!("{:?}", response);
+    info!(
+        "method=\"{}\" uri=\"{}\" status=\"{}\" request_headers={:?} response_headers={:?}",
+        method,
+        uri,
+        response.status(),
+        headers,
+        response.headers()
+    );
+    Ok(response)
+}
+
+fn serve_connection<I>(io: I, issued_tokens: Arc<AtomicUsize>)
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let service = service_fn(move |req| route(req, issued_tokens.clone()));
+    tokio::task::spawn(async move {
+        let builder = HyperBuilder::new(TokioExecutor::new());
+        builder
+            .serve_connection_with_upgrades(io, service)
+            .await
+            .unwrap();
+    });
+}
+
 pub async fn start_test_server(
     port: Option<u16>,
 ) -> (u16, oneshot::Sender<()>, impl Future<Output = ()>) {
@@ -83,37 +318,10 @@ pub async fn start_test_server(
     let local_addr = listener.local_addr().unwrap();
 
     let server = tokio::spawn(async move {
-        let service = service_fn(|req: Request<Body>| async {
-            debug!("{:?}", req);
-            let method = req.method().to_string();
-            let uri = req.uri().to_string();
-            let headers = req.headers().clone();
-            let response = match req.uri().path() {
-                "/" => echo_route(req).await,
-                _ => Response::builder()
-                    .status(StatusCode::NOT_FOUND)
-                    .body(empty())
-                    .unwrap(),
-            };
-            debug!("{:?}", response);
-            info!(
-                "method=\"{}\" uri=\"{}\" status=\"{}\" request_headers={:?} response_headers={:?}",
-                method,
-                uri,
-                response.status(),
-                headers,
-                response.headers()
-            );
-            Ok::<_, Error>(response)
-        });
-
+        let issued_tokens = Arc::new(AtomicUsize::new(0));
         loop {
             let (stream, _) = listener.accept().await.unwrap();
-            let stream = TokioIo::new(stream);
-            tokio::task::spawn(async move {
-                let builder = HyperBuilder::new(TokioExecutor::new());
-                builder.serve_connection(stream, service).await.unwrap();
-            });
+            serve_connection(TokioIo::new(stream), issued_tokens.clone());
         }
     });
 
@@ -129,6 +337,33 @@ pub async fn start_test_server(
     (port, tx, handle)
 }
 
+/// Starts the same test server listening on a unix domain socket at `path`. Any file already at
+/// the path is removed first
+#[cfg(unix)]
+pub async fn start_unix_test_server(
+    path: &Path,
+) -> (oneshot::Sender<()>, impl Future<Output = ()>) {
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path).unwrap();
+
+    let server = tokio::spawn(async move {
+        let issued_tokens = Arc::new(AtomicUsize::new(0));
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve_connection(TokioIo::new(stream), issued_tokens.clone());
+        }
+    });
+
+    let (tx, rx) = oneshot::channel();
+
+    let future = select(server, rx);
+
+    debug!("start_unix_test_server tokio::spawn future");
+    let handle = tokio::spawn(future).map(|_| ());
+
+    (tx, handle)
+}
+
 #[derive(Clone)]
 pub struct TestWriter(Arc<Mutex<(bool, Vec<u8>)>>);
 
@@ -8,7 +8,8 @@ use tokio::runtime;
 mod args {
     use clap::{Args, Parser, Subcommand};
     use pewpew::{
-        ExecConfig, RunConfig, RunOutputFormat, StatsFileFormat, TryConfig, TryFilter, TryRunFormat,
+        EvalConfig, ExecConfig, ImportConfig, RunConfig, RunOutputFormat, StatsFileFormat,
+        TryConfig, TryFilter, TryRunFormat,
     };
     use std::{
         fs::create_dir_all,
@@ -57,6 +58,10 @@ mod args {
         Run(RunConfigTmp),
         /// Runs the specified endpoint(s) a single time for testing purposes
         Try(TryConfigTmp),
+        /// Creates a config file from a HAR file, curl commands or an OpenAPI document
+        Import(ImportConfig),
+        /// Evaluates an expression against a JSON document
+        Eval(EvalConfig),
     }
 
     impl From<ExecConfigTmp> for ExecConfig {
@@ -64,6 +69,8 @@ mod args {
             match value {
                 ExecConfigTmp::Try(t) => Self::Try(t.into()),
                 ExecConfigTmp::Run(r) => Self::Run(r.into()),
+                ExecConfigTmp::Import(i) => Self::Import(i),
+                ExecConfigTmp::Eval(e) => Self::Eval(e),
             }
         }
     }
@@ -218,6 +225,14 @@ fn main() {
             info!("log::max_level()={}", log::max_level());
             debug!("{{\"try_config\":{}}}", try_config);
         }
+        ExecConfig::Import(ref import_config) => {
+            env_logger::init();
+            debug!("{{\"import_config\":{}}}", import_config);
+        }
+        ExecConfig::Eval(ref eval_config) => {
+            env_logger::init();
+            debug!("{{\"eval_config\":{}}}", eval_config);
+        }
     }
 
     // Create Future to run full load test or try test.
@@ -737,4 +752,76 @@ mod tests {
         assert!(try_config.results_dir.is_some());
         assert_eq!(try_config.results_dir.unwrap().to_str().unwrap(), TEST_DIR);
     }
+
+    #[test]
+    fn cli_import() {
+        let cli_config = args::try_parse_from([
+            "myprog",
+            "import",
+            "-f",
+            "curl",
+            "-o",
+            YAML_FILE,
+            "requests.sh",
+        ])
+        .unwrap();
+        let ExecConfig::Import(import_config) = cli_config else {
+            panic!("subcommand was not `import`")
+        };
+        assert_eq!(import_config.input_file.to_str().unwrap(), "requests.sh");
+        assert!(matches!(
+            import_config.format,
+            Some(pewpew::ImportFormat::Curl)
+        ));
+        assert_eq!(
+            import_config.output_file.unwrap().to_str().unwrap(),
+            YAML_FILE
+        );
+
+        let cli_config = args::try_parse_from(["myprog", "import", "requests.har"]).unwrap();
+        let ExecConfig::Import(import_config) = cli_config else {
+            panic!("subcommand was not `import`")
+        };
+        assert!(import_config.format.is_none());
+        assert!(import_config.output_file.is_none());
+    }
+
+    #[test]
+    fn cli_eval() {
+        let cli_config = args::try_parse_from([
+            "myprog",
+            "eval",
+            "-v",
+            "status=200",
+            "--var",
+            "name=pewpew",
+            "response.status == status",
+            "response.json",
+        ])
+        .unwrap();
+        let ExecConfig::Eval(eval_config) = cli_config else {
+            panic!("subcommand was not `eval`")
+        };
+        assert_eq!(eval_config.expression, "response.status == status");
+        assert_eq!(
+            eval_config.data_file.unwrap().to_str().unwrap(),
+            "response.json"
+        );
+        assert_eq!(
+            eval_config.vars,
+            vec![
+                ("status".to_string(), serde_json::json!(200)),
+                ("name".to_string(), serde_json::json!("pewpew"))
+            ]
+        );
+
+        let cli_config = args::try_parse_from(["myprog", "eval", "1 + 1"]).unwrap();
+        let ExecConfig::Eval(eval_config) = cli_config else {
+            panic!("subcommand was not `eval`")
+        };
+        assert!(eval_config.data_file.is_none());
+        assert!(eval_config.vars.is_empty());
+
+        assert!(args::try_parse_from(["myprog", "eval", "-v", "status", "1 + 1"]).is_err());
+    }
 }
@@ -12,7 +12,20 @@ fn main() {
 
         println!("Listening on port {port}");
 
+        // also listen on a unix domain socket when `UNIX_SOCKET` is set to its path
+        #[cfg(unix)]
+        let unix_server = match std::env::var("UNIX_SOCKET") {
+            Ok(path) => {
+                let server = test_common::start_unix_test_server(path.as_ref()).await;
+                println!("Listening on unix socket {path}");
+                Some(server)
+            }
+            Err(_) => None,
+        };
+
         handle.await;
         drop(rx);
+        #[cfg(unix)]
+        drop(unix_server);
     });
 }
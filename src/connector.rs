@@ -0,0 +1,270 @@
+use hyper::{
+    rt::{Read, ReadBufCursor, Write},
+    Uri,
+};
+use hyper_tls::{HttpsConnector, MaybeHttpsStream};
+use hyper_util::{
+    client::legacy::connect::{dns::GaiResolver, Connected, Connection, HttpConnector},
+    rt::TokioIo,
+};
+use percent_encoding::percent_decode_str;
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tower_service::Service;
+
+use std::{
+    borrow::Cow,
+    error::Error as StdError,
+    future::Future,
+    io,
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+// the scheme of urls which target a unix domain socket, like `unix:///var/run/app.sock:/path`
+const UNIX_SCHEME: &str = "unix";
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// Connects to the host in a request's uri over tcp (with tls for https), or to a unix domain
+/// socket when the uri was made by `request_uri` from a `unix:` url
+#[derive(Clone)]
+pub(crate) struct Connector {
+    https: HttpsConnector<HttpConnector<GaiResolver>>,
+}
+
+impl Connector {
+    pub(crate) fn new(https: HttpsConnector<HttpConnector<GaiResolver>>) -> Self {
+        Connector { https }
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = Stream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Stream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.https.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        if uri.scheme_str() != Some(UNIX_SCHEME) {
+            let connecting = self.https.call(uri);
+            return Box::pin(async move { connecting.await.map(Stream::Https) });
+        }
+        let path = uri.host().and_then(decode_socket_path);
+        Box::pin(async move {
+            let path = path.ok_or_else(|| format!("invalid unix socket uri `{uri}`"))?;
+            connect_unix(path).await
+        })
+    }
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: PathBuf) -> Result<Stream, BoxError> {
+    let stream = UnixStream::connect(path).await?;
+    Ok(Stream::Unix(TokioIo::new(stream)))
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_path: PathBuf) -> Result<Stream, BoxError> {
+    Err("unix sockets are not supported on this platform".into())
+}
+
+pub(crate) enum Stream {
+    Https(MaybeHttpsStream<TokioIo<TcpStream>>),
+    #[cfg(unix)]
+    Unix(TokioIo<UnixStream>),
+}
+
+impl Read for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Https(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Https(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Https(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Https(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Stream::Https(s) => s.is_write_vectored(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.is_write_vectored(),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Https(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+        }
+    }
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        match self {
+            Stream::Https(s) => s.connected(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.connected(),
+        }
+    }
+}
+
+// a unix url has the socket path, then a `:`, then the path of the request. Any other url has no
+// socket path
+pub(crate) fn split_unix_url(url: &url::Url) -> (&str, &str) {
+    if url.scheme() != UNIX_SCHEME {
+        return ("", url.path());
+    }
+    match url.path().split_once(':') {
+        Some((socket, path)) if path.starts_with('/') => (socket, path),
+        Some((socket, _)) => (socket, "/"),
+        None => (url.path(), "/"),
+    }
+}
+
+/// The uri a request for the url is sent to. For a `unix:` url the socket path is hex encoded as
+/// the host, so the uri is valid and requests to the same socket share connections
+pub(crate) fn request_uri(url: &url::Url) -> Cow<'_, str> {
+    if url.scheme() != UNIX_SCHEME {
+        return url.as_str().into();
+    }
+    let (socket, path) = split_unix_url(url);
+    let host: String = percent_decode_str(socket)
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let query = url.query().map(|q| format!("?{q}")).unwrap_or_default();
+    format!("{UNIX_SCHEME}://{host}{path}{query}").into()
+}
+
+/// The value of the `host` header for a request to the url
+pub(crate) fn host_header(url: &url::Url) -> &str {
+    if url.scheme() == UNIX_SCHEME {
+        "localhost"
+    } else {
+        url.host_str().expect("should be a valid url")
+    }
+}
+
+/// Resolves the `location` of a redirect. Relative redirects from a `unix:` url stay on the same
+/// socket
+pub(crate) fn join_location(url: &url::Url, location: &str) -> Option<url::Url> {
+    if url.scheme() != UNIX_SCHEME || url::Url::parse(location).is_ok() {
+        return url.join(location).ok();
+    }
+    let (socket, path) = split_unix_url(url);
+    let base = url::Url::parse("http://localhost").ok()?.join(path).ok()?;
+    let joined = base.join(location).ok()?;
+    let query = joined.query().map(|q| format!("?{q}")).unwrap_or_default();
+    url::Url::parse(&format!(
+        "{UNIX_SCHEME}://{socket}:{}{query}",
+        joined.path()
+    ))
+    .ok()
+}
+
+fn decode_socket_path(host: &str) -> Option<PathBuf> {
+    if !host.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..host.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(host.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_unix_urls() {
+        let checks = [
+            (
+                "unix:///var/run/app.sock:/status?verbose=1",
+                "unix://2f7661722f72756e2f6170702e736f636b/status?verbose=1",
+                "/var/run/app.sock",
+            ),
+            (
+                "unix:///tmp/app.sock",
+                "unix://2f746d702f6170702e736f636b/",
+                "/tmp/app.sock",
+            ),
+        ];
+        for (url, expected, socket) in checks.iter() {
+            let url = url::Url::parse(url).unwrap();
+            let uri = request_uri(&url);
+            assert_eq!(uri, *expected);
+            assert_eq!(host_header(&url), "localhost");
+            let uri: Uri = uri.parse().unwrap();
+            let path = uri.host().and_then(decode_socket_path);
+            assert_eq!(path, Some(PathBuf::from(socket)));
+        }
+
+        let url = url::Url::parse("https://localhost:8080/a?b=c").unwrap();
+        assert_eq!(request_uri(&url), "https://localhost:8080/a?b=c");
+        assert_eq!(host_header(&url), "localhost");
+
+        assert_eq!(decode_socket_path("2f7"), None);
+        assert_eq!(decode_socket_path("zz"), None);
+    }
+
+    #[test]
+    fn joins_unix_redirects() {
+        let url = url::Url::parse("unix:///tmp/app.sock:/a/b?c=d").unwrap();
+        let checks = [
+            ("/login?next=b", "unix:///tmp/app.sock:/login?next=b"),
+            ("c", "unix:///tmp/app.sock:/a/c"),
+            ("http://127.0.0.1/", "http://127.0.0.1/"),
+        ];
+        for (location, expected) in checks.iter() {
+            let joined = join_location(&url, location).unwrap();
+            assert_eq!(joined.as_str(), *expected);
+        }
+    }
+}
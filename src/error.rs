@@ -5,10 +5,13 @@ use std::{error::Error as StdError, fmt, path::PathBuf, sync::Arc, time::SystemT
 // An error that can happen in normal execution of an endpoint, but should not halt the test
 #[derive(Clone, Debug)]
 pub enum RecoverableError {
+    AssertionFailed(String),
+    AuthTokenErr(String),
     ProviderDelay(String),
     BodyErr(Arc<dyn StdError + Send + Sync>),
     ConnectionErr(SystemTime, Arc<dyn StdError + Send + Sync>),
     ExecutingExpression(Box<config::ExecutingExpressionError>),
+    SchemaViolation(String),
     Timeout(SystemTime),
 }
 
@@ -22,6 +25,9 @@ impl RecoverableError {
             ExecutingExpression(..) => 3,
             Timeout(_) => 4,
             ProviderDelay(_) => 5,
+            SchemaViolation(_) => 6,
+            AssertionFailed(_) => 7,
+            AuthTokenErr(_) => 8,
         }
     }
 }
@@ -29,10 +35,13 @@ impl RecoverableError {
 impl fmt::Display for RecoverableError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            AssertionFailed(name) => write!(f, "assertion `{name}` failed"),
+            AuthTokenErr(e) => write!(f, "could not get an auth token: {e}"),
             BodyErr(e) => write!(f, "body error: {e}"),
             ConnectionErr(_, e) => write!(f, "connection error: `{e}`"),
             ExecutingExpression(e) => e.fmt(f),
             ProviderDelay(p) => write!(f, "endpoint was delayed waiting for provider `{p}`"),
+            SchemaViolation(e) => write!(f, "response does not match the OpenAPI spec: {e}"),
             Timeout(..) => write!(f, "request timed out"),
         }
     }
@@ -41,12 +50,18 @@ impl fmt::Display for RecoverableError {
 // The types of errors that we may encounter during a test
 #[derive(Clone, Debug)]
 pub enum TestError {
+    AssertionLimit(String, usize),
     CannotCreateLoggerFile(String, Arc<std::io::Error>),
     CannotCreateStatsFile(String, Arc<std::io::Error>),
     CannotOpenFile(PathBuf, Arc<std::io::Error>),
     Config(Box<config::Error>),
+    EvalErr(String),
     FileReading(String, Arc<std::io::Error>),
+    GrpcMethodNotFound(String),
+    ImportErr(String),
     InvalidConfigFilePath(PathBuf),
+    InvalidGrpcDescriptor(PathBuf, Arc<prost_reflect::DescriptorError>),
+    InvalidOpenApiSpec(PathBuf, String),
     InvalidUrl(String),
     Recoverable(RecoverableError),
     RequestBuilderErr(Arc<HttpError>),
@@ -65,14 +80,24 @@ use TestError::*;
 impl fmt::Display for TestError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            AssertionLimit(name, count) => write!(f, "assertion `{name}` failed {count} times"),
             CannotCreateLoggerFile(s, e) => write!(f, "error creating logger file `{s}`: {e}"),
             CannotCreateStatsFile(s, e) => write!(f, "error creating stats file `{s}`: {e}"),
             CannotOpenFile(p, e) => write!(f, "error opening file `{}`: {}", p.display(), e),
             Config(e) => e.fmt(f),
+            EvalErr(e) => write!(f, "error evaluating expression: {e}"),
             FileReading(s, e) => write!(f, "error reading file `{s}`: {e}"),
+            GrpcMethodNotFound(m) => write!(f, "could not find grpc method `{m}` in descriptor"),
+            ImportErr(e) => write!(f, "error importing requests: {e}"),
             InvalidConfigFilePath(p) => {
                 write!(f, "could not find config file at path `{}`", p.display())
             }
+            InvalidGrpcDescriptor(p, e) => {
+                write!(f, "invalid grpc descriptor `{}`: {}", p.display(), e)
+            }
+            InvalidOpenApiSpec(p, e) => {
+                write!(f, "invalid OpenAPI spec `{}`: {}", p.display(), e)
+            }
             InvalidUrl(u) => write!(f, "invalid url `{u}`"),
             Recoverable(r) => write!(f, "recoverable error: {r}"),
             RequestBuilderErr(e) => write!(f, "error creating request: {e}"),
@@ -90,6 +115,7 @@ impl StdError for TestError {
             CannotOpenFile(_, e) => Some(&**e),
             Config(e) => Some(e),
             FileReading(_, e) => Some(&**e),
+            InvalidGrpcDescriptor(_, e) => Some(&**e),
             Recoverable(BodyErr(e)) => Some(&**e),
             Recoverable(ConnectionErr(_, e)) => Some(&**e),
             RequestBuilderErr(e) => Some(&**e),
@@ -103,7 +129,8 @@ impl StdError for TestError {
 impl From<config::Error> for TestError {
     fn from(ce: config::Error) -> Self {
         if let config::Error::ExpressionErr(config::CreatingExpressionError::Executing(
-            e @ config::ExecutingExpressionError::IndexingIntoJson(..),
+            e @ config::ExecutingExpressionError::IndexingIntoJson(..)
+            | e @ config::ExecutingExpressionError::InvalidOperand(..),
         )) = ce
         {
             Recoverable(ExecutingExpression(e.into()))
@@ -121,7 +148,10 @@ impl From<config::CreatingExpressionError> for TestError {
 
 impl From<config::ExecutingExpressionError> for TestError {
     fn from(e: config::ExecutingExpressionError) -> Self {
-        Config(Box::new(e.into()))
+        match e {
+            config::ExecutingExpressionError::InvalidOperand(..) => Recoverable(e.into()),
+            _ => Config(Box::new(e.into())),
+        }
     }
 }
 
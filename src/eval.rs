@@ -0,0 +1,99 @@
+use crate::error::TestError;
+use crate::EvalConfig;
+
+use serde_json as json;
+
+use std::{
+    io::{self, IsTerminal, Read},
+    path::Path,
+};
+
+/// Evaluates the expression of an `eval` against its data, read from a file or stdin, and
+/// returns the result as pretty printed JSON
+pub(crate) fn eval(config: &EvalConfig) -> Result<String, TestError> {
+    let data = match &config.data_file {
+        Some(path) if path != Path::new("-") => {
+            std::fs::read(path).map_err(|e| TestError::CannotOpenFile(path.clone(), e.into()))?
+        }
+        // without a file or anything piped in there is no data
+        _ if io::stdin().is_terminal() => Vec::new(),
+        _ => {
+            let mut bytes = Vec::new();
+            io::stdin()
+                .lock()
+                .read_to_end(&mut bytes)
+                .map_err(|e| TestError::FileReading("stdin".into(), e.into()))?;
+            bytes
+        }
+    };
+    evaluate(config, &data)
+}
+
+fn evaluate(config: &EvalConfig, data: &[u8]) -> Result<String, TestError> {
+    let data = if data.iter().all(u8::is_ascii_whitespace) {
+        json::json!({})
+    } else {
+        json::from_slice(data)
+            .map_err(|e| TestError::EvalErr(format!("data is not valid json: {e}")))?
+    };
+    let vars = config.vars.iter().cloned().collect();
+    let value = config::evaluate_expression(&config.expression, &data, vars)
+        .map_err(|e| TestError::EvalErr(e.to_string()))?;
+    let mut output = json::to_string_pretty(&value).expect("json should serialize");
+    output.push('\n');
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_config(expression: &str, vars: Vec<(&str, json::Value)>) -> EvalConfig {
+        EvalConfig {
+            expression: expression.into(),
+            data_file: None,
+            vars: vars.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+        }
+    }
+
+    #[test]
+    fn evaluates_against_data() {
+        let data = br#"{"response": {"status": 200, "body": {"items": [{"id": 1}, {"id": 2}]}}}"#;
+
+        let config = eval_config("response.body.items[1]", Vec::new());
+        assert_eq!(evaluate(&config, data).unwrap(), "{\n  \"id\": 2\n}\n");
+
+        let config = eval_config(
+            "response.status == expected",
+            vec![("expected", json::json!(200))],
+        );
+        assert_eq!(evaluate(&config, data).unwrap(), "true\n");
+
+        let config = eval_config("upper(name)", vec![("name", json::json!("pewpew"))]);
+        assert_eq!(evaluate(&config, b"\n").unwrap(), "\"PEWPEW\"\n");
+    }
+
+    #[test]
+    fn reports_errors() {
+        let config = eval_config("response.status", Vec::new());
+        let e = evaluate(&config, b"{").unwrap_err();
+        assert!(matches!(e, TestError::EvalErr(_)), "{}", e);
+
+        let config = eval_config("upper(response.body", Vec::new());
+        let e = evaluate(&config, b"{}").unwrap_err();
+        assert!(
+            e.to_string().contains("\n1 | upper(response.body\n"),
+            "{}",
+            e
+        );
+
+        let config = eval_config("response.status.code", Vec::new());
+        let e = evaluate(&config, br#"{"response": {"status": 200}}"#).unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with("error evaluating expression: cannot get `code` from `200`"),
+            "{}",
+            e
+        );
+    }
+}
@@ -0,0 +1,1701 @@
+use crate::connector::split_unix_url;
+use crate::error::TestError;
+use crate::openapi::{parse_document, resolve, server_url, METHODS};
+use crate::ImportFormat;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json as json;
+use yaml_rust2::{yaml::Hash, Yaml, YamlEmitter};
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+// headers which are set by pewpew, or would make the responses unreadable
+const SKIPPED_HEADERS: &[&str] = &[
+    "accept-encoding",
+    "connection",
+    "content-length",
+    "host",
+    "transfer-encoding",
+];
+
+// names which can't be used for a provider because they have a special meaning in expressions
+const RESERVED_NAMES: &[&str] = &["auth", "error", "for_each", "request", "response", "stats"];
+
+// short curl flags which take a value
+const SHORT_VALUE_FLAGS: &str = "ACDEFHKPQTUXYbcdemortuwxyz";
+
+// the long curl flags which take a value, other than the ones mapped to a short flag. Any long flag
+// not listed here or mapped to a short flag is a switch, so the argument after it isn't skipped
+const LONG_VALUE_FLAGS: &[&str] = &[
+    "abstract-unix-socket",
+    "alt-svc",
+    "aws-sigv4",
+    "cacert",
+    "capath",
+    "cert",
+    "cert-type",
+    "ciphers",
+    "connect-timeout",
+    "connect-to",
+    "continue-at",
+    "cookie-jar",
+    "create-file-mode",
+    "crlfile",
+    "curves",
+    "delegation",
+    "dns-interface",
+    "dns-ipv4-addr",
+    "dns-ipv6-addr",
+    "dns-servers",
+    "doh-url",
+    "dump-header",
+    "ech",
+    "egd-file",
+    "engine",
+    "etag-compare",
+    "etag-save",
+    "expect100-timeout",
+    "form-string",
+    "ftp-account",
+    "ftp-alternative-to-user",
+    "ftp-method",
+    "ftp-port",
+    "ftp-ssl-ccc-mode",
+    "happy-eyeballs-timeout-ms",
+    "haproxy-clientip",
+    "hostpubmd5",
+    "hostpubsha256",
+    "hsts",
+    "interface",
+    "ip-tos",
+    "ipfs-gateway",
+    "keepalive-cnt",
+    "keepalive-time",
+    "key",
+    "key-type",
+    "krb",
+    "libcurl",
+    "limit-rate",
+    "local-port",
+    "login-options",
+    "mail-auth",
+    "mail-from",
+    "mail-rcpt",
+    "max-filesize",
+    "max-redirs",
+    "max-time",
+    "netrc-file",
+    "noproxy",
+    "oauth2-bearer",
+    "output",
+    "output-dir",
+    "parallel-max",
+    "pass",
+    "pinnedpubkey",
+    "preproxy",
+    "proto",
+    "proto-default",
+    "proto-redir",
+    "proxy",
+    "proxy-cacert",
+    "proxy-capath",
+    "proxy-cert",
+    "proxy-cert-type",
+    "proxy-ciphers",
+    "proxy-crlfile",
+    "proxy-header",
+    "proxy-key",
+    "proxy-key-type",
+    "proxy-pass",
+    "proxy-pinnedpubkey",
+    "proxy-service-name",
+    "proxy-tls13-ciphers",
+    "proxy-tlsauthtype",
+    "proxy-tlspassword",
+    "proxy-tlsuser",
+    "proxy-user",
+    "proxy1.0",
+    "pubkey",
+    "quote",
+    "random-file",
+    "range",
+    "rate",
+    "request-target",
+    "resolve",
+    "retry",
+    "retry-delay",
+    "retry-max-time",
+    "sasl-authzid",
+    "service-name",
+    "socks4",
+    "socks4a",
+    "socks5",
+    "socks5-gssapi-service",
+    "socks5-hostname",
+    "speed-limit",
+    "speed-time",
+    "stderr",
+    "telnet-option",
+    "tftp-blksize",
+    "time-cond",
+    "tls-max",
+    "tls13-ciphers",
+    "tlsauthtype",
+    "tlspassword",
+    "tlsuser",
+    "trace",
+    "trace-ascii",
+    "trace-config",
+    "unix-socket",
+    "url-query",
+    "variable",
+    "vlan-priority",
+    "write-out",
+];
+
+#[derive(Debug, Default, PartialEq)]
+struct ImportedRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+    sign: Option<AwsSigv4>,
+    response: Option<json::Value>,
+}
+
+// the `aws_sigv4` signing for a request, from curl's `--aws-sigv4` and `--user`
+#[derive(Debug, PartialEq)]
+struct AwsSigv4 {
+    service: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl ImportedRequest {
+    fn has_header(&self, name: &str) -> bool {
+        self.headers
+            .iter()
+            .any(|(n, _)| n.eq_ignore_ascii_case(name))
+    }
+}
+
+// a value from a response which is sent again in a later request
+#[derive(Debug, PartialEq)]
+struct DynamicValue {
+    name: String,
+    value: String,
+    select: String,
+    endpoint: usize,
+}
+
+/// Creates a config file from the requests in a HAR file or a list of curl commands, or from the
+/// operations in an OpenAPI document. Values from JSON responses which are sent in later requests
+/// become response providers
+pub(crate) fn import(
+    input: &[u8],
+    format: Option<ImportFormat>,
+    input_file: &Path,
+) -> Result<String, TestError> {
+    let input = std::str::from_utf8(input)
+        .map_err(|e| TestError::ImportErr(format!("input is not valid utf-8: {e}")))?;
+    let format = format.unwrap_or_else(|| detect_format(input));
+    let requests = match format {
+        ImportFormat::Har => from_har(input),
+        ImportFormat::Curl => from_curl(input),
+        ImportFormat::Openapi => {
+            // the config is expected to be next to the document
+            let spec = input_file
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            return from_openapi(input, &spec).map_err(TestError::ImportErr);
+        }
+    }
+    .map_err(TestError::ImportErr)?;
+    if requests.is_empty() {
+        return Err(TestError::ImportErr("no requests were found".into()));
+    }
+    let dynamic_values = find_dynamic_values(&requests);
+    to_yaml(&requests, &dynamic_values).map_err(TestError::ImportErr)
+}
+
+fn detect_format(input: &str) -> ImportFormat {
+    if input.trim_start().starts_with('{') {
+        let is_openapi =
+            json::from_str::<json::Value>(input).is_ok_and(|doc| doc.get("openapi").is_some());
+        if is_openapi {
+            ImportFormat::Openapi
+        } else {
+            ImportFormat::Har
+        }
+    } else if input.lines().any(|line| line.starts_with("openapi:")) {
+        ImportFormat::Openapi
+    } else {
+        ImportFormat::Curl
+    }
+}
+
+fn from_har(input: &str) -> Result<Vec<ImportedRequest>, String> {
+    let har: json::Value = json::from_str(input).map_err(|e| format!("invalid HAR file: {e}"))?;
+    let entries = har
+        .pointer("/log/entries")
+        .and_then(json::Value::as_array)
+        .ok_or("invalid HAR file: missing `log.entries`")?;
+    let mut requests = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let request = &entry["request"];
+        let (method, url) = match (request["method"].as_str(), request["url"].as_str()) {
+            (Some(method), Some(url)) => (method, url),
+            _ => {
+                return Err(format!(
+                    "HAR entry {i} is missing the request method or url"
+                ))
+            }
+        };
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            continue;
+        }
+        let mut imported = ImportedRequest {
+            method: method.to_uppercase(),
+            url: url.into(),
+            ..Default::default()
+        };
+        for header in request["headers"].as_array().into_iter().flatten() {
+            if let (Some(name), Some(value)) = (header["name"].as_str(), header["value"].as_str()) {
+                add_header(&mut imported.headers, name, value);
+            }
+        }
+        let post_data = &request["postData"];
+        imported.body = match (post_data["text"].as_str(), post_data["params"].as_array()) {
+            (Some(text), _) => Some(text.into()),
+            (None, Some(params)) => {
+                let mut form = url::form_urlencoded::Serializer::new(String::new());
+                for param in params {
+                    form.append_pair(
+                        param["name"].as_str().unwrap_or_default(),
+                        param["value"].as_str().unwrap_or_default(),
+                    );
+                }
+                Some(form.finish())
+            }
+            _ => None,
+        };
+        if let Some(mime_type) = post_data["mimeType"].as_str() {
+            if imported.body.is_some() && !imported.has_header("content-type") {
+                add_header(&mut imported.headers, "content-type", mime_type);
+            }
+        }
+        let content = &entry["response"]["content"];
+        imported.response = content["text"].as_str().and_then(|text| {
+            if content["encoding"].as_str() == Some("base64") {
+                let bytes = STANDARD.decode(text).ok()?;
+                json::from_slice(&bytes).ok()
+            } else {
+                json::from_str(text).ok()
+            }
+        });
+        requests.push(imported);
+    }
+    Ok(requests)
+}
+
+fn add_header(headers: &mut Vec<(String, String)>, name: &str, value: &str) {
+    if name.starts_with(':') || SKIPPED_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+        return;
+    }
+    match headers
+        .iter_mut()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+    {
+        Some((n, v)) => {
+            let separator = if n.eq_ignore_ascii_case("cookie") {
+                "; "
+            } else {
+                ", "
+            };
+            v.push_str(separator);
+            v.push_str(value);
+        }
+        None => headers.push((name.into(), value.into())),
+    }
+}
+
+fn from_curl(input: &str) -> Result<Vec<ImportedRequest>, String> {
+    split_commands(input)?
+        .iter()
+        .enumerate()
+        .map(|(i, args)| parse_curl(args).map_err(|e| format!("curl command {}: {}", i + 1, e)))
+        .collect()
+}
+
+// splits the input into the words of each command the way a shell would. Commands end at a
+// newline which isn't escaped or quoted
+fn split_commands(input: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\n') => (),
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => (),
+            },
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => w.push(c),
+                        None => return Err("unterminated `'`".into()),
+                    }
+                }
+            }
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => w.push(c),
+                            Some('\n') => (),
+                            Some(c) => {
+                                w.push('\\');
+                                w.push(c);
+                            }
+                            None => return Err("unterminated `\"`".into()),
+                        },
+                        Some(c) => w.push(c),
+                        None => return Err("unterminated `\"`".into()),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => w.push('\n'),
+                            Some('r') => w.push('\r'),
+                            Some('t') => w.push('\t'),
+                            Some(c @ ('\\' | '\'' | '"')) => w.push(c),
+                            Some(c) => {
+                                w.push('\\');
+                                w.push(c);
+                            }
+                            None => return Err("unterminated `$'`".into()),
+                        },
+                        Some(c) => w.push(c),
+                        None => return Err("unterminated `$'`".into()),
+                    }
+                }
+            }
+            '#' if word.is_none() => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            '\n' => {
+                words.extend(word.take());
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word.take());
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    Ok(commands)
+}
+
+fn parse_curl(args: &[String]) -> Result<ImportedRequest, String> {
+    match args.first() {
+        Some(curl) if curl == "curl" => (),
+        _ => return Err("expected the command to start with `curl`".into()),
+    }
+    let mut request = ImportedRequest::default();
+    let mut method = None;
+    let mut url = None;
+    let mut data: Vec<String> = Vec::new();
+    let mut json_data = false;
+    let mut get = false;
+    let mut head = false;
+    let mut user = None;
+    let mut aws_sigv4 = None;
+    let mut unix_socket = None;
+    let mut url_query: Vec<String> = Vec::new();
+
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        let mut take_value = |flag: &str, attached: Option<&str>| {
+            attached
+                .map(String::from)
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| format!("missing value for `{flag}`"))
+        };
+        let (flag, value) = if let Some(long) = arg.strip_prefix("--") {
+            let (name, attached) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let flag = match name {
+                "request" => 'X',
+                "header" => 'H',
+                "data" | "data-ascii" | "data-binary" => 'd',
+                "data-raw" => '1',
+                "data-urlencode" => '2',
+                "json" => '3',
+                "cookie" => 'b',
+                "user" => 'u',
+                "user-agent" => 'A',
+                "referer" => 'e',
+                "url" => '4',
+                "form" => 'F',
+                "upload-file" => 'T',
+                "config" => 'K',
+                "unix-socket" => '5',
+                "oauth2-bearer" => '6',
+                "aws-sigv4" => '7',
+                "url-query" => '8',
+                "abstract-unix-socket" | "form-string" | "request-target" => {
+                    return Err(format!("`--{name}` is not supported"))
+                }
+                "get" => {
+                    get = true;
+                    continue;
+                }
+                "head" => {
+                    head = true;
+                    continue;
+                }
+                // the rest of the flags which take a value don't change the request
+                _ if LONG_VALUE_FLAGS.contains(&name) => {
+                    take_value(arg, attached)?;
+                    continue;
+                }
+                // every other long flag is a switch which doesn't change the request
+                _ => continue,
+            };
+            (flag, take_value(arg, attached)?)
+        } else if let Some(short) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            // short flags can be combined, like `-sSX POST`, and only the last can take a value
+            let mut value_flag = None;
+            for (i, c) in short.char_indices() {
+                if SHORT_VALUE_FLAGS.contains(c) {
+                    let attached = Some(&short[i + 1..]).filter(|s| !s.is_empty());
+                    value_flag = Some((c, attached));
+                    break;
+                }
+                match c {
+                    'G' => get = true,
+                    'I' => head = true,
+                    _ => (),
+                }
+            }
+            match value_flag {
+                Some((c, attached)) => (c, take_value(&format!("-{c}"), attached)?),
+                None => continue,
+            }
+        } else {
+            if url.is_some() {
+                return Err(format!("unexpected argument `{arg}`"));
+            }
+            url = Some(arg.clone());
+            continue;
+        };
+
+        match flag {
+            'X' => method = Some(value.to_uppercase()),
+            'H' => match value.split_once(':') {
+                Some((name, v)) if !v.trim().is_empty() => {
+                    add_header(&mut request.headers, name.trim(), v.trim())
+                }
+                // `name:` removes a header and `name;` sends it empty
+                Some(_) => (),
+                None => match value.strip_suffix(';') {
+                    Some(name) => add_header(&mut request.headers, name.trim(), ""),
+                    None => return Err(format!("invalid header `{value}`")),
+                },
+            },
+            'd' if value.starts_with('@') => {
+                return Err("reading data from a file is not supported".into())
+            }
+            'd' | '1' => data.push(value),
+            '2' => data.push(urlencode_data(&value)),
+            '3' => {
+                json_data = true;
+                data.push(value);
+            }
+            'b' if value.contains('=') => add_header(&mut request.headers, "cookie", &value),
+            'b' => return Err("reading cookies from a file is not supported".into()),
+            // the credentials are for `--aws-sigv4` when it's given, so they're used after the loop
+            'u' => user = Some(value),
+            'A' => add_header(&mut request.headers, "user-agent", &value),
+            'e' => add_header(&mut request.headers, "referer", &value),
+            '4' => url = Some(value),
+            'F' => return Err("`--form` is not supported".into()),
+            'T' => return Err("`--upload-file` is not supported".into()),
+            'K' => return Err("`--config` is not supported".into()),
+            '5' => unix_socket = Some(value),
+            '6' => add_header(
+                &mut request.headers,
+                "authorization",
+                &format!("Bearer {value}"),
+            ),
+            '7' => aws_sigv4 = Some(value),
+            // a `+` prefix means the value is already encoded
+            '8' => match value.strip_prefix('+') {
+                Some(value) => url_query.push(value.into()),
+                None => url_query.push(urlencode_data(&value)),
+            },
+            // the rest of the flags which take a value don't change the request
+            _ => (),
+        }
+    }
+
+    let mut url = url.ok_or("missing the url")?;
+    if !url.contains("://") {
+        url = format!("http://{url}");
+    }
+    let sign_credentials = match (&aws_sigv4, user) {
+        (Some(_), None) => return Err("`--aws-sigv4` needs the credentials from `--user`".into()),
+        (Some(_), user) => user,
+        (None, Some(user)) => {
+            let credentials = STANDARD.encode(user.as_bytes());
+            add_header(
+                &mut request.headers,
+                "authorization",
+                &format!("Basic {credentials}"),
+            );
+            None
+        }
+        (None, None) => None,
+    };
+    if get {
+        url_query.append(&mut data);
+    }
+    if !url_query.is_empty() {
+        let separator = if url.contains('?') { '&' } else { '?' };
+        url = format!("{url}{separator}{}", url_query.join("&"));
+    }
+    if !data.is_empty() {
+        let content_type = if json_data {
+            "application/json"
+        } else {
+            "application/x-www-form-urlencoded"
+        };
+        if !request.has_header("content-type") {
+            add_header(&mut request.headers, "content-type", content_type);
+        }
+        if json_data && !request.has_header("accept") {
+            add_header(&mut request.headers, "accept", "application/json");
+        }
+        request.body = Some(data.join("&"));
+    }
+    let parsed = url::Url::parse(&url).map_err(|e| format!("invalid url `{url}`: {e}"))?;
+    if let Some((aws_sigv4, user)) = aws_sigv4.zip(sign_credentials) {
+        request.sign = Some(parse_aws_sigv4(&aws_sigv4, &user, &parsed)?);
+    }
+    if let Some(socket) = unix_socket {
+        // the request is sent over the socket with the path and query from the url
+        if !socket.starts_with('/') {
+            return Err(format!(
+                "the `--unix-socket` path `{socket}` must be absolute"
+            ));
+        }
+        let query = parsed.query().map(|q| format!("?{q}")).unwrap_or_default();
+        url = format!("unix://{socket}:{}{query}", parsed.path());
+        url::Url::parse(&url).map_err(|e| format!("invalid url `{url}`: {e}"))?;
+    }
+    request.method = match (method, head, &request.body) {
+        (Some(method), ..) => method,
+        (None, true, _) => "HEAD".into(),
+        (None, false, Some(_)) => "POST".into(),
+        (None, false, None) => "GET".into(),
+    };
+    request.url = url;
+    Ok(request)
+}
+
+// url encodes the content of a `--data-urlencode` or `--url-query` value, keeping a `name=` prefix
+fn urlencode_data(value: &str) -> String {
+    let encode =
+        |s: &str| -> String { url::form_urlencoded::byte_serialize(s.as_bytes()).collect() };
+    match value.split_once('=') {
+        Some((name, content)) => format!("{name}={}", encode(content)),
+        None => encode(value),
+    }
+}
+
+// parses curl's `--aws-sigv4 provider1[:provider2[:region[:service]]]` with the `--user` credentials.
+// Like curl, a missing region or service comes from a `service.region.amazonaws.com` host
+fn parse_aws_sigv4(value: &str, user: &str, url: &url::Url) -> Result<AwsSigv4, String> {
+    let mut parts = value.split(':');
+    if parts.next() != Some("aws") {
+        return Err(format!(
+            "only the `aws` provider of `--aws-sigv4` is supported, not `{value}`"
+        ));
+    }
+    let mut region = parts.nth(1).map(String::from);
+    let mut service = parts.next().map(String::from);
+    let (access_key_id, secret_access_key) = user
+        .split_once(':')
+        .filter(|(id, secret)| !id.is_empty() && !secret.is_empty())
+        .ok_or("`--user` should be `access_key_id:secret_access_key` for `--aws-sigv4`")?;
+    let mut host = url.host_str().unwrap_or_default().split('.');
+    service = service.or_else(|| host.next().map(String::from));
+    region = region.or_else(|| host.next().map(String::from));
+    match (service, region) {
+        (Some(service), Some(region)) if !service.is_empty() && !region.is_empty() => {
+            Ok(AwsSigv4 {
+                service,
+                region,
+                access_key_id: access_key_id.into(),
+                secret_access_key: secret_access_key.into(),
+            })
+        }
+        _ => Err(format!(
+            "could not find the region and service for `--aws-sigv4 {value}`"
+        )),
+    }
+}
+
+// finds the values from JSON responses which are sent in a later request
+fn find_dynamic_values(requests: &[ImportedRequest]) -> Vec<DynamicValue> {
+    let mut candidates = Vec::new();
+    let mut seen = BTreeSet::new();
+    for (i, request) in requests.iter().enumerate() {
+        let mut found = Vec::new();
+        if let Some(response) = &request.response {
+            collect_candidates(response, &mut Vec::new(), &mut found);
+        }
+        for (value, path) in found {
+            // values which were sent before this response can't have come from it
+            let sent_before = requests[..=i].iter().any(|r| request_contains(r, &value));
+            if seen.insert(value.clone()) && !sent_before {
+                candidates.push((value, path, i));
+            }
+        }
+    }
+
+    let mut names = BTreeSet::new();
+    candidates
+        .into_iter()
+        .filter(|(value, _, i)| requests[i + 1..].iter().any(|r| request_contains(r, value)))
+        .map(|(value, path, endpoint)| {
+            let mut select = String::from("response.body");
+            for key in &path {
+                match key {
+                    json::Value::String(k) if is_identifier(k) => {
+                        select.push('.');
+                        select.push_str(k);
+                    }
+                    _ => select.push_str(&format!("[{key}]")),
+                }
+            }
+            DynamicValue {
+                name: unique_name(&path, &mut names),
+                value,
+                select,
+                endpoint,
+            }
+        })
+        .collect()
+}
+
+// the path has the keys of objects as strings and the indexes of arrays as numbers
+fn collect_candidates(
+    value: &json::Value,
+    path: &mut Vec<json::Value>,
+    found: &mut Vec<(String, Vec<json::Value>)>,
+) {
+    match value {
+        json::Value::Object(map) => {
+            for (key, value) in map {
+                path.push(key.as_str().into());
+                collect_candidates(value, path, found);
+                path.pop();
+            }
+        }
+        json::Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                path.push(i.into());
+                collect_candidates(value, path, found);
+                path.pop();
+            }
+        }
+        json::Value::String(s) => {
+            let len = s.chars().count();
+            let looks_dynamic = len >= 20 || (len >= 6 && s.chars().any(|c| c.is_ascii_digit()));
+            if looks_dynamic && !s.chars().any(char::is_whitespace) {
+                found.push((s.clone(), path.clone()));
+            }
+        }
+        json::Value::Number(n) if n.as_u64().is_some_and(|n| n >= 1000) => {
+            found.push((n.to_string(), path.clone()));
+        }
+        _ => (),
+    }
+}
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+// names the provider after the closest key which isn't an array index, in snake case
+fn unique_name(path: &[json::Value], names: &mut BTreeSet<String>) -> String {
+    let key = path
+        .iter()
+        .rev()
+        .find_map(json::Value::as_str)
+        .unwrap_or_default();
+    let mut base = String::new();
+    let mut previous = None;
+    for c in key.chars() {
+        let after_lowercase =
+            previous.is_some_and(|p: char| p.is_ascii_lowercase() || p.is_ascii_digit());
+        if c.is_ascii_uppercase() && after_lowercase {
+            base.push('_');
+        }
+        if c.is_ascii_alphanumeric() {
+            base.push(c.to_ascii_lowercase());
+        } else if !base.ends_with('_') {
+            base.push('_');
+        }
+        previous = Some(c);
+    }
+    let mut base = base.trim_matches('_').to_string();
+    if base.is_empty() {
+        base = "value".into();
+    } else if base.starts_with(|c: char| c.is_ascii_digit()) {
+        base = format!("v_{base}");
+    } else if RESERVED_NAMES.contains(&base.as_str()) {
+        base = format!("{base}_value");
+    }
+    let mut name = base.clone();
+    let mut n = 2;
+    while !names.insert(name.clone()) {
+        name = format!("{base}_{n}");
+        n += 1;
+    }
+    name
+}
+
+fn request_contains(request: &ImportedRequest, value: &str) -> bool {
+    let contains = |s: &str| find_value(s, value).next().is_some();
+    contains(&request.url)
+        || request.headers.iter().any(|(_, v)| contains(v))
+        || request.body.as_deref().is_some_and(contains)
+}
+
+// the positions of the value in the text, where it isn't part of a longer word
+fn find_value<'a>(text: &'a str, value: &'a str) -> impl Iterator<Item = usize> + 'a {
+    text.match_indices(value).filter_map(move |(i, _)| {
+        let before = text[..i].chars().next_back();
+        let after = text[i + value.len()..].chars().next();
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+        (!is_word(before) && !is_word(after)).then_some(i)
+    })
+}
+
+// replaces the values in the text, with the longest values replaced first so a value which is
+// part of another isn't replaced inside of it
+fn replace_values(
+    text: &str,
+    values: &[&DynamicValue],
+    replace: impl Fn(&str) -> String,
+) -> String {
+    let mut values = values.to_vec();
+    values.sort_by_key(|v| std::cmp::Reverse(v.value.len()));
+    let mut replacements: BTreeMap<usize, (usize, &str)> = BTreeMap::new();
+    for v in values {
+        for start in find_value(text, &v.value) {
+            let end = start + v.value.len();
+            let overlaps = replacements
+                .range(..end)
+                .next_back()
+                .is_some_and(|(s, (e, _))| *e > start && *s < end);
+            if !overlaps {
+                replacements.insert(start, (end, &v.name));
+            }
+        }
+    }
+    let mut replaced = String::new();
+    let mut last = 0;
+    for (start, (end, name)) in replacements {
+        replaced.push_str(&text[last..start]);
+        replaced.push_str(&replace(name));
+        last = end;
+    }
+    replaced.push_str(&text[last..]);
+    replaced
+}
+
+fn to_yaml(
+    requests: &[ImportedRequest],
+    dynamic_values: &[DynamicValue],
+) -> Result<String, String> {
+    let string = |s: &str| Yaml::String(s.into());
+    let template = |name: &str| format!("${{{name}}}");
+
+    let mut config = Hash::new();
+    config.insert(string("load_pattern"), load_pattern());
+
+    if !dynamic_values.is_empty() {
+        let mut providers = Hash::new();
+        for v in dynamic_values {
+            let mut response = Hash::new();
+            response.insert(string("auto_return"), string("if_not_full"));
+            let mut provider = Hash::new();
+            provider.insert(string("response"), Yaml::Hash(response));
+            providers.insert(string(&v.name), Yaml::Hash(provider));
+        }
+        config.insert(string("providers"), Yaml::Hash(providers));
+    }
+
+    let mut endpoints = Vec::new();
+    for (i, request) in requests.iter().enumerate() {
+        // only the values from earlier responses are replaced
+        let values: Vec<_> = dynamic_values.iter().filter(|v| v.endpoint < i).collect();
+        let mut endpoint = Hash::new();
+        if request.method != "GET" {
+            endpoint.insert(string("method"), string(&request.method));
+        }
+        endpoint.insert(
+            string("url"),
+            Yaml::String(replace_values(&request.url, &values, template)),
+        );
+        if !request.headers.is_empty() {
+            let mut headers = Hash::new();
+            for (name, value) in &request.headers {
+                headers.insert(
+                    string(name),
+                    Yaml::String(replace_values(value, &values, template)),
+                );
+            }
+            endpoint.insert(string("headers"), Yaml::Hash(headers));
+        }
+        if let Some(body) = &request.body {
+            endpoint.insert(
+                string("body"),
+                Yaml::String(replace_values(body, &values, template)),
+            );
+        }
+        if let Some(sign) = &request.sign {
+            let mut aws_sigv4 = Hash::new();
+            aws_sigv4.insert(string("service"), string(&sign.service));
+            aws_sigv4.insert(string("region"), string(&sign.region));
+            aws_sigv4.insert(string("access_key_id"), string(&sign.access_key_id));
+            aws_sigv4.insert(string("secret_access_key"), string(&sign.secret_access_key));
+            let mut sign = Hash::new();
+            sign.insert(string("aws_sigv4"), Yaml::Hash(aws_sigv4));
+            endpoint.insert(string("sign"), Yaml::Hash(sign));
+        }
+        let path = url::Url::parse(&request.url)
+            .map(|u| split_unix_url(&u).1.to_string())
+            .unwrap_or_default();
+        let mut tags = Hash::new();
+        tags.insert(
+            string("name"),
+            Yaml::String(format!(
+                "{} {}",
+                request.method,
+                replace_values(&path, &values, |_| "*".into())
+            )),
+        );
+        endpoint.insert(string("tags"), Yaml::Hash(tags));
+
+        let provides: Vec<_> = dynamic_values.iter().filter(|v| v.endpoint == i).collect();
+        if provides.is_empty() {
+            endpoint.insert(string("peak_load"), string("1hps"));
+        } else {
+            let mut provides_section = Hash::new();
+            for v in provides {
+                let mut provide = Hash::new();
+                provide.insert(string("select"), string(&v.select));
+                provides_section.insert(string(&v.name), Yaml::Hash(provide));
+            }
+            endpoint.insert(string("provides"), Yaml::Hash(provides_section));
+            // endpoints which provide values are only called when another endpoint needs them
+            endpoint.insert(string("on_demand"), Yaml::Boolean(true));
+        }
+        endpoints.push(Yaml::Hash(endpoint));
+    }
+    config.insert(string("endpoints"), Yaml::Array(endpoints));
+    emit(config)
+}
+
+// the generated values are only placeholders, so nested schemas stop after this depth
+const MAX_EXAMPLE_DEPTH: usize = 8;
+
+// creates an endpoint for each operation in an OpenAPI document, with a list provider for each of
+// the parameters sent with it
+fn from_openapi(input: &str, spec_file: &str) -> Result<String, String> {
+    let string = |s: &str| Yaml::String(s.into());
+
+    let doc = parse_document(input).map_err(|e| format!("invalid OpenAPI document: {e}"))?;
+    let version = doc["openapi"].as_str().unwrap_or_default();
+    if !version.starts_with('3') {
+        return Err("only OpenAPI 3 documents can be imported".into());
+    }
+    let paths = doc["paths"]
+        .as_object()
+        .ok_or("the OpenAPI document has no `paths`")?;
+
+    let mut base_url = doc["servers"]
+        .get(0)
+        .and_then(server_url)
+        .unwrap_or_default();
+    if !base_url.contains("://") {
+        base_url = format!("http://localhost/{}", base_url.trim_start_matches('/'));
+    }
+    let base_url = base_url.trim_end_matches('/');
+
+    let mut names = BTreeSet::new();
+    names.insert("base_url".to_string());
+    // parameters with the same name share a provider
+    let mut param_providers = BTreeMap::new();
+    let mut providers = Hash::new();
+    let mut endpoints = Vec::new();
+    for (path, item) in paths {
+        let item = resolve(&doc, item);
+        for method in METHODS {
+            let operation = match item.get(method) {
+                Some(o) => resolve(&doc, o),
+                None => continue,
+            };
+            let mut params: Vec<&json::Value> = Vec::new();
+            let declared = item["parameters"]
+                .as_array()
+                .into_iter()
+                .chain(operation["parameters"].as_array())
+                .flatten();
+            for param in declared {
+                let param = resolve(&doc, param);
+                // parameters on the operation replace the ones on the path item
+                params.retain(|p| p["name"] != param["name"] || p["in"] != param["in"]);
+                params.push(param);
+            }
+
+            let mut url = format!("${{base_url}}{path}");
+            let mut query = Vec::new();
+            let mut headers = Hash::new();
+            for param in params {
+                let name = param["name"].as_str().unwrap_or_default();
+                let location = param["in"].as_str().unwrap_or_default();
+                let required = param["required"].as_bool().unwrap_or_default();
+                let sent = match location {
+                    "path" => true,
+                    // these headers are described by other parts of the operation
+                    "header" => {
+                        required
+                            && !["accept", "authorization", "content-type"]
+                                .contains(&name.to_ascii_lowercase().as_str())
+                    }
+                    "query" => required,
+                    _ => false,
+                };
+                if name.is_empty() || !sent {
+                    continue;
+                }
+                let provider: &String = param_providers.entry(name).or_insert_with(|| {
+                    let provider = unique_name(&[name.into()], &mut names);
+                    let mut list = Hash::new();
+                    list.insert(
+                        string("list"),
+                        Yaml::Array(vec![json_to_yaml(param_example(&doc, param))]),
+                    );
+                    providers.insert(string(&provider), Yaml::Hash(list));
+                    provider
+                });
+                let template = format!("${{{provider}}}");
+                match location {
+                    "path" => url = url.replace(&format!("{{{name}}}"), &template),
+                    "query" => query.push(format!("{name}={template}")),
+                    _ => {
+                        headers.insert(string(name), Yaml::String(template));
+                    }
+                }
+            }
+            if !query.is_empty() {
+                url = format!("{url}?{}", query.join("&"));
+            }
+
+            let mut body = None;
+            let content = resolve(&doc, &operation["requestBody"])["content"].as_object();
+            let media = content.and_then(|content| {
+                content
+                    .iter()
+                    .find(|(content_type, _)| content_type.contains("json"))
+            });
+            if let Some((content_type, media)) = media {
+                let example = media.get("example").cloned().or_else(|| {
+                    let examples = media["examples"].as_object()?;
+                    let (_, example) = examples.iter().next()?;
+                    resolve(&doc, example).get("value").cloned()
+                });
+                let example = example.unwrap_or_else(|| schema_example(&doc, &media["schema"], 0));
+                headers.insert(string("content-type"), string(content_type));
+                body = Some(example.to_string());
+            }
+
+            let mut endpoint = Hash::new();
+            let method = method.to_ascii_uppercase();
+            if method != "GET" {
+                endpoint.insert(string("method"), string(&method));
+            }
+            endpoint.insert(string("url"), Yaml::String(url));
+            if !headers.is_empty() {
+                endpoint.insert(string("headers"), Yaml::Hash(headers));
+            }
+            if let Some(body) = body {
+                endpoint.insert(string("body"), Yaml::String(body));
+            }
+            let mut tags = Hash::new();
+            let mut tag_path = String::new();
+            for (i, segment) in path.split('/').enumerate() {
+                if i > 0 {
+                    tag_path.push('/');
+                }
+                if segment.starts_with('{') && segment.ends_with('}') {
+                    tag_path.push('*');
+                } else {
+                    tag_path.push_str(segment);
+                }
+            }
+            tags.insert(string("name"), Yaml::String(format!("{method} {tag_path}")));
+            endpoint.insert(string("tags"), Yaml::Hash(tags));
+            endpoint.insert(string("peak_load"), string("1hps"));
+            endpoints.push(Yaml::Hash(endpoint));
+        }
+    }
+    if endpoints.is_empty() {
+        return Err("the OpenAPI document has no operations".into());
+    }
+
+    let mut config = Hash::new();
+    let mut vars = Hash::new();
+    vars.insert(string("base_url"), string(base_url));
+    config.insert(string("vars"), Yaml::Hash(vars));
+    let mut openapi = Hash::new();
+    openapi.insert(string("spec"), string(spec_file));
+    config.insert(string("openapi"), Yaml::Hash(openapi));
+    config.insert(string("load_pattern"), load_pattern());
+    if !providers.is_empty() {
+        config.insert(string("providers"), Yaml::Hash(providers));
+    }
+    config.insert(string("endpoints"), Yaml::Array(endpoints));
+    emit(config)
+}
+
+fn param_example(doc: &json::Value, param: &json::Value) -> json::Value {
+    if let Some(example) = param.get("example") {
+        return example.clone();
+    }
+    let example = param["examples"]
+        .as_object()
+        .and_then(|examples| examples.values().next())
+        .and_then(|example| resolve(doc, example).get("value"));
+    match example {
+        Some(example) => example.clone(),
+        None => schema_example(doc, &param["schema"], 0),
+    }
+}
+
+// creates a value which matches the schema, preferring the examples in the document
+fn schema_example(doc: &json::Value, schema: &json::Value, depth: usize) -> json::Value {
+    let schema = resolve(doc, schema);
+    if depth > MAX_EXAMPLE_DEPTH {
+        return json::Value::Null;
+    }
+    for key in ["example", "default", "const"] {
+        if let Some(value) = schema.get(key) {
+            return value.clone();
+        }
+    }
+    if let Some(value) = schema["enum"].get(0) {
+        return value.clone();
+    }
+    if let Some(all_of) = schema["allOf"].as_array() {
+        let mut merged = json::Map::new();
+        for part in all_of {
+            match schema_example(doc, part, depth + 1) {
+                json::Value::Object(o) => merged.extend(o),
+                other => return other,
+            }
+        }
+        return merged.into();
+    }
+    let first_option = schema["oneOf"].get(0).or_else(|| schema["anyOf"].get(0));
+    if let Some(option) = first_option {
+        return schema_example(doc, option, depth + 1);
+    }
+    // 3.1 documents can list several types
+    let type_ = match &schema["type"] {
+        json::Value::Array(types) => types
+            .iter()
+            .find_map(|t| t.as_str().filter(|t| *t != "null")),
+        t => t.as_str(),
+    };
+    let type_ = type_.unwrap_or(if schema.get("properties").is_some() {
+        "object"
+    } else {
+        ""
+    });
+    match type_ {
+        "object" => schema["properties"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(name, property)| (name.clone(), schema_example(doc, property, depth + 1)))
+            .collect::<json::Map<_, _>>()
+            .into(),
+        "array" => vec![schema_example(doc, &schema["items"], depth + 1)].into(),
+        "integer" | "number" => 1.into(),
+        "boolean" => true.into(),
+        "string" => match schema["format"].as_str().unwrap_or_default() {
+            "date" => "2024-01-01".into(),
+            "date-time" => "2024-01-01T00:00:00Z".into(),
+            "email" => "user@example.com".into(),
+            "uuid" => "00000000-0000-0000-0000-000000000000".into(),
+            _ => "string".into(),
+        },
+        _ => json::Value::Null,
+    }
+}
+
+fn json_to_yaml(value: json::Value) -> Yaml {
+    match value {
+        json::Value::Null => Yaml::Null,
+        json::Value::Bool(b) => Yaml::Boolean(b),
+        json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        json::Value::String(s) => Yaml::String(s),
+        json::Value::Array(a) => Yaml::Array(a.into_iter().map(json_to_yaml).collect()),
+        json::Value::Object(o) => Yaml::Hash(
+            o.into_iter()
+                .map(|(k, v)| (Yaml::String(k), json_to_yaml(v)))
+                .collect(),
+        ),
+    }
+}
+
+// ramps up to the `peak_load` of the endpoints over five minutes
+fn load_pattern() -> Yaml {
+    let string = |s: &str| Yaml::String(s.into());
+    let mut linear = Hash::new();
+    linear.insert(string("to"), string("100%"));
+    linear.insert(string("over"), string("5m"));
+    let mut segment = Hash::new();
+    segment.insert(string("linear"), Yaml::Hash(linear));
+    Yaml::Array(vec![Yaml::Hash(segment)])
+}
+
+fn emit(config: Hash) -> Result<String, String> {
+    let mut out = String::new();
+    let mut emitter = YamlEmitter::new(&mut out);
+    emitter.multiline_strings(true);
+    emitter
+        .dump(&Yaml::Hash(config))
+        .map_err(|e| format!("could not create the config: {e}"))?;
+    let out = out.trim_start_matches("---").trim_start();
+    Ok(format!(
+        "# created by `pewpew import`. Check the load_pattern and peak_loads before running a load test\n{out}\n"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn args(s: &str) -> Vec<String> {
+        split_commands(s).unwrap().remove(0)
+    }
+
+    #[test]
+    fn splits_curl_commands() {
+        let input = r#"
+# log in
+curl -X POST 'http://localhost/login' \
+  -H "content-type: application/json" \
+  --data-raw $'{"name":"it\'s me"}'
+curl "http://localhost/a b" -H 'x-a: "quoted"'  # trailing comment
+"#;
+        let commands = split_commands(input).unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                vec![
+                    "curl",
+                    "-X",
+                    "POST",
+                    "http://localhost/login",
+                    "-H",
+                    "content-type: application/json",
+                    "--data-raw",
+                    r#"{"name":"it's me"}"#,
+                ],
+                vec!["curl", "http://localhost/a b", "-H", r#"x-a: "quoted""#],
+            ]
+        );
+
+        assert!(split_commands("curl 'http://localhost").is_err());
+        assert!(split_commands(r#"curl "http://localhost"#).is_err());
+    }
+
+    #[test]
+    fn parses_curl_flags() {
+        let request = parse_curl(&args(
+            "curl -sS localhost:8080/a --json '{\"a\":1}' -H 'Accept: text/plain' -u user:pass --compressed",
+        ))
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url, "http://localhost:8080/a");
+        assert_eq!(request.body.as_deref(), Some(r#"{"a":1}"#));
+        assert_eq!(
+            request.headers,
+            vec![
+                ("Accept".to_string(), "text/plain".to_string()),
+                ("authorization".into(), "Basic dXNlcjpwYXNz".into()),
+                ("content-type".into(), "application/json".into()),
+            ]
+        );
+
+        let request = parse_curl(&args(
+            "curl -G http://localhost/search?x=1 -d q=a --data-urlencode 'name=a b' -XHEAD -b a=1 --max-time 5",
+        ))
+        .unwrap();
+        assert_eq!(request.method, "HEAD");
+        assert_eq!(request.url, "http://localhost/search?x=1&q=a&name=a+b");
+        assert_eq!(request.body, None);
+        assert_eq!(request.headers, vec![("cookie".into(), "a=1".into())]);
+
+        let request = parse_curl(&args(
+            "curl --url=https://localhost/ -d a=1 -d b=2 -H 'Host: x'",
+        ))
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.body.as_deref(), Some("a=1&b=2"));
+        assert_eq!(
+            request.headers,
+            vec![(
+                "content-type".into(),
+                "application/x-www-form-urlencoded".into()
+            )]
+        );
+
+        let request = parse_curl(&args("curl -I http://localhost/")).unwrap();
+        assert_eq!(request.method, "HEAD");
+
+        // the values of options which don't change the request aren't taken as the url
+        let request = parse_curl(&args(
+            "curl --proxy-user u:p --interface eth0 --connect-to a:1:b:2 -E cert.pem -z yesterday \
+                --retry-delay 1 --compressed http://localhost/a --url-query 'q=a b' --url-query +r=%20",
+        ))
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.url, "http://localhost/a?q=a+b&r=%20");
+        assert_eq!(request.headers, vec![]);
+
+        let request = parse_curl(&args(
+            "curl --unix-socket /tmp/app.sock http://localhost/a?b=1 --oauth2-bearer abc",
+        ))
+        .unwrap();
+        assert_eq!(request.url, "unix:///tmp/app.sock:/a?b=1");
+        assert_eq!(
+            request.headers,
+            vec![("authorization".into(), "Bearer abc".into())]
+        );
+
+        let request = parse_curl(&args(
+            "curl https://abc.execute-api.us-east-1.amazonaws.com/prod --aws-sigv4 aws:amz:us-west-2:execute-api -u KEY:SECRET",
+        ))
+        .unwrap();
+        assert_eq!(request.headers, vec![]);
+        assert_eq!(
+            request.sign,
+            Some(AwsSigv4 {
+                service: "execute-api".into(),
+                region: "us-west-2".into(),
+                access_key_id: "KEY".into(),
+                secret_access_key: "SECRET".into(),
+            })
+        );
+
+        let request = parse_curl(&args(
+            "curl --aws-sigv4 aws:amz -u KEY:SECRET https://s3.eu-west-1.amazonaws.com/bucket",
+        ))
+        .unwrap();
+        let sign = request.sign.unwrap();
+        assert_eq!(
+            (sign.service.as_str(), sign.region.as_str()),
+            ("s3", "eu-west-1")
+        );
+
+        for command in [
+            "curl -F a=1 http://localhost/",
+            "curl --form-string a=1 http://localhost/",
+            "curl --unix-socket app.sock http://localhost/",
+            "curl --aws-sigv4 aws:amz:us-east-1:s3 http://localhost/",
+            "curl --aws-sigv4 aws:amz -u KEY:SECRET http://localhost/",
+            "curl --aws-sigv4 aws:amz:us-east-1:s3 -u KEY http://localhost/",
+            "curl -d @body.json http://localhost/",
+            "curl http://localhost/ -H",
+            "curl",
+            "wget http://localhost/",
+            "curl http://localhost/ http://localhost/b",
+        ] {
+            assert!(parse_curl(&args(command)).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn reads_har_files() {
+        let har = json::json!({
+            "log": {
+                "entries": [
+                    {
+                        "request": {
+                            "method": "post",
+                            "url": "https://localhost/login",
+                            "headers": [
+                                { "name": ":authority", "value": "localhost" },
+                                { "name": "accept-encoding", "value": "gzip" },
+                                { "name": "cookie", "value": "a=1" },
+                                { "name": "cookie", "value": "b=2" }
+                            ],
+                            "postData": {
+                                "mimeType": "application/x-www-form-urlencoded",
+                                "params": [{ "name": "user", "value": "a b" }]
+                            }
+                        },
+                        "response": {
+                            "content": {
+                                "text": STANDARD.encode(r#"{"token":"abc"}"#),
+                                "encoding": "base64"
+                            }
+                        }
+                    },
+                    {
+                        "request": { "method": "GET", "url": "data:text/plain,abc", "headers": [] },
+                        "response": {}
+                    },
+                    {
+                        "request": { "method": "GET", "url": "http://localhost/me" },
+                        "response": { "content": { "text": "not json" } }
+                    }
+                ]
+            }
+        });
+        let requests = from_har(&har.to_string()).unwrap();
+        assert_eq!(
+            requests,
+            vec![
+                ImportedRequest {
+                    method: "POST".into(),
+                    url: "https://localhost/login".into(),
+                    headers: vec![
+                        ("cookie".into(), "a=1; b=2".into()),
+                        (
+                            "content-type".into(),
+                            "application/x-www-form-urlencoded".into()
+                        ),
+                    ],
+                    body: Some("user=a+b".into()),
+                    sign: None,
+                    response: Some(json::json!({ "token": "abc" })),
+                },
+                ImportedRequest {
+                    method: "GET".into(),
+                    url: "http://localhost/me".into(),
+                    ..Default::default()
+                },
+            ]
+        );
+
+        assert!(from_har("{}").is_err());
+        assert!(from_har(r#"{"log":{"entries":[{"request":{}}]}}"#).is_err());
+    }
+
+    #[test]
+    fn finds_dynamic_values() {
+        let request =
+            |url: &str, body: Option<&str>, response: Option<json::Value>| ImportedRequest {
+                method: "GET".into(),
+                url: url.into(),
+                body: body.map(Into::into),
+                response,
+                ..Default::default()
+            };
+        let mut requests = vec![
+            request(
+                "http://localhost/login",
+                Some("user=a1b2c3"),
+                Some(json::json!({
+                    "accessToken": "eyJhbGciOiJIUzI1NiJ9",
+                    "user": { "id": 123456, "name": "a1b2c3" },
+                    "items": [{ "id": "item-2000" }, { "id": "unused-1" }],
+                    "other key": "abcdef1",
+                })),
+            ),
+            request("http://localhost/users/123456", None, None),
+            request(
+                "http://localhost/items/item-2000?b=abcdef1",
+                Some(r#"{"id":123456,"ids":[1234567]}"#),
+                None,
+            ),
+        ];
+        requests[1]
+            .headers
+            .push(("authorization".into(), "Bearer eyJhbGciOiJIUzI1NiJ9".into()));
+
+        let values = find_dynamic_values(&requests);
+        let found: Vec<_> = values
+            .iter()
+            .map(|v| {
+                (
+                    v.name.as_str(),
+                    v.value.as_str(),
+                    v.select.as_str(),
+                    v.endpoint,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "access_token",
+                    "eyJhbGciOiJIUzI1NiJ9",
+                    "response.body.accessToken",
+                    0
+                ),
+                ("id", "123456", "response.body.user.id", 0),
+                ("id_2", "item-2000", "response.body.items[0].id", 0),
+                ("other_key", "abcdef1", r#"response.body["other key"]"#, 0),
+            ]
+        );
+
+        let refs: Vec<_> = values.iter().collect();
+        assert_eq!(
+            replace_values(&requests[2].url, &refs, |n| format!("${{{n}}}")),
+            "http://localhost/items/${id_2}?b=${other_key}"
+        );
+        assert_eq!(
+            replace_values(requests[2].body.as_ref().unwrap(), &refs, |n| format!(
+                "${{{n}}}"
+            )),
+            r#"{"id":${id},"ids":[1234567]}"#
+        );
+    }
+
+    #[test]
+    fn creates_configs() {
+        let input = r#"
+curl -X POST http://localhost/login -d 'user=a'
+curl -H 'authorization: Bearer token-123' http://localhost/items/item-123/details
+"#;
+        let mut requests = from_curl(input).unwrap();
+        requests[0].response = Some(json::json!({ "token": "token-123", "item": "item-123" }));
+        let values = find_dynamic_values(&requests);
+        let yaml = to_yaml(&requests, &values).unwrap();
+        assert_eq!(
+            yaml,
+            r#"# created by `pewpew import`. Check the load_pattern and peak_loads before running a load test
+load_pattern:
+  - linear:
+      to: 100%
+      over: 5m
+providers:
+  token:
+    response:
+      auto_return: if_not_full
+  item:
+    response:
+      auto_return: if_not_full
+endpoints:
+  - method: POST
+    url: "http://localhost/login"
+    headers:
+      content-type: application/x-www-form-urlencoded
+    body: user=a
+    tags:
+      name: POST /login
+    provides:
+      token:
+        select: response.body.token
+      item:
+        select: response.body.item
+    on_demand: true
+  - url: "http://localhost/items/${item}/details"
+    headers:
+      authorization: "Bearer ${token}"
+    tags:
+      name: GET /items/*/details
+    peak_load: 1hps
+"#
+        );
+
+        config::LoadTest::from_config(
+            yaml.as_bytes(),
+            &PathBuf::from("import.yaml"),
+            &Default::default(),
+        )
+        .unwrap();
+
+        let e = import(b"# nothing here", None, Path::new("requests.txt")).unwrap_err();
+        assert!(matches!(e, TestError::ImportErr(_)), "{}", e);
+
+        let input = r#"
+curl --unix-socket /tmp/app.sock http://localhost/status
+curl --aws-sigv4 aws:amz:us-east-1:execute-api -u KEY:SECRET https://localhost/orders
+"#;
+        let requests = from_curl(input).unwrap();
+        let yaml = to_yaml(&requests, &[]).unwrap();
+        assert!(yaml.contains(
+            r#"  - url: "unix:///tmp/app.sock:/status"
+    tags:
+      name: GET /status
+"#
+        ));
+        assert!(yaml.contains(
+            r#"    sign:
+      aws_sigv4:
+        service: execute-api
+        region: us-east-1
+        access_key_id: KEY
+        secret_access_key: SECRET
+"#
+        ));
+        config::LoadTest::from_config(
+            yaml.as_bytes(),
+            &PathBuf::from("import.yaml"),
+            &Default::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn creates_configs_from_openapi() {
+        let input = r##"
+openapi: 3.0.3
+servers:
+  - url: "https://{host}/v1"
+    variables:
+      host:
+        default: api.example.com
+paths:
+  /pets/{petId}:
+    parameters:
+      - $ref: "#/components/parameters/PetId"
+    get:
+      parameters:
+        - name: fields
+          in: query
+          required: true
+          schema:
+            type: string
+            enum: [name, tag]
+        - name: limit
+          in: query
+          schema:
+            type: integer
+    put:
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: "#/components/schemas/Pet"
+components:
+  parameters:
+    PetId:
+      name: petId
+      in: path
+      required: true
+      schema:
+        type: integer
+        example: 7
+  schemas:
+    Pet:
+      allOf:
+        - type: object
+          properties:
+            name:
+              type: string
+        - properties:
+            born:
+              type: string
+              format: date
+            tags:
+              type: array
+              items:
+                type: string
+"##;
+        assert!(matches!(detect_format(input), ImportFormat::Openapi));
+        let yaml = import(input.as_bytes(), None, Path::new("specs/petstore.yaml")).unwrap();
+        assert_eq!(
+            yaml,
+            r#"# created by `pewpew import`. Check the load_pattern and peak_loads before running a load test
+vars:
+  base_url: "https://api.example.com/v1"
+openapi:
+  spec: petstore.yaml
+load_pattern:
+  - linear:
+      to: 100%
+      over: 5m
+providers:
+  pet_id:
+    list:
+      - 7
+  fields:
+    list:
+      - name
+endpoints:
+  - url: "${base_url}/pets/${pet_id}?fields=${fields}"
+    tags:
+      name: GET /pets/*
+    peak_load: 1hps
+  - method: PUT
+    url: "${base_url}/pets/${pet_id}"
+    headers:
+      content-type: application/json
+    body: "{\"name\":\"string\",\"born\":\"2024-01-01\",\"tags\":[\"string\"]}"
+    tags:
+      name: PUT /pets/*
+    peak_load: 1hps
+"#
+        );
+
+        config::LoadTest::from_config(
+            yaml.as_bytes(),
+            &PathBuf::from("import.yaml"),
+            &Default::default(),
+        )
+        .unwrap();
+
+        let e = import(b"openapi: 2.0", None, Path::new("swagger.yaml")).unwrap_err();
+        assert!(matches!(e, TestError::ImportErr(_)), "{}", e);
+    }
+}
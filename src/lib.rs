@@ -3,13 +3,18 @@
 #![type_length_limit = "19550232"]
 #![allow(clippy::type_complexity)]
 
+mod connector;
 mod error;
+mod eval;
+mod import;
 mod line_writer;
+mod openapi;
 mod providers;
 mod request;
 mod stats;
 mod util;
 
+use crate::connector::Connector;
 use crate::stats::{create_stats_channel, create_try_run_stats_channel, StatsMessage};
 pub use error::TestError;
 
@@ -406,12 +411,71 @@ impl fmt::Display for TryConfig {
     }
 }
 
+#[derive(Copy, Clone, Debug, Serialize, ValueEnum)]
+pub enum ImportFormat {
+    Har,
+    Curl,
+    Openapi,
+}
+
+#[derive(Clone, Debug, Serialize, Args)]
+pub struct ImportConfig {
+    /// HAR file, file of curl commands or OpenAPI document to create the config from
+    #[arg(value_name = "INPUT")]
+    pub input_file: PathBuf,
+    /// Format of the input file. By default it is detected from the contents
+    #[arg(short, long)]
+    pub format: Option<ImportFormat>,
+    /// Write the config to the specified file instead of stdout
+    #[arg(short = 'o', long)]
+    pub output_file: Option<PathBuf>,
+}
+
+impl fmt::Display for ImportConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string(&self).unwrap_or_default())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Args)]
+pub struct EvalConfig {
+    /// The expression to evaluate
+    #[arg(value_name = "EXPRESSION")]
+    pub expression: String,
+    /// JSON file the expression is evaluated against, with the `request`, `response`, `stats`
+    /// and provider values the expression uses. It is read from stdin when not specified or `-`
+    #[arg(value_name = "DATA")]
+    pub data_file: Option<PathBuf>,
+    /// A var available to the expression, in the format "name=value". The value is parsed as
+    /// JSON, or used as a string if it is not valid JSON
+    #[arg(short = 'v', long = "var", value_parser = parse_eval_var, value_name = "VAR")]
+    pub vars: Vec<(String, json::Value)>,
+}
+
+fn parse_eval_var(s: &str) -> Result<(String, json::Value), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid var `{s}`, expected the format \"name=value\""))?;
+    let value = json::from_str(value).unwrap_or_else(|_| value.into());
+    Ok((name.into(), value))
+}
+
+impl fmt::Display for EvalConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string(&self).unwrap_or_default())
+    }
+}
+
 #[derive(Serialize, Subcommand, Debug)]
 pub enum ExecConfig {
     /// Runs a full load test
     Run(RunConfig),
     /// Runs the specified endpoint(s) a single time for testing purposes
     Try(TryConfig),
+    /// Creates a config file from a HAR file, curl commands or an OpenAPI document
+    Import(ImportConfig),
+    /// Evaluates an expression against a JSON document
+    Eval(EvalConfig),
 }
 
 impl fmt::Display for ExecConfig {
@@ -425,13 +489,15 @@ impl ExecConfig {
         match self {
             Self::Run(r) => &r.config_file,
             Self::Try(t) => &t.config_file,
+            Self::Import(i) => &i.input_file,
+            Self::Eval(_) => unreachable!("evals finish before a config file is read"),
         }
     }
 
     fn get_output_format(&self) -> RunOutputFormat {
         match self {
             Self::Run(r) => r.output_format,
-            Self::Try(_) => RunOutputFormat::Human,
+            Self::Try(_) | Self::Import(_) | Self::Eval(_) => RunOutputFormat::Human,
         }
     }
 }
@@ -471,6 +537,14 @@ async fn _create_run(
     env_vars: BTreeMap<String, String>,
 ) -> Result<TestEndReason, TestError> {
     debug!("{{\"_create_run enter");
+    if let ExecConfig::Eval(e) = exec_config {
+        let output = spawn_blocking(move || eval::eval(&e))
+            .await
+            .map_err(|e| TestError::EvalErr(e.to_string()))??;
+        let mut stdout = stdout;
+        let _ = stdout.send(MsgType::Other(output)).await;
+        return Ok(TestEndReason::Completed);
+    }
     let config_file = exec_config.get_config_file().clone();
     let config_file2 = config_file.clone();
     debug!("{{\"_create_run spawn_blocking start");
@@ -503,6 +577,20 @@ async fn _create_run(
         TestError::CannotOpenFile(config_file2, e.into())
     })??;
 
+    if let ExecConfig::Import(i) = exec_config {
+        let yaml = import::import(&config_bytes, i.format, &i.input_file)?;
+        match i.output_file {
+            Some(path) => std::fs::write(&path, yaml).map_err(|e| {
+                TestError::WritingToFile(path.to_string_lossy().into_owned(), e.into())
+            })?,
+            None => {
+                let mut stdout = stdout;
+                let _ = stdout.send(MsgType::Other(yaml)).await;
+            }
+        }
+        return Ok(TestEndReason::Completed);
+    }
+
     // watch for ctrl-c and kill the test
     let test_ended_tx2 = test_ended_tx.clone();
     let mut test_ended_rx2 = BroadcastStream::new(test_ended_tx.subscribe());
@@ -527,10 +615,14 @@ async fn _create_run(
     let mut config =
         config::LoadTest::from_config(&config_bytes, exec_config.get_config_file(), &env_vars)?;
     debug!("config::LoadTest::from_config finished");
+    let script_paths = load_script_files(&config, &config_file_path)?;
     let test_runner = match exec_config {
         ExecConfig::Try(t) => {
             create_try_run_future(config, t, test_ended_tx.clone(), stdout, stderr).map(Either::A)
         }
+        ExecConfig::Import(_) | ExecConfig::Eval(_) => {
+            unreachable!("imports and evals finish before the config is loaded")
+        }
         ExecConfig::Run(r) => {
             let config_providers = mem::take(&mut config.providers);
             // Create channel to track when provider sources exhaust
@@ -566,6 +658,7 @@ async fn _create_run(
                     output_format,
                     r.clone(),
                     config_file_path,
+                    script_paths,
                     stats_tx.clone(),
                     config_providers,
                     Arc::downgrade(&providers),
@@ -761,6 +854,7 @@ fn create_config_watcher(
     output_format: RunOutputFormat,
     run_config: RunConfig,
     config_file_path: PathBuf,
+    mut script_paths: Vec<PathBuf>,
     stats_tx: FCUnboundedSender<StatsMessage>,
     mut previous_config_providers: BTreeMap<String, config::Provider>,
     mut previous_providers: std::sync::Weak<BTreeMap<String, providers::Provider>>,
@@ -793,6 +887,11 @@ fn create_config_watcher(
                 },
                 Err(_) => continue,
             };
+            // a change to one of the config's script files also reloads the config
+            let modified = script_paths
+                .iter()
+                .filter_map(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+                .fold(modified, std::cmp::max);
 
             // Check the last modified. If we don't have one, or it hasn't changed, continue to the next loop
             match last_modified {
@@ -819,9 +918,17 @@ fn create_config_watcher(
             // A decent amount of this code seems similar to that in `_create_run`; could
             // this be unified into a common function?
 
-            let config = config::LoadTest::from_config(&config_bytes, &config_file_path, &env_vars);
+            let config = config::LoadTest::from_config(&config_bytes, &config_file_path, &env_vars)
+                .map_err(TestError::from)
+                .and_then(|config| {
+                    let paths = load_script_files(&config, &config_file_path)?;
+                    Ok((config, paths))
+                });
             let mut config = match config {
-                Ok(m) => m,
+                Ok((m, paths)) => {
+                    script_paths = paths;
+                    m
+                }
                 Err(e) => {
                     let msg = match output_format {
                         RunOutputFormat::Human => format!(
@@ -1117,18 +1224,37 @@ fn create_try_run_future(
     }
 
     let client = create_http_client(config_config.client.keepalive)?;
+    let grpc_client = create_grpc_client(config_config.client.keepalive)?;
 
     // create the stats channel
     let test_complete = BroadcastStream::new(test_ended_tx.subscribe());
     let stats_tx = create_try_run_stats_channel(test_complete, stderr);
 
+    let client = Arc::new(client);
+    let auth = config.auth.map(|auth| {
+        let timeout = config_config.client.request_timeout;
+        Arc::new(request::AuthManager::new(auth, client.clone(), timeout))
+    });
+    let openapi = match config.openapi {
+        Some(mut openapi) => {
+            util::tweak_path(&mut openapi.spec, &try_config.config_file);
+            Some(Arc::new(openapi::Spec::load(Path::new(&openapi.spec))?))
+        }
+        None => None,
+    };
+
     let mut builder_ctx = request::BuilderContext {
         config: config_config,
         config_path: try_config.config_file,
-        client: Arc::new(client),
+        client,
+        grpc_client: Arc::new(grpc_client),
         loggers,
         providers: providers.into(),
         stats_tx,
+        cookie_jars: Default::default(),
+        auth,
+        openapi,
+        test_killer: test_ended_tx.clone(),
     };
 
     let endpoint_calls = endpoints.build(filter_fn, &mut builder_ctx, &response_providers)?;
@@ -1229,14 +1355,33 @@ fn create_load_test_future(
         .collect();
 
     let client = create_http_client(config_config.client.keepalive)?;
+    let grpc_client = create_grpc_client(config_config.client.keepalive)?;
+
+    let client = Arc::new(client);
+    let auth = config.auth.map(|auth| {
+        let timeout = config_config.client.request_timeout;
+        Arc::new(request::AuthManager::new(auth, client.clone(), timeout))
+    });
+    let openapi = match config.openapi {
+        Some(mut openapi) => {
+            util::tweak_path(&mut openapi.spec, &run_config.config_file);
+            Some(Arc::new(openapi::Spec::load(Path::new(&openapi.spec))?))
+        }
+        None => None,
+    };
 
     let mut builder_ctx = request::BuilderContext {
         config: config_config,
         config_path: run_config.config_file,
-        client: Arc::new(client),
+        client,
+        grpc_client: Arc::new(grpc_client),
         loggers,
         providers,
         stats_tx: stats_tx.clone(),
+        cookie_jars: Default::default(),
+        auth,
+        openapi,
+        test_killer: test_ended_tx.clone(),
     };
 
     let endpoint_calls = builders
@@ -1332,15 +1477,50 @@ fn create_load_test_future(
 
 pub(crate) fn create_http_client(
     keepalive: Duration,
-) -> Result<Client<HttpsConnector<HttpConnector<GaiResolver>>, Body>, TestError> {
+) -> Result<Client<Connector, Body>, TestError> {
+    let https = HttpsConnector::from((http_connector(keepalive), TlsConnector::new()?.into()));
+    Ok(Client::builder(TokioExecutor::new())
+        .set_host(false)
+        .build::<_, Body>(Connector::new(https)))
+}
+
+/// The client for `grpc` endpoints, which only speaks http/2
+pub(crate) fn create_grpc_client(
+    keepalive: Duration,
+) -> Result<Client<Connector, Body>, TestError> {
+    let tls = TlsConnector::builder().request_alpns(&["h2"]).build()?;
+    let https = HttpsConnector::from((http_connector(keepalive), tls.into()));
+    Ok(Client::builder(TokioExecutor::new())
+        .http2_only(true)
+        .build::<_, Body>(Connector::new(https)))
+}
+
+fn http_connector(keepalive: Duration) -> HttpConnector<GaiResolver> {
     let mut http = HttpConnector::new();
     http.set_keepalive(Some(keepalive));
     http.set_reuse_address(true);
     http.enforce_http(false);
-    let https = HttpsConnector::from((http, TlsConnector::new()?.into()));
-    Ok(Client::builder(TokioExecutor::new())
-        .set_host(false)
-        .build::<_, Body>(https))
+    http
+}
+
+/// Reads and loads the script files a config uses, returning their paths
+fn load_script_files(
+    config: &config::LoadTest,
+    config_path: &Path,
+) -> Result<Vec<PathBuf>, TestError> {
+    config
+        .script_files
+        .iter()
+        .map(|script_file| {
+            let mut path = script_file.path.clone();
+            util::tweak_path(&mut path, config_path);
+            let path = PathBuf::from(path);
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| TestError::CannotOpenFile(path.clone(), e.into()))?;
+            script_file.load(&source)?;
+            Ok(path)
+        })
+        .collect()
 }
 
 type ProvidersResult = Result<(BTreeMap<String, providers::Provider>, BTreeSet<String>), TestError>;
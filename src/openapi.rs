@@ -0,0 +1,740 @@
+use crate::error::TestError;
+
+use regex::Regex;
+use serde_json as json;
+use yaml_rust2::{Yaml, YamlLoader};
+
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+// the http methods which can have an operation in a path item
+pub(crate) const METHODS: &[&str] = &["get", "put", "post", "delete", "options", "head", "patch"];
+
+// how many `$ref`s are followed without going deeper into the value, which only happens with a
+// schema that refers to itself
+const MAX_REF_DEPTH: usize = 32;
+
+enum Segment {
+    Literal(String),
+    Param,
+}
+
+struct Operation {
+    method: String,
+    segments: Vec<Segment>,
+    // json pointer to the operation object in the document
+    pointer: String,
+}
+
+/// An OpenAPI 3 document, used to check that the body of each response matches the schema
+/// declared for its operation and status
+pub struct Spec {
+    doc: json::Value,
+    base_paths: Vec<String>,
+    operations: Vec<Operation>,
+    patterns: BTreeMap<String, Regex>,
+}
+
+/// Checks the responses of one operation in a [`Spec`]
+pub(crate) struct ResponseValidator {
+    spec: Arc<Spec>,
+    operation: usize,
+}
+
+/// Parses an OpenAPI document, which can be JSON or YAML
+pub(crate) fn parse_document(input: &str) -> Result<json::Value, String> {
+    if input.trim_start().starts_with('{') {
+        return json::from_str(input).map_err(|e| e.to_string());
+    }
+    let mut docs = YamlLoader::load_from_str(input).map_err(|e| e.to_string())?;
+    if docs.is_empty() {
+        return Err("document is empty".into());
+    }
+    Ok(yaml_to_json(docs.swap_remove(0)))
+}
+
+fn yaml_to_json(yaml: Yaml) -> json::Value {
+    match yaml {
+        Yaml::Real(s) => s
+            .parse::<f64>()
+            .ok()
+            .and_then(json::Number::from_f64)
+            .map(json::Value::Number)
+            .unwrap_or(json::Value::String(s)),
+        Yaml::Integer(i) => i.into(),
+        Yaml::String(s) => s.into(),
+        Yaml::Boolean(b) => b.into(),
+        Yaml::Array(a) => a.into_iter().map(yaml_to_json).collect(),
+        Yaml::Hash(h) => h
+            .into_iter()
+            .filter_map(|(k, v)| {
+                let key = match k {
+                    Yaml::String(s) | Yaml::Real(s) => s,
+                    Yaml::Integer(i) => i.to_string(),
+                    Yaml::Boolean(b) => b.to_string(),
+                    _ => return None,
+                };
+                Some((key, yaml_to_json(v)))
+            })
+            .collect::<json::Map<_, _>>()
+            .into(),
+        _ => json::Value::Null,
+    }
+}
+
+/// The url of a server object, with any variables replaced by their default values
+pub(crate) fn server_url(server: &json::Value) -> Option<String> {
+    let mut url = server["url"].as_str()?.to_string();
+    for (name, variable) in server["variables"].as_object().into_iter().flatten() {
+        if let Some(default) = variable["default"].as_str() {
+            url = url.replace(&format!("{{{name}}}"), default);
+        }
+    }
+    Some(url)
+}
+
+/// Follows the `$ref`s in a value to the part of the document they point to
+pub(crate) fn resolve<'a>(doc: &'a json::Value, mut value: &'a json::Value) -> &'a json::Value {
+    for _ in 0..MAX_REF_DEPTH {
+        let target = value["$ref"]
+            .as_str()
+            .and_then(|r| r.strip_prefix('#'))
+            .and_then(|pointer| doc.pointer(pointer));
+        match target {
+            Some(target) => value = target,
+            None => break,
+        }
+    }
+    value
+}
+
+impl Spec {
+    /// Loads the document at the path
+    pub fn load(path: &Path) -> Result<Self, TestError> {
+        let input = std::fs::read_to_string(path)
+            .map_err(|e| TestError::CannotOpenFile(path.into(), e.into()))?;
+        parse_document(&input)
+            .and_then(Self::from_document)
+            .map_err(|e| TestError::InvalidOpenApiSpec(path.into(), e))
+    }
+
+    fn from_document(doc: json::Value) -> Result<Self, String> {
+        if !doc["openapi"].as_str().is_some_and(|v| v.starts_with('3')) {
+            return Err("only OpenAPI 3 documents are supported".into());
+        }
+        let paths = doc["paths"]
+            .as_object()
+            .ok_or("the document does not have any `paths`")?;
+
+        let mut base_paths: Vec<String> = doc["servers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(server_url)
+            .map(|url| {
+                let path = match url::Url::parse(&url) {
+                    Ok(url) => url.path().to_string(),
+                    // relative server urls are just a path
+                    Err(_) => url,
+                };
+                path.trim_end_matches('/').to_string()
+            })
+            .collect();
+        if base_paths.is_empty() {
+            base_paths.push(String::new());
+        }
+
+        let mut operations = Vec::new();
+        for (path, item) in paths {
+            for method in METHODS {
+                if item.get(*method).is_none() {
+                    continue;
+                }
+                let segments = path
+                    .split('/')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| {
+                        if s.contains('{') {
+                            Segment::Param
+                        } else {
+                            Segment::Literal(s.into())
+                        }
+                    })
+                    .collect();
+                let escaped = path.replace('~', "~0").replace('/', "~1");
+                operations.push(Operation {
+                    method: method.to_uppercase(),
+                    segments,
+                    pointer: format!("/paths/{escaped}/{method}"),
+                });
+            }
+        }
+
+        // compile the patterns up front so they aren't compiled for every response
+        let mut patterns = BTreeMap::new();
+        collect_patterns(&doc, &mut patterns);
+
+        Ok(Spec {
+            doc,
+            base_paths,
+            operations,
+            patterns,
+        })
+    }
+
+    /// Finds the operation for a request. Paths without parameters are preferred over templated
+    /// ones, like `/pets/mine` over `/pets/{id}`
+    pub(crate) fn validator(
+        self: &Arc<Self>,
+        method: &str,
+        path: &str,
+    ) -> Option<ResponseValidator> {
+        let segments: Vec<_> = self
+            .base_paths
+            .iter()
+            .filter_map(|base| {
+                let rest = path.strip_prefix(base.as_str())?;
+                (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+            })
+            .map(|rest| {
+                rest.split('/')
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        self.operations
+            .iter()
+            .enumerate()
+            .filter(|(_, op)| op.method == method)
+            .filter(|(_, op)| {
+                segments.iter().any(|segments| {
+                    op.segments.len() == segments.len()
+                        && op.segments.iter().zip(segments).all(|(s, p)| match s {
+                            Segment::Literal(l) => l == p,
+                            Segment::Param => true,
+                        })
+                })
+            })
+            .max_by_key(|(_, op)| {
+                op.segments
+                    .iter()
+                    .filter(|s| matches!(s, Segment::Literal(_)))
+                    .count()
+            })
+            .map(|(operation, _)| ResponseValidator {
+                spec: self.clone(),
+                operation,
+            })
+    }
+
+    fn resolve<'a>(&'a self, value: &'a json::Value) -> &'a json::Value {
+        resolve(&self.doc, value)
+    }
+
+    // `applied` has the schemas already being checked against this same value, through `allOf`,
+    // `anyOf`, `oneOf` or `not`. Seeing one of them again means the schema refers to itself
+    // without going deeper into the value, which would never end
+    fn check<'a>(
+        &'a self,
+        schema: &'a json::Value,
+        value: &json::Value,
+        at: &str,
+        applied: &[&'a json::Value],
+    ) -> Result<(), String> {
+        let schema = self.resolve(schema);
+        if applied.iter().any(|s| std::ptr::eq(*s, schema)) {
+            return Err(format!("the schema for `{at}` refers to itself"));
+        }
+        let applied = [applied, &[schema]].concat();
+        let schema = match schema.as_object() {
+            Some(s) => s,
+            // `true` or an empty schema allows anything
+            None => return Ok(()),
+        };
+        if value.is_null() && schema.get("nullable").and_then(json::Value::as_bool) == Some(true) {
+            return Ok(());
+        }
+
+        if let Some(all_of) = schema.get("allOf").and_then(json::Value::as_array) {
+            for s in all_of {
+                self.check(s, value, at, &applied)?;
+            }
+        }
+        if let Some(any_of) = schema.get("anyOf").and_then(json::Value::as_array) {
+            if !any_of
+                .iter()
+                .any(|s| self.check(s, value, at, &applied).is_ok())
+            {
+                return Err(format!("`{at}` does not match any of the `anyOf` schemas"));
+            }
+        }
+        if let Some(one_of) = schema.get("oneOf").and_then(json::Value::as_array) {
+            let matches = one_of
+                .iter()
+                .filter(|s| self.check(s, value, at, &applied).is_ok())
+                .count();
+            if matches != 1 {
+                return Err(format!(
+                    "`{at}` matches {matches} of the `oneOf` schemas instead of 1"
+                ));
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if self.check(not, value, at, &applied).is_ok() {
+                return Err(format!("`{at}` matches the `not` schema"));
+            }
+        }
+        if let Some(values) = schema.get("enum").and_then(json::Value::as_array) {
+            if !values.contains(value) {
+                return Err(format!("`{at}` is not one of the `enum` values"));
+            }
+        }
+
+        let types: Vec<&str> = match schema.get("type") {
+            Some(json::Value::String(t)) => vec![t],
+            Some(json::Value::Array(t)) => t.iter().filter_map(json::Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| is_type(value, t)) {
+            return Err(format!("`{at}` should be {}", describe_types(&types)));
+        }
+
+        let limit = |key: &str| schema.get(key).and_then(json::Value::as_f64);
+        match value {
+            json::Value::String(s) => {
+                let len = s.chars().count() as f64;
+                if limit("minLength").is_some_and(|min| len < min) {
+                    return Err(format!("`{at}` is shorter than the `minLength`"));
+                }
+                if limit("maxLength").is_some_and(|max| len > max) {
+                    return Err(format!("`{at}` is longer than the `maxLength`"));
+                }
+                let pattern = schema
+                    .get("pattern")
+                    .and_then(json::Value::as_str)
+                    .and_then(|p| self.patterns.get(p));
+                if pattern.is_some_and(|p| !p.is_match(s)) {
+                    return Err(format!("`{at}` does not match the `pattern`"));
+                }
+            }
+            json::Value::Number(n) => {
+                let n = n.as_f64().unwrap_or_default();
+                let exclusive = |key: &str| schema.get(key).and_then(json::Value::as_bool);
+                // `exclusiveMinimum` is a boolean in OpenAPI 3.0 and a number in 3.1
+                let below = match (limit("minimum"), exclusive("exclusiveMinimum")) {
+                    (Some(min), Some(true)) => n <= min,
+                    (Some(min), _) => n < min,
+                    _ => false,
+                } || limit("exclusiveMinimum").is_some_and(|min| n <= min);
+                let above = match (limit("maximum"), exclusive("exclusiveMaximum")) {
+                    (Some(max), Some(true)) => n >= max,
+                    (Some(max), _) => n > max,
+                    _ => false,
+                } || limit("exclusiveMaximum").is_some_and(|max| n >= max);
+                if below {
+                    return Err(format!("`{at}` is less than the minimum"));
+                }
+                if above {
+                    return Err(format!("`{at}` is greater than the maximum"));
+                }
+            }
+            json::Value::Array(values) => {
+                let len = values.len() as f64;
+                if limit("minItems").is_some_and(|min| len < min) {
+                    return Err(format!("`{at}` has fewer items than the `minItems`"));
+                }
+                if limit("maxItems").is_some_and(|max| len > max) {
+                    return Err(format!("`{at}` has more items than the `maxItems`"));
+                }
+                if let Some(items) = schema.get("items") {
+                    // array indexes are left out so each violation is counted under one message
+                    let at = format!("{at}[*]");
+                    for v in values {
+                        self.check(items, v, &at, &[])?;
+                    }
+                }
+            }
+            json::Value::Object(map) => {
+                for required in schema
+                    .get("required")
+                    .and_then(json::Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(json::Value::as_str)
+                {
+                    if !map.contains_key(required) {
+                        return Err(format!("`{at}` is missing the property `{required}`"));
+                    }
+                }
+                let properties = schema.get("properties").and_then(json::Value::as_object);
+                for (key, v) in map {
+                    match (
+                        properties.and_then(|p| p.get(key)),
+                        schema.get("additionalProperties"),
+                    ) {
+                        (Some(property), _) => {
+                            self.check(property, v, &format!("{at}.{key}"), &[])?
+                        }
+                        (None, Some(json::Value::Bool(false))) => {
+                            return Err(format!("`{at}` has the unexpected property `{key}`"))
+                        }
+                        (None, Some(additional)) => {
+                            self.check(additional, v, &format!("{at}.*"), &[])?
+                        }
+                        (None, None) => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+impl ResponseValidator {
+    /// Checks the body of a response against the schema declared for its status. Statuses and
+    /// media types which aren't declared, or don't have a JSON schema, are not checked
+    pub(crate) fn validate(&self, status: u16, body: &json::Value) -> Result<(), String> {
+        let spec = &self.spec;
+        let operation = &spec.operations[self.operation];
+        let responses = match spec.doc.pointer(&operation.pointer) {
+            Some(op) => &op["responses"],
+            None => return Ok(()),
+        };
+        let status_range = format!("{}XX", status / 100);
+        let response = responses
+            .get(status.to_string())
+            .or_else(|| responses.get(&status_range))
+            .or_else(|| responses.get(status_range.to_lowercase()))
+            .or_else(|| responses.get("default"));
+        let content = match response.map(|r| &spec.resolve(r)["content"]) {
+            Some(json::Value::Object(content)) => content,
+            _ => return Ok(()),
+        };
+        let schema = content
+            .iter()
+            .find(|(media_type, _)| media_type.contains("json") || *media_type == "*/*")
+            .and_then(|(_, media)| media.get("schema"));
+        match schema {
+            Some(schema) => spec.check(schema, body, "$", &[]),
+            None => Ok(()),
+        }
+    }
+}
+
+fn collect_patterns(value: &json::Value, patterns: &mut BTreeMap<String, Regex>) {
+    match value {
+        json::Value::Object(map) => {
+            if let Some(json::Value::String(p)) = map.get("pattern") {
+                if let Ok(regex) = Regex::new(p) {
+                    patterns.insert(p.clone(), regex);
+                }
+            }
+            for v in map.values() {
+                collect_patterns(v, patterns);
+            }
+        }
+        json::Value::Array(values) => {
+            for v in values {
+                collect_patterns(v, patterns);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn is_type(value: &json::Value, t: &str) -> bool {
+    match t {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn describe_types(types: &[&str]) -> String {
+    let describe = |t: &&str| match *t {
+        "array" | "integer" | "object" => format!("an {t}"),
+        "null" => "null".to_string(),
+        t => format!("a {t}"),
+    };
+    types.iter().map(describe).collect::<Vec<_>>().join(" or ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PETSTORE: &str = r##"
+openapi: 3.0.3
+info:
+  title: Petstore
+  version: 1.0.0
+servers:
+  - url: "http://{host}/v1"
+    variables:
+      host:
+        default: localhost
+paths:
+  /pets:
+    get:
+      parameters:
+        - name: limit
+          in: query
+          schema:
+            type: integer
+      responses:
+        "200":
+          description: the pets
+          content:
+            application/json:
+              schema:
+                type: array
+                items:
+                  $ref: "#/components/schemas/Pet"
+  /pets/{petId}:
+    get:
+      responses:
+        "200":
+          $ref: "#/components/responses/Pet"
+        4XX:
+          description: an error
+          content:
+            application/json:
+              schema:
+                type: object
+                required: [message]
+                properties:
+                  message:
+                    type: string
+  /pets/mine:
+    get:
+      responses:
+        default:
+          description: anything
+          content:
+            text/plain:
+              schema:
+                type: string
+components:
+  responses:
+    Pet:
+      description: a pet
+      content:
+        application/json:
+          schema:
+            $ref: "#/components/schemas/Pet"
+  schemas:
+    Pet:
+      type: object
+      required: [id, name]
+      additionalProperties: false
+      properties:
+        id:
+          type: integer
+          minimum: 1
+        name:
+          type: string
+          pattern: "^[A-Z]"
+          maxLength: 10
+        tag:
+          type: string
+          nullable: true
+          enum: [cat, dog]
+        parent:
+          $ref: "#/components/schemas/Pet"
+        owner:
+          oneOf:
+            - type: string
+            - type: object
+              properties:
+                id:
+                  type: integer
+"##;
+
+    fn spec() -> Arc<Spec> {
+        Arc::new(Spec::from_document(parse_document(PETSTORE).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn finds_operations() {
+        let spec = spec();
+        let pointer = |method: &str, path: &str| {
+            spec.validator(method, path)
+                .map(|v| spec.operations[v.operation].pointer.clone())
+        };
+        assert_eq!(pointer("GET", "/v1/pets"), Some("/paths/~1pets/get".into()));
+        assert_eq!(
+            pointer("GET", "/v1/pets/"),
+            Some("/paths/~1pets/get".into())
+        );
+        assert_eq!(
+            pointer("GET", "/v1/pets/123"),
+            Some("/paths/~1pets~1{petId}/get".into())
+        );
+        assert_eq!(
+            pointer("GET", "/v1/pets/mine"),
+            Some("/paths/~1pets~1mine/get".into())
+        );
+        assert_eq!(pointer("POST", "/v1/pets"), None);
+        assert_eq!(pointer("GET", "/pets"), None);
+        assert_eq!(pointer("GET", "/v1/pets/1/toys"), None);
+        assert_eq!(pointer("GET", "/v10/pets"), None);
+    }
+
+    #[test]
+    fn validates_responses() {
+        let spec = spec();
+        let pets = spec.validator("GET", "/v1/pets").unwrap();
+        let pet = spec.validator("GET", "/v1/pets/1").unwrap();
+        let mine = spec.validator("GET", "/v1/pets/mine").unwrap();
+
+        let valid = json::json!([
+            { "id": 1, "name": "Rex", "tag": "dog", "parent": { "id": 2, "name": "Max" } },
+            { "id": 2.0, "name": "Tom", "tag": null, "owner": "someone" },
+            { "id": 3, "name": "Bob", "owner": { "id": 4 } },
+        ]);
+        assert_eq!(pets.validate(200, &valid), Ok(()));
+        assert_eq!(pet.validate(200, &valid[0]), Ok(()));
+        // statuses and media types without a JSON schema aren't checked
+        assert_eq!(pets.validate(500, &valid), Ok(()));
+        assert_eq!(mine.validate(200, &json::json!(1)), Ok(()));
+
+        let checks = vec![
+            (&pets, 200, json::json!({}), "`$` should be an array"),
+            (
+                &pets,
+                200,
+                json::json!([{ "id": 1 }]),
+                "`$[*]` is missing the property `name`",
+            ),
+            (
+                &pets,
+                200,
+                json::json!([{ "id": "1", "name": "Rex" }]),
+                "`$[*].id` should be an integer",
+            ),
+            (
+                &pet,
+                200,
+                json::json!({ "id": 0, "name": "Rex" }),
+                "`$.id` is less than the minimum",
+            ),
+            (
+                &pet,
+                200,
+                json::json!({ "id": 1, "name": "rex" }),
+                "`$.name` does not match the `pattern`",
+            ),
+            (
+                &pet,
+                200,
+                json::json!({ "id": 1, "name": "Rexxxxxxxxxx" }),
+                "`$.name` is longer than the `maxLength`",
+            ),
+            (
+                &pet,
+                200,
+                json::json!({ "id": 1, "name": "Rex", "tag": "fish" }),
+                "`$.tag` is not one of the `enum` values",
+            ),
+            (
+                &pet,
+                200,
+                json::json!({ "id": 1, "name": "Rex", "age": 3 }),
+                "`$` has the unexpected property `age`",
+            ),
+            (
+                &pet,
+                200,
+                json::json!({ "id": 1, "name": "Rex", "parent": { "id": 2 } }),
+                "`$.parent` is missing the property `name`",
+            ),
+            (
+                &pet,
+                200,
+                json::json!({ "id": 1, "name": "Rex", "owner": 5 }),
+                "`$.owner` matches 0 of the `oneOf` schemas instead of 1",
+            ),
+            (
+                &pet,
+                404,
+                json::json!({ "error": "not found" }),
+                "`$` is missing the property `message`",
+            ),
+            (
+                &pet,
+                200,
+                json::json!("<<binary data>>"),
+                "`$` should be an object",
+            ),
+        ];
+        for (validator, status, body, expected) in checks {
+            assert_eq!(
+                validator.validate(status, &body),
+                Err(expected.to_string()),
+                "{}",
+                body
+            );
+        }
+    }
+
+    #[test]
+    fn stops_at_schemas_which_refer_to_themselves() {
+        let doc = parse_document(
+            r##"
+openapi: 3.0.0
+paths:
+  /a:
+    get:
+      responses:
+        "200":
+          content:
+            application/json:
+              schema:
+                $ref: "#/components/schemas/A"
+components:
+  schemas:
+    A:
+      allOf:
+        - $ref: "#/components/schemas/B"
+    B:
+      anyOf:
+        - $ref: "#/components/schemas/A"
+        - $ref: "#/components/schemas/A"
+      oneOf:
+        - $ref: "#/components/schemas/B"
+      not:
+        $ref: "#/components/schemas/A"
+"##,
+        )
+        .unwrap();
+        let spec = Arc::new(Spec::from_document(doc).unwrap());
+        let a = spec.validator("GET", "/a").unwrap();
+        assert_eq!(
+            a.validate(200, &json::json!({ "a": 1 })),
+            Err("`$` does not match any of the `anyOf` schemas".into())
+        );
+    }
+
+    #[test]
+    fn parses_documents() {
+        let doc = parse_document(r#"{"openapi":"3.1.0","paths":{}}"#).unwrap();
+        assert!(Spec::from_document(doc).is_ok());
+
+        let doc = parse_document("swagger: '2.0'\npaths: {}").unwrap();
+        assert!(Spec::from_document(doc).is_err());
+        let doc = parse_document("openapi: 3.0.0").unwrap();
+        assert!(Spec::from_document(doc).is_err());
+        assert!(parse_document("").is_err());
+        assert!(parse_document("{").is_err());
+
+        let doc = parse_document("a: 1.5\nb: [yes, 2]\n3: x").unwrap();
+        assert_eq!(doc, json::json!({ "a": 1.5, "b": ["yes", 2], "3": "x" }));
+    }
+}
@@ -1,10 +1,28 @@
 #![allow(clippy::type_complexity)]
+mod assertions;
+mod auth;
 mod body_handler;
+mod compression;
+mod cookie_jar;
+mod graphql;
+mod grpc;
 mod request_maker;
 mod response_handler;
-
+mod sign;
+mod socket;
+mod stream_handler;
+mod throttle;
+mod websocket;
+
+use self::assertions::Assertions;
+pub use self::auth::AuthManager;
 use self::body_handler::BodyHandler;
+pub use self::cookie_jar::CookieJars;
+use self::grpc::GrpcMaker;
 use self::request_maker::RequestMaker;
+use self::socket::SocketMaker;
+use self::throttle::Throttle;
+use self::websocket::WebSocketMaker;
 
 use log::debug;
 use request_maker::ProviderDelays;
@@ -18,29 +36,30 @@ use futures::{
     sink::SinkExt,
     stream, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt,
 };
+use futures_timer::Delay;
 use http_body_util::{combinators::BoxBody, BodyExt, StreamBody};
 use hyper::{
     header::{Entry as HeaderEntry, HeaderName, HeaderValue, CONTENT_DISPOSITION},
     Method, Response,
 };
-use hyper_tls::HttpsConnector;
-use hyper_util::client::legacy::{
-    connect::{dns::GaiResolver, HttpConnector},
-    Client,
-};
+use hyper_util::client::legacy::Client;
 use rand::distr::{Alphanumeric, Distribution};
 use select_any::select_any;
 use serde_json as json;
 use tokio::{
     fs::File as TokioFile,
     io::{AsyncRead, ReadBuf},
+    sync::broadcast,
 };
 use zip_all::zip_all;
 
+use crate::connector::Connector;
 use crate::error::{RecoverableError, TestError};
+use crate::openapi::Spec;
 use crate::providers;
 use crate::stats;
 use crate::util::tweak_path;
+use crate::TestEndReason;
 use config::{
     BodyTemplate, EndpointProvidesSendOptions, MultipartBody, ProviderStream, Select, Template,
 };
@@ -56,7 +75,7 @@ use std::{
     str,
     sync::Arc,
     task::{Context, Poll},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 type HyperBody = BoxBody<Bytes, std::io::Error>;
@@ -209,13 +228,23 @@ pub struct BuilderContext {
     #[allow(dead_code)]
     pub config_path: PathBuf,
     // the http client
-    pub client: Arc<Client<HttpsConnector<HttpConnector<GaiResolver>>, HyperBody>>,
+    pub client: Arc<Client<Connector, HyperBody>>,
+    // the http/2 client used by `grpc` endpoints
+    pub grpc_client: Arc<Client<Connector, HyperBody>>,
     // a mapping of names to their prospective providers
     pub providers: Arc<BTreeMap<String, providers::Provider>>,
     // a mapping of names to their prospective loggers
     pub loggers: BTreeMap<String, providers::Logger>,
     // channel that receives and aggregates stats for the test
     pub stats_tx: StatsTx,
+    // the cookie jars shared by endpoints with a `cookie_jar`
+    pub cookie_jars: CookieJars,
+    // gets the token for the `auth` block
+    pub auth: Option<Arc<AuthManager>>,
+    // the OpenAPI spec which responses are validated against
+    pub openapi: Option<Arc<Spec>>,
+    // ends the test when an assertion fails too many times
+    pub test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
 }
 
 pub struct EndpointBuilder {
@@ -243,6 +272,7 @@ impl EndpointBuilder {
         let mut on_demand_streams: OnDemandStreams = Vec::new();
 
         let config::Endpoint {
+            assertions,
             method,
             headers,
             body,
@@ -255,6 +285,16 @@ impl EndpointBuilder {
             on_demand,
             tags,
             request_timeout,
+            follow_redirects,
+            cookie_jar,
+            retry,
+            honor_retry_after,
+            compress_body,
+            websocket,
+            stream_response,
+            grpc,
+            socket,
+            sign,
             ..
         } = self.endpoint;
         debug!("EndpointBuilder.build method=\"{}\" url=\"{}\" body=\"{}\" headers=\"{:?}\" no_auto_returns=\"{}\" \
@@ -263,6 +303,7 @@ impl EndpointBuilder {
             max_parallel_requests, convert_to_debug(&provides), convert_to_debug(&logs), on_demand, request_timeout);
 
         let timeout = request_timeout.unwrap_or(ctx.config.client.request_timeout);
+        let follow_redirects = follow_redirects.unwrap_or(ctx.config.client.follow_redirects);
 
         let mut provides_set = if self.start_stream.is_none() && !provides.is_empty() {
             Some(BTreeSet::new())
@@ -291,12 +332,17 @@ impl EndpointBuilder {
             })
             .collect();
 
+        let throttle = honor_retry_after.then(Throttle::default);
         let mut streams: StreamCollection = Vec::new();
         if let Some(start_stream) = self.start_stream {
-            streams.push((
-                true,
-                Box::new(start_stream.map(|(_, d)| Ok(StreamItem::Instant(d)))),
-            ));
+            let start_stream = start_stream.map(|(_, d)| Ok(StreamItem::Instant(d)));
+            match &throttle {
+                Some(throttle) => {
+                    let stream = throttle.clone().throttle_stream(start_stream);
+                    streams.push((true, Box::new(stream)));
+                }
+                None => streams.push((true, Box::new(start_stream))),
+            }
         } else if let Some(set) = provides_set {
             let stream = stream::poll_fn(move |_| {
                 let done = set.iter().all(channel::Sender::no_receivers);
@@ -326,9 +372,42 @@ impl EndpointBuilder {
         let precheck_rr_providers = providers_to_stream.get_where_special();
         // go through the list of required providers and make sure we have them all
         for name in providers_to_stream.unique_providers() {
-            let provider = match ctx.providers.get(&name) {
-                Some(p) => p,
-                None => continue,
+            let provider = match (ctx.providers.get(&name), &ctx.auth) {
+                (Some(p), _) => p,
+                (None, Some(auth)) if name == "auth" => {
+                    let auth = auth.clone();
+                    let stats_tx = ctx.stats_tx.clone();
+                    let tags = Arc::new(tags.clone());
+                    let auth_stream = stream::repeat(()).then(move |_| {
+                        let auth = auth.clone();
+                        let stats_tx = stats_tx.clone();
+                        let tags = tags.clone();
+                        async move {
+                            // not getting a token is recorded as an error and the token is asked
+                            // for again, rather than ending the test
+                            let token = loop {
+                                match auth.token().await {
+                                    Ok(token) => break token,
+                                    Err(e) => {
+                                        let kind = stats::StatKind::RecoverableError(e);
+                                        let tags = evaluate_tags(&tags, &TemplateValues::new());
+                                        send_stat(&stats_tx, kind, None, tags);
+                                        Delay::new(auth::RETRY_DELAY).await;
+                                    }
+                                }
+                            };
+                            Ok(StreamItem::TemplateValue(
+                                "auth".into(),
+                                token,
+                                None,
+                                Instant::now(),
+                            ))
+                        }
+                    });
+                    streams.push((false, Box::new(Box::pin(auth_stream))));
+                    continue;
+                }
+                (None, _) => continue,
             };
             debug!("EndpointBuilder.build unique_providers name=\"{}\"", name);
             let receiver = provider.rx.clone();
@@ -366,7 +445,22 @@ impl EndpointBuilder {
             streams.push((false, Box::new(stream)));
         }
         let stats_tx = ctx.stats_tx.clone();
-        let client = ctx.client.clone();
+        let client = match grpc {
+            Some(_) => ctx.grpc_client.clone(),
+            None => ctx.client.clone(),
+        };
+        let grpc = grpc.map(|mut grpc| {
+            tweak_path(&mut grpc.descriptor, &ctx.config_path);
+            grpc
+        });
+        let mut body = body;
+        if let BodyTemplate::Graphql(config::GraphqlBody {
+            query: config::GraphqlQuery::File(path),
+            ..
+        }) = &mut body
+        {
+            tweak_path(path, &ctx.config_path);
+        }
         Endpoint {
             body,
             client,
@@ -384,6 +478,19 @@ impl EndpointBuilder {
             stream_collection: streams,
             url,
             timeout,
+            follow_redirects,
+            cookie_jar,
+            cookie_jars: ctx.cookie_jars.clone(),
+            retry,
+            throttle,
+            compress_body,
+            websocket,
+            stream_response,
+            grpc,
+            socket,
+            sign,
+            openapi: ctx.openapi.clone(),
+            assertions: Assertions::new(assertions, ctx.test_killer.clone()).map(Arc::new),
         }
     }
 }
@@ -591,6 +698,22 @@ fn body_template_as_hyper_body(
 ) -> impl Future<Output = Result<(u64, HyperBody), TestError>> {
     let template = match body_template {
         BodyTemplate::File(_, t) => t,
+        BodyTemplate::Graphql(graphql) => {
+            let body = match graphql::request_body(graphql, template_values) {
+                Ok(b) => b,
+                Err(e) => return Either3::B(future::err(e)),
+            };
+            if let HeaderEntry::Vacant(entry) = content_type_entry {
+                entry.insert(HeaderValue::from_static("application/json"));
+            }
+            if copy_body_value {
+                *body_value = Some(body.clone());
+            }
+            return Either3::B(future::ok((
+                body.len() as u64,
+                body.map_err(|never| match never {}).boxed(),
+            )));
+        }
         BodyTemplate::Multipart(m) => {
             let r = multipart_body_as_hyper_body(
                 m,
@@ -631,10 +754,11 @@ type StreamCollection = Vec<(
 )>;
 type OnDemandStreams = Vec<Box<dyn Stream<Item = ()> + Send + Unpin + 'static>>;
 pub type StatsTx = futures_channel::UnboundedSender<stats::StatsMessage>;
+type Tags = Arc<BTreeMap<String, String>>;
 
 pub struct Endpoint {
     body: BodyTemplate,
-    client: Arc<Client<HttpsConnector<HttpConnector<GaiResolver>>, HyperBody>>,
+    client: Arc<Client<Connector, HyperBody>>,
     headers: Vec<(String, Template)>,
     max_parallel_requests: Option<NonZeroUsize>,
     method: Method,
@@ -649,6 +773,19 @@ pub struct Endpoint {
     stream_collection: StreamCollection,
     timeout: Duration,
     url: Template,
+    follow_redirects: usize,
+    cookie_jar: Option<Template>,
+    cookie_jars: CookieJars,
+    retry: Option<config::Retry>,
+    throttle: Option<Throttle>,
+    compress_body: Option<config::ContentEncoding>,
+    websocket: Option<config::WebSocket>,
+    stream_response: Option<config::StreamResponse>,
+    grpc: Option<config::Grpc>,
+    socket: Option<config::Socket>,
+    sign: Option<config::Sign>,
+    openapi: Option<Arc<Spec>>,
+    assertions: Option<Arc<Assertions>>,
 }
 
 impl Endpoint {
@@ -675,7 +812,20 @@ impl Endpoint {
         let url = self.url;
         let method = self.method;
         let headers = self.headers;
-        let body = self.body;
+        let mut body = self.body;
+        let mut tags = self.tags;
+        if let BodyTemplate::Graphql(graphql) = &mut body {
+            let operation = match graphql::load_query(graphql) {
+                Ok(operation) => operation,
+                Err(e) => return Box::new(future::err(e)),
+            };
+            // results are grouped by the graphql operation
+            if let Some(operation) = operation {
+                Arc::make_mut(&mut tags)
+                    .entry("operation".into())
+                    .or_insert_with(|| Template::simple(&operation));
+            }
+        }
         let rr_providers = self.rr_providers;
         let client = self.client;
         let stats_tx = self.stats_tx;
@@ -704,13 +854,25 @@ impl Endpoint {
         } else {
             zipped_streams.b()
         };
+        // a load pattern already skips hits while paused, this holds back the ones driven by
+        // providers until the pause is over
+        let stream = match &self.throttle {
+            Some(throttle) => throttle.clone().hold_stream(stream).a(),
+            None => stream.b(),
+        };
         let mut outgoing = self.outgoing;
         outgoing.extend(self.provides);
         let outgoing = Arc::new(outgoing);
         let precheck_rr_providers = self.precheck_rr_providers;
         let timeout = self.timeout;
         let max_parallel_requests = self.max_parallel_requests;
-        let tags = self.tags;
+        let follow_redirects = self.follow_redirects;
+        let cookie_jar = self.cookie_jar;
+        let cookie_jars = self.cookie_jars;
+        let retry = self.retry;
+        let throttle = self.throttle;
+        let compress_body = self.compress_body;
+        let stream_response = self.stream_response;
         let blocking_outgoing: Vec<_> = outgoing
             .iter()
             .filter_map(|o| match (&o.tx, o.select.get_send_behavior().is_block()) {
@@ -722,20 +884,6 @@ impl Endpoint {
             "into_future method=\"{}\" url=\"{:?}\" request_headers={:?} tags={:?}",
             method, url, headers, tags
         );
-        let rm = RequestMaker {
-            url,
-            method,
-            headers,
-            body,
-            rr_providers,
-            client,
-            stats_tx,
-            no_auto_returns,
-            outgoing,
-            precheck_rr_providers,
-            tags,
-            timeout,
-        };
         let limit_fn: Option<Box<dyn FnMut(usize) -> usize + Send + Unpin>> =
             match (blocking_outgoing.is_empty(), max_parallel_requests) {
                 (false, Some(n)) => {
@@ -825,6 +973,81 @@ impl Endpoint {
                 (true, Some(n)) => Some(Box::new(move |_| n.get())),
                 (true, None) => None,
             };
+        if let Some(grpc) = self.grpc {
+            let method = match grpc::load_method(&grpc) {
+                Ok(method) => method,
+                Err(e) => return Box::new(future::err(e)),
+            };
+            let gm = Arc::new(GrpcMaker {
+                url,
+                headers,
+                body,
+                method,
+                client,
+                stats_tx,
+                no_auto_returns,
+                outgoing,
+                tags,
+                timeout,
+            });
+            let f = ForEachParallel::new(limit_fn, stream, move |values| gm.send_request(values));
+            return Box::new(f);
+        }
+        if let Some(socket) = self.socket {
+            let sm = Arc::new(SocketMaker {
+                url,
+                body,
+                socket,
+                stats_tx,
+                no_auto_returns,
+                outgoing,
+                tags,
+                timeout,
+            });
+            let f = ForEachParallel::new(limit_fn, stream, move |values| sm.send_request(values));
+            return Box::new(f);
+        }
+        if let Some(websocket) = self.websocket {
+            let wsm = Arc::new(WebSocketMaker {
+                url,
+                headers,
+                body,
+                rr_providers,
+                stats_tx,
+                no_auto_returns,
+                outgoing,
+                tags,
+                timeout,
+                correlate: websocket.correlate,
+                connections: Default::default(),
+            });
+            let f = ForEachParallel::new(limit_fn, stream, move |values| wsm.send_message(values));
+            return Box::new(f);
+        }
+        let rm = RequestMaker {
+            url,
+            method,
+            headers,
+            body,
+            rr_providers,
+            client,
+            stats_tx,
+            no_auto_returns,
+            outgoing,
+            precheck_rr_providers,
+            tags,
+            timeout,
+            follow_redirects,
+            cookie_jar,
+            cookie_jars,
+            retry,
+            throttle,
+            compress_body,
+            stream_response,
+            sign: self.sign,
+            openapi: self.openapi,
+            assertions: self.assertions,
+        };
         let f = ForEachParallel::new(limit_fn, stream, move |values| rm.send_request(values));
         Box::new(f)
     }
@@ -919,6 +1142,60 @@ impl<V: Iterator<Item = Result<json::Value, RecoverableError>> + Unpin> Drop for
     }
 }
 
+/// Sends the selected values to each provider and logger whose `where` clause matches the
+/// template values. Used for responses which are handled one message or event at a time
+fn send_outgoing(
+    outgoing: &[Outgoing],
+    template_values: &Arc<json::Value>,
+) -> Vec<impl Future<Output = Result<(), RecoverableError>>> {
+    outgoing
+        .iter()
+        .filter_map(|o| {
+            let iter = o
+                .select
+                .execute_where(template_values)
+                .and_then(|included| {
+                    included
+                        .then(|| o.select.clone().iter(template_values.clone()))
+                        .transpose()
+                });
+            match iter {
+                Ok(Some(iter)) => {
+                    let iter = iter.map(|v| v.map_err(Into::into));
+                    Some(BlockSender::new(iter, o.tx.clone()).a())
+                }
+                Ok(None) => None,
+                Err(e) => Some(future::err(e.into()).b()),
+            }
+        })
+        .collect()
+}
+
+/// Evaluates an endpoint's tags for a single request or message
+fn evaluate_tags(tags: &BTreeMap<String, Template>, template_values: &TemplateValues) -> Tags {
+    let tags = tags
+        .iter()
+        .filter_map(|(k, t)| {
+            t.evaluate(Cow::Borrowed(template_values.as_json()), None)
+                .ok()
+                .map(|v| (k.clone(), v))
+        })
+        .collect();
+    Arc::new(tags)
+}
+
+fn send_stat(stats_tx: &StatsTx, kind: stats::StatKind, rtt: Option<u64>, tags: Tags) {
+    let _ = stats_tx.unbounded_send(
+        stats::ResponseStat {
+            kind,
+            rtt,
+            time: SystemTime::now(),
+            tags,
+        }
+        .into(),
+    );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
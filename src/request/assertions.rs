@@ -0,0 +1,91 @@
+use crate::error::TestError;
+use crate::TestEndReason;
+
+use serde_json as json;
+use tokio::sync::broadcast;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The `assert` checks of an endpoint, along with how many times each has failed
+pub(super) struct Assertions {
+    checks: Vec<(config::Assertion, AtomicUsize)>,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+}
+
+impl Assertions {
+    pub(super) fn new(
+        assertions: Vec<config::Assertion>,
+        test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    ) -> Option<Self> {
+        if assertions.is_empty() {
+            return None;
+        }
+        let checks = assertions
+            .into_iter()
+            .map(|a| (a, AtomicUsize::new(0)))
+            .collect();
+        Some(Self {
+            checks,
+            test_killer,
+        })
+    }
+
+    /// Checks the values for a response against each assertion and returns the names of those
+    /// which failed. The test is stopped when an assertion reaches its `abort_after` failures
+    pub(super) fn check(&self, template_values: &json::Value) -> Vec<String> {
+        let mut failed = Vec::new();
+        for (assertion, failures) in &self.checks {
+            if assertion.passes(template_values) {
+                continue;
+            }
+            let count = failures.fetch_add(1, Ordering::Relaxed) + 1;
+            if assertion.abort_after.map(usize::from) == Some(count) {
+                let e = TestError::AssertionLimit(assertion.name.clone(), count);
+                let _ = self.test_killer.send(Err(e));
+            }
+            failed.push(assertion.name.clone());
+        }
+        failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn counts_failures() {
+        let (test_killer, mut test_killed_rx) = broadcast::channel(1);
+        assert!(Assertions::new(Vec::new(), test_killer.clone()).is_none());
+
+        let assertions = vec![
+            config::Assertion::simple("ok", "response.status == 200", None),
+            config::Assertion::simple(
+                "items",
+                "len(response.body.items) > 0",
+                NonZeroUsize::new(2),
+            ),
+        ];
+        let assertions = Assertions::new(assertions, test_killer).unwrap();
+
+        let passing = json::json!({"response": {"status": 200, "body": {"items": [1]}}});
+        assert!(assertions.check(&passing).is_empty());
+
+        let failing = json::json!({"response": {"status": 500, "body": "oops"}});
+        assert_eq!(assertions.check(&failing), vec!["ok", "items"]);
+        assert!(
+            test_killed_rx.try_recv().is_err(),
+            "test should not be killed"
+        );
+
+        let failing = json::json!({"response": {"status": 200, "body": {"items": []}}});
+        assert_eq!(assertions.check(&failing), vec!["items"]);
+        let killed = matches!(
+            test_killed_rx.try_recv(),
+            Ok(Err(TestError::AssertionLimit(ref name, 2))) if name == "items"
+        );
+        assert!(killed, "test should be killed");
+    }
+}
@@ -0,0 +1,328 @@
+use crate::connector::Connector;
+use crate::error::RecoverableError;
+
+use bytes::Bytes;
+use config::{Auth, AuthGrant};
+use futures::{
+    future::{self, Either},
+    lock::Mutex as AsyncMutex,
+};
+use futures_timer::Delay;
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    header::{ACCEPT, CONTENT_TYPE},
+    Method, Request,
+};
+use hyper_util::client::legacy::Client;
+use log::{debug, warn};
+use serde_json as json;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::HyperBody;
+
+// how long to keep using the current token after a failed refresh, or to wait when there is no
+// token, before trying again
+pub(super) const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+struct Token {
+    value: json::Value,
+    // tokens without an `expires_in` are never refreshed
+    refresh_at: Option<Instant>,
+    expires_at: Option<Instant>,
+}
+
+impl Token {
+    fn needs_refresh(&self, now: Instant) -> bool {
+        self.refresh_at.is_some_and(|t| now >= t)
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|t| now >= t)
+    }
+}
+
+/// Gets and caches the token for the `auth` block, which is shared by every endpoint. Only one
+/// request at a time goes to the token endpoint. While a token is being refreshed other requests
+/// keep using the current one, or wait for the new one if the current one has expired
+pub struct AuthManager {
+    auth: Auth,
+    client: Arc<Client<Connector, HyperBody>>,
+    timeout: Duration,
+    token: Mutex<Option<Token>>,
+    refreshing: AsyncMutex<()>,
+}
+
+impl AuthManager {
+    pub fn new(auth: Auth, client: Arc<Client<Connector, HyperBody>>, timeout: Duration) -> Self {
+        AuthManager {
+            auth,
+            client,
+            timeout,
+            token: Mutex::new(None),
+            refreshing: AsyncMutex::new(()),
+        }
+    }
+
+    /// The value of `auth` in templates, which is an object with the `token` and `token_type`
+    pub async fn token(&self) -> Result<json::Value, RecoverableError> {
+        loop {
+            let now = Instant::now();
+            let current = self.current_token();
+            let usable = match current {
+                Some(t) if !t.needs_refresh(now) => return Ok(t.value),
+                Some(t) if !t.is_expired(now) => Some(t),
+                _ => None,
+            };
+            let _guard = match (self.refreshing.try_lock(), &usable) {
+                (Some(guard), _) => guard,
+                // another request is refreshing the token so use the current one for now
+                (None, Some(t)) => return Ok(t.value.clone()),
+                (None, None) => {
+                    // wait for the other refresh to finish, then check again
+                    let _ = self.refreshing.lock().await;
+                    continue;
+                }
+            };
+            // a refresh could have finished between checking the token and taking the lock
+            if let Some(t) = self.current_token().filter(|t| !t.needs_refresh(now)) {
+                return Ok(t.value);
+            }
+            let result = self.request_token().await;
+            let mut token = self
+                .token
+                .lock()
+                .expect("auth token lock should not be poisoned");
+            return match (result, usable) {
+                (Ok(t), _) => {
+                    debug!("AuthManager got a new token from {}", self.auth.token_url);
+                    let value = t.value.clone();
+                    *token = Some(t);
+                    Ok(value)
+                }
+                (Err(e), Some(mut t)) => {
+                    warn!(
+                        "could not refresh the auth token, using the current one: {}",
+                        e
+                    );
+                    t.refresh_at = Some(now + RETRY_DELAY);
+                    let value = t.value.clone();
+                    *token = Some(t);
+                    Ok(value)
+                }
+                (Err(e), None) => Err(RecoverableError::AuthTokenErr(e)),
+            };
+        }
+    }
+
+    fn current_token(&self) -> Option<Token> {
+        self.token
+            .lock()
+            .expect("auth token lock should not be poisoned")
+            .clone()
+    }
+
+    fn form(&self) -> String {
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+        match &self.auth.grant {
+            AuthGrant::ClientCredentials => {
+                form.append_pair("grant_type", "client_credentials");
+            }
+            AuthGrant::Password { username, password } => {
+                form.append_pair("grant_type", "password")
+                    .append_pair("username", username)
+                    .append_pair("password", password);
+            }
+        }
+        form.append_pair("client_id", &self.auth.client_id);
+        if let Some(secret) = &self.auth.client_secret {
+            form.append_pair("client_secret", secret);
+        }
+        if let Some(scope) = &self.auth.scope {
+            form.append_pair("scope", scope);
+        }
+        form.finish()
+    }
+
+    async fn request_token(&self) -> Result<Token, String> {
+        let body = Full::new(Bytes::from(self.form()))
+            .map_err(|never| match never {})
+            .boxed();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.auth.token_url.as_str())
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(ACCEPT, "application/json")
+            .body(body)
+            .map_err(|e| e.to_string())?;
+
+        let call = Box::pin(self.client.request(request));
+        let response = match future::select(call, Delay::new(self.timeout)).await {
+            Either::Left((response, _)) => response.map_err(|e| e.to_string())?,
+            Either::Right(_) => return Err("request to the token endpoint timed out".into()),
+        };
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_bytes();
+        if !status.is_success() {
+            return Err(format!(
+                "token endpoint responded with {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        parse_token(&body, Instant::now(), self.auth.refresh_before)
+    }
+}
+
+fn parse_token(body: &[u8], now: Instant, refresh_before: Duration) -> Result<Token, String> {
+    let body: json::Value =
+        json::from_slice(body).map_err(|e| format!("invalid token response: {}", e))?;
+    let access_token = body
+        .get("access_token")
+        .and_then(json::Value::as_str)
+        .ok_or("token response is missing `access_token`")?;
+    let token_type = body
+        .get("token_type")
+        .and_then(json::Value::as_str)
+        .unwrap_or("Bearer");
+    let expires_in = match body.get("expires_in") {
+        Some(json::Value::Number(n)) => n.as_u64(),
+        Some(json::Value::String(s)) => s.parse().ok(),
+        _ => None,
+    }
+    .map(Duration::from_secs);
+    // short lived tokens are refreshed half way through their lifetime. A token which expires
+    // too far in the future to represent is treated as never expiring
+    let refresh_at = expires_in.and_then(|expires_in| {
+        now.checked_add(
+            expires_in
+                .checked_sub(refresh_before)
+                .unwrap_or(expires_in / 2),
+        )
+    });
+    Ok(Token {
+        value: json::json!({ "token": access_token, "token_type": token_type }),
+        refresh_at,
+        expires_at: expires_in.and_then(|expires_in| now.checked_add(expires_in)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join_all;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn parses_token_responses() {
+        let now = Instant::now();
+        let refresh_before = Duration::from_secs(60);
+
+        let token = parse_token(
+            br#"{"access_token":"abc","token_type":"bearer","expires_in":3600}"#,
+            now,
+            refresh_before,
+        )
+        .unwrap();
+        assert_eq!(
+            token.value,
+            json::json!({ "token": "abc", "token_type": "bearer" })
+        );
+        assert_eq!(token.refresh_at, Some(now + Duration::from_secs(3540)));
+        assert_eq!(token.expires_at, Some(now + Duration::from_secs(3600)));
+
+        let token = parse_token(
+            br#"{"access_token":"abc","expires_in":"30"}"#,
+            now,
+            refresh_before,
+        )
+        .unwrap();
+        assert_eq!(token.value["token_type"], "Bearer");
+        assert_eq!(token.refresh_at, Some(now + Duration::from_secs(15)));
+
+        let token = parse_token(br#"{"access_token":"abc"}"#, now, refresh_before).unwrap();
+        assert!(token.refresh_at.is_none());
+        assert!(!token.needs_refresh(now + Duration::from_secs(1_000_000)));
+
+        let token = parse_token(
+            br#"{"access_token":"abc","expires_in":18446744073709551615}"#,
+            now,
+            refresh_before,
+        )
+        .unwrap();
+        assert!(token.refresh_at.is_none());
+        assert!(token.expires_at.is_none());
+
+        assert!(parse_token(br#"{"token_type":"bearer"}"#, now, refresh_before).is_err());
+        assert!(parse_token(b"not json", now, refresh_before).is_err());
+    }
+
+    fn auth_manager(token_url: String, grant: AuthGrant) -> AuthManager {
+        let auth = Auth {
+            token_url,
+            grant,
+            client_id: "pewpew".into(),
+            client_secret: Some("secret".into()),
+            scope: None,
+            refresh_before: Duration::from_secs(60),
+        };
+        let client = crate::create_http_client(Duration::from_secs(60)).unwrap();
+        AuthManager::new(auth, Arc::new(client), Duration::from_secs(10))
+    }
+
+    #[test]
+    fn requests_a_token_once() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let url = format!("http://127.0.0.1:{}/token", port);
+            let auth = auth_manager(url, AuthGrant::ClientCredentials);
+
+            let tokens = join_all((0..50).map(|_| auth.token())).await;
+            for token in tokens {
+                assert_eq!(token.unwrap()["token"], "token-1");
+            }
+        });
+    }
+
+    #[test]
+    fn refreshes_tokens_before_they_expire() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let url = format!("http://127.0.0.1:{}/token?expires_in=1", port);
+            let grant = AuthGrant::Password {
+                username: "user".into(),
+                password: "pass".into(),
+            };
+            let auth = auth_manager(url, grant);
+
+            assert_eq!(auth.token().await.unwrap()["token"], "token-1");
+            assert_eq!(auth.token().await.unwrap()["token"], "token-1");
+            Delay::new(Duration::from_millis(600)).await;
+            assert_eq!(auth.token().await.unwrap()["token"], "token-2");
+        });
+    }
+
+    #[test]
+    fn errors_without_a_token() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let url = format!("http://127.0.0.1:{}/missing", port);
+            let auth = auth_manager(url, AuthGrant::ClientCredentials);
+
+            let e = auth.token().await.unwrap_err();
+            assert!(matches!(e, RecoverableError::AuthTokenErr(_)), "{}", e);
+        });
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::RecoverableError;
+use crate::openapi::ResponseValidator;
 use crate::stats;
 
 use config::{EndpointProvidesSendOptions, Template};
@@ -15,20 +16,28 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     future::Future,
     sync::Arc,
-    time::{Instant, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
-use super::{BlockSender, Outgoing, ProviderDelays, ProviderOrLogger, StatsTx, TemplateValues};
+use super::{
+    assertions::Assertions, graphql, BlockSender, Outgoing, ProviderDelays, ProviderOrLogger,
+    StatsTx, TemplateValues,
+};
 
 pub(super) struct BodyHandler {
     pub(super) included_outgoing_indexes: BTreeSet<usize>,
     pub(super) now: Instant,
     pub(super) outgoing: Arc<Vec<Outgoing>>,
     pub(super) provider_delays: ProviderDelays,
+    pub(super) retries: usize,
     pub(super) stats_tx: StatsTx,
     pub(super) status: u16,
     pub(super) tags: Arc<BTreeMap<String, Template>>,
     pub(super) template_values: TemplateValues,
+    pub(super) throttled: Option<Duration>,
+    pub(super) graphql: bool,
+    pub(super) validator: Option<ResponseValidator>,
+    pub(super) assertions: Option<Arc<Assertions>>,
 }
 
 impl BodyHandler {
@@ -47,9 +56,17 @@ impl BodyHandler {
         let has_logger = outgoing.iter().any(|o| o.tx.is_logger());
         let rtt = self.now.elapsed().as_micros() as u64;
         let mut template_values = self.template_values;
-        template_values.insert("stats".into(), json::json!({ "rtt": rtt as f64 / 1000.0 }));
+        template_values.insert(
+            "stats".into(),
+            json::json!({ "rtt": rtt as f64 / 1000.0, "retries": self.retries }),
+        );
+        let mut graphql_errors = false;
+        let mut schema_violation = None;
         let error_result = match result {
             Ok(Some(body)) => {
+                graphql_errors = self.graphql && graphql::has_errors(&body);
+                let status = self.status;
+                schema_violation = self.validator.and_then(|v| v.validate(status, &body).err());
                 template_values
                     .get_mut("response")
                     .expect("template_values should have `response`")
@@ -74,6 +91,17 @@ impl BodyHandler {
             .collect();
         let tags = Arc::new(tags);
         self.provider_delays.log(&tags, &stats_tx);
+        if let Some(duration) = self.throttled {
+            let _ = stats_tx.unbounded_send(
+                stats::ResponseStat {
+                    kind: stats::StatKind::Throttled(duration),
+                    rtt: None,
+                    time: SystemTime::now(),
+                    tags: tags.clone(),
+                }
+                .into(),
+            );
+        }
 
         let send_response_stat = move |kind, rtt| {
             let mut futures = Vec::new();
@@ -118,6 +146,7 @@ impl BodyHandler {
         if let Some(f) = auto_returns {
             futures.push(f.map(|_| Ok(())).a().b3());
         }
+        let failed = error_result.is_some();
         if let Some(e) = error_result {
             let kind = stats::StatKind::RecoverableError(e);
             futures.push(send_response_stat(kind, None).a3());
@@ -206,7 +235,28 @@ impl BodyHandler {
                 futures.push(f.b().b3());
             }
         }
-        futures.push(send_response_stat(stats::StatKind::Response(self.status), Some(rtt)).a3());
+        // a response with graphql errors is a failure even when the status is a success
+        if graphql_errors {
+            futures.push(send_response_stat(stats::StatKind::GraphqlErrors, None).a3());
+        }
+        if let Some(e) = schema_violation {
+            let kind = stats::StatKind::RecoverableError(RecoverableError::SchemaViolation(e));
+            futures.push(send_response_stat(kind, None).a3());
+        }
+        // a failed assertion is counted by its name, and like the other errors above
+        // `send_response_stat` sends it to the endpoint's loggers with `error` set
+        if let (Some(assertions), false) = (&self.assertions, failed) {
+            for name in assertions.check(&template_values) {
+                let kind =
+                    stats::StatKind::RecoverableError(RecoverableError::AssertionFailed(name));
+                futures.push(send_response_stat(kind, None).a3());
+            }
+        }
+        let mut kind = stats::StatKind::Response(self.status);
+        if self.retries > 0 {
+            kind = stats::StatKind::Retry(kind.into());
+        }
+        futures.push(send_response_stat(kind, Some(rtt)).a3());
         try_join_all(futures).map_ok(|_| ())
     }
 }
@@ -214,9 +264,11 @@ impl BodyHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{line_writer::MsgType, providers};
     use channel::{Limit, Receiver};
     use futures::{channel::mpsc as futures_channel, executor::block_on, StreamExt};
     use maplit::{btreemap, btreeset};
+    use tokio::sync::broadcast;
 
     use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -260,12 +312,17 @@ mod tests {
         let bh = BodyHandler {
             now,
             provider_delays: ProviderDelays::new(),
+            retries: 0,
             template_values,
             included_outgoing_indexes,
             outgoing,
             stats_tx,
             status,
             tags,
+            throttled: None,
+            graphql: false,
+            validator: None,
+            assertions: None,
         };
 
         let auto_return_called = Arc::new(AtomicBool::new(false));
@@ -379,12 +436,17 @@ mod tests {
         let bh = BodyHandler {
             now,
             provider_delays: ProviderDelays::new(),
+            retries: 0,
             template_values,
             included_outgoing_indexes,
             outgoing,
             stats_tx,
             status,
             tags,
+            throttled: None,
+            graphql: false,
+            validator: None,
+            assertions: None,
         };
 
         type AutoReturns = Option<Box<dyn Future<Output = ()> + Send + Unpin>>;
@@ -433,4 +495,60 @@ mod tests {
         };
         assert!(b, "receiver 3 is closed, {:?}", r);
     }
+
+    #[test]
+    fn logs_failed_assertions() {
+        let now = Instant::now();
+        let template_values = json::json!({"response": {"status": 500}}).into();
+        let included_outgoing_indexes = btreeset!(0);
+
+        let logger = config::Logger {
+            to: "".into(),
+            pretty: false,
+            limit: None,
+            kill: false,
+        };
+        let (test_killer, _) = broadcast::channel(1);
+        let (writer, mut writer_rx) = futures_channel::channel(5);
+        let logger = providers::logger(logger, &test_killer, writer);
+        let select = Select::simple("error.msg", Block, None, Some("error"), None);
+        let outgoing = vec![Outgoing::new(select, ProviderOrLogger::Logger(logger))].into();
+
+        let assertions = vec![config::Assertion::simple(
+            "ok",
+            "response.status == 200",
+            None,
+        )];
+        let assertions = Assertions::new(assertions, test_killer).map(Arc::new);
+
+        let (stats_tx, _) = futures_channel::unbounded();
+        let bh = BodyHandler {
+            now,
+            provider_delays: ProviderDelays::new(),
+            retries: 0,
+            template_values,
+            included_outgoing_indexes,
+            outgoing,
+            stats_tx,
+            status: 500,
+            tags: Default::default(),
+            throttled: None,
+            graphql: false,
+            validator: None,
+            assertions,
+        };
+
+        type AutoReturns = Option<Box<dyn Future<Output = ()> + Send + Unpin>>;
+        let auto_returns: AutoReturns = None;
+
+        let r = block_on(bh.handle(Ok(Some(json::json!("oops"))), auto_returns));
+        assert!(r.is_ok());
+
+        let r = writer_rx.next().now_or_never();
+        let b = matches!(&r, Some(Some(MsgType::Other(s))) if s == "assertion `ok` failed\n");
+        assert!(b, "logger received the failed assertion, {:?}", r);
+        let r = writer_rx.next().now_or_never();
+        let b = matches!(r, Some(None));
+        assert!(b, "logger is closed, {:?}", r);
+    }
 }
@@ -0,0 +1,100 @@
+use crate::error::{RecoverableError, TestError};
+
+use bytes::Bytes;
+use config::ContentEncoding;
+use http_body_util::{BodyExt, Full};
+use libflate::{gzip, zlib};
+
+use std::{io::Write, sync::Arc};
+
+use super::HyperBody;
+
+/// Compresses a request body with the `compress_body` encoding. The whole body is buffered so
+/// the compressed length can be sent in the `content-length` header
+pub(super) async fn compress_body(
+    encoding: ContentEncoding,
+    body: HyperBody,
+) -> Result<(u64, HyperBody), TestError> {
+    let bytes = body
+        .collect()
+        .await
+        .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?
+        .to_bytes();
+    let compressed =
+        compress(encoding, &bytes).map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+    let length = compressed.len() as u64;
+    let body = Full::new(Bytes::from(compressed))
+        .map_err(|never| match never {})
+        .boxed();
+    Ok((length, body))
+}
+
+fn compress(encoding: ContentEncoding, bytes: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    match encoding {
+        ContentEncoding::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+            encoder.write_all(bytes)?;
+            Ok(encoder.into_inner())
+        }
+        // `deflate` as a content coding is zlib wrapped data, not raw deflate
+        ContentEncoding::Deflate => {
+            let mut encoder = zlib::Encoder::new(Vec::new())?;
+            encoder.write_all(bytes)?;
+            encoder.finish().into_result()
+        }
+        ContentEncoding::Gzip => {
+            let mut encoder = gzip::Encoder::new(Vec::new())?;
+            encoder.write_all(bytes)?;
+            encoder.finish().into_result()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use body_reader::{BodyReader, Compression};
+    use bytes::BytesMut;
+    use futures::executor::block_on;
+    use std::io::Read;
+
+    #[test]
+    fn compresses_bodies() {
+        let text = "the quick brown fox jumps over the lazy dog ".repeat(100);
+        let decode = |compression, compressed| {
+            let mut decompressed = BytesMut::new();
+            BodyReader::new(compression)
+                .decode(compressed, &mut decompressed)
+                .unwrap();
+            decompressed.to_vec()
+        };
+        for encoding in [
+            ContentEncoding::Brotli,
+            ContentEncoding::Deflate,
+            ContentEncoding::Gzip,
+        ] {
+            let body = Full::new(Bytes::from(text.clone()))
+                .map_err(|never| match never {})
+                .boxed();
+            let (length, body) = block_on(compress_body(encoding, body)).unwrap();
+            let compressed = block_on(body.collect()).unwrap().to_bytes();
+            assert_eq!(length, compressed.len() as u64);
+            assert!(compressed.len() < text.len(), "{:?}", encoding);
+
+            let decompressed = match encoding {
+                // decoded the way a server inflating the body per spec would
+                ContentEncoding::Deflate => {
+                    let mut decompressed = Vec::new();
+                    zlib::Decoder::new(&compressed[..])
+                        .unwrap()
+                        .read_to_end(&mut decompressed)
+                        .unwrap();
+                    decompressed
+                }
+                ContentEncoding::Brotli => decode(Compression::Brotli, compressed),
+                ContentEncoding::Gzip => decode(Compression::Gzip, compressed),
+            };
+            assert_eq!(decompressed, text.as_bytes(), "{:?}", encoding);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use cookie_store::{CookieStore, RawCookie};
+use hyper::header::{HeaderMap, SET_COOKIE};
+use serde_json as json;
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// All the cookie jars for a test. Each jar is identified by the evaluated `cookie_jar` template
+/// of an endpoint, so endpoints which evaluate to the same key share cookies
+#[derive(Clone, Default)]
+pub struct CookieJars(Arc<Mutex<BTreeMap<String, CookieStore>>>);
+
+impl CookieJars {
+    pub(super) fn jar(&self, key: String) -> CookieJar {
+        CookieJar {
+            jars: self.clone(),
+            key,
+        }
+    }
+}
+
+/// A handle to a single cookie jar
+#[derive(Clone)]
+pub(super) struct CookieJar {
+    jars: CookieJars,
+    key: String,
+}
+
+impl CookieJar {
+    /// The value for a `cookie` header for a request to `url`, if the jar has any matching cookies
+    pub(super) fn request_cookie_header(&self, url: &url::Url) -> Option<String> {
+        let jars = self
+            .jars
+            .0
+            .lock()
+            .expect("cookie jars lock should not be poisoned");
+        let cookies: Vec<_> = jars
+            .get(&self.key)?
+            .get_request_values(url)
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect();
+        if cookies.is_empty() {
+            None
+        } else {
+            Some(cookies.join("; "))
+        }
+    }
+
+    /// Stores any cookies from the `set-cookie` headers of a response to `url`
+    pub(super) fn store_response_cookies(&self, url: &url::Url, headers: &HeaderMap) {
+        let cookies: Vec<_> = response_cookies(headers).collect();
+        if cookies.is_empty() {
+            return;
+        }
+        let mut jars = self
+            .jars
+            .0
+            .lock()
+            .expect("cookie jars lock should not be poisoned");
+        jars.entry(self.key.clone())
+            .or_default()
+            .store_response_cookies(cookies.into_iter(), url);
+    }
+}
+
+fn response_cookies(headers: &HeaderMap) -> impl Iterator<Item = RawCookie<'static>> + '_ {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| RawCookie::parse(v.to_string()).ok())
+}
+
+/// The cookies in a `cookie` header as a json object for `request.cookies`
+pub(super) fn request_cookies_json(headers: &HeaderMap) -> json::Value {
+    let cookies = headers
+        .get_all(hyper::header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|c| {
+            let (name, value) = c.trim().split_once('=')?;
+            Some((name.to_string(), json::Value::String(value.to_string())))
+        })
+        .collect();
+    json::Value::Object(cookies)
+}
+
+/// The cookies in the `set-cookie` headers of a response as a json object for `response.cookies`
+pub(super) fn response_cookies_json(headers: &HeaderMap) -> json::Value {
+    let cookies = response_cookies(headers)
+        .map(|c| {
+            (
+                c.name().to_string(),
+                json::Value::String(c.value().to_string()),
+            )
+        })
+        .collect();
+    json::Value::Object(cookies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    #[test]
+    fn cookie_jar_domain_and_path() {
+        let jars = CookieJars::default();
+        let jar = jars.jar("user1".into());
+        let url = url::Url::parse("http://example.com/account/login").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static("session=abc; Path=/account"),
+        );
+        headers.append(SET_COOKIE, HeaderValue::from_static("theme=dark; Path=/"));
+        headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static("old=1; Expires=Thu, 01 Jan 1970 00:00:00 GMT"),
+        );
+        jar.store_response_cookies(&url, &headers);
+
+        let url = url::Url::parse("http://example.com/account/home").unwrap();
+        let mut cookie = jar.request_cookie_header(&url).unwrap();
+        let mut pieces: Vec<_> = cookie.split("; ").collect();
+        pieces.sort_unstable();
+        assert_eq!(pieces, vec!["session=abc", "theme=dark"]);
+
+        let url = url::Url::parse("http://example.com/other").unwrap();
+        cookie = jar.request_cookie_header(&url).unwrap();
+        assert_eq!(cookie, "theme=dark");
+
+        let url = url::Url::parse("http://other.com/account/home").unwrap();
+        assert!(jar.request_cookie_header(&url).is_none());
+
+        let jar2 = jars.jar("user2".into());
+        let url = url::Url::parse("http://example.com/account/home").unwrap();
+        assert!(jar2.request_cookie_header(&url).is_none());
+    }
+
+    #[test]
+    fn cookies_json() {
+        let mut headers = HeaderMap::new();
+        headers.insert(hyper::header::COOKIE, HeaderValue::from_static("a=1; b=2"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("c=3; HttpOnly"));
+        assert_eq!(
+            request_cookies_json(&headers),
+            json::json!({"a": "1", "b": "2"})
+        );
+        assert_eq!(response_cookies_json(&headers), json::json!({"c": "3"}));
+    }
+}
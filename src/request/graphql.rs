@@ -0,0 +1,171 @@
+use crate::error::{RecoverableError, TestError};
+
+use config::{GraphqlBody, GraphqlQuery, GraphqlVariable};
+use serde_json as json;
+
+use std::{borrow::Cow, path::PathBuf, sync::Arc};
+
+use super::TemplateValues;
+
+/// Reads the query of a `graphql` body from its file, and returns the name of its operation
+pub(super) fn load_query(graphql: &mut GraphqlBody) -> Result<Option<String>, TestError> {
+    if let GraphqlQuery::File(path) = &graphql.query {
+        let path = PathBuf::from(path);
+        let query = std::fs::read_to_string(&path)
+            .map_err(|e| TestError::CannotOpenFile(path, Arc::new(e)))?;
+        graphql.query = GraphqlQuery::String(query);
+    }
+    let name = match (&graphql.operation_name, &graphql.query) {
+        (Some(name), _) => Some(name.clone()),
+        (None, GraphqlQuery::String(query)) => operation_name(query).map(Into::into),
+        (None, GraphqlQuery::File(_)) => None,
+    };
+    Ok(name)
+}
+
+/// The name of the first operation in a query, like `GetUser` in `query GetUser { ... }`
+fn operation_name(query: &str) -> Option<&str> {
+    let mut rest = query.trim_start();
+    // skip any leading comments
+    while let Some(comment) = rest.strip_prefix('#') {
+        rest = comment.split_once('\n')?.1.trim_start();
+    }
+    let rest = ["query", "mutation", "subscription"]
+        .iter()
+        .find_map(|op| rest.strip_prefix(op))?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let end = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    (end > 0).then(|| &rest[..end])
+}
+
+/// Builds the json request body. Variables are sent as strings, except for the ones marked as
+/// json which are parsed
+pub(super) fn request_body(
+    graphql: &GraphqlBody,
+    template_values: &TemplateValues,
+) -> Result<String, TestError> {
+    let query = match &graphql.query {
+        GraphqlQuery::String(query) => query,
+        GraphqlQuery::File(_) => unreachable!("graphql query should have been loaded"),
+    };
+    let variables = graphql
+        .variables
+        .iter()
+        .map(|(k, v)| {
+            let value = match v {
+                GraphqlVariable::String(t) => {
+                    json::Value::String(t.evaluate(Cow::Borrowed(template_values.as_json()), None)?)
+                }
+                GraphqlVariable::Json(t) => {
+                    let value = t.evaluate(Cow::Borrowed(template_values.as_json()), None)?;
+                    json::from_str(&value).map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?
+                }
+            };
+            Ok((k.clone(), value))
+        })
+        .collect::<Result<json::Map<_, _>, TestError>>()?;
+    let mut body = json::json!({ "query": query, "variables": variables });
+    if let Some(name) = &graphql.operation_name {
+        body["operationName"] = name.as_str().into();
+    }
+    Ok(body.to_string())
+}
+
+/// Whether a response body has any graphql errors
+pub(super) fn has_errors(body: &json::Value) -> bool {
+    matches!(body.get("errors"), Some(json::Value::Array(errors)) if !errors.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Template;
+
+    #[test]
+    fn finds_operation_names() {
+        let values = vec![
+            (
+                "query GetUser($id: ID!) { user(id: $id) { name } }",
+                Some("GetUser"),
+            ),
+            (
+                "# a comment\n  mutation\n  AddUser { add }",
+                Some("AddUser"),
+            ),
+            ("subscription OnEvent{ event }", Some("OnEvent")),
+            ("query { user { name } }", None),
+            ("{ user { name } }", None),
+            ("queryGetUser { user }", None),
+            ("fragment F on User { name } query GetUser { user }", None),
+        ];
+        for (query, expected) in values {
+            assert_eq!(operation_name(query), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn builds_request_bodies() {
+        let mut graphql = GraphqlBody {
+            query: GraphqlQuery::String("query GetUser($id: ID!) { user(id: $id) }".into()),
+            variables: vec![
+                (
+                    "id".into(),
+                    GraphqlVariable::String(Template::simple("123")),
+                ),
+                (
+                    "name".into(),
+                    GraphqlVariable::String(Template::simple("bob")),
+                ),
+                ("age".into(), GraphqlVariable::Json(Template::simple("42"))),
+                (
+                    "filter".into(),
+                    GraphqlVariable::Json(Template::simple(r#"{"active": true}"#)),
+                ),
+            ],
+            operation_name: None,
+        };
+        assert_eq!(load_query(&mut graphql).unwrap(), Some("GetUser".into()));
+        let body: json::Value =
+            json::from_str(&request_body(&graphql, &TemplateValues::new()).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json::json!({
+                "query": "query GetUser($id: ID!) { user(id: $id) }",
+                "variables": {
+                    "id": "123",
+                    "name": "bob",
+                    "age": 42,
+                    "filter": { "active": true },
+                },
+            })
+        );
+
+        graphql.variables = vec![("age".into(), GraphqlVariable::Json(Template::simple("x")))];
+        assert!(matches!(
+            request_body(&graphql, &TemplateValues::new()),
+            Err(TestError::Recoverable(RecoverableError::BodyErr(_)))
+        ));
+        graphql.variables.clear();
+
+        graphql.operation_name = Some("Other".into());
+        assert_eq!(load_query(&mut graphql).unwrap(), Some("Other".into()));
+        let body: json::Value =
+            json::from_str(&request_body(&graphql, &TemplateValues::new()).unwrap()).unwrap();
+        assert_eq!(body["operationName"], "Other");
+    }
+
+    #[test]
+    fn detects_errors() {
+        assert!(has_errors(
+            &json::json!({ "data": null, "errors": [{ "message": "not found" }] })
+        ));
+        assert!(!has_errors(&json::json!({ "data": {}, "errors": [] })));
+        assert!(!has_errors(&json::json!({ "data": {} })));
+        assert!(!has_errors(&json::json!("errors")));
+    }
+}
@@ -0,0 +1,455 @@
+use crate::connector::Connector;
+use crate::error::{RecoverableError, TestError};
+use crate::stats;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use config::{BodyTemplate, Template};
+use futures::future::{self, join_all, Either};
+use futures_timer::Delay;
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::Incoming,
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, TE},
+    Method, Request, Response,
+};
+use hyper_util::client::legacy::Client;
+use log::debug;
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MethodDescriptor, SerializeOptions};
+use serde_json as json;
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    future::Future,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{
+    evaluate_tags, request_maker::collect_stream_items, send_outgoing, send_stat, HyperBody,
+    Outgoing, StatsTx, StreamItem, Tags, TemplateValues,
+};
+
+// the length prefixed framing of a gRPC message is a compressed flag followed by the length
+const FRAME_HEADER_LEN: usize = 5;
+
+/// Loads the descriptor for an endpoint's gRPC method from a protobuf descriptor set
+pub(super) fn load_method(grpc: &config::Grpc) -> Result<MethodDescriptor, TestError> {
+    let path = PathBuf::from(&grpc.descriptor);
+    let bytes =
+        std::fs::read(&path).map_err(|e| TestError::CannotOpenFile(path.clone(), e.into()))?;
+    let pool = DescriptorPool::decode(bytes.as_slice())
+        .map_err(|e| TestError::InvalidGrpcDescriptor(path, e.into()))?;
+    let (service, method) = grpc
+        .method
+        .split_once('/')
+        .expect("grpc method should have been validated by the config");
+    pool.get_service_by_name(service)
+        .and_then(|s| s.methods().find(|m| m.name() == method))
+        .ok_or_else(|| TestError::GrpcMethodNotFound(grpc.method.clone()))
+}
+
+/// Makes the calls for a `grpc` endpoint. Each hit sends a single request message, built from the
+/// json body, and every message in the response is run through the `provides` and `logs`
+pub(super) struct GrpcMaker {
+    pub(super) url: Template,
+    pub(super) headers: Vec<(String, Template)>,
+    pub(super) body: BodyTemplate,
+    pub(super) method: MethodDescriptor,
+    pub(super) client: Arc<Client<Connector, HyperBody>>,
+    pub(super) stats_tx: StatsTx,
+    pub(super) no_auto_returns: bool,
+    pub(super) outgoing: Arc<Vec<Outgoing>>,
+    pub(super) tags: Arc<BTreeMap<String, Template>>,
+    pub(super) timeout: Duration,
+}
+
+impl GrpcMaker {
+    pub(super) fn send_request(
+        self: &Arc<Self>,
+        values: Vec<StreamItem>,
+    ) -> impl Future<Output = Result<(), TestError>> {
+        let (template_values, auto_returns, provider_delays) =
+            collect_stream_items(values, self.no_auto_returns);
+        let maker = self.clone();
+        async move {
+            let tags = evaluate_tags(&maker.tags, &template_values);
+            let call = Box::pin(maker.call(template_values, &tags));
+            let result = match future::select(call, Delay::new(maker.timeout)).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => Err(RecoverableError::Timeout(SystemTime::now()).into()),
+            };
+            match result {
+                Ok(()) => (),
+                Err(TestError::Recoverable(e)) => {
+                    let rtt = match e {
+                        RecoverableError::Timeout(_) => Some(maker.timeout.as_micros() as u64),
+                        _ => None,
+                    };
+                    let kind = stats::StatKind::RecoverableError(e);
+                    send_stat(&maker.stats_tx, kind, rtt, tags.clone());
+                }
+                Err(e) => return Err(e),
+            }
+            provider_delays.log(&tags, &maker.stats_tx);
+            join_all(auto_returns).await;
+            Ok(())
+        }
+    }
+
+    async fn call(
+        &self,
+        mut template_values: TemplateValues,
+        tags: &Tags,
+    ) -> Result<(), TestError> {
+        let url = self
+            .url
+            .evaluate(Cow::Borrowed(template_values.as_json()), None)?;
+        let url = format!(
+            "{}/{}/{}",
+            url.trim_end_matches('/'),
+            self.method.parent_service().full_name(),
+            self.method.name()
+        );
+        let uri: hyper::Uri = url
+            .parse()
+            .map_err(|_| TestError::InvalidUrl(url.clone()))?;
+        let body = match &self.body {
+            BodyTemplate::String(t) => {
+                t.evaluate(Cow::Borrowed(template_values.as_json()), None)?
+            }
+            _ => "{}".into(),
+        };
+        let message = encode_message(&self.method, &body)?;
+
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(CONTENT_TYPE, "application/grpc")
+            .header(TE, "trailers");
+        for (k, v) in &self.headers {
+            let key = HeaderName::from_bytes(k.as_bytes())
+                .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+            let value =
+                HeaderValue::from_str(&v.evaluate(Cow::Borrowed(template_values.as_json()), None)?)
+                    .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+            request = request.header(key, value);
+        }
+        let request = request
+            .body(Full::new(message).map_err(|never| match never {}).boxed())
+            .map_err(|e| TestError::RequestBuilderErr(e.into()))?;
+        template_values.insert(
+            "request".into(),
+            json::json!({ "url": url, "method": "POST", "body": body }),
+        );
+
+        debug!("GrpcMaker::call url=\"{}\" body=\"{}\"", url, body);
+        let now = Instant::now();
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|e| RecoverableError::ConnectionErr(SystemTime::now(), Arc::new(e)))?;
+        self.handle_response(response, template_values, now, tags)
+            .await
+    }
+
+    async fn handle_response(
+        &self,
+        response: Response<Incoming>,
+        template_values: TemplateValues,
+        now: Instant,
+        tags: &Tags,
+    ) -> Result<(), TestError> {
+        let status = response.status().as_u16();
+        let (parts, mut body) = response.into_parts();
+        // an error with no messages can be sent as "trailers-only", which puts them in the headers
+        let mut grpc_status = parse_grpc_status(&parts.headers);
+        let mut buffer = BytesMut::new();
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+            let frame = match frame.into_data() {
+                Ok(data) => {
+                    buffer.extend_from_slice(&data);
+                    while let Some(message) = next_message(&mut buffer)? {
+                        let body = decode_message(&self.method, message)?;
+                        self.handle_message(&template_values, body, status, now, tags)
+                            .await;
+                    }
+                    continue;
+                }
+                Err(frame) => frame,
+            };
+            if let Some(code) = frame.trailers_ref().and_then(parse_grpc_status) {
+                grpc_status = Some(code);
+            }
+        }
+        let rtt = now.elapsed().as_micros() as u64;
+        let kind = stats::StatKind::Response(status);
+        send_stat(&self.stats_tx, kind, Some(rtt), tags.clone());
+        if let Some(code) = grpc_status {
+            let kind = stats::StatKind::GrpcStatus(code);
+            send_stat(&self.stats_tx, kind, None, tags.clone());
+        }
+        Ok(())
+    }
+
+    async fn handle_message(
+        &self,
+        template_values: &TemplateValues,
+        body: json::Value,
+        status: u16,
+        now: Instant,
+        tags: &Tags,
+    ) {
+        let mut template_values = template_values.clone();
+        template_values.insert(
+            "response".into(),
+            json::json!({ "body": body, "status": status }),
+        );
+        let rtt = now.elapsed().as_micros() as u64;
+        template_values.insert("stats".into(), json::json!({ "rtt": rtt as f64 / 1000.0 }));
+        let template_values = Arc::new(template_values.0);
+        for result in join_all(send_outgoing(&self.outgoing, &template_values)).await {
+            if let Err(e) = result {
+                let kind = stats::StatKind::RecoverableError(e);
+                send_stat(&self.stats_tx, kind, None, tags.clone());
+            }
+        }
+    }
+}
+
+/// Builds the request message from its json and frames it
+fn encode_message(method: &MethodDescriptor, body: &str) -> Result<Bytes, RecoverableError> {
+    let mut deserializer = json::Deserializer::from_str(body);
+    let message = DynamicMessage::deserialize(method.input(), &mut deserializer)
+        .and_then(|m| deserializer.end().map(|_| m))
+        .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+    let len = message.encoded_len();
+    let mut buf = BytesMut::with_capacity(FRAME_HEADER_LEN + len);
+    buf.put_u8(0);
+    buf.put_u32(len as u32);
+    message
+        .encode(&mut buf)
+        .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+    Ok(buf.freeze())
+}
+
+/// Takes the next complete message off the front of the buffer, if there is one
+fn next_message(buffer: &mut BytesMut) -> Result<Option<Bytes>, RecoverableError> {
+    if buffer.len() < FRAME_HEADER_LEN {
+        return Ok(None);
+    }
+    let compressed = buffer[0] != 0;
+    let len = u32::from_be_bytes([buffer[1], buffer[2], buffer[3], buffer[4]]) as usize;
+    if buffer.len() < FRAME_HEADER_LEN + len {
+        return Ok(None);
+    }
+    // compression is never requested, so a server shouldn't compress its messages
+    if compressed {
+        return Err(RecoverableError::BodyErr(Arc::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "received a compressed grpc message",
+        ))));
+    }
+    buffer.advance(FRAME_HEADER_LEN);
+    Ok(Some(buffer.split_to(len).freeze()))
+}
+
+/// Decodes a response message into json
+fn decode_message(
+    method: &MethodDescriptor,
+    message: Bytes,
+) -> Result<json::Value, RecoverableError> {
+    let message = DynamicMessage::decode(method.output(), message)
+        .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+    // fields with default values are kept so they can be selected like any other field
+    let options = SerializeOptions::new().skip_default_fields(false);
+    message
+        .serialize_with_options(json::value::Serializer, &options)
+        .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))
+}
+
+fn parse_grpc_status(headers: &HeaderMap) -> Option<u32> {
+    headers.get("grpc-status")?.to_str().ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::ProviderOrLogger;
+    use channel::Limit;
+    use config::{EndpointProvidesSendOptions::Block, Select};
+    use futures::{channel::mpsc as futures_channel, StreamExt};
+    use maplit::btreemap;
+    use prost_reflect::prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        MethodDescriptorProto, ServiceDescriptorProto,
+    };
+    use tokio::runtime::Runtime;
+
+    // the descriptor for a `test.Echo` service with a unary and a server streaming method
+    fn echo_descriptor() -> Vec<u8> {
+        let message = DescriptorProto {
+            name: Some("Message".into()),
+            field: vec![
+                FieldDescriptorProto {
+                    name: Some("text".into()),
+                    json_name: Some("text".into()),
+                    number: Some(1),
+                    label: Some(Label::Optional.into()),
+                    r#type: Some(Type::String.into()),
+                    ..Default::default()
+                },
+                FieldDescriptorProto {
+                    name: Some("count".into()),
+                    json_name: Some("count".into()),
+                    number: Some(2),
+                    label: Some(Label::Optional.into()),
+                    r#type: Some(Type::Int32.into()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let method = |name: &str, server_streaming| MethodDescriptorProto {
+            name: Some(name.into()),
+            input_type: Some(".test.Message".into()),
+            output_type: Some(".test.Message".into()),
+            server_streaming: Some(server_streaming),
+            ..Default::default()
+        };
+        let file = FileDescriptorProto {
+            name: Some("echo.proto".into()),
+            package: Some("test".into()),
+            message_type: vec![message],
+            service: vec![ServiceDescriptorProto {
+                name: Some("Echo".into()),
+                method: vec![method("Unary", false), method("Stream", true)],
+                ..Default::default()
+            }],
+            syntax: Some("proto3".into()),
+            ..Default::default()
+        };
+        FileDescriptorSet { file: vec![file] }.encode_to_vec()
+    }
+
+    fn load(method: &str) -> Result<MethodDescriptor, TestError> {
+        let dir = std::env::temp_dir().join(format!("pewpew-grpc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.pb", method.replace('/', "_")));
+        std::fs::write(&path, echo_descriptor()).unwrap();
+        let grpc = config::Grpc {
+            descriptor: path.to_string_lossy().into_owned(),
+            method: method.into(),
+        };
+        load_method(&grpc)
+    }
+
+    #[test]
+    fn encodes_and_decodes_messages() {
+        let method = load("test.Echo/Unary").unwrap();
+        assert!(matches!(
+            load("test.Echo/Missing"),
+            Err(TestError::GrpcMethodNotFound(_))
+        ));
+
+        let mut buffer = BytesMut::new();
+        let message = encode_message(&method, r#"{"text": "hi"}"#).unwrap();
+        buffer.extend_from_slice(&message[..3]);
+        assert!(next_message(&mut buffer).unwrap().is_none());
+        buffer.extend_from_slice(&message[3..]);
+        buffer.extend_from_slice(&message);
+        for _ in 0..2 {
+            let message = next_message(&mut buffer).unwrap().unwrap();
+            assert_eq!(
+                decode_message(&method, message).unwrap(),
+                json::json!({ "text": "hi", "count": 0 })
+            );
+        }
+        assert!(buffer.is_empty());
+
+        assert!(encode_message(&method, r#"{"other": 1}"#).is_err());
+        assert!(encode_message(&method, "hi").is_err());
+    }
+
+    #[test]
+    fn calls_grpc_methods() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            for (name, expected) in [("Unary", 1), ("Stream", 3)] {
+                let (tx, rx) = channel::channel(Limit::Static(5), false, "grpc");
+                let select = Select::simple("response.body.count", Block, None, None, None);
+                let outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))];
+                let (stats_tx, stats_rx) = futures_channel::unbounded();
+                let tags = btreemap! {"method".into() => Template::simple("POST")};
+                let gm = Arc::new(GrpcMaker {
+                    url: Template::simple(&format!("http://127.0.0.1:{}/", port)),
+                    headers: Vec::new(),
+                    body: BodyTemplate::String(Template::simple(r#"{"count": 7}"#)),
+                    method: load(&format!("test.Echo/{}", name)).unwrap(),
+                    client: crate::create_grpc_client(Duration::from_secs(60))
+                        .unwrap()
+                        .into(),
+                    stats_tx,
+                    no_auto_returns: true,
+                    outgoing: outgoing.into(),
+                    tags: tags.into(),
+                    timeout: Duration::from_secs(10),
+                });
+                gm.send_request(Vec::new()).await.unwrap();
+                drop(gm);
+
+                let values: Vec<_> = rx.take(expected).collect().await;
+                assert_eq!(values, vec![json::json!(7); expected]);
+
+                let kinds: Vec<_> = stats_rx
+                    .map(|s| match s {
+                        stats::StatsMessage::ResponseStat(rs) => format!("{:?}", rs.kind),
+                        _ => panic!("expected a response stat"),
+                    })
+                    .collect()
+                    .await;
+                assert_eq!(kinds, vec!["Response(200)", "GrpcStatus(0)"]);
+            }
+        });
+    }
+
+    #[test]
+    fn invalid_metadata_is_recoverable() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (stats_tx, stats_rx) = futures_channel::unbounded();
+            let gm = Arc::new(GrpcMaker {
+                url: Template::simple("http://127.0.0.1:1/"),
+                headers: vec![("x-id".into(), Template::simple("a\nb"))],
+                body: BodyTemplate::None,
+                method: load("test.Echo/Unary").unwrap(),
+                client: crate::create_grpc_client(Duration::from_secs(60))
+                    .unwrap()
+                    .into(),
+                stats_tx,
+                no_auto_returns: true,
+                outgoing: Default::default(),
+                tags: Default::default(),
+                timeout: Duration::from_secs(10),
+            });
+            gm.send_request(Vec::new()).await.unwrap();
+            drop(gm);
+
+            let kinds: Vec<_> = stats_rx
+                .map(|s| match s {
+                    stats::StatsMessage::ResponseStat(rs) => format!("{:?}", rs.kind),
+                    _ => panic!("expected a response stat"),
+                })
+                .collect()
+                .await;
+            assert_eq!(kinds.len(), 1);
+            assert!(kinds[0].contains("BodyErr"), "{:?}", kinds);
+        });
+    }
+}
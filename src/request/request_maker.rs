@@ -1,9 +1,12 @@
+use crate::connector::{self, Connector};
 use crate::error::{RecoverableError, TestError};
+use crate::openapi::Spec;
 use crate::stats;
 
+use bytes::Bytes;
 use config::{
-    BodyTemplate, Template, REQUEST_BODY, REQUEST_HEADERS, REQUEST_HEADERS_ALL, REQUEST_STARTLINE,
-    REQUEST_URL,
+    BodyTemplate, ContentEncoding, Retry, RetryOn, Sign, StreamResponse, Template, REQUEST_BODY,
+    REQUEST_COOKIES, REQUEST_HEADERS, REQUEST_HEADERS_ALL, REQUEST_STARTLINE, REQUEST_URL,
 };
 use ether::EitherExt;
 use futures::{
@@ -11,22 +14,28 @@ use futures::{
     FutureExt, TryFutureExt,
 };
 use futures_timer::Delay;
-use http_body_util::{combinators::BoxBody, BodyExt};
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
 use hyper::{
-    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, HOST},
-    Method, Request,
-};
-use hyper_tls::HttpsConnector;
-use hyper_util::client::legacy::{
-    connect::{dns::GaiResolver, HttpConnector},
-    Client,
+    body::Incoming,
+    header::{
+        HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH,
+        CONTENT_TYPE, COOKIE, HOST, LOCATION, SET_COOKIE,
+    },
+    Method, Request, Response, StatusCode,
 };
+use hyper_util::client::legacy::Client;
 use log::{debug, info};
+use rand::Rng;
 use serde_json as json;
 
 use super::{
-    body_template_as_hyper_body, response_handler::ResponseHandler, AutoReturn, BlockSender,
-    Outgoing, StatsTx, StreamItem, TemplateValues,
+    assertions::Assertions,
+    body_template_as_hyper_body, compression,
+    cookie_jar::{request_cookies_json, CookieJar, CookieJars},
+    response_handler::ResponseHandler,
+    sign,
+    throttle::{self, Throttle},
+    AutoReturn, BlockSender, HyperBody, Outgoing, StatsTx, StreamItem, TemplateValues,
 };
 
 use std::{
@@ -34,7 +43,10 @@ use std::{
     collections::BTreeMap,
     error::Error as StdError,
     future::Future,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     task::Poll,
     time::{Duration, Instant, SystemTime},
 };
@@ -45,15 +57,23 @@ pub(super) struct RequestMaker {
     pub(super) headers: Vec<(String, Template)>,
     pub(super) body: BodyTemplate,
     pub(super) rr_providers: u16,
-    pub(super) client: Arc<
-        Client<HttpsConnector<HttpConnector<GaiResolver>>, BoxBody<bytes::Bytes, std::io::Error>>,
-    >,
+    pub(super) client: Arc<Client<Connector, BoxBody<bytes::Bytes, std::io::Error>>>,
     pub(super) stats_tx: StatsTx,
     pub(super) no_auto_returns: bool,
     pub(super) outgoing: Arc<Vec<Outgoing>>,
     pub(super) precheck_rr_providers: u16,
     pub(super) tags: Arc<BTreeMap<String, Template>>,
     pub(super) timeout: Duration,
+    pub(super) follow_redirects: usize,
+    pub(super) cookie_jar: Option<Template>,
+    pub(super) cookie_jars: CookieJars,
+    pub(super) retry: Option<Retry>,
+    pub(super) throttle: Option<Throttle>,
+    pub(super) compress_body: Option<ContentEncoding>,
+    pub(super) stream_response: Option<StreamResponse>,
+    pub(super) sign: Option<Sign>,
+    pub(super) openapi: Option<Arc<Spec>>,
+    pub(super) assertions: Option<Arc<Assertions>>,
 }
 
 pub(super) struct ProviderDelays {
@@ -85,6 +105,53 @@ impl ProviderDelays {
     }
 }
 
+/// Collects the values from an endpoint's streams into the template values for a single request,
+/// along with the auto returns to run afterwards and any providers which delayed the request
+pub(super) fn collect_stream_items(
+    values: Vec<StreamItem>,
+    no_auto_returns: bool,
+) -> (
+    TemplateValues,
+    Vec<impl Future<Output = ()>>,
+    ProviderDelays,
+) {
+    let mut template_values = TemplateValues::new();
+    let mut auto_returns = Vec::new();
+    let mut target_instant = None;
+    let mut provider_delays = ProviderDelays::new();
+    for tv in values {
+        match tv {
+            StreamItem::Instant(next_trigger) => {
+                target_instant = next_trigger;
+            }
+            StreamItem::Declare(name, value, returns, instant) => {
+                match target_instant {
+                    Some(target_instant) if instant > target_instant => {
+                        provider_delays.push(name.clone());
+                    }
+                    _ => (),
+                }
+                template_values.insert(name, value);
+                auto_returns.extend(returns.into_iter().map(AutoReturn::into_future));
+            }
+            StreamItem::None => (),
+            StreamItem::TemplateValue(name, value, auto_return, instant) => {
+                match target_instant {
+                    Some(target_instant) if instant > target_instant => {
+                        provider_delays.push(name.clone());
+                    }
+                    _ => (),
+                }
+                template_values.insert(name, value);
+                if let (Some(ar), false) = (auto_return, no_auto_returns) {
+                    auto_returns.push(ar.into_future());
+                }
+            }
+        };
+    }
+    (template_values, auto_returns, provider_delays)
+}
+
 impl RequestMaker {
     // this function is not async because of a compiler bug which raises a nonsensical error
     // https://github.com/rust-lang/rust/issues/71723
@@ -92,40 +159,8 @@ impl RequestMaker {
         &self,
         values: Vec<StreamItem>,
     ) -> impl Future<Output = Result<(), TestError>> {
-        let mut template_values = TemplateValues::new();
-        let mut auto_returns = Vec::new();
-        let mut target_instant = None;
-        let mut provider_delays = ProviderDelays::new();
-        for tv in values {
-            match tv {
-                StreamItem::Instant(next_trigger) => {
-                    target_instant = next_trigger;
-                }
-                StreamItem::Declare(name, value, returns, instant) => {
-                    match target_instant {
-                        Some(target_instant) if instant > target_instant => {
-                            provider_delays.push(name.clone());
-                        }
-                        _ => (),
-                    }
-                    template_values.insert(name, value);
-                    auto_returns.extend(returns.into_iter().map(AutoReturn::into_future));
-                }
-                StreamItem::None => (),
-                StreamItem::TemplateValue(name, value, auto_return, instant) => {
-                    match target_instant {
-                        Some(target_instant) if instant > target_instant => {
-                            provider_delays.push(name.clone());
-                        }
-                        _ => (),
-                    }
-                    template_values.insert(name, value);
-                    if let (Some(ar), false) = (auto_return, self.no_auto_returns) {
-                        auto_returns.push(ar.into_future());
-                    }
-                }
-            };
-        }
+        let (mut template_values, auto_returns, provider_delays) =
+            collect_stream_items(values, self.no_auto_returns);
         let auto_returns = if auto_returns.is_empty() {
             None
         } else {
@@ -147,7 +182,7 @@ impl RequestMaker {
         };
         let request = Request::builder()
             .method(self.method.clone())
-            .uri(url.as_str());
+            .uri(connector::request_uri(&url).as_ref());
         let headers = self
             .headers
             .iter()
@@ -165,6 +200,15 @@ impl RequestMaker {
             Ok(h) => h,
             Err(e) => return future::ready(Err(e)).a(),
         };
+        let cookie_jar = self
+            .cookie_jar
+            .as_ref()
+            .map(|t| t.evaluate(Cow::Borrowed(template_values.as_json()), None))
+            .transpose();
+        let cookie_jar = match cookie_jar {
+            Ok(key) => key.map(|key| self.cookie_jars.jar(key)),
+            Err(e) => return future::ready(Err(e.into())).a(),
+        };
         let ct_entry = headers.entry(CONTENT_TYPE);
         let mut body_value = None;
         let body = body_template_as_hyper_body(
@@ -174,6 +218,26 @@ impl RequestMaker {
             &mut body_value,
             ct_entry,
         );
+        let compress_body = match self.body {
+            BodyTemplate::None => None,
+            _ => self.compress_body,
+        };
+        let body = match compress_body {
+            Some(encoding) => body
+                .and_then(move |(_, body)| compression::compress_body(encoding, body))
+                .a(),
+            None => body.b(),
+        };
+        // a signed request needs the whole body to compute its signature
+        let sign = self.sign.clone();
+        let signature_headers = sign
+            .as_ref()
+            .map(sign::signature_headers)
+            .unwrap_or_default();
+        let body = match sign {
+            Some(_) => body.and_then(sign::buffer_body).a(),
+            None => body.map_ok(|(length, body)| (length, body, None)).b(),
+        };
 
         let client = self.client.clone();
         let stats_tx = self.stats_tx.clone();
@@ -185,8 +249,15 @@ impl RequestMaker {
         let timeout = self.timeout;
         let tags = self.tags.clone();
         let auto_returns2 = auto_returns.clone();
+        let follow_redirects = self.follow_redirects;
+        let retry = self.retry.clone();
+        let throttle = self.throttle.clone();
+        let stream_response = self.stream_response.clone();
+        let graphql = matches!(self.body, BodyTemplate::Graphql(_));
+        let openapi = self.openapi.clone();
+        let assertions = self.assertions.clone();
 
-        body.and_then(move |(content_length, body)| {
+        body.and_then(move |(content_length, body, body_bytes)| {
             let request = request.body(body);
             let mut request = match request {
                 Ok(r) => r,
@@ -198,13 +269,29 @@ impl RequestMaker {
             // add the host header
             headers.insert(
                 HOST,
-                HeaderValue::from_str(url.host_str().expect("should be a valid url"))
+                HeaderValue::from_str(connector::host_header(&url))
                     .expect("url should be a valid string"),
             );
             // add the content-lengh header, if needed
             if content_length > 0 {
                 headers.insert(CONTENT_LENGTH, content_length.into());
             }
+            // add the content-encoding header for a compressed body
+            if let Some(encoding) = compress_body {
+                headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+            }
+            // add any cookies from the cookie jar
+            let cookie = headers.get(COOKIE).cloned();
+            if let Some(jar) = &cookie_jar {
+                merge_cookie_header(&mut headers, cookie.as_ref(), jar.request_cookie_header(&url));
+            }
+            // sign the request once all the other headers are set
+            if let (Some(sign), Some(body_bytes)) = (&sign, &body_bytes) {
+                let signed = sign::sign_request(sign, &method, &url, &mut headers, body_bytes, SystemTime::now());
+                if let Err(e) = signed {
+                    return future::ready(Err(e.into())).a();
+                }
+            }
             debug!("final headers={:?}", headers);
             info!("RequestMaker::send_request method=\"{}\" url=\"{}\" request_headers={:?} tags={:?}", method, url.as_str(), headers, tags);
             let mut request_provider = json::json!({});
@@ -275,6 +362,9 @@ impl RequestMaker {
                 }
                 request_obj.insert("headers_all".into(), json::Value::Object(headers_json));
             }
+            if rr_providers & REQUEST_COOKIES != 0 {
+                request_obj.insert("cookies".into(), request_cookies_json(&headers));
+            }
             if rr_providers & REQUEST_BODY != 0 {
                 let body_string = body_value.unwrap_or_else(|| "".into());
                 request_obj.insert("body".into(), body_string.into());
@@ -283,17 +373,14 @@ impl RequestMaker {
             template_values.insert("request".into(), request_provider);
             request.headers_mut().extend(headers);
 
-            let mut response_future = client.request(request).map_err(|e| {
-                let err: Arc<dyn StdError + Send + Sync> = Arc::new(e);
-                TestError::from(RecoverableError::ConnectionErr(SystemTime::now(), err))
-            });
-            let outgoing2 = outgoing.clone();
-            let mut template_values2 = template_values.clone();
-            let stats_tx2 = stats_tx.clone();
-            let tags2 = tags.clone();
-            let now = Instant::now();
-
-            let mut timeout = Delay::new(timeout);
+            let retries = Arc::new(AtomicUsize::new(0));
+            let response_future = if follow_redirects == 0 && retry.is_none() {
+                let mut response_future = client.request(request).map_err(|e| {
+                    let err: Arc<dyn StdError + Send + Sync> = Arc::new(e);
+                    TestError::from(RecoverableError::ConnectionErr(SystemTime::now(), err))
+                });
+                let now = Instant::now();
+                let mut timeout = Delay::new(timeout);
                 future::poll_fn(move |cx| {
                     match timeout.poll_unpin(cx) {
                         Poll::Ready(_) => Poll::Ready(Err(TestError::from(RecoverableError::Timeout(SystemTime::now())))),
@@ -307,7 +394,57 @@ impl RequestMaker {
                 }).and_then(|r| {
                     future::ready(r)
                 })
-                .and_then(move |response| {
+                .map_ok(move |response| {
+                    if let Some(jar) = &cookie_jar {
+                        jar.store_response_cookies(&url, response.headers());
+                    }
+                    Sent {
+                        response,
+                        redirects: Vec::new(),
+                        now,
+                        method,
+                        url,
+                    }
+                })
+                .a()
+            } else {
+                // redirect hops and retried attempts are recorded with the tags as they evaluate
+                // before the response
+                let hop_tags: BTreeMap<_, _> = tags
+                    .iter()
+                    .filter_map(|(k, v)| {
+                        v.evaluate(Cow::Borrowed(template_values.as_json()), None)
+                            .ok()
+                            .map(|v| (k.clone(), v))
+                    })
+                    .collect();
+                let sender = RequestSender {
+                    client: client.clone(),
+                    cookie,
+                    cookie_jar,
+                    signature_headers,
+                    max_redirects: follow_redirects,
+                    retry,
+                    retries: retries.clone(),
+                    honor_retry_after: throttle.is_some(),
+                    stats_tx: stats_tx.clone(),
+                    tags: Arc::new(hop_tags),
+                    timeout,
+                };
+                Box::pin(sender.send(request, url)).b()
+            };
+            let outgoing2 = outgoing.clone();
+            let mut template_values2 = template_values.clone();
+            let stats_tx2 = stats_tx.clone();
+            let tags2 = tags.clone();
+            let retries2 = retries.clone();
+
+                response_future
+                .and_then(move |Sent { response, redirects, now, method, url }| {
+                    // the operation is found from the final hop, which is the one that responded
+                    let validator = openapi.as_ref().and_then(|spec| {
+                        spec.validator(method.as_str(), connector::split_unix_url(&url).1)
+                    });
                     let rh = ResponseHandler {
                         provider_delays,
                         template_values,
@@ -317,6 +454,13 @@ impl RequestMaker {
                         now,
                         stats_tx,
                         tags,
+                        redirects,
+                        retries: retries.load(Ordering::Relaxed),
+                        throttle,
+                        stream_response,
+                        graphql,
+                        validator,
+                        assertions,
                     };
                     debug!("RequestMaker::send_request Response<Incoming>={:?}", response);
                     // Convert from a Response<Incoming> to a Response<BoxBody> to pass to handle()
@@ -367,9 +511,13 @@ impl RequestMaker {
                         RecoverableError::Timeout(_) => Some(timeout_in_micros),
                         _ => None,
                     };
+                    let mut kind = stats::StatKind::RecoverableError(r);
+                    if retries2.load(Ordering::Relaxed) > 0 {
+                        kind = stats::StatKind::Retry(kind.into());
+                    }
                     let _ = stats_tx2.unbounded_send(
                         stats::ResponseStat {
-                            kind: stats::StatKind::RecoverableError(r),
+                            kind,
                             rtt,
                             time,
                             tags,
@@ -386,48 +534,862 @@ impl RequestMaker {
     }
 }
 
+/// The final response to a request, after any redirects were followed
+struct Sent {
+    response: Response<Incoming>,
+    // the redirect chain, for `response.redirects`
+    redirects: Vec<json::Value>,
+    // when the final hop was sent
+    now: Instant,
+    // the method and url of the final hop, which change when a redirect is followed
+    method: Method,
+    url: url::Url,
+}
+
+struct RequestSender {
+    client: Arc<Client<Connector, HyperBody>>,
+    // the templated `cookie` header, before any cookies from the jar were added
+    cookie: Option<HeaderValue>,
+    cookie_jar: Option<CookieJar>,
+    // the headers added by signing the request, which are dropped on a cross origin redirect
+    signature_headers: Vec<HeaderName>,
+    max_redirects: usize,
+    retry: Option<Retry>,
+    retries: Arc<AtomicUsize>,
+    honor_retry_after: bool,
+    stats_tx: StatsTx,
+    tags: Arc<BTreeMap<String, String>>,
+    timeout: Duration,
+}
+
+impl RequestSender {
+    /// Sends the request, following up to `max_redirects` redirects and retrying according to
+    /// the `retry` config. Every hop and attempt except the final one is recorded in stats.
+    async fn send(self, request: Request<HyperBody>, url: url::Url) -> Result<Sent, TestError> {
+        let (parts, body) = request.into_parts();
+        // the body is buffered so it can be resent on a retry, 307 or 308
+        let body = body
+            .collect()
+            .await
+            .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?
+            .to_bytes();
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .send_attempt(
+                    parts.method.clone(),
+                    parts.headers.clone(),
+                    body.clone(),
+                    url.clone(),
+                    attempt,
+                )
+                .await;
+            let retry = match &self.retry {
+                Some(retry) if attempt < retry.max_attempts.get() => retry,
+                _ => return result,
+            };
+            let (kind, rtt, retry_after) = match result {
+                Ok(Sent { response, now, .. })
+                    if retry.on_status.contains(&response.status().as_u16()) =>
+                {
+                    let status = response.status().as_u16();
+                    let retry_after = self
+                        .honor_retry_after
+                        .then(|| throttle::retry_after(response.headers()))
+                        .flatten();
+                    let _ = response.into_body().collect().await;
+                    let rtt = now.elapsed().as_micros() as u64;
+                    (stats::StatKind::Response(status), Some(rtt), retry_after)
+                }
+                Err(TestError::Recoverable(e @ RecoverableError::ConnectionErr(..)))
+                    if retry.on_errors.contains(&RetryOn::ConnectionError) =>
+                {
+                    (stats::StatKind::RecoverableError(e), None, None)
+                }
+                Err(TestError::Recoverable(e @ RecoverableError::Timeout(..)))
+                    if retry.on_errors.contains(&RetryOn::Timeout) =>
+                {
+                    let rtt = self.timeout.as_micros() as u64;
+                    (stats::StatKind::RecoverableError(e), Some(rtt), None)
+                }
+                r => return r,
+            };
+            debug!(
+                "RequestSender::send retrying attempt={} url=\"{}\" kind={:?}",
+                attempt, url, kind
+            );
+            self.send_stat(kind, rtt, attempt);
+            // a server asking us to wait longer than the backoff wins
+            let delay = retry_delay(retry, attempt).max(retry_after.unwrap_or_default());
+            Delay::new(delay).await;
+            self.retries.store(attempt, Ordering::Relaxed);
+            attempt += 1;
+        }
+    }
+
+    async fn send_attempt(
+        &self,
+        mut method: Method,
+        mut headers: HeaderMap,
+        mut body: Bytes,
+        mut url: url::Url,
+        attempt: usize,
+    ) -> Result<Sent, TestError> {
+        let mut redirects = Vec::new();
+        let mut cookie = self.cookie.clone();
+        // the whole redirect chain, including reading the bodies of redirects, has to finish
+        // within the timeout
+        let deadline = Instant::now() + self.timeout;
+        let time_left = || Delay::new(deadline.saturating_duration_since(Instant::now()));
+        loop {
+            let mut request = Request::builder()
+                .method(method.clone())
+                .uri(connector::request_uri(&url).as_ref())
+                .body(
+                    Full::new(body.clone())
+                        .map_err(|never| match never {})
+                        .boxed(),
+                )
+                .map_err(|e| TestError::RequestBuilderErr(e.into()))?;
+            request.headers_mut().extend(headers.clone());
+            let now = Instant::now();
+            let response_future = self.client.request(request).map_err(|e| {
+                let err: Arc<dyn StdError + Send + Sync> = Arc::new(e);
+                TestError::from(RecoverableError::ConnectionErr(SystemTime::now(), err))
+            });
+            let response = match future::select(response_future, time_left()).await {
+                future::Either::Left((response, _)) => response?,
+                future::Either::Right(_) => {
+                    return Err(RecoverableError::Timeout(SystemTime::now()).into())
+                }
+            };
+            if let Some(jar) = &self.cookie_jar {
+                jar.store_response_cookies(&url, response.headers());
+            }
+            let status = response.status();
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .and_then(|l| connector::join_location(&url, l));
+            let location = match location {
+                Some(l) if status.is_redirection() && redirects.len() < self.max_redirects => l,
+                _ => {
+                    return Ok(Sent {
+                        response,
+                        redirects,
+                        now,
+                        method,
+                        url,
+                    })
+                }
+            };
+            debug!(
+                "RequestSender::send_attempt status={} url=\"{}\" location=\"{}\"",
+                status, url, location
+            );
+            // credentials are only sent to the origin they were meant for
+            let same_origin = location.origin() == url.origin();
+            if !same_origin {
+                headers.remove(AUTHORIZATION);
+                for name in &self.signature_headers {
+                    headers.remove(name);
+                }
+                cookie = None;
+            }
+            match &self.cookie_jar {
+                Some(jar) => merge_cookie_header(
+                    &mut headers,
+                    cookie.as_ref(),
+                    jar.request_cookie_header(&location),
+                ),
+                None if same_origin => update_cookie_header(&mut headers, response.headers()),
+                None => {
+                    headers.remove(COOKIE);
+                }
+            }
+            // read the body of the redirect so the connection can be reused
+            let drain = response.into_body().collect();
+            if let future::Either::Right(_) = future::select(drain, time_left()).await {
+                return Err(RecoverableError::Timeout(SystemTime::now()).into());
+            }
+            let rtt = now.elapsed().as_micros() as u64;
+            self.send_stat(
+                stats::StatKind::Response(status.as_u16()),
+                Some(rtt),
+                attempt,
+            );
+            redirects.push(json::json!({
+                "status": status.as_u16(),
+                "url": url.as_str(),
+                "location": location.as_str(),
+                "rtt": rtt as f64 / 1000.0,
+            }));
+            if redirect_changes_to_get(status, &method) {
+                method = Method::GET;
+                body = Bytes::new();
+                headers.remove(CONTENT_LENGTH);
+                headers.remove(CONTENT_ENCODING);
+                headers.remove(CONTENT_TYPE);
+            }
+            if let Ok(host) = HeaderValue::from_str(connector::host_header(&location)) {
+                headers.insert(HOST, host);
+            }
+            url = location;
+        }
+    }
+
+    // stats from any attempt after the first are tracked as retries
+    fn send_stat(&self, kind: stats::StatKind, rtt: Option<u64>, attempt: usize) {
+        let kind = if attempt > 1 {
+            stats::StatKind::Retry(kind.into())
+        } else {
+            kind
+        };
+        let _ = self.stats_tx.unbounded_send(
+            stats::ResponseStat {
+                kind,
+                rtt,
+                time: SystemTime::now(),
+                tags: self.tags.clone(),
+            }
+            .into(),
+        );
+    }
+}
+
+/// How long to wait before the next attempt. The backoff doubles with every attempt, is capped
+/// at `max_backoff`, and with jitter a random duration between zero and that value is used.
+fn retry_delay(retry: &Retry, attempt: usize) -> Duration {
+    let exponent = (attempt - 1).min(16) as u32;
+    let mut delay = retry.backoff.saturating_mul(2u32.pow(exponent));
+    if let Some(max_backoff) = retry.max_backoff {
+        delay = delay.min(max_backoff);
+    }
+    if retry.jitter {
+        let micros = delay.as_micros() as u64;
+        delay = Duration::from_micros(rand::rng().random_range(0..=micros));
+    }
+    delay
+}
+
+/// Whether following a redirect with the given status changes the method to GET (and drops the
+/// body). A 303 always does (except for HEAD), as does a 301 or 302 from a POST.
+/// 307 and 308 never change the method.
+fn redirect_changes_to_get(status: StatusCode, method: &Method) -> bool {
+    match status {
+        StatusCode::SEE_OTHER => method != Method::GET && method != Method::HEAD,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => method == Method::POST,
+        _ => false,
+    }
+}
+
+/// Sets the `cookie` header to the templated cookies followed by the cookies from the jar, or
+/// removes it when there are neither
+fn merge_cookie_header(
+    headers: &mut HeaderMap,
+    cookie: Option<&HeaderValue>,
+    jar_cookies: Option<String>,
+) {
+    let cookie = match (cookie, jar_cookies) {
+        (Some(cookie), Some(jar_cookies)) => match cookie.to_str() {
+            Ok(c) => HeaderValue::from_str(&format!("{c}; {jar_cookies}")).ok(),
+            Err(_) => Some(cookie.clone()),
+        },
+        (Some(cookie), None) => Some(cookie.clone()),
+        (None, Some(jar_cookies)) => HeaderValue::from_str(&jar_cookies).ok(),
+        (None, None) => None,
+    };
+    match cookie {
+        Some(cookie) => {
+            headers.insert(COOKIE, cookie);
+        }
+        None => {
+            headers.remove(COOKIE);
+        }
+    }
+}
+
+/// Merges any `set-cookie` headers from a redirect response into the `cookie` header so they are
+/// carried to the next hop
+fn update_cookie_header(headers: &mut HeaderMap, response_headers: &HeaderMap) {
+    let mut cookies: Vec<(String, String)> = headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|c| {
+            let (name, value) = c.trim().split_once('=')?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+    let mut changed = false;
+    for set_cookie in response_headers.get_all(SET_COOKIE) {
+        let pair = set_cookie
+            .to_str()
+            .ok()
+            .and_then(|c| c.split(';').next())
+            .and_then(|c| c.trim().split_once('='));
+        if let Some((name, value)) = pair {
+            changed = true;
+            match cookies.iter_mut().find(|(n, _)| n == name) {
+                Some((_, v)) => *v = value.to_string(),
+                None => cookies.push((name.to_string(), value.to_string())),
+            }
+        }
+    }
+    if !changed {
+        return;
+    }
+    let cookie = cookies
+        .iter()
+        .map(|(n, v)| format!("{n}={v}"))
+        .collect::<Vec<_>>()
+        .join("; ");
+    if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+        headers.insert(COOKIE, cookie);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::create_http_client;
-    use futures::channel::mpsc as futures_channel;
+    use crate::request::ProviderOrLogger;
+    use channel::Limit;
+    use config::{EndpointProvidesSendOptions::Block, Select};
+    use futures::{channel::mpsc as futures_channel, StreamExt};
     use tokio::runtime::Runtime;
 
+    // a request maker with everything turned off, so a test only sets the fields it cares about
+    fn test_request_maker(url: &str) -> RequestMaker {
+        let (stats_tx, _) = futures_channel::unbounded();
+        RequestMaker {
+            url: Template::simple(url),
+            method: Method::GET,
+            headers: Vec::new(),
+            body: BodyTemplate::None,
+            rr_providers: 0,
+            client: create_http_client(Duration::from_secs(60)).unwrap().into(),
+            stats_tx,
+            no_auto_returns: true,
+            outgoing: Vec::new().into(),
+            precheck_rr_providers: 0,
+            tags: Arc::new(BTreeMap::new()),
+            timeout: Duration::from_secs(120),
+            follow_redirects: 0,
+            cookie_jar: None,
+            cookie_jars: Default::default(),
+            retry: None,
+            throttle: None,
+            compress_body: None,
+            stream_response: None,
+            sign: None,
+            openapi: None,
+            assertions: None,
+        }
+    }
+
     #[test]
     fn sends_request() {
         let rt = Runtime::new().unwrap();
         rt.block_on(async move {
             let (port, ..) = test_common::start_test_server(None).await;
-            let url = Template::simple(&format!("https://127.0.0.1:{}", port));
-            let method = Method::GET;
-            let headers = Vec::new();
-            let body = BodyTemplate::None;
-            let rr_providers = 0;
-            let precheck_rr_providers = 0;
-            let client = create_http_client(Duration::from_secs(60)).unwrap().into();
-            let (stats_tx, _) = futures_channel::unbounded();
-            let no_auto_returns = true;
-            let outgoing = Vec::new().into();
-            let timeout = Duration::from_secs(120);
-            let tags = Arc::new(BTreeMap::new());
+            let rm = test_request_maker(&format!("https://127.0.0.1:{}", port));
+
+            let r = rm.send_request(Vec::new()).await;
+            assert!(r.is_ok());
+        });
+    }
+
+    #[test]
+    fn follows_redirects() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let url = format!("http://127.0.0.1:{}/redirect?count=3&status=303", port);
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
 
             let rm = RequestMaker {
-                url,
-                method,
-                headers,
-                body,
-                rr_providers,
-                client,
+                method: Method::POST,
+                body: BodyTemplate::String(Template::simple("foo")),
                 stats_tx,
-                no_auto_returns,
-                outgoing,
-                precheck_rr_providers,
-                tags,
-                timeout,
+                follow_redirects: 5,
+                ..test_request_maker(&url)
             };
 
             let r = rm.send_request(Vec::new()).await;
             assert!(r.is_ok());
+            drop(rm);
+
+            let mut statuses = Vec::new();
+            while let Some(stats::StatsMessage::ResponseStat(stat)) = stats_rx.next().await {
+                if let stats::StatKind::Response(status) = stat.kind {
+                    statuses.push(status);
+                }
+            }
+            assert_eq!(statuses, vec![303, 303, 303, 204]);
         });
     }
+
+    #[test]
+    fn times_out_redirect_chains() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            // each hop is well within the timeout but the whole chain is not
+            let url = format!("http://127.0.0.1:{}/redirect?count=3&wait=200", port);
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+
+            let rm = RequestMaker {
+                stats_tx,
+                follow_redirects: 5,
+                timeout: Duration::from_millis(500),
+                ..test_request_maker(&url)
+            };
+
+            let r = rm.send_request(Vec::new()).await;
+            assert!(r.is_ok());
+            drop(rm);
+
+            let mut kinds = Vec::new();
+            while let Some(stats::StatsMessage::ResponseStat(stat)) = stats_rx.next().await {
+                kinds.push(stat.kind);
+            }
+            assert!(
+                matches!(
+                    kinds.as_slice(),
+                    [
+                        stats::StatKind::Response(302),
+                        stats::StatKind::Response(302),
+                        stats::StatKind::RecoverableError(RecoverableError::Timeout(..)),
+                    ]
+                ),
+                "{:?}",
+                kinds
+            );
+        });
+    }
+
+    #[test]
+    fn stores_cookies_in_jar() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let url = format!("http://127.0.0.1:{}/redirect?count=2", port);
+            let cookie_jars = CookieJars::default();
+
+            let rm = RequestMaker {
+                follow_redirects: 5,
+                cookie_jar: Some(Template::simple("global")),
+                cookie_jars: cookie_jars.clone(),
+                ..test_request_maker(&url)
+            };
+
+            let r = rm.send_request(Vec::new()).await;
+            assert!(r.is_ok());
+
+            let url = url::Url::parse(&url).unwrap();
+            let cookie = cookie_jars.jar("global".into()).request_cookie_header(&url);
+            let cookie = cookie.unwrap();
+            let mut pieces: Vec<_> = cookie.split("; ").collect();
+            pieces.sort_unstable();
+            assert_eq!(pieces, vec!["redirect1=1", "redirect2=2"]);
+            assert!(cookie_jars
+                .jar("other".into())
+                .request_cookie_header(&url)
+                .is_none());
+        });
+    }
+
+    // sends a request with a templated `cookie` header through two redirects to `to` and returns
+    // the cookies the last hop received, sorted because a jar's cookies have no set order
+    async fn redirected_cookie(port: u16, to: &str, cookie_jar: Option<Template>) -> Vec<String> {
+        let url = url::Url::parse_with_params(
+            &format!("http://127.0.0.1:{}/redirect?count=2", port),
+            &[("to", to)],
+        )
+        .unwrap();
+        let (tx, mut rx) = channel::channel(Limit::Static(5), false, "cookie");
+        let select = Select::simple("response.body", Block, None, None, None);
+        let outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))];
+
+        let rm = RequestMaker {
+            headers: vec![("cookie".into(), Template::simple("user=1"))],
+            rr_providers: config::RESPONSE_BODY,
+            outgoing: outgoing.into(),
+            follow_redirects: 5,
+            cookie_jar,
+            ..test_request_maker(url.as_str())
+        };
+
+        let r = rm.send_request(Vec::new()).await;
+        assert!(r.is_ok());
+        let cookie = rx.next().await.unwrap();
+        let mut cookies: Vec<_> = cookie
+            .as_str()
+            .unwrap()
+            .split("; ")
+            .filter(|c| !c.is_empty())
+            .map(String::from)
+            .collect();
+        cookies.sort();
+        cookies
+    }
+
+    #[test]
+    fn drops_cookies_on_cross_origin_redirects() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let (other_port, ..) = test_common::start_test_server(None).await;
+
+            let to = format!("http://127.0.0.1:{}/?header=cookie", port);
+            assert_eq!(
+                redirected_cookie(port, &to, None).await,
+                ["redirect1=1", "redirect2=2", "user=1"]
+            );
+            let to = format!("http://127.0.0.1:{}/?header=cookie", other_port);
+            assert!(redirected_cookie(port, &to, None).await.is_empty());
+        });
+    }
+
+    #[test]
+    fn merges_templated_cookies_with_jar_on_redirects() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let (other_port, ..) = test_common::start_test_server(None).await;
+
+            let to = format!("http://127.0.0.1:{}/?header=cookie", port);
+            let jar = Some(Template::simple("same_origin"));
+            assert_eq!(
+                redirected_cookie(port, &to, jar).await,
+                ["redirect1=1", "redirect2=2", "user=1"]
+            );
+            // the jar's cookies aren't port specific, but the templated cookie is dropped
+            let to = format!("http://127.0.0.1:{}/?header=cookie", other_port);
+            let jar = Some(Template::simple("cross_origin"));
+            assert_eq!(
+                redirected_cookie(port, &to, jar).await,
+                ["redirect1=1", "redirect2=2"]
+            );
+        });
+    }
+
+    #[test]
+    fn signs_requests() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let (tx, mut rx) = channel::channel(Limit::Static(5), false, "signature");
+            let select = Select::simple("request.headers.signature", Block, None, None, None);
+            let outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))];
+            let sign = Sign::Hmac(config::HmacSign {
+                key: "key".into(),
+                header: "Signature".into(),
+                prefix: String::new(),
+                parts: vec![config::SignPart::Method, config::SignPart::Body],
+                separator: "\n".into(),
+                encoding: config::SignatureEncoding::Hex,
+            });
+
+            let rm = RequestMaker {
+                method: Method::POST,
+                body: BodyTemplate::String(Template::simple("hello")),
+                rr_providers: REQUEST_HEADERS,
+                outgoing: outgoing.into(),
+                sign: Some(sign),
+                ..test_request_maker(&format!("http://127.0.0.1:{}/", port))
+            };
+
+            let r = rm.send_request(Vec::new()).await;
+            assert!(r.is_ok());
+            // HMAC-SHA256("key", "POST\nhello")
+            assert_eq!(
+                rx.next().await,
+                Some(json::json!(
+                    "9296d8e75d6338715961b97c6454773da8bfa3d417910aa89043a7877b3bfd06"
+                ))
+            );
+        });
+    }
+
+    #[test]
+    fn drops_signatures_on_cross_origin_redirects() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let (other_port, ..) = test_common::start_test_server(None).await;
+
+            for (to_port, signed) in [(port, true), (other_port, false)] {
+                let to = format!("http://127.0.0.1:{}/?header=signature", to_port);
+                let url = url::Url::parse_with_params(
+                    &format!("http://127.0.0.1:{}/redirect?count=1", port),
+                    &[("to", &*to)],
+                )
+                .unwrap();
+                let (tx, mut rx) = channel::channel(Limit::Static(5), false, "signature");
+                let select = Select::simple("response.body", Block, None, None, None);
+                let outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))];
+                let sign = Sign::Hmac(config::HmacSign {
+                    key: "key".into(),
+                    header: "Signature".into(),
+                    prefix: String::new(),
+                    parts: vec![config::SignPart::Method],
+                    separator: "\n".into(),
+                    encoding: config::SignatureEncoding::Hex,
+                });
+
+                let rm = RequestMaker {
+                    rr_providers: config::RESPONSE_BODY,
+                    outgoing: outgoing.into(),
+                    follow_redirects: 5,
+                    sign: Some(sign),
+                    ..test_request_maker(url.as_str())
+                };
+
+                let r = rm.send_request(Vec::new()).await;
+                assert!(r.is_ok());
+                let signature = rx.next().await.unwrap();
+                assert_eq!(
+                    !signature.as_str().unwrap().is_empty(),
+                    signed,
+                    "redirected to port {}",
+                    to_port
+                );
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sends_requests_over_unix_sockets() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let path = std::env::temp_dir().join(format!("pewpew-{}.sock", std::process::id()));
+            let (_tx, _) = test_common::start_unix_test_server(&path).await;
+            // the redirects are relative so they stay on the socket
+            let url = format!("unix://{}:/redirect?count=2&echo=hello", path.display());
+            let (tx, mut rx) = channel::channel(Limit::Static(5), false, "body");
+            let select = Select::simple("response.body", Block, None, None, None);
+            let outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))];
+
+            let rm = RequestMaker {
+                rr_providers: config::RESPONSE_BODY,
+                outgoing: outgoing.into(),
+                follow_redirects: 5,
+                ..test_request_maker(&url)
+            };
+
+            let r = rm.send_request(Vec::new()).await;
+            assert!(r.is_ok());
+            assert_eq!(rx.next().await, Some(json::json!("hello")));
+            let _ = std::fs::remove_file(&path);
+        });
+    }
+
+    #[test]
+    fn validates_responses_against_openapi_spec() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            std::io::Write::write_all(
+                &mut file,
+                br#"{
+                    "openapi": "3.0.0",
+                    "paths": { "/": { "post": { "responses": { "200": { "content": {
+                        "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["id"],
+                            "properties": { "id": { "type": "integer" } }
+                        } }
+                    } } } } } }
+                }"#,
+            )
+            .unwrap();
+            let spec = Arc::new(Spec::load(file.path()).unwrap());
+
+            let client: Arc<_> = create_http_client(Duration::from_secs(60)).unwrap().into();
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+            for body in [r#"{"id":1}"#, r#"{"id":"1"}"#] {
+                let rm = RequestMaker {
+                    method: Method::POST,
+                    headers: vec![("content-type".into(), Template::simple("application/json"))],
+                    body: BodyTemplate::String(Template::simple(body)),
+                    client: client.clone(),
+                    stats_tx: stats_tx.clone(),
+                    openapi: Some(spec.clone()),
+                    ..test_request_maker(&format!("http://127.0.0.1:{}/", port))
+                };
+                let r = rm.send_request(Vec::new()).await;
+                assert!(r.is_ok());
+            }
+            drop(stats_tx);
+
+            let mut violations = Vec::new();
+            while let Some(stats::StatsMessage::ResponseStat(stat)) = stats_rx.next().await {
+                if let stats::StatKind::RecoverableError(e) = stat.kind {
+                    violations.push(e.to_string());
+                }
+            }
+            assert_eq!(
+                violations,
+                vec!["response does not match the OpenAPI spec: `$.id` should be an integer"]
+            );
+        });
+    }
+
+    #[test]
+    fn validates_redirected_responses_against_the_final_operation() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            std::io::Write::write_all(
+                &mut file,
+                br#"{
+                    "openapi": "3.0.0",
+                    "paths": {
+                        "/redirect": { "get": { "responses": { "302": { "description": "" } } } },
+                        "/": { "get": { "responses": { "200": { "content": {
+                            "application/json": { "schema": {
+                                "type": "object",
+                                "properties": { "id": { "type": "integer" } }
+                            } }
+                        } } } } }
+                    }
+                }"#,
+            )
+            .unwrap();
+            let spec = Arc::new(Spec::load(file.path()).unwrap());
+
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+            let rm = RequestMaker {
+                follow_redirects: 5,
+                stats_tx,
+                openapi: Some(spec),
+                ..test_request_maker(&format!(
+                    "http://127.0.0.1:{}/redirect?count=1&echo=%7B%22id%22%3A%221%22%7D",
+                    port
+                ))
+            };
+            let r = rm.send_request(Vec::new()).await;
+            assert!(r.is_ok());
+            drop(rm);
+
+            let mut violations = Vec::new();
+            while let Some(stats::StatsMessage::ResponseStat(stat)) = stats_rx.next().await {
+                if let stats::StatKind::RecoverableError(e) = stat.kind {
+                    violations.push(e.to_string());
+                }
+            }
+            assert_eq!(
+                violations,
+                vec!["response does not match the OpenAPI spec: `$.id` should be an integer"]
+            );
+        });
+    }
+
+    #[test]
+    fn retries_connection_errors() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            // bind then drop a listener to get a port nothing is listening on
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+            let retry = Retry {
+                max_attempts: std::num::NonZeroUsize::new(3).unwrap(),
+                backoff: Duration::from_millis(1),
+                max_backoff: None,
+                jitter: true,
+                on_errors: vec![RetryOn::ConnectionError],
+                on_status: Vec::new(),
+            };
+
+            let rm = RequestMaker {
+                stats_tx,
+                retry: Some(retry),
+                ..test_request_maker(&format!("http://127.0.0.1:{}/", port))
+            };
+
+            let r = rm.send_request(Vec::new()).await;
+            assert!(r.is_ok());
+            drop(rm);
+
+            let mut kinds = Vec::new();
+            while let Some(stats::StatsMessage::ResponseStat(stat)) = stats_rx.next().await {
+                kinds.push(stat.kind);
+            }
+            assert_eq!(kinds.len(), 3);
+            assert!(matches!(
+                kinds[0],
+                stats::StatKind::RecoverableError(RecoverableError::ConnectionErr(..))
+            ));
+            for kind in &kinds[1..] {
+                assert!(matches!(
+                    kind,
+                    stats::StatKind::Retry(k)
+                        if matches!(**k, stats::StatKind::RecoverableError(RecoverableError::ConnectionErr(..)))
+                ));
+            }
+        });
+    }
+
+    #[test]
+    fn retry_delays() {
+        let mut retry = Retry {
+            max_attempts: std::num::NonZeroUsize::new(10).unwrap(),
+            backoff: Duration::from_millis(100),
+            max_backoff: Some(Duration::from_millis(500)),
+            jitter: false,
+            on_errors: Vec::new(),
+            on_status: Vec::new(),
+        };
+        assert_eq!(retry_delay(&retry, 1), Duration::from_millis(100));
+        assert_eq!(retry_delay(&retry, 2), Duration::from_millis(200));
+        assert_eq!(retry_delay(&retry, 3), Duration::from_millis(400));
+        assert_eq!(retry_delay(&retry, 4), Duration::from_millis(500));
+        retry.jitter = true;
+        for attempt in 1..10 {
+            assert!(retry_delay(&retry, attempt) <= Duration::from_millis(500));
+        }
+    }
+
+    #[test]
+    fn redirect_method_rules() {
+        let checks = [
+            (StatusCode::MOVED_PERMANENTLY, Method::POST, true),
+            (StatusCode::MOVED_PERMANENTLY, Method::PUT, false),
+            (StatusCode::FOUND, Method::POST, true),
+            (StatusCode::FOUND, Method::GET, false),
+            (StatusCode::SEE_OTHER, Method::PUT, true),
+            (StatusCode::SEE_OTHER, Method::HEAD, false),
+            (StatusCode::TEMPORARY_REDIRECT, Method::POST, false),
+            (StatusCode::PERMANENT_REDIRECT, Method::POST, false),
+        ];
+        for (status, method, expect) in checks.iter() {
+            assert_eq!(
+                redirect_changes_to_get(*status, method),
+                *expect,
+                "{status} {method}"
+            );
+        }
+    }
+
+    #[test]
+    fn carries_redirect_cookies() {
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("a=1; b=2"));
+        let mut response_headers = HeaderMap::new();
+        response_headers.append(SET_COOKIE, HeaderValue::from_static("b=3; Path=/"));
+        response_headers.append(SET_COOKIE, HeaderValue::from_static("c=4; HttpOnly"));
+        update_cookie_header(&mut headers, &response_headers);
+        assert_eq!(headers.get(COOKIE).unwrap(), "a=1; b=3; c=4");
+    }
 }
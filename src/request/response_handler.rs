@@ -1,7 +1,14 @@
 use super::*;
 
-use config::{RESPONSE_BODY, RESPONSE_HEADERS, RESPONSE_HEADERS_ALL, RESPONSE_STARTLINE, STATS};
-use futures::TryStreamExt;
+use super::cookie_jar::response_cookies_json;
+use super::stream_handler::StreamHandler;
+use crate::openapi::ResponseValidator;
+use config::StreamResponse;
+use config::{
+    RESPONSE_BODY, RESPONSE_COOKIES, RESPONSE_HEADERS, RESPONSE_HEADERS_ALL, RESPONSE_REDIRECTS,
+    RESPONSE_STARTLINE, STATS,
+};
+use futures::{StreamExt, TryStreamExt};
 use http_body_util::{combinators::BoxBody, BodyExt};
 
 pub(super) struct ResponseHandler {
@@ -13,6 +20,15 @@ pub(super) struct ResponseHandler {
     pub(super) now: Instant,
     pub(super) stats_tx: StatsTx,
     pub(super) tags: Arc<BTreeMap<String, Template>>,
+    pub(super) redirects: Vec<json::Value>,
+    pub(super) retries: usize,
+    pub(super) throttle: Option<Throttle>,
+    pub(super) stream_response: Option<StreamResponse>,
+    // graphql responses are always read to check for errors
+    pub(super) graphql: bool,
+    // responses are always read when they are checked against an OpenAPI spec
+    pub(super) validator: Option<ResponseValidator>,
+    pub(super) assertions: Option<Arc<Assertions>>,
 }
 
 impl ResponseHandler {
@@ -36,7 +52,23 @@ impl ResponseHandler {
             status,
             headers
         );
-        let response_provider = json::json!({ "status": status });
+        let mut response_provider = json::json!({ "status": status });
+        let rp = response_provider
+            .as_object_mut()
+            .expect("should be a json object");
+        if (self.rr_providers | self.precheck_rr_providers) & RESPONSE_REDIRECTS != 0 {
+            rp.insert("redirects".into(), self.redirects.into());
+        }
+        if (self.rr_providers | self.precheck_rr_providers) & RESPONSE_COOKIES != 0 {
+            rp.insert("cookies".into(), response_cookies_json(response.headers()));
+        }
+        // a 429 or 503 with a `retry-after` pauses the endpoint when `honor_retry_after` is set
+        let throttled = match (&self.throttle, status) {
+            (Some(throttle), 429 | 503) => {
+                throttle::retry_after(response.headers()).and_then(|d| throttle.pause(d))
+            }
+            _ => None,
+        };
         let mut template_values = self.template_values;
         template_values.insert("response".into(), response_provider);
         let mut response_fields_added = 0b00_0111;
@@ -51,6 +83,48 @@ impl ResponseHandler {
             &response,
         );
         let rr_providers = self.rr_providers;
+        if let Some(stream_response) = self.stream_response {
+            // a streamed response runs the `where` clauses for every event. The config rejects
+            // `assert` and graphql bodies with `stream_response`, and the events are not checked
+            // against an OpenAPI spec
+            handle_response_requirements(
+                rr_providers,
+                &mut response_fields_added,
+                template_values
+                    .get_mut("response")
+                    .expect("template_values should have `response`")
+                    .as_object_mut()
+                    .expect("`response` in template_values should be an object"),
+                &response,
+            );
+            let compression = response
+                .headers()
+                .get("content-encoding")
+                .and_then(|h| h.to_str().ok())
+                .and_then(body_reader::Compression::try_from)
+                .unwrap_or(body_reader::Compression::None);
+            let mut br = body_reader::BodyReader::new(compression);
+            let body = response.into_data_stream().map(move |chunk| {
+                let chunk = chunk.map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+                let mut decoded = bytes::BytesMut::new();
+                br.decode(chunk, &mut decoded)
+                    .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+                Ok(decoded.freeze())
+            });
+            let sh = StreamHandler {
+                stream_response,
+                now: self.now,
+                outgoing: self.outgoing,
+                provider_delays: self.provider_delays,
+                retries: self.retries,
+                stats_tx: self.stats_tx,
+                status,
+                tags: self.tags,
+                template_values,
+                throttled,
+            };
+            return sh.handle(body, auto_returns).c3();
+        }
         let where_clause_special_providers = self.precheck_rr_providers;
         // executing the where clause determine which of the provides and logs need
         // to be executed
@@ -82,15 +156,18 @@ impl ResponseHandler {
             .collect::<Result<BTreeSet<_>, RecoverableError>>();
         let included_outgoing_indexes = match included_outgoing_indexes {
             Ok(i) => i,
-            Err(e) => return future::err(e).a(),
+            Err(e) => return future::err(e).a3(),
         };
         let ce_header = response.headers().get("content-encoding").map(|h| {
             h.to_str()
                 .expect("content-encoding header should cast to str")
         });
         let ce_header = ce_header.unwrap_or("");
+        let graphql = self.graphql;
+        let validator = self.validator;
+        let assertions = self.assertions;
         let body_future = match (
-            response_fields_added & RESPONSE_BODY != 0,
+            response_fields_added & RESPONSE_BODY != 0 || graphql || validator.is_some(),
             body_reader::Compression::try_from(ce_header),
         ) {
             (true, Some(ce)) => {
@@ -130,6 +207,7 @@ impl ResponseHandler {
         let outgoing = self.outgoing;
         let stats_tx = self.stats_tx;
         let tags = self.tags;
+        let retries = self.retries;
         body_future
             .then(move |body_value| {
                 log::info!("ResponseHandler::handle status={:?} headers={:?} tags={:?} body_value=\"{:?}\"", status, headers, tags, body_value);
@@ -138,14 +216,19 @@ impl ResponseHandler {
                     now,
                     outgoing,
                     provider_delays,
+                    retries,
                     stats_tx,
                     status,
                     tags,
                     template_values,
+                    throttled,
+                    graphql,
+                    validator,
+                    assertions,
                 };
                 bh.handle(body_value, auto_returns)
             })
-            .b()
+            .b3()
     }
 }
 
@@ -222,6 +305,13 @@ mod tests {
             now,
             stats_tx,
             tags,
+            redirects: Vec::new(),
+            retries: 0,
+            throttle: None,
+            stream_response: None,
+            graphql: false,
+            validator: None,
+            assertions: None,
         };
 
         let auto_returns: Option<futures::future::Pending<_>> = None;
@@ -229,4 +319,64 @@ mod tests {
         let r = block_on(rh.handle(Default::default(), auto_returns));
         assert!(r.is_ok());
     }
+
+    #[test]
+    fn records_throttling_of_streamed_responses() {
+        let mut template_values = TemplateValues::new();
+        template_values.insert("request".into(), json::json!({}));
+        let (stats_tx, stats_rx) = futures_channel::unbounded();
+        let rh = ResponseHandler {
+            provider_delays: ProviderDelays::new(),
+            template_values,
+            precheck_rr_providers: 0,
+            rr_providers: 0,
+            outgoing: Vec::new().into(),
+            now: Instant::now(),
+            stats_tx,
+            tags: Arc::new(BTreeMap::new()),
+            redirects: Vec::new(),
+            retries: 0,
+            throttle: Some(Throttle::default()),
+            stream_response: Some(StreamResponse {
+                format: config::StreamFormat::Ndjson,
+                max_duration: None,
+                max_events: None,
+            }),
+            graphql: false,
+            validator: None,
+            assertions: None,
+        };
+        let body = http_body_util::Full::new(bytes::Bytes::from_static(b"{\"a\": 1}\n"))
+            .map_err(|never| match never {})
+            .boxed();
+        let response = hyper::Response::builder()
+            .status(503)
+            .header("retry-after", "5")
+            .body(body)
+            .unwrap();
+
+        let auto_returns: Option<futures::future::Pending<_>> = None;
+        let r = block_on(rh.handle(response, auto_returns));
+        assert!(r.is_ok());
+
+        let kinds: Vec<_> = block_on(stats_rx.collect::<Vec<_>>())
+            .into_iter()
+            .filter_map(|m| match m {
+                stats::StatsMessage::ResponseStat(stat) => Some(stat.kind),
+                _ => None,
+            })
+            .collect();
+        assert!(
+            matches!(
+                kinds.as_slice(),
+                [
+                    stats::StatKind::Throttled(d),
+                    stats::StatKind::Stream { .. },
+                    stats::StatKind::Response(503),
+                ] if *d == Duration::from_secs(5)
+            ),
+            "{:?}",
+            kinds
+        );
+    }
 }
@@ -0,0 +1,395 @@
+use crate::error::{RecoverableError, TestError};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use config::{AwsSigV4, HashAlgorithm::Sha256, HmacSign, Sign, SignPart, SignatureEncoding};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    Method,
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use url::Url;
+
+use std::{fmt::Write, sync::Arc, time::SystemTime};
+
+use super::HyperBody;
+
+// the characters which are percent encoded in a canonical request. Everything except the
+// unreserved characters `A-Z a-z 0-9 - _ . ~`
+const AWS_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+const AWS_ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Buffers a request body so it can be signed, keeping a copy of its bytes
+pub(super) async fn buffer_body(
+    (length, body): (u64, HyperBody),
+) -> Result<(u64, HyperBody, Option<Bytes>), TestError> {
+    let bytes = body
+        .collect()
+        .await
+        .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?
+        .to_bytes();
+    let body = Full::new(bytes.clone())
+        .map_err(|never| match never {})
+        .boxed();
+    Ok((length, body, Some(bytes)))
+}
+
+/// Signs a request by adding headers to it. This happens after all other headers have been added,
+/// so the signature covers the final request
+pub(super) fn sign_request(
+    sign: &Sign,
+    method: &Method,
+    url: &Url,
+    headers: &mut HeaderMap,
+    body: &[u8],
+    now: SystemTime,
+) -> Result<(), RecoverableError> {
+    match sign {
+        Sign::AwsSigV4(aws) => sign_aws_sigv4(aws, method, url, headers, body, now.into()),
+        Sign::Hmac(hmac) => sign_hmac(hmac, method, url, headers, body),
+    }
+}
+
+/// The headers `sign_request` adds. They are only meant for the origin the request was signed for
+pub(super) fn signature_headers(sign: &Sign) -> Vec<HeaderName> {
+    match sign {
+        Sign::AwsSigV4(_) => vec![
+            AUTHORIZATION,
+            HeaderName::from_static("x-amz-date"),
+            HeaderName::from_static("x-amz-security-token"),
+            HeaderName::from_static("x-amz-content-sha256"),
+        ],
+        Sign::Hmac(hmac) => HeaderName::from_bytes(hmac.header.as_bytes())
+            .into_iter()
+            .collect(),
+    }
+}
+
+fn sign_aws_sigv4(
+    aws: &AwsSigV4,
+    method: &Method,
+    url: &Url,
+    headers: &mut HeaderMap,
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Result<(), RecoverableError> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = &amz_date[..8];
+    let payload_hash = hex(&Sha256.digest(body));
+    headers.insert("x-amz-date", header_value(&amz_date)?);
+    if let Some(token) = &aws.session_token {
+        headers.insert("x-amz-security-token", header_value(token)?);
+    }
+    // s3 requires the payload hash as a header
+    if aws.service == "s3" {
+        headers.insert("x-amz-content-sha256", header_value(&payload_hash)?);
+    }
+
+    // only the headers which shouldn't be changed by a proxy are signed
+    let mut signed_headers: Vec<&str> = headers
+        .keys()
+        .map(HeaderName::as_str)
+        .filter(|k| {
+            matches!(*k, "host" | "content-type" | "content-md5") || k.starts_with("x-amz-")
+        })
+        .collect();
+    signed_headers.sort_unstable();
+    signed_headers.dedup();
+    let mut canonical_headers = String::new();
+    for name in &signed_headers {
+        let values: Vec<_> = headers
+            .get_all(*name)
+            .iter()
+            .map(|v| {
+                String::from_utf8_lossy(v.as_bytes())
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        let _ = writeln!(canonical_headers, "{}:{}", name, values.join(","));
+    }
+    let signed_headers = signed_headers.join(";");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method.as_str(),
+        canonical_uri(url, aws.service != "s3"),
+        canonical_query(url),
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, aws.region, aws.service);
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        AWS_ALGORITHM,
+        amz_date,
+        scope,
+        hex(&Sha256.digest(canonical_request.as_bytes()))
+    );
+
+    let key = format!("AWS4{}", aws.secret_access_key);
+    let key = Sha256.hmac(key.as_bytes(), date.as_bytes());
+    let key = Sha256.hmac(&key, aws.region.as_bytes());
+    let key = Sha256.hmac(&key, aws.service.as_bytes());
+    let key = Sha256.hmac(&key, b"aws4_request");
+    let signature = hex(&Sha256.hmac(&key, string_to_sign.as_bytes()));
+
+    let authorization = format!(
+        "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+        AWS_ALGORITHM, aws.access_key_id, scope, signed_headers, signature
+    );
+    headers.insert(AUTHORIZATION, header_value(&authorization)?);
+    Ok(())
+}
+
+/// The path of the url with each segment encoded. Services other than s3 encode it twice
+fn canonical_uri(url: &Url, double_encode: bool) -> String {
+    let path = url
+        .path()
+        .split('/')
+        .map(|segment| {
+            let segment = percent_decode_str(segment).decode_utf8_lossy();
+            let encoded = utf8_percent_encode(&segment, AWS_ENCODE_SET).to_string();
+            if double_encode {
+                utf8_percent_encode(&encoded, AWS_ENCODE_SET).to_string()
+            } else {
+                encoded
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    if path.is_empty() {
+        "/".into()
+    } else {
+        path
+    }
+}
+
+/// The query parameters of the url, encoded and sorted
+fn canonical_query(url: &Url) -> String {
+    let mut params: Vec<(String, String)> = url
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (k, v) = param.split_once('=').unwrap_or((param, ""));
+            let encode = |s: &str| {
+                let s = percent_decode_str(s).decode_utf8_lossy();
+                utf8_percent_encode(&s, AWS_ENCODE_SET).to_string()
+            };
+            (encode(k), encode(v))
+        })
+        .collect();
+    params.sort();
+    params
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn sign_hmac(
+    sign: &HmacSign,
+    method: &Method,
+    url: &Url,
+    headers: &mut HeaderMap,
+    body: &[u8],
+) -> Result<(), RecoverableError> {
+    let mut message = Vec::new();
+    for (i, part) in sign.parts.iter().enumerate() {
+        if i > 0 {
+            message.extend_from_slice(sign.separator.as_bytes());
+        }
+        match part {
+            SignPart::Method => message.extend_from_slice(method.as_str().as_bytes()),
+            SignPart::Url => message.extend_from_slice(url.as_str().as_bytes()),
+            SignPart::Path => message.extend_from_slice(url.path().as_bytes()),
+            SignPart::Query => message.extend_from_slice(url.query().unwrap_or("").as_bytes()),
+            SignPart::Body => message.extend_from_slice(body),
+            SignPart::Header(name) => {
+                if let Some(value) = headers.get(name) {
+                    message.extend_from_slice(value.as_bytes());
+                }
+            }
+        }
+    }
+    let signature = Sha256.hmac(sign.key.as_bytes(), &message);
+    let signature = match sign.encoding {
+        SignatureEncoding::Base64 => STANDARD.encode(signature),
+        SignatureEncoding::Hex => hex(&signature),
+    };
+    let name = HeaderName::from_bytes(sign.header.as_bytes())
+        .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+    let value = format!("{}{}", sign.prefix, signature);
+    headers.insert(name, header_value(&value)?);
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, RecoverableError> {
+    HeaderValue::from_str(value).map_err(|e| RecoverableError::BodyErr(Arc::new(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HOST;
+
+    use std::time::Duration;
+
+    fn aws_sigv4(service: &str) -> Sign {
+        Sign::AwsSigV4(AwsSigV4 {
+            service: service.into(),
+            region: "us-east-1".into(),
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            session_token: None,
+        })
+    }
+
+    // 2015-08-30T12:36:00Z, the time used in the AWS signature test suite
+    fn test_time() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_440_938_160)
+    }
+
+    #[test]
+    fn signs_aws_sigv4_requests() {
+        // the `get-vanilla` and `get-vanilla-query-order-key-case` cases from the test suite
+        let values = vec![
+            (
+                "https://example.amazonaws.com/",
+                "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+            ),
+            (
+                "https://example.amazonaws.com/?Param2=value2&Param1=value1",
+                "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500",
+            ),
+        ];
+        for (url, signature) in values {
+            let url = Url::parse(url).unwrap();
+            let mut headers = HeaderMap::new();
+            headers.insert(HOST, HeaderValue::from_static("example.amazonaws.com"));
+            sign_request(
+                &aws_sigv4("service"),
+                &Method::GET,
+                &url,
+                &mut headers,
+                b"",
+                test_time(),
+            )
+            .unwrap();
+            assert_eq!(headers["x-amz-date"], "20150830T123600Z");
+            let expected = format!(
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                SignedHeaders=host;x-amz-date, Signature={}",
+                signature
+            );
+            assert_eq!(headers[AUTHORIZATION], expected.as_str(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn builds_canonical_requests() {
+        let url = Url::parse("https://example.com/a b/c%2Fd/?b=2&a=2&a=1&c").unwrap();
+        assert_eq!(canonical_uri(&url, false), "/a%20b/c%2Fd/");
+        assert_eq!(canonical_uri(&url, true), "/a%2520b/c%252Fd/");
+        assert_eq!(canonical_query(&url), "a=1&a=2&b=2&c=");
+        let url = Url::parse("https://example.com").unwrap();
+        assert_eq!(canonical_uri(&url, true), "/");
+        assert_eq!(canonical_query(&url), "");
+    }
+
+    #[test]
+    fn signs_s3_and_session_requests() {
+        let mut sign = aws_sigv4("s3");
+        if let Sign::AwsSigV4(aws) = &mut sign {
+            aws.session_token = Some("token".into());
+        }
+        let url = Url::parse("https://bucket.s3.amazonaws.com/key").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("bucket.s3.amazonaws.com"));
+        sign_request(&sign, &Method::PUT, &url, &mut headers, b"", test_time()).unwrap();
+        assert_eq!(headers["x-amz-security-token"], "token");
+        assert_eq!(
+            headers["x-amz-content-sha256"],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let authorization = headers[AUTHORIZATION].to_str().unwrap();
+        assert!(
+            authorization.contains(
+                "SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token,"
+            ),
+            "{}",
+            authorization
+        );
+    }
+
+    #[test]
+    fn signs_hmac_requests() {
+        let url = Url::parse("https://example.com/users?id=1").unwrap();
+        let mut sign = HmacSign {
+            key: "key".into(),
+            header: "X-Signature".into(),
+            prefix: String::new(),
+            parts: vec![
+                SignPart::Method,
+                SignPart::Path,
+                SignPart::Query,
+                SignPart::Header("x-date".into()),
+                SignPart::Body,
+            ],
+            separator: "\n".into(),
+            encoding: SignatureEncoding::Hex,
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("x-date", HeaderValue::from_static("2020-01-01"));
+        let body = br#"{"name":"bob"}"#;
+        sign_request(
+            &Sign::Hmac(sign.clone()),
+            &Method::POST,
+            &url,
+            &mut headers,
+            body,
+            test_time(),
+        )
+        .unwrap();
+        // HMAC-SHA256("key", "POST\n/users\nid=1\n2020-01-01\n{\"name\":\"bob\"}")
+        let expected = "9b02e540e5e40ec5bd9ec9ba2875cce969d95efab1043a087a0fcd924152c1c4";
+        assert_eq!(headers["x-signature"], expected);
+
+        sign.header = "Authorization".into();
+        sign.prefix = "HMAC ".into();
+        sign.parts = vec![SignPart::Url];
+        sign.encoding = SignatureEncoding::Base64;
+        sign_request(
+            &Sign::Hmac(sign),
+            &Method::POST,
+            &url,
+            &mut headers,
+            body,
+            test_time(),
+        )
+        .unwrap();
+        let expected = format!(
+            "HMAC {}",
+            STANDARD.encode(Sha256.hmac(b"key", url.as_str().as_bytes()))
+        );
+        assert_eq!(headers[AUTHORIZATION], expected.as_str());
+    }
+}
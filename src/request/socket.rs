@@ -0,0 +1,412 @@
+use crate::error::{RecoverableError, TestError};
+use crate::stats;
+
+use config::{BodyTemplate, Socket, SocketProtocol, Template};
+use futures::future::{self, join_all, Either};
+use futures_timer::Delay;
+use log::debug;
+use serde_json as json;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{lookup_host, TcpStream, UdpSocket},
+};
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    future::Future,
+    io,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{
+    evaluate_tags, request_maker::collect_stream_items, send_outgoing, send_stat, Outgoing,
+    StatsTx, StreamItem, Tags, TemplateValues,
+};
+
+// the largest payload which can be received in a single read
+const READ_BUFFER_LEN: usize = 64 * 1024;
+
+/// Sends the body of a `tcp` or `udp` endpoint to the `host:port` in its url. Each hit uses a
+/// new connection, and when a response is read it is run through the `provides` and `logs`
+pub(super) struct SocketMaker {
+    pub(super) url: Template,
+    pub(super) body: BodyTemplate,
+    pub(super) socket: Socket,
+    pub(super) stats_tx: StatsTx,
+    pub(super) no_auto_returns: bool,
+    pub(super) outgoing: Arc<Vec<Outgoing>>,
+    pub(super) tags: Arc<BTreeMap<String, Template>>,
+    pub(super) timeout: Duration,
+}
+
+impl SocketMaker {
+    pub(super) fn send_request(
+        self: &Arc<Self>,
+        values: Vec<StreamItem>,
+    ) -> impl Future<Output = Result<(), TestError>> {
+        let (template_values, auto_returns, provider_delays) =
+            collect_stream_items(values, self.no_auto_returns);
+        let maker = self.clone();
+        async move {
+            let tags = evaluate_tags(&maker.tags, &template_values);
+            let call = Box::pin(maker.call(template_values, &tags));
+            let result = match future::select(call, Delay::new(maker.timeout)).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => Err(RecoverableError::Timeout(SystemTime::now()).into()),
+            };
+            match result {
+                Ok(()) => (),
+                Err(TestError::Recoverable(e)) => {
+                    let rtt = match e {
+                        RecoverableError::Timeout(_) => Some(maker.timeout.as_micros() as u64),
+                        _ => None,
+                    };
+                    let kind = stats::StatKind::RecoverableError(e);
+                    send_stat(&maker.stats_tx, kind, rtt, tags.clone());
+                }
+                Err(e) => return Err(e),
+            }
+            provider_delays.log(&tags, &maker.stats_tx);
+            join_all(auto_returns).await;
+            Ok(())
+        }
+    }
+
+    async fn call(
+        &self,
+        mut template_values: TemplateValues,
+        tags: &Tags,
+    ) -> Result<(), TestError> {
+        let url = self
+            .url
+            .evaluate(Cow::Borrowed(template_values.as_json()), None)?;
+        let body = match &self.body {
+            BodyTemplate::String(t) => {
+                t.evaluate(Cow::Borrowed(template_values.as_json()), None)?
+            }
+            _ => String::new(),
+        };
+        let method = protocol_name(self.socket.protocol);
+        template_values.insert(
+            "request".into(),
+            json::json!({ "url": url, "method": method, "body": body }),
+        );
+
+        debug!(
+            "SocketMaker::call protocol={:?} url=\"{}\" body=\"{}\"",
+            self.socket.protocol, url, body
+        );
+        let now = Instant::now();
+        let mut connection = Connection::open(self.socket.protocol, &url)
+            .await
+            .map_err(connection_err)?;
+        connection
+            .send(body.as_bytes())
+            .await
+            .map_err(connection_err)?;
+        if !self.socket.reads_response() {
+            let rtt = now.elapsed().as_micros() as u64;
+            send_stat(
+                &self.stats_tx,
+                stats::StatKind::Sent,
+                Some(rtt),
+                tags.clone(),
+            );
+            return Ok(());
+        }
+        let response = connection
+            .read_response(&self.socket)
+            .await
+            .map_err(connection_err)?;
+        let rtt = now.elapsed().as_micros() as u64;
+        send_stat(
+            &self.stats_tx,
+            stats::StatKind::Message,
+            Some(rtt),
+            tags.clone(),
+        );
+
+        let body = String::from_utf8_lossy(&response).into_owned();
+        debug!("SocketMaker::call response=\"{}\"", body);
+        let body = json::from_str(&body).unwrap_or(json::Value::String(body));
+        template_values.insert("response".into(), json::json!({ "body": body }));
+        template_values.insert("stats".into(), json::json!({ "rtt": rtt as f64 / 1000.0 }));
+        let template_values = Arc::new(template_values.0);
+        for result in join_all(send_outgoing(&self.outgoing, &template_values)).await {
+            if let Err(e) = result {
+                let kind = stats::StatKind::RecoverableError(e);
+                send_stat(&self.stats_tx, kind, None, tags.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl Connection {
+    async fn open(protocol: SocketProtocol, url: &str) -> io::Result<Self> {
+        let connection = match protocol {
+            SocketProtocol::Tcp => Connection::Tcp(TcpStream::connect(url).await?),
+            SocketProtocol::Udp => {
+                let addr = lookup_host(url).await?.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "could not resolve address")
+                })?;
+                let local: SocketAddr = if addr.is_ipv4() {
+                    ([0, 0, 0, 0], 0).into()
+                } else {
+                    ([0u16; 8], 0).into()
+                };
+                let socket = UdpSocket::bind(local).await?;
+                socket.connect(addr).await?;
+                Connection::Udp(socket)
+            }
+        };
+        Ok(connection)
+    }
+
+    async fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.write_all(payload).await,
+            Connection::Udp(socket) => socket.send(payload).await.map(|_| ()),
+        }
+    }
+
+    /// Reads into the buffer, returning `None` when a tcp connection has been closed
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        match self {
+            Connection::Tcp(stream) => {
+                let n = stream.read(buf).await?;
+                Ok(Some(n).filter(|n| *n > 0))
+            }
+            Connection::Udp(socket) => socket.recv(buf).await.map(Some),
+        }
+    }
+
+    /// Reads a response until the delimiter is received, the connection is closed or the
+    /// `read_timeout` is reached. Without a delimiter a udp response is the first datagram
+    async fn read_response(&mut self, socket: &Socket) -> io::Result<Vec<u8>> {
+        let is_udp = matches!(self, Connection::Udp(_));
+        let mut response = Vec::new();
+        let read = async {
+            let mut buf = vec![0; READ_BUFFER_LEN];
+            while let Some(n) = self.recv(&mut buf).await? {
+                response.extend_from_slice(&buf[..n]);
+                match &socket.read_until {
+                    Some(delimiter) => {
+                        if let Some(i) = find(&response, delimiter.as_bytes()) {
+                            response.truncate(i);
+                            break;
+                        }
+                    }
+                    None if is_udp => break,
+                    None => (),
+                }
+            }
+            Ok::<_, io::Error>(())
+        };
+        match socket.read_timeout {
+            // whatever was received before the `read_timeout` is the response
+            Some(read_timeout) => {
+                if let Ok(result) = tokio::time::timeout(read_timeout, read).await {
+                    result?;
+                }
+            }
+            None => read.await?,
+        }
+        Ok(response)
+    }
+}
+
+/// The position of the delimiter in the bytes
+fn find(bytes: &[u8], delimiter: &[u8]) -> Option<usize> {
+    bytes
+        .windows(delimiter.len())
+        .position(|window| window == delimiter)
+}
+
+fn protocol_name(protocol: SocketProtocol) -> &'static str {
+    match protocol {
+        SocketProtocol::Tcp => "TCP",
+        SocketProtocol::Udp => "UDP",
+    }
+}
+
+fn connection_err(e: io::Error) -> RecoverableError {
+    RecoverableError::ConnectionErr(SystemTime::now(), Arc::new(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::ProviderOrLogger;
+    use channel::Limit;
+    use config::{EndpointProvidesSendOptions::Block, Select};
+    use futures::{channel::mpsc as futures_channel, StreamExt};
+    use maplit::btreemap;
+    use tokio::{net::TcpListener, runtime::Runtime};
+
+    fn socket_maker(
+        url: String,
+        socket: Socket,
+        outgoing: Vec<Outgoing>,
+        stats_tx: StatsTx,
+    ) -> Arc<SocketMaker> {
+        let method = protocol_name(socket.protocol);
+        Arc::new(SocketMaker {
+            url: Template::simple(&url),
+            body: BodyTemplate::String(Template::simple("{\"n\": 1}\n")),
+            socket,
+            stats_tx,
+            no_auto_returns: true,
+            outgoing: outgoing.into(),
+            tags: btreemap! {"method".into() => Template::simple(method)}.into(),
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn finds_delimiters() {
+        assert_eq!(find(b"foo\nbar\n", b"\n"), Some(3));
+        assert_eq!(find(b"foo\r\nbar", b"\r\n"), Some(3));
+        assert_eq!(find(b"foo", b"\n"), None);
+        assert_eq!(find(b"", b"\n"), None);
+    }
+
+    #[test]
+    fn sends_tcp_payloads() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            // echoes each line back, followed by a trailing line that shouldn't be read
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                loop {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    tokio::spawn(async move {
+                        let mut buf = vec![0; 1024];
+                        let n = stream.read(&mut buf).await.unwrap();
+                        stream.write_all(&buf[..n]).await.unwrap();
+                        let _ = stream.write_all(b"extra\n").await;
+                    });
+                }
+            });
+
+            let (tx, mut rx) = channel::channel(Limit::Static(5), false, "tcp");
+            let select = Select::simple("response.body.n", Block, None, None, None);
+            let outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))];
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+            let socket = Socket {
+                protocol: SocketProtocol::Tcp,
+                read_until: Some("\n".into()),
+                read_timeout: None,
+            };
+            let url = format!("127.0.0.1:{}", port);
+            let sm = socket_maker(url, socket, outgoing, stats_tx.clone());
+            sm.send_request(Vec::new()).await.unwrap();
+            assert_eq!(rx.next().await, Some(json::json!(1)));
+
+            // without reading a response, the payload is only sent
+            let socket = Socket {
+                protocol: SocketProtocol::Tcp,
+                read_until: None,
+                read_timeout: None,
+            };
+            let url = format!("127.0.0.1:{}", port);
+            let sm = socket_maker(url, socket, Vec::new(), stats_tx);
+            sm.send_request(Vec::new()).await.unwrap();
+
+            let mut kinds = Vec::new();
+            for _ in 0..2 {
+                match stats_rx.next().await {
+                    Some(stats::StatsMessage::ResponseStat(rs)) => {
+                        assert!(rs.rtt.is_some());
+                        kinds.push((format!("{:?}", rs.kind), rs.tags["method"].clone()));
+                    }
+                    _ => panic!("expected a response stat"),
+                }
+            }
+            assert_eq!(
+                kinds,
+                vec![
+                    ("Message".into(), "TCP".into()),
+                    ("Sent".into(), "TCP".into()),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn sends_udp_payloads() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let port = server.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                let mut buf = vec![0; 1024];
+                loop {
+                    let (n, addr) = server.recv_from(&mut buf).await.unwrap();
+                    server.send_to(&buf[..n], addr).await.unwrap();
+                }
+            });
+
+            let (tx, mut rx) = channel::channel(Limit::Static(5), false, "udp");
+            let select = Select::simple("response.body.n", Block, None, None, None);
+            let outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))];
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+            let socket = Socket {
+                protocol: SocketProtocol::Udp,
+                read_until: None,
+                read_timeout: Some(Duration::from_secs(5)),
+            };
+            let url = format!("127.0.0.1:{}", port);
+            let sm = socket_maker(url, socket, outgoing, stats_tx);
+            sm.send_request(Vec::new()).await.unwrap();
+            assert_eq!(rx.next().await, Some(json::json!(1)));
+
+            match stats_rx.next().await {
+                Some(stats::StatsMessage::ResponseStat(rs)) => {
+                    assert_eq!(format!("{:?}", rs.kind), "Message");
+                    assert_eq!(rs.tags["method"], "UDP");
+                    // the first datagram is the response, so the read timeout isn't reached
+                    assert!(rs.rtt.unwrap() < 5_000_000);
+                }
+                _ => panic!("expected a response stat"),
+            }
+        });
+    }
+
+    #[test]
+    fn records_connection_errors() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            // find a port with nothing listening on it
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            drop(listener);
+
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+            let socket = Socket {
+                protocol: SocketProtocol::Tcp,
+                read_until: None,
+                read_timeout: None,
+            };
+            let url = format!("127.0.0.1:{}", port);
+            let sm = socket_maker(url, socket, Vec::new(), stats_tx);
+            sm.send_request(Vec::new()).await.unwrap();
+            match stats_rx.next().await {
+                Some(stats::StatsMessage::ResponseStat(rs)) => match rs.kind {
+                    stats::StatKind::RecoverableError(RecoverableError::ConnectionErr(..)) => (),
+                    kind => panic!("expected a connection error, got {:?}", kind),
+                },
+                _ => panic!("expected a response stat"),
+            }
+        });
+    }
+}
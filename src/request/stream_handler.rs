@@ -0,0 +1,324 @@
+use crate::error::RecoverableError;
+use crate::stats;
+
+use bytes::Bytes;
+use config::{StreamFormat, StreamResponse, Template};
+use futures::{future::join_all, Stream, StreamExt};
+use futures_timer::Delay;
+use serde_json as json;
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{send_outgoing, Outgoing, ProviderDelays, StatsTx, TemplateValues};
+
+/// Handles a response body as a stream of events when the endpoint has `stream_response`. Each
+/// event is run through the `provides` and `logs` as it arrives instead of waiting for the whole
+/// body
+pub(super) struct StreamHandler {
+    pub(super) stream_response: StreamResponse,
+    pub(super) now: Instant,
+    pub(super) outgoing: Arc<Vec<Outgoing>>,
+    pub(super) provider_delays: ProviderDelays,
+    pub(super) retries: usize,
+    pub(super) stats_tx: StatsTx,
+    pub(super) status: u16,
+    pub(super) tags: Arc<BTreeMap<String, Template>>,
+    pub(super) template_values: TemplateValues,
+    // how long a `retry-after` header paused the endpoint for
+    pub(super) throttled: Option<Duration>,
+}
+
+impl StreamHandler {
+    pub(super) async fn handle<S, F>(
+        self,
+        body: S,
+        auto_returns: Option<F>,
+    ) -> Result<(), RecoverableError>
+    where
+        S: Stream<Item = Result<Bytes, RecoverableError>> + Send,
+        F: Future<Output = ()> + Send,
+    {
+        let StreamHandler {
+            stream_response,
+            now,
+            outgoing,
+            provider_delays,
+            retries,
+            stats_tx,
+            status,
+            tags,
+            template_values,
+            throttled,
+        } = self;
+        let tags: BTreeMap<String, String> = tags
+            .iter()
+            .filter_map(|(k, t)| {
+                t.evaluate(Cow::Borrowed(template_values.as_json()), None)
+                    .ok()
+                    .map(|v| (k.clone(), v))
+            })
+            .collect();
+        let tags = Arc::new(tags);
+        provider_delays.log(&tags, &stats_tx);
+        let send_stat = |kind, rtt| {
+            let _ = stats_tx.unbounded_send(
+                stats::ResponseStat {
+                    kind,
+                    rtt,
+                    time: SystemTime::now(),
+                    tags: tags.clone(),
+                }
+                .into(),
+            );
+        };
+        if let Some(duration) = throttled {
+            send_stat(stats::StatKind::Throttled(duration), None);
+        }
+
+        // stop reading the body after `max_duration`, which drops the connection
+        let body = match stream_response.max_duration {
+            Some(d) => body.take_until(Delay::new(d)).left_stream(),
+            None => body.right_stream(),
+        };
+        let mut body = pin!(body);
+        let mut parser = EventParser::new(stream_response.format);
+        let max_events = stream_response.max_events.map_or(usize::MAX, |n| n.get());
+        let mut first_event = None;
+        let mut between_events = Vec::new();
+        let mut last_event = None;
+        let mut event_count = 0;
+        let mut error = None;
+        loop {
+            let (events, end) = match body.next().await {
+                Some(Ok(chunk)) => (parser.push(&chunk), false),
+                Some(Err(e)) => {
+                    error = Some(e);
+                    break;
+                }
+                None => (parser.finish().into_iter().collect(), true),
+            };
+            for event in events.into_iter().take(max_events - event_count) {
+                let received = Instant::now();
+                let since = last_event.unwrap_or(now);
+                let micros = received.duration_since(since).as_micros() as u64;
+                match last_event {
+                    Some(_) => between_events.push(micros),
+                    None => first_event = Some(micros),
+                }
+                last_event = Some(received);
+                event_count += 1;
+
+                let mut template_values = template_values.clone();
+                let body = json::from_str(&event).unwrap_or(json::Value::String(event));
+                template_values
+                    .get_mut("response")
+                    .expect("template_values should have `response`")
+                    .as_object_mut()
+                    .expect("`response` in template_values should be an object")
+                    .insert("body".into(), body);
+                let rtt = now.elapsed().as_micros() as u64;
+                template_values.insert(
+                    "stats".into(),
+                    json::json!({ "rtt": rtt as f64 / 1000.0, "retries": retries }),
+                );
+                let template_values = Arc::new(template_values.0);
+                for result in join_all(send_outgoing(&outgoing, &template_values)).await {
+                    if let Err(e) = result {
+                        send_stat(stats::StatKind::RecoverableError(e), None);
+                    }
+                }
+            }
+            if end || event_count >= max_events {
+                break;
+            }
+        }
+        let rtt = now.elapsed().as_micros() as u64;
+        send_stat(
+            stats::StatKind::Stream {
+                first_event,
+                between_events,
+            },
+            None,
+        );
+        match error {
+            Some(e) => send_stat(stats::StatKind::RecoverableError(e), None),
+            None => {
+                let mut kind = stats::StatKind::Response(status);
+                if retries > 0 {
+                    kind = stats::StatKind::Retry(kind.into());
+                }
+                send_stat(kind, Some(rtt));
+            }
+        }
+        if let Some(auto_returns) = auto_returns {
+            auto_returns.await;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a response body into events. Server-sent events are separated by a blank line and
+/// their value is the `data` field. Newline delimited json has an event on each line
+struct EventParser {
+    format: StreamFormat,
+    buffer: Vec<u8>,
+    data: Option<String>,
+}
+
+impl EventParser {
+    fn new(format: StreamFormat) -> Self {
+        Self {
+            format,
+            buffer: Vec::new(),
+            data: None,
+        }
+    }
+
+    /// Adds a chunk of the body and returns any events it completes
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(i) = self.buffer.iter().position(|b| *b == b'\n') {
+            let mut line: Vec<_> = self.buffer.drain(..=i).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            let line = String::from_utf8_lossy(&line).into_owned();
+            events.extend(self.line(line));
+        }
+        events
+    }
+
+    /// Called at the end of the body. Returns the last event if it wasn't terminated
+    fn finish(&mut self) -> Option<String> {
+        let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+        match self.format {
+            StreamFormat::Ndjson => self.line(line),
+            StreamFormat::Sse => {
+                self.line(line);
+                self.data.take()
+            }
+        }
+    }
+
+    fn line(&mut self, line: String) -> Option<String> {
+        match self.format {
+            StreamFormat::Ndjson if line.trim().is_empty() => None,
+            StreamFormat::Ndjson => Some(line),
+            // a blank line dispatches the event
+            StreamFormat::Sse if line.is_empty() => self.data.take(),
+            StreamFormat::Sse => {
+                let (field, value) = line.split_once(':').unwrap_or((&line, ""));
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                // other fields, and comments which start with a colon, are ignored
+                if field == "data" {
+                    match &mut self.data {
+                        Some(data) => {
+                            data.push('\n');
+                            data.push_str(value);
+                        }
+                        None => self.data = Some(value.into()),
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::mpsc as futures_channel, executor::block_on, stream};
+    use maplit::btreemap;
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn parses_events() {
+        let mut parser = EventParser::new(StreamFormat::Sse);
+        assert!(parser
+            .push(b": comment\nevent: delta\ndata: {\"a\":")
+            .is_empty());
+        assert_eq!(
+            parser.push(b" 1}\n\ndata: x\r\ndata: y\r\n\r\n"),
+            vec!["{\"a\": 1}".to_string(), "x\ny".into()]
+        );
+        assert!(parser.push(b"data: last").is_empty());
+        assert_eq!(parser.finish(), Some("last".into()));
+
+        let mut parser = EventParser::new(StreamFormat::Ndjson);
+        assert_eq!(
+            parser.push(b"{\"a\": 1}\n\n{\"a\""),
+            vec!["{\"a\": 1}".to_string()]
+        );
+        assert_eq!(parser.push(b": 2}\n"), vec!["{\"a\": 2}".to_string()]);
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn handles_stream() {
+        let (tx, rx) = channel::channel(channel::Limit::Static(10), false, "stream");
+        let select = config::Select::simple(
+            "response.body.n",
+            config::EndpointProvidesSendOptions::Block,
+            None,
+            None,
+            None,
+        );
+        let outgoing = vec![Outgoing::new(
+            select,
+            super::super::ProviderOrLogger::Provider(tx),
+        )];
+        let (stats_tx, stats_rx) = futures_channel::unbounded();
+        let mut template_values = TemplateValues::new();
+        template_values.insert("response".into(), json::json!({ "status": 200 }));
+        let sh = StreamHandler {
+            stream_response: StreamResponse {
+                format: StreamFormat::Ndjson,
+                max_duration: None,
+                max_events: NonZeroUsize::new(3),
+            },
+            now: Instant::now(),
+            outgoing: outgoing.into(),
+            provider_delays: ProviderDelays::new(),
+            retries: 0,
+            stats_tx,
+            status: 200,
+            tags: btreemap! {"_id".into() => Template::simple("0")}.into(),
+            template_values,
+            throttled: None,
+        };
+        let chunks = ["{\"n\": 1}\n{\"n\"", ": 2}\n{\"n\": 3}\n{\"n\": 4}\n"]
+            .iter()
+            .map(|c| Ok(Bytes::from_static(c.as_bytes())));
+        let auto_returns: Option<futures::future::Ready<()>> = None;
+        block_on(sh.handle(stream::iter(chunks), auto_returns)).unwrap();
+
+        let values: Vec<_> = block_on(rx.take(3).collect());
+        assert_eq!(values, vec![json::json!(1), json::json!(2), json::json!(3)]);
+
+        let stats: Vec<_> = block_on(stats_rx.collect());
+        match &stats[..] {
+            [stats::StatsMessage::ResponseStat(events), stats::StatsMessage::ResponseStat(response)] =>
+            {
+                match &events.kind {
+                    stats::StatKind::Stream {
+                        first_event: Some(_),
+                        between_events,
+                    } => assert_eq!(between_events.len(), 2),
+                    kind => panic!("unexpected stat kind {:?}", kind),
+                }
+                assert!(matches!(response.kind, stats::StatKind::Response(200)));
+            }
+            _ => panic!("expected two response stats"),
+        }
+    }
+}
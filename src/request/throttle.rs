@@ -0,0 +1,172 @@
+use chrono::DateTime;
+use futures::{FutureExt, Stream, StreamExt};
+use futures_timer::Delay;
+use hyper::header::{HeaderMap, RETRY_AFTER};
+
+use std::{
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// The longest a single `retry-after` header can pause an endpoint
+pub(super) const MAX_PAUSE: Duration = Duration::from_secs(60 * 60);
+
+/// Tracks when an endpoint has been asked to back off with a `retry-after` header
+#[derive(Clone, Default)]
+pub(super) struct Throttle(Arc<Mutex<Option<Instant>>>);
+
+impl Throttle {
+    /// Pauses the endpoint for `duration`. Returns how much longer the endpoint is paused than it
+    /// already was, or `None` if an existing pause already covers it
+    pub(super) fn pause(&self, duration: Duration) -> Option<Duration> {
+        let now = Instant::now();
+        let until = now.checked_add(duration.min(MAX_PAUSE)).unwrap_or(now);
+        let mut paused_until = self.0.lock().expect("throttle lock should not be poisoned");
+        let start = paused_until.filter(|p| *p > now).unwrap_or(now);
+        if until <= start {
+            return None;
+        }
+        *paused_until = Some(until);
+        Some(until - start)
+    }
+
+    fn is_paused(&self) -> bool {
+        self.remaining().is_some()
+    }
+
+    // how much longer the endpoint is paused for, if it is paused
+    fn remaining(&self) -> Option<Duration> {
+        let paused_until = self.0.lock().expect("throttle lock should not be poisoned");
+        let now = Instant::now();
+        paused_until.filter(|p| *p > now).map(|p| p - now)
+    }
+
+    /// Wraps an endpoint's load pattern stream so any hits scheduled while the endpoint is paused
+    /// are skipped. The inner stream keeps being polled so it stays on schedule and doesn't burst
+    /// once the pause is over
+    pub(super) fn throttle_stream<S, T>(self, mut stream: S) -> impl Stream<Item = T>
+    where
+        S: Stream<Item = T> + Unpin,
+    {
+        futures::stream::poll_fn(move |cx| loop {
+            match stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(_)) if self.is_paused() => continue,
+                p => return p,
+            }
+        })
+    }
+
+    /// Wraps an endpoint's stream of hits so nothing is passed along while the endpoint is
+    /// paused. Unlike `throttle_stream` the hits are held rather than skipped, which is what
+    /// endpoints without a load pattern need as they are driven by their providers
+    pub(super) fn hold_stream<S, T>(self, mut stream: S) -> impl Stream<Item = T>
+    where
+        S: Stream<Item = T> + Unpin,
+    {
+        let mut delay: Option<Delay> = None;
+        futures::stream::poll_fn(move |cx| loop {
+            if let Some(d) = &mut delay {
+                futures::ready!(d.poll_unpin(cx));
+                delay = None;
+            }
+            match self.remaining() {
+                Some(remaining) => delay = Some(Delay::new(remaining)),
+                None => return stream.poll_next_unpin(cx),
+            }
+        })
+    }
+}
+
+/// Parses a `retry-after` header, which can be either a number of seconds or an HTTP date. The
+/// result is capped at `MAX_PAUSE`
+pub(super) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let duration = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let date: SystemTime = DateTime::parse_from_rfc2822(value).ok()?.into();
+            date.duration_since(SystemTime::now()).unwrap_or_default()
+        }
+    };
+    Some(duration.min(MAX_PAUSE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, future::poll_immediate};
+    use hyper::header::HeaderValue;
+
+    #[test]
+    fn parses_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(0)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("18446744073709551615"),
+        );
+        assert_eq!(retry_after(&headers), Some(MAX_PAUSE));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn pause_extends() {
+        let throttle = Throttle::default();
+        assert!(!throttle.is_paused());
+        let added = throttle.pause(Duration::from_secs(10)).unwrap();
+        assert!(added <= Duration::from_secs(10));
+        assert!(throttle.is_paused());
+        assert_eq!(throttle.pause(Duration::from_secs(5)), None);
+        let added = throttle.pause(Duration::from_secs(20)).unwrap();
+        assert!(added >= Duration::from_secs(10) && added < Duration::from_secs(11));
+        let added = throttle.pause(Duration::MAX).unwrap();
+        assert!(added <= MAX_PAUSE);
+    }
+
+    #[test]
+    fn skips_items_while_paused() {
+        let throttle = Throttle::default();
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let mut stream = throttle.clone().throttle_stream(rx);
+        let mut next = || block_on(poll_immediate(stream.next()));
+
+        tx.unbounded_send(0).unwrap();
+        assert_eq!(next(), Some(Some(0)));
+
+        throttle.pause(Duration::from_secs(10));
+        tx.unbounded_send(1).unwrap();
+        assert_eq!(next(), None);
+
+        *throttle.0.lock().unwrap() = None;
+        tx.unbounded_send(2).unwrap();
+        assert_eq!(next(), Some(Some(2)));
+    }
+
+    #[test]
+    fn holds_items_while_paused() {
+        let throttle = Throttle::default();
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let mut stream = throttle.clone().hold_stream(rx);
+
+        throttle.pause(Duration::from_millis(50));
+        tx.unbounded_send(0).unwrap();
+        assert_eq!(block_on(poll_immediate(stream.next())), None);
+
+        let start = Instant::now();
+        assert_eq!(block_on(stream.next()), Some(0));
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,548 @@
+use crate::error::{RecoverableError, TestError};
+use crate::stats;
+
+use config::{BodyTemplate, Template, REQUEST_BODY};
+use futures::{
+    future::{self, join_all, Either},
+    lock::Mutex as AsyncMutex,
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use futures_timer::Delay;
+use log::debug;
+use serde_json as json;
+use tokio::{net::TcpStream, task::JoinHandle};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{
+        self,
+        client::IntoClientRequest,
+        http::{HeaderName, HeaderValue},
+        Message,
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, VecDeque},
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{
+    evaluate_tags, request_maker::collect_stream_items, send_outgoing, send_stat, Outgoing,
+    StatsTx, StreamItem, TemplateValues,
+};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// the most sent messages which can be waiting for a reply on a connection. When it is full the
+// oldest message is given up on and recorded as a timeout
+const MAX_PENDING: usize = 10_000;
+
+/// A message sent on a websocket which is waiting for a reply. Replies are matched to the
+/// messages by the `correlate` field when there is one, otherwise in the order they were sent
+struct PendingMessage {
+    sent: Instant,
+    // the value of the `correlate` field in the message
+    id: Option<json::Value>,
+    template_values: TemplateValues,
+}
+
+type Pending = Arc<Mutex<VecDeque<PendingMessage>>>;
+
+// removes the pending messages which have gone longer than `timeout` without a reply, along with
+// the oldest ones past `MAX_PENDING`, and records them as timeouts
+fn expire_pending(
+    pending: &mut VecDeque<PendingMessage>,
+    timeout: Duration,
+    stats_tx: &StatsTx,
+    tags: &BTreeMap<String, Template>,
+) {
+    let now = Instant::now();
+    while let Some(p) = pending.front() {
+        if pending.len() <= MAX_PENDING && now.duration_since(p.sent) <= timeout {
+            break;
+        }
+        let p = pending.pop_front().expect("pending should not be empty");
+        let kind = stats::StatKind::RecoverableError(RecoverableError::Timeout(SystemTime::now()));
+        let tags = evaluate_tags(tags, &p.template_values);
+        send_stat(stats_tx, kind, Some(timeout.as_micros() as u64), tags);
+    }
+}
+
+/// An open websocket connection. Received messages are handled by a spawned task which is
+/// stopped when the connection is dropped
+pub(super) struct Connection {
+    sink: SplitSink<WsStream, Message>,
+    pending: Pending,
+    closed: Arc<AtomicBool>,
+    reader: JoinHandle<()>,
+}
+
+impl Connection {
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// The url and headers a connection was opened with
+type ConnectionKey = (String, Vec<(String, String)>);
+
+type ConnectionSlot = Arc<AsyncMutex<Option<Connection>>>;
+
+/// The connections of a `websocket` endpoint, keyed by the evaluated url and headers. Hits which
+/// evaluate to the same url and headers share a connection, so each value from a provider (like
+/// a user's token) gets a connection of its own
+#[derive(Clone, Default)]
+pub(super) struct Connections(Arc<Mutex<BTreeMap<ConnectionKey, ConnectionSlot>>>);
+
+impl Connections {
+    fn get(&self, key: &ConnectionKey) -> ConnectionSlot {
+        let mut connections = self
+            .0
+            .lock()
+            .expect("connections lock should not be poisoned");
+        if let Some(slot) = connections.get(key) {
+            return slot.clone();
+        }
+        // forget the connections which have closed and are not in use before adding another
+        connections.retain(|_, slot| match slot.try_lock() {
+            Some(connection) => connection.as_ref().is_some_and(|c| !c.is_closed()),
+            None => true,
+        });
+        connections.entry(key.clone()).or_default().clone()
+    }
+}
+
+/// Sends the messages for a `websocket` endpoint. A connection is opened on the first hit for
+/// its url and headers and reopened on the next hit after it closes
+pub(super) struct WebSocketMaker {
+    pub(super) url: Template,
+    pub(super) headers: Vec<(String, Template)>,
+    pub(super) body: BodyTemplate,
+    pub(super) rr_providers: u16,
+    pub(super) stats_tx: StatsTx,
+    pub(super) no_auto_returns: bool,
+    pub(super) outgoing: Arc<Vec<Outgoing>>,
+    pub(super) tags: Arc<BTreeMap<String, Template>>,
+    pub(super) timeout: Duration,
+    pub(super) correlate: Option<String>,
+    pub(super) connections: Connections,
+}
+
+impl WebSocketMaker {
+    pub(super) fn send_message(
+        self: &Arc<Self>,
+        values: Vec<StreamItem>,
+    ) -> impl Future<Output = Result<(), TestError>> {
+        let (mut template_values, auto_returns, provider_delays) =
+            collect_stream_items(values, self.no_auto_returns);
+        let maker = self.clone();
+        async move {
+            let result = maker.send(&mut template_values).await;
+            let tags = evaluate_tags(&maker.tags, &template_values);
+            match result {
+                Ok(()) => (),
+                Err(TestError::Recoverable(e)) => {
+                    let rtt = match e {
+                        RecoverableError::Timeout(_) => Some(maker.timeout.as_micros() as u64),
+                        _ => None,
+                    };
+                    let kind = stats::StatKind::RecoverableError(e);
+                    send_stat(&maker.stats_tx, kind, rtt, tags.clone());
+                }
+                Err(e) => return Err(e),
+            }
+            provider_delays.log(&tags, &maker.stats_tx);
+            join_all(auto_returns).await;
+            Ok(())
+        }
+    }
+
+    async fn send(&self, template_values: &mut TemplateValues) -> Result<(), TestError> {
+        let message = match &self.body {
+            BodyTemplate::String(t) => {
+                Some(t.evaluate(Cow::Borrowed(template_values.as_json()), None)?)
+            }
+            _ => None,
+        };
+        let key = self.connection_key(template_values)?;
+        let slot = self.connections.get(&key);
+        let mut connection = slot.lock().await;
+        if connection.as_ref().is_none_or(Connection::is_closed) {
+            *connection = Some(self.connect(key, template_values).await?);
+        }
+        let connection = connection.as_mut().expect("connection should be open");
+        if let Some(message) = message {
+            if self.rr_providers & REQUEST_BODY != 0 {
+                template_values.insert("request".into(), json::json!({ "body": message }));
+            }
+            debug!("WebSocketMaker::send message=\"{}\"", message);
+            let id = self.correlate.as_ref().and_then(|field| {
+                json::from_str::<json::Value>(&message)
+                    .ok()
+                    .and_then(|m| m.get(field).cloned())
+            });
+            {
+                let mut pending = connection
+                    .pending
+                    .lock()
+                    .expect("pending lock should not be poisoned");
+                pending.push_back(PendingMessage {
+                    sent: Instant::now(),
+                    id,
+                    template_values: template_values.clone(),
+                });
+                expire_pending(&mut pending, self.timeout, &self.stats_tx, &self.tags);
+            }
+            if let Err(e) = connection.sink.send(Message::text(message)).await {
+                connection.closed.store(true, Ordering::Relaxed);
+                return Err(connection_err(e).into());
+            }
+        }
+        Ok(())
+    }
+
+    fn connection_key(&self, template_values: &TemplateValues) -> Result<ConnectionKey, TestError> {
+        let url = self
+            .url
+            .evaluate(Cow::Borrowed(template_values.as_json()), None)?;
+        let headers = self
+            .headers
+            .iter()
+            .map(|(k, v)| {
+                let value = v.evaluate(Cow::Borrowed(template_values.as_json()), None)?;
+                Ok((k.clone(), value))
+            })
+            .collect::<Result<_, TestError>>()?;
+        Ok((url, headers))
+    }
+
+    async fn connect(
+        &self,
+        (url, headers): ConnectionKey,
+        template_values: &TemplateValues,
+    ) -> Result<Connection, TestError> {
+        let mut request = url
+            .as_str()
+            .into_client_request()
+            .map_err(|_| TestError::InvalidUrl(url.clone()))?;
+        for (k, v) in headers {
+            let key = HeaderName::from_bytes(k.as_bytes())
+                .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+            let value =
+                HeaderValue::from_str(&v).map_err(|e| RecoverableError::BodyErr(Arc::new(e)))?;
+            request.headers_mut().insert(key, value);
+        }
+        debug!("WebSocketMaker::connect url=\"{}\"", url);
+        let now = Instant::now();
+        let connect = Box::pin(connect_async(request));
+        let (stream, response) = match future::select(connect, Delay::new(self.timeout)).await {
+            Either::Left((result, _)) => result.map_err(connection_err)?,
+            Either::Right(_) => {
+                return Err(RecoverableError::Timeout(SystemTime::now()).into());
+            }
+        };
+        // the handshake is recorded like any other response
+        let rtt = now.elapsed().as_micros() as u64;
+        let kind = stats::StatKind::Response(response.status().as_u16());
+        let tags = evaluate_tags(&self.tags, template_values);
+        send_stat(&self.stats_tx, kind, Some(rtt), tags);
+
+        let (sink, stream) = stream.split();
+        let pending: Pending = Default::default();
+        let closed: Arc<AtomicBool> = Default::default();
+        let reader = MessageReader {
+            template_values: template_values.clone(),
+            pending: pending.clone(),
+            closed: closed.clone(),
+            outgoing: self.outgoing.clone(),
+            stats_tx: self.stats_tx.clone(),
+            tags: self.tags.clone(),
+            timeout: self.timeout,
+            correlate: self.correlate.clone(),
+        };
+        let reader = tokio::spawn(reader.read(stream));
+        Ok(Connection {
+            sink,
+            pending,
+            closed,
+            reader,
+        })
+    }
+}
+
+/// Handles the messages received on a websocket connection
+struct MessageReader {
+    // the values from the hit which opened the connection, used for messages which aren't a
+    // reply to a message that was sent
+    template_values: TemplateValues,
+    pending: Pending,
+    closed: Arc<AtomicBool>,
+    outgoing: Arc<Vec<Outgoing>>,
+    stats_tx: StatsTx,
+    tags: Arc<BTreeMap<String, Template>>,
+    timeout: Duration,
+    correlate: Option<String>,
+}
+
+impl MessageReader {
+    async fn read(self, mut stream: SplitStream<WsStream>) {
+        loop {
+            // wake up at least once every timeout so messages which never get a reply are
+            // recorded even when nothing else is received
+            let next = future::select(stream.next(), Delay::new(self.timeout)).await;
+            let message = match next {
+                Either::Left((Some(message), _)) => message,
+                Either::Left((None, _)) => break,
+                Either::Right(_) => {
+                    self.expire_pending();
+                    continue;
+                }
+            };
+            let body = match message {
+                Ok(Message::Text(t)) => t.to_string(),
+                Ok(Message::Binary(b)) => String::from_utf8_lossy(&b).into_owned(),
+                Ok(Message::Close(_)) => break,
+                Ok(_) => continue,
+                Err(e) => {
+                    let kind = stats::StatKind::RecoverableError(connection_err(e));
+                    let tags = evaluate_tags(&self.tags, &self.template_values);
+                    send_stat(&self.stats_tx, kind, None, tags);
+                    break;
+                }
+            };
+            debug!("MessageReader::read message=\"{}\"", body);
+            self.handle_message(body).await;
+        }
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    fn expire_pending(&self) {
+        let mut pending = self
+            .pending
+            .lock()
+            .expect("pending lock should not be poisoned");
+        expire_pending(&mut pending, self.timeout, &self.stats_tx, &self.tags);
+    }
+
+    async fn handle_message(&self, body: String) {
+        let received = Instant::now();
+        let body = json::from_str(&body).unwrap_or(json::Value::String(body));
+        let reply_to = {
+            let mut pending = self
+                .pending
+                .lock()
+                .expect("pending lock should not be poisoned");
+            expire_pending(&mut pending, self.timeout, &self.stats_tx, &self.tags);
+            match &self.correlate {
+                // a message without the id of a pending message was pushed by the server
+                Some(field) => body
+                    .get(field)
+                    .and_then(|id| pending.iter().position(|p| p.id.as_ref() == Some(id)))
+                    .and_then(|i| pending.remove(i)),
+                None => pending.pop_front(),
+            }
+        };
+        let (mut template_values, rtt) = match reply_to {
+            Some(p) => {
+                let rtt = received.duration_since(p.sent).as_micros() as u64;
+                (p.template_values, Some(rtt))
+            }
+            None => (self.template_values.clone(), None),
+        };
+        template_values.insert("response".into(), json::json!({ "body": body }));
+        template_values.insert(
+            "stats".into(),
+            json::json!({ "rtt": rtt.map(|rtt| rtt as f64 / 1000.0) }),
+        );
+        // received messages are kept separate from the handshake in the stats
+        let mut tags = (*evaluate_tags(&self.tags, &template_values)).clone();
+        tags.insert("method".into(), "MESSAGE".into());
+        let tags = Arc::new(tags);
+        send_stat(&self.stats_tx, stats::StatKind::Message, rtt, tags.clone());
+
+        let template_values = Arc::new(template_values.0);
+        let futures = send_outgoing(&self.outgoing, &template_values);
+        for result in join_all(futures).await {
+            if let Err(e) = result {
+                let kind = stats::StatKind::RecoverableError(e);
+                send_stat(&self.stats_tx, kind, None, tags.clone());
+            }
+        }
+    }
+}
+
+fn connection_err(e: tungstenite::Error) -> RecoverableError {
+    RecoverableError::ConnectionErr(SystemTime::now(), Arc::new(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::ProviderOrLogger;
+    use channel::Limit;
+    use config::{EndpointProvidesSendOptions::Block, Select};
+    use futures::channel::mpsc as futures_channel;
+    use maplit::btreemap;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn sends_and_receives_messages() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, ..) = test_common::start_test_server(None).await;
+            let url = Template::simple(&format!("ws://127.0.0.1:{}/websocket", port));
+            let (tx, mut rx) = channel::channel(Limit::Static(5), false, "websocket");
+            let select = Select::simple("response.body.n", Block, None, None, None);
+            let outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))];
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+            let tags = btreemap! {"method".into() => Template::simple("GET")};
+            let wsm = Arc::new(WebSocketMaker {
+                url,
+                headers: Vec::new(),
+                body: BodyTemplate::String(Template::simple(r#"{"n": 1}"#)),
+                rr_providers: 0,
+                stats_tx,
+                no_auto_returns: true,
+                outgoing: outgoing.into(),
+                tags: tags.into(),
+                timeout: Duration::from_secs(10),
+                correlate: None,
+                connections: Default::default(),
+            });
+
+            wsm.send_message(Vec::new()).await.unwrap();
+            wsm.send_message(Vec::new()).await.unwrap();
+            assert_eq!(rx.next().await, Some(json::json!(1)));
+            assert_eq!(rx.next().await, Some(json::json!(1)));
+
+            let mut kinds = Vec::new();
+            for _ in 0..3 {
+                match stats_rx.next().await {
+                    Some(stats::StatsMessage::ResponseStat(rs)) => {
+                        assert!(rs.rtt.is_some());
+                        kinds.push((format!("{:?}", rs.kind), rs.tags["method"].clone()));
+                    }
+                    _ => panic!("expected a response stat"),
+                }
+            }
+            assert_eq!(
+                kinds,
+                vec![
+                    ("Response(101)".into(), "GET".into()),
+                    ("Message".into(), "MESSAGE".into()),
+                    ("Message".into(), "MESSAGE".into()),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn expires_pending_messages() {
+        let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+        let tags = BTreeMap::new();
+        let timeout = Duration::from_secs(10);
+        let message = |sent| PendingMessage {
+            sent,
+            id: None,
+            template_values: TemplateValues::new(),
+        };
+        let now = Instant::now();
+        let mut pending: VecDeque<_> = (0..MAX_PENDING).map(|_| message(now)).collect();
+        pending.push_front(message(now - Duration::from_secs(11)));
+
+        expire_pending(&mut pending, timeout, &stats_tx, &tags);
+        assert_eq!(pending.len(), MAX_PENDING);
+        pending.push_back(message(now));
+        expire_pending(&mut pending, timeout, &stats_tx, &tags);
+        assert_eq!(pending.len(), MAX_PENDING);
+
+        drop(stats_tx);
+        let kinds: Vec<_> = futures::executor::block_on_stream(&mut stats_rx)
+            .map(|s| match s {
+                stats::StatsMessage::ResponseStat(rs) => format!("{:?}", rs.kind),
+                _ => panic!("expected a response stat"),
+            })
+            .collect();
+        assert_eq!(kinds.len(), 2);
+        assert!(kinds.iter().all(|k| k.contains("Timeout")), "{:?}", kinds);
+    }
+
+    #[test]
+    fn shares_connections_by_url_and_headers() {
+        let connections = Connections::default();
+        let key = |url: &str, token: &str| {
+            (
+                url.to_string(),
+                vec![("authorization".to_string(), token.to_string())],
+            )
+        };
+        let a = connections.get(&key("ws://localhost/a", "1"));
+        assert!(Arc::ptr_eq(
+            &a,
+            &connections.get(&key("ws://localhost/a", "1"))
+        ));
+        assert!(!Arc::ptr_eq(
+            &a,
+            &connections.get(&key("ws://localhost/a", "2"))
+        ));
+        assert!(!Arc::ptr_eq(
+            &a,
+            &connections.get(&key("ws://localhost/b", "1"))
+        ));
+    }
+
+    #[test]
+    fn matches_replies_by_correlate_field() {
+        let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+        let message = |id| PendingMessage {
+            sent: Instant::now(),
+            id: Some(json::json!(id)),
+            template_values: TemplateValues::new(),
+        };
+        let pending: Pending = Arc::new(Mutex::new(vec![message(1), message(2)].into()));
+        let reader = MessageReader {
+            template_values: TemplateValues::new(),
+            pending: pending.clone(),
+            closed: Default::default(),
+            outgoing: Default::default(),
+            stats_tx,
+            tags: Default::default(),
+            timeout: Duration::from_secs(10),
+            correlate: Some("id".into()),
+        };
+        let ids = |pending: &Pending| -> Vec<_> {
+            let pending = pending.lock().unwrap();
+            pending.iter().map(|p| p.id.clone().unwrap()).collect()
+        };
+
+        // a message pushed by the server leaves the pending messages alone
+        futures::executor::block_on(reader.handle_message(r#"{"event":"tick"}"#.into()));
+        assert_eq!(ids(&pending), vec![json::json!(1), json::json!(2)]);
+        // a reply can come back out of order
+        futures::executor::block_on(reader.handle_message(r#"{"id":2}"#.into()));
+        assert_eq!(ids(&pending), vec![json::json!(1)]);
+        drop(reader);
+
+        let rtts: Vec<_> = futures::executor::block_on_stream(&mut stats_rx)
+            .map(|s| match s {
+                stats::StatsMessage::ResponseStat(rs) => rs.rtt.is_some(),
+                _ => panic!("expected a response stat"),
+            })
+            .collect();
+        assert_eq!(rtts, vec![false, true]);
+    }
+}
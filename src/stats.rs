@@ -199,7 +199,31 @@ struct BucketGroupStats {
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     status_counts: BTreeMap<u16, u64>,
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    grpc_status_counts: BTreeMap<u32, u64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     test_errors: BTreeMap<String, u64>,
+    // failures of the endpoint's `assert` checks, by name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    assertion_failures: BTreeMap<String, u64>,
+    // responses which had graphql errors
+    #[serde(skip_serializing_if = "is_zero")]
+    graphql_errors: u64,
+    // the outcomes of retried attempts, kept separate so they don't hide first-attempt failures
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    retries: BTreeMap<String, u64>,
+    // how many times the endpoint was paused by a `retry-after`, and for how long (microseconds)
+    #[serde(skip_serializing_if = "is_zero")]
+    throttle_count: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    throttled_time: u64,
+    // messages received on a websocket
+    #[serde(skip_serializing_if = "is_zero")]
+    messages_received: u64,
+    // for streamed responses, the time until the first event and between the events
+    #[serde(with = "histogram_serde", skip_serializing_if = "Histogram::is_empty")]
+    first_event_histogram: Histogram<u64>,
+    #[serde(with = "histogram_serde", skip_serializing_if = "Histogram::is_empty")]
+    between_events_histogram: Histogram<u64>,
 }
 
 impl Default for BucketGroupStats {
@@ -208,7 +232,16 @@ impl Default for BucketGroupStats {
             request_timeouts: 0,
             rtt_histogram: Histogram::new(3).expect("could not create histogram"),
             status_counts: Default::default(),
+            grpc_status_counts: Default::default(),
             test_errors: Default::default(),
+            assertion_failures: Default::default(),
+            graphql_errors: 0,
+            retries: Default::default(),
+            throttle_count: 0,
+            throttled_time: 0,
+            messages_received: 0,
+            first_event_histogram: Histogram::new(3).expect("could not create histogram"),
+            between_events_histogram: Histogram::new(3).expect("could not create histogram"),
         }
     }
 }
@@ -218,6 +251,13 @@ impl BucketGroupStats {
     fn append(&mut self, stat: ResponseStat) {
         match stat.kind {
             StatKind::RecoverableError(RecoverableError::Timeout(..)) => self.request_timeouts += 1,
+            StatKind::RecoverableError(RecoverableError::AssertionFailed(name)) => {
+                self.assertion_failures
+                    .entry(name)
+                    .and_modify(|n| *n += 1)
+                    .or_insert(1);
+                return;
+            }
             StatKind::RecoverableError(r) => {
                 let msg = format!("{r}");
                 self.test_errors
@@ -231,6 +271,57 @@ impl BucketGroupStats {
                     .and_modify(|n| *n += 1)
                     .or_insert(1);
             }
+            StatKind::Retry(kind) => {
+                // retried attempts don't count towards the rtt histogram
+                let outcome = match *kind {
+                    StatKind::Response(status) => status.to_string(),
+                    StatKind::RecoverableError(r) => format!("{r}"),
+                    StatKind::Retry(_)
+                    | StatKind::Throttled(_)
+                    | StatKind::Message
+                    | StatKind::Stream { .. }
+                    | StatKind::GrpcStatus(_)
+                    | StatKind::GraphqlErrors
+                    | StatKind::Sent => {
+                        unreachable!("a retry should only wrap a response or an error")
+                    }
+                };
+                self.retries
+                    .entry(outcome)
+                    .and_modify(|n| *n += 1)
+                    .or_insert(1);
+                return;
+            }
+            StatKind::Throttled(duration) => {
+                self.throttle_count += 1;
+                self.throttled_time += duration.as_micros() as u64;
+                return;
+            }
+            StatKind::Message => self.messages_received += 1,
+            StatKind::Sent => (),
+            StatKind::Stream {
+                first_event,
+                between_events,
+            } => {
+                if let Some(first_event) = first_event {
+                    self.first_event_histogram += first_event;
+                }
+                for micros in between_events {
+                    self.between_events_histogram += micros;
+                }
+                return;
+            }
+            StatKind::GraphqlErrors => {
+                self.graphql_errors += 1;
+                return;
+            }
+            StatKind::GrpcStatus(code) => {
+                self.grpc_status_counts
+                    .entry(code)
+                    .and_modify(|n| *n += 1)
+                    .or_insert(1);
+                return;
+            }
         }
         if let Some(rtt) = stat.rtt {
             self.rtt_histogram += rtt;
@@ -247,12 +338,38 @@ impl BucketGroupStats {
                 .and_modify(|n| *n += count)
                 .or_insert(*count);
         }
+        for (code, count) in &rhs.grpc_status_counts {
+            self.grpc_status_counts
+                .entry(*code)
+                .and_modify(|n| *n += count)
+                .or_insert(*count);
+        }
         for (description, count) in &rhs.test_errors {
             self.test_errors
                 .entry(description.clone())
                 .and_modify(|n| *n += count)
                 .or_insert(*count);
         }
+        for (name, count) in &rhs.assertion_failures {
+            self.assertion_failures
+                .entry(name.clone())
+                .and_modify(|n| *n += count)
+                .or_insert(*count);
+        }
+        for (outcome, count) in &rhs.retries {
+            self.retries
+                .entry(outcome.clone())
+                .and_modify(|n| *n += count)
+                .or_insert(*count);
+        }
+        self.graphql_errors += rhs.graphql_errors;
+        self.throttle_count += rhs.throttle_count;
+        self.throttled_time += rhs.throttled_time;
+        self.messages_received += rhs.messages_received;
+        let _ = self.first_event_histogram.add(&rhs.first_event_histogram);
+        let _ = self
+            .between_events_histogram
+            .add(&rhs.between_events_histogram);
     }
 
     // create a string summary for this `BucketGroupStats`
@@ -266,7 +383,14 @@ impl BucketGroupStats {
     ) -> String {
         let calls_made = self.rtt_histogram.len();
         let mut print_string = String::new();
-        if calls_made == 0 && self.test_errors.is_empty() && self.request_timeouts == 0 {
+        if calls_made == 0
+            && self.test_errors.is_empty()
+            && self.assertion_failures.is_empty()
+            && self.request_timeouts == 0
+            && self.retries.is_empty()
+            && self.throttle_count == 0
+            && self.messages_received == 0
+        {
             return print_string;
         }
         const MICROS_TO_MS: f64 = 1_000.0;
@@ -284,13 +408,25 @@ impl BucketGroupStats {
         match format {
             RunOutputFormat::Human => {
                 // human format
+                let name = match tags.get("operation") {
+                    Some(operation) => format!("- {method} {url} ({operation}):"),
+                    None => format!("- {method} {url}:"),
+                };
                 let piece = format!(
                     "\n{}\n  calls made: {}\n  status counts: {:?}\n",
-                    (format!("- {method} {url}:")).yellow().dim(),
+                    name.yellow().dim(),
                     calls_made,
                     self.status_counts
                 );
                 print_string.push_str(&piece);
+                if !self.grpc_status_counts.is_empty() {
+                    let piece = format!("  grpc status counts: {:?}\n", self.grpc_status_counts);
+                    print_string.push_str(&piece);
+                }
+                if self.messages_received > 0 {
+                    let piece = format!("  messages received: {}\n", self.messages_received);
+                    print_string.push_str(&piece);
+                }
                 if self.request_timeouts > 0 {
                     let piece = format!("  request timeouts: {:?}\n", self.request_timeouts);
                     print_string.push_str(&piece);
@@ -299,6 +435,34 @@ impl BucketGroupStats {
                     let piece = format!("  test errors: {:?}\n", self.test_errors);
                     print_string.push_str(&piece);
                 }
+                if !self.assertion_failures.is_empty() {
+                    let piece = format!("  assertion failures: {:?}\n", self.assertion_failures);
+                    print_string.push_str(&piece);
+                }
+                if self.graphql_errors > 0 {
+                    let piece = format!("  graphql errors: {}\n", self.graphql_errors);
+                    print_string.push_str(&piece);
+                }
+                if !self.retries.is_empty() {
+                    let piece = format!("  retries: {:?}\n", self.retries);
+                    print_string.push_str(&piece);
+                }
+                if !self.first_event_histogram.is_empty() {
+                    let piece = format!(
+                        "  time to first event: {}\n  time between events: {}\n",
+                        histogram_summary(&self.first_event_histogram),
+                        histogram_summary(&self.between_events_histogram)
+                    );
+                    print_string.push_str(&piece);
+                }
+                if self.throttle_count > 0 {
+                    let piece = format!(
+                        "  throttled: {} times for {}ms\n",
+                        self.throttle_count,
+                        self.throttled_time as f64 / MICROS_TO_MS
+                    );
+                    print_string.push_str(&piece);
+                }
                 let piece = format!(
                     "  p50: {p50}ms, p90: {p90}ms, p95: {p95}ms, p99: {p99}ms, p99.9: {p99_9}ms\n  \
                      min: {min}ms, max: {max}ms, avg: {mean}ms, std. dev: {stddev}ms\n"
@@ -320,6 +484,10 @@ impl BucketGroupStats {
                         self.status_counts.iter()
                             .map(|(status, count)| json::json!({ "status": status, "count": count }))
                             .collect::<Vec<_>>(),
+                    "grpcStatusCounts":
+                        self.grpc_status_counts.iter()
+                            .map(|(status, count)| json::json!({ "status": status, "count": count }))
+                            .collect::<Vec<_>>(),
                     "requestTimeouts": self.request_timeouts,
                     "testErrors":
                         self.test_errors.iter()
@@ -328,6 +496,23 @@ impl BucketGroupStats {
                     "testErrorCount":
                         self.test_errors.iter()
                             .fold(0, |sum, (_, c)| sum + c),
+                    "assertionFailures":
+                        self.assertion_failures.iter()
+                            .map(|(name, count)| json::json!({ "name": name, "count": count }))
+                            .collect::<Vec<_>>(),
+                    "graphqlErrors": self.graphql_errors,
+                    "retries":
+                        self.retries.iter()
+                            .map(|(outcome, count)| json::json!({ "outcome": outcome, "count": count }))
+                            .collect::<Vec<_>>(),
+                    "retryCount":
+                        self.retries.iter()
+                            .fold(0, |sum, (_, c)| sum + c),
+                    "throttleCount": self.throttle_count,
+                    "throttledTime": self.throttled_time as f64 / MICROS_TO_MS,
+                    "messagesReceived": self.messages_received,
+                    "firstEvent": histogram_json(&self.first_event_histogram),
+                    "betweenEvents": histogram_json(&self.between_events_histogram),
                     "p50": p50,
                     "p90": p90,
                     "p95": p95,
@@ -349,6 +534,26 @@ impl BucketGroupStats {
     }
 }
 
+// a short human readable summary of a histogram of microseconds
+fn histogram_summary(histogram: &Histogram<u64>) -> String {
+    let json = histogram_json(histogram);
+    format!(
+        "p50: {}ms, p95: {}ms, p99: {}ms, max: {}ms",
+        json["p50"], json["p95"], json["p99"], json["max"]
+    )
+}
+
+// the percentiles of a histogram of microseconds, in milliseconds
+fn histogram_json(histogram: &Histogram<u64>) -> json::Value {
+    const MICROS_TO_MS: f64 = 1_000.0;
+    json::json!({
+        "p50": histogram.value_at_quantile(0.5) as f64 / MICROS_TO_MS,
+        "p95": histogram.value_at_quantile(0.95) as f64 / MICROS_TO_MS,
+        "p99": histogram.value_at_quantile(0.99) as f64 / MICROS_TO_MS,
+        "max": histogram.max() as f64 / MICROS_TO_MS,
+    })
+}
+
 // helper function used by serde
 #[allow(clippy::trivially_copy_pass_by_ref)]
 fn is_zero(n: &u64) -> bool {
@@ -613,7 +818,18 @@ pub struct ResponseStat {
 #[derive(Debug)]
 pub enum StatKind {
     RecoverableError(RecoverableError),
-    Response(u16), // u16 represents the HTTP response status code
+    Response(u16),        // u16 represents the HTTP response status code
+    Retry(Box<StatKind>), // the outcome of an attempt after the first
+    Throttled(Duration),  // the endpoint was paused by a `retry-after` header
+    Message,              // a message was received on a websocket
+    // the time in microseconds until the first event of a streamed response, and between events
+    Stream {
+        first_event: Option<u64>,
+        between_events: Vec<u64>,
+    },
+    GrpcStatus(u32), // the `grpc-status` of a gRPC call
+    GraphqlErrors,   // a graphql response had errors
+    Sent,            // a `tcp` or `udp` payload was sent without reading a response
 }
 
 impl From<ResponseStat> for StatsMessage {
@@ -713,6 +929,10 @@ pub fn create_try_run_stats_channel(
             stats.rtt_histogram.len(),
             stats.status_counts
         );
+        if !stats.grpc_status_counts.is_empty() {
+            let piece = format!("\n  grpc status counts: {:?}", stats.grpc_status_counts);
+            output.push_str(&piece);
+        }
         if stats.request_timeouts > 0 {
             let piece = format!("\n  request timeouts: {:?}", stats.request_timeouts);
             output.push_str(&piece);
@@ -721,6 +941,14 @@ pub fn create_try_run_stats_channel(
             let piece = format!("\n  test errors: {:?}", stats.test_errors);
             output.push_str(&piece);
         }
+        if !stats.assertion_failures.is_empty() {
+            let piece = format!("\n  assertion failures: {:?}", stats.assertion_failures);
+            output.push_str(&piece);
+        }
+        if stats.graphql_errors > 0 {
+            let piece = format!("\n  graphql errors: {}", stats.graphql_errors);
+            output.push_str(&piece);
+        }
         output.push('\n');
 
         let _ = console.send(MsgType::Final(output)).await;